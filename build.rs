@@ -0,0 +1,26 @@
+/// Generates include/nojoy.h from the C API in src/ffi.rs when the ffi
+/// feature is on, so that the header can't drift from the exports. The
+/// header is checked in for C callers that don't build the crate.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+fn main() {
+    #[cfg(feature = "ffi")]
+    header();
+}
+
+#[cfg(feature = "ffi")]
+fn header() {
+    use std::{env, path::PathBuf};
+
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+    let bindings = cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(dir.join("src/ffi.rs"))
+        .generate()
+        .expect("src/ffi.rs can be read by cbindgen");
+    // only written when it changed, the file may be read only in a package
+    bindings.write_to_file(dir.join("include/nojoy.h"));
+}
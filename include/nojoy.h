@@ -0,0 +1,161 @@
+/*
+ * nojoy C API - built with `cargo build --release --features ffi`
+ *
+ * Every function returns one of the NOJOY_ status codes below. When a call
+ * fails, nojoy_last_error_message() returns a description for the calling
+ * thread, which must be released with nojoy_free_string().
+ *
+ * Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+ */
+
+#ifndef NOJOY_H
+#define NOJOY_H
+
+/* Generated by build.rs with cbindgen from src/ffi.rs, don't edit */
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define NOJOY_OK 0
+
+#define NOJOY_NOT_FOUND 1
+
+#define NOJOY_NOT_PRESENT 2
+
+#define NOJOY_ACCESS_DENIED 3
+
+#define NOJOY_CONFIGRET 4
+
+#define NOJOY_WIN32 5
+
+#define NOJOY_INVALID_ARGUMENT 6
+
+#define NOJOY_BUFFER_TOO_SMALL 7
+
+#define NOJOY_PANIC 8
+
+#define NOJOY_OTHER 9
+
+#define NOJOY_NOT_DISABLEABLE 10
+
+#define NOJOY_RESTART_REQUIRED 11
+
+#define NOJOY_TIMEOUT 12
+
+#if defined(_WIN32)
+/**
+ * Registration for controller events, created by `nojoy_watch_open`.
+ * Watching is only there on Windows.
+ */
+typedef struct NojoyWatch NojoyWatch;
+#endif
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Write the connected controllers as UTF-8 JSON (not null terminated) into
+ * `out_buf`.
+ *
+ * `*out_len` must hold the size of `out_buf` on entry and receives the
+ * size of the JSON on return. Pass a null `out_buf` to query the size;
+ * `NOJOY_BUFFER_TOO_SMALL` is returned when the buffer can't hold it.
+ *
+ * # Safety
+ * `out_len` must be valid, and `out_buf` null or valid for `*out_len` bytes.
+ */
+int32_t nojoy_list(uint8_t *out_buf, size_t *out_len);
+
+/**
+ * Allocate the connected controllers as null terminated UTF-8 JSON like
+ * `nojoy_list` writes them, for callers that would rather not size a
+ * buffer. `*out_json` receives the JSON, to be released with
+ * `nojoy_free_list`, and `*out_len` its length without the terminator
+ * unless `out_len` is null.
+ *
+ * # Safety
+ * `out_json` must be valid, and `out_len` null or valid.
+ */
+int32_t nojoy_list_alloc(char **out_json, size_t *out_len);
+
+/**
+ * Release a list from `nojoy_list_alloc`. Null is ignored.
+ *
+ * # Safety
+ * `json` must come from `nojoy_list_alloc` and must not be used afterwards.
+ */
+void nojoy_free_list(char *json);
+
+/**
+ * Enable the device with given null terminated UTF-8 instance id.
+ *
+ * # Safety
+ * `instance_id` must be null or a valid null terminated string.
+ */
+int32_t nojoy_enable(const char *instance_id_utf8);
+
+/**
+ * Disable the device with given null terminated UTF-8 instance id.
+ *
+ * # Safety
+ * `instance_id` must be null or a valid null terminated string.
+ */
+int32_t nojoy_disable(const char *instance_id_utf8);
+
+/**
+ * Message of the last failed call on the calling thread, or null if the last
+ * call succeeded. Release the result with `nojoy_free_string`.
+ */
+char *nojoy_last_error_message(void);
+
+/**
+ * Release a string returned by this library. Null is ignored.
+ *
+ * # Safety
+ * `s` must come from this library and must not be used afterwards.
+ */
+void nojoy_free_string(char *s);
+
+#if defined(_WIN32)
+/**
+ * Start watching the game controllers, events are read with
+ * `nojoy_watch_next`.
+ *
+ * # Safety
+ * `out_watch` must be valid. The watch it receives must be released with
+ * `nojoy_watch_close`.
+ */
+int32_t nojoy_watch_open(NojoyWatch **out_watch);
+#endif
+
+#if defined(_WIN32)
+/**
+ * Wait up to `timeout_ms` for the next event and write it as UTF-8 JSON
+ * like `nojoy_list` does, an object with `time`, `kind` and `controller`.
+ * Returns `NOJOY_TIMEOUT` if nothing happened. An event that doesn't fit
+ * `out_buf` is kept for the next call.
+ *
+ * # Safety
+ * `watch` must come from `nojoy_watch_open` and not be closed, the buffer
+ * arguments are as in `nojoy_list`.
+ */
+int32_t nojoy_watch_next(NojoyWatch *watch, uint32_t timeout_ms, uint8_t *out_buf, size_t *out_len);
+#endif
+
+#if defined(_WIN32)
+/**
+ * Stop watching. Null is ignored.
+ *
+ * # Safety
+ * `watch` must come from `nojoy_watch_open` and must not be used
+ * afterwards.
+ */
+void nojoy_watch_close(NojoyWatch *watch);
+#endif
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* NOJOY_H */
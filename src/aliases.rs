@@ -0,0 +1,146 @@
+/// User-defined names for device selectors
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{collections::BTreeMap, env, fmt, fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::selector::Selector;
+
+const ALIASES_FILE_NAME: &str = "aliases.toml";
+
+/// Environment variable that overrides where the files are kept, for the
+/// service which runs under an account with another %APPDATA%
+pub const CONFIG_DIR_VAR: &str = "NOJOY_CONFIG_DIR";
+
+/// Environment variable the directory is under otherwise. On Linux that's
+/// `$XDG_CONFIG_HOME` if set, `$HOME/.config` by default.
+#[cfg(windows)]
+pub const BASE_DIR_VAR: &str = "APPDATA";
+#[cfg(not(windows))]
+pub const BASE_DIR_VAR: &str = "HOME";
+
+#[derive(Debug)]
+pub enum AliasError {
+    /// Alias names may only contain letters, digits, '-' and '_'
+    InvalidName(String),
+    InvalidSelector(String),
+    UnknownAlias(String),
+    /// %APPDATA%, or $HOME on Linux, isn't set, so we don't know where to
+    /// keep the aliases
+    NoConfigDir,
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+}
+
+impl fmt::Display for AliasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AliasError::InvalidName(name) => write!(
+                f,
+                "invalid alias name \"{}\": only letters, digits, '-' and '_' are allowed",
+                name
+            ),
+            AliasError::InvalidSelector(selector) => write!(f, "invalid selector \"{}\"", selector),
+            AliasError::UnknownAlias(name) => write!(f, "no such alias \"{}\"", name),
+            AliasError::NoConfigDir => {
+                write!(f, "{} environment variable is not set", BASE_DIR_VAR)
+            }
+            AliasError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            AliasError::Parse(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+/// Name → selector map, persisted in the user's application data folder
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Aliases {
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+}
+
+impl Aliases {
+    /// Load the aliases of the current user, an absent file means no aliases
+    pub fn load() -> Result<Self, AliasError> {
+        let path = path()?;
+        match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| AliasError::Parse(path, e)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(AliasError::Io(path, err)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), AliasError> {
+        let path = path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| AliasError::Io(dir.to_path_buf(), e))?;
+        }
+        let text = toml::to_string(self).expect("aliases are always serializable");
+        fs::write(&path, text).map_err(|e| AliasError::Io(path, e))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(String::as_str)
+    }
+
+    /// Add or replace an alias. Selector syntax is checked up front so a typo
+    /// doesn't surface only when the alias is used.
+    pub fn add(&mut self, name: &str, selector: &str) -> Result<(), AliasError> {
+        validate_name(name)?;
+        selector
+            .parse::<Selector>()
+            .map_err(|_| AliasError::InvalidSelector(selector.to_string()))?;
+        self.aliases.insert(name.to_string(), selector.to_string());
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), AliasError> {
+        self.aliases
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| AliasError::UnknownAlias(name.to_string()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.aliases.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+/// Point an alias at another selector in the saved aliases
+pub fn repoint(name: &str, selector: &str) -> Result<(), AliasError> {
+    let mut aliases = Aliases::load()?;
+    aliases.add(name, selector)?;
+    aliases.save()
+}
+
+/// Alias names can't contain backslashes or colons so they never look like
+/// an instance id or a selector.
+fn validate_name(name: &str) -> Result<(), AliasError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    match valid {
+        true => Ok(()),
+        false => Err(AliasError::InvalidName(name.to_string())),
+    }
+}
+
+/// Directory where per-user nojoy files are kept, `NOJOY_CONFIG_DIR` if set
+pub fn config_dir() -> Result<PathBuf, AliasError> {
+    if let Some(dir) = env::var_os(CONFIG_DIR_VAR) {
+        return Ok(PathBuf::from(dir));
+    }
+    #[cfg(windows)]
+    let base = env::var_os(BASE_DIR_VAR).map(PathBuf::from);
+    #[cfg(not(windows))]
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os(BASE_DIR_VAR).map(|home| PathBuf::from(home).join(".config")));
+    base.map(|dir| dir.join("nojoy"))
+        .ok_or(AliasError::NoConfigDir)
+}
+
+fn path() -> Result<PathBuf, AliasError> {
+    config_dir().map(|dir| dir.join(ALIASES_FILE_NAME))
+}
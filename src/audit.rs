@@ -0,0 +1,141 @@
+//! Recording every change to a device in the Windows Application event log
+//!
+//! Off until [`enable`] is called. Events come from the "nojoy" source, which
+//! [`register`] sets up with the message file of the .NET Framework, whose
+//! messages are the text they're given. Without it Event Viewer still shows
+//! the events, with a note that the description can't be found.
+//!
+//! Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use windows::{
+    core::{w, HSTRING, PCWSTR},
+    Win32::{
+        Foundation::{ERROR_ACCESS_DENIED, ERROR_FILE_NOT_FOUND, ERROR_SUCCESS, WIN32_ERROR},
+        System::{
+            EventLog::{
+                DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+                EVENTLOG_INFORMATION_TYPE,
+            },
+            Registry::{
+                RegCloseKey, RegCreateKeyExW, RegDeleteKeyW, RegSetValueExW, HKEY,
+                HKEY_LOCAL_MACHINE, KEY_SET_VALUE, REG_EXPAND_SZ, REG_OPTION_NON_VOLATILE,
+            },
+        },
+    },
+};
+
+use crate::devenum::Error;
+
+const SOURCE: PCWSTR = w!("nojoy");
+const SOURCE_KEY: PCWSTR = w!("SYSTEM\\CurrentControlSet\\Services\\EventLog\\Application\\nojoy");
+const MESSAGE_FILE: &str =
+    "%SystemRoot%\\Microsoft.NET\\Framework64\\v4.0.30319\\EventLogMessages.dll";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// What was done to a device, also the event id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Enable = 1,
+    Disable = 2,
+    Remove = 3,
+    /// Wake arming or selective suspend
+    Power = 4,
+}
+
+impl Action {
+    fn name(self) -> &'static str {
+        match self {
+            Action::Enable => "enable",
+            Action::Disable => "disable",
+            Action::Remove => "remove",
+            Action::Power => "change power settings of",
+        }
+    }
+}
+
+/// Write an event for every change from now on
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+fn check(result: WIN32_ERROR) -> Result<(), Error> {
+    match result {
+        ERROR_SUCCESS => Ok(()),
+        ERROR_ACCESS_DENIED => Err(Error::AccessDenied),
+        err => Err(windows::core::Error::from(err.to_hresult()).into()),
+    }
+}
+
+/// Create the event source, needs an elevated process
+pub fn register() -> Result<(), Error> {
+    unsafe {
+        let mut key = HKEY::default();
+        check(RegCreateKeyExW(
+            HKEY_LOCAL_MACHINE,
+            SOURCE_KEY,
+            0,
+            None,
+            REG_OPTION_NON_VOLATILE,
+            KEY_SET_VALUE,
+            None,
+            &mut key,
+            None,
+        ))?;
+        let value: Vec<u8> = MESSAGE_FILE
+            .encode_utf16()
+            .chain(Some(0))
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let result = RegSetValueExW(key, w!("EventMessageFile"), 0, REG_EXPAND_SZ, Some(&value));
+        let _ = RegCloseKey(key);
+        check(result)
+    }
+}
+
+/// Remove the event source, the events stay in the log
+pub fn unregister() -> Result<(), Error> {
+    match unsafe { RegDeleteKeyW(HKEY_LOCAL_MACHINE, SOURCE_KEY) } {
+        ERROR_FILE_NOT_FOUND => Ok(()),
+        result => check(result),
+    }
+}
+
+/// Record what was done to the device with given instance id, if auditing
+/// is enabled. Failures are recorded as errors.
+pub fn record<T>(action: Action, id: &str, result: &Result<T, Error>) {
+    if !ENABLED.load(Ordering::SeqCst) {
+        return;
+    }
+    let user = match (std::env::var("USERDOMAIN"), std::env::var("USERNAME")) {
+        (Ok(domain), Ok(name)) => format!("{}\\{}", domain, name),
+        (_, Ok(name)) => name,
+        _ => "unknown user".to_string(),
+    };
+    let (kind, outcome) = match result {
+        Ok(_) => (EVENTLOG_INFORMATION_TYPE, "succeeded".to_string()),
+        Err(err) => (EVENTLOG_ERROR_TYPE, format!("failed: {}", err)),
+    };
+    let text = HSTRING::from(format!("{} {} by {} {}", action.name(), id, user, outcome));
+    unsafe {
+        let source = match RegisterEventSourceW(None, SOURCE) {
+            Ok(source) => source,
+            Err(err) => return log::warn!("RegisterEventSourceW failed: {}", err),
+        };
+        let strings = [PCWSTR(text.as_ptr())];
+        if let Err(err) = ReportEventW(
+            source,
+            kind,
+            0,
+            action as u32,
+            None,
+            0,
+            Some(&strings),
+            None,
+        ) {
+            log::warn!("ReportEventW failed: {}", err);
+        }
+        let _ = DeregisterEventSource(source);
+    }
+}
@@ -0,0 +1,336 @@
+/// Defaults for command line options, read from the user's config file
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+#[cfg(windows)]
+use std::collections::BTreeMap;
+use std::{fmt, fs, io, path::PathBuf};
+
+use serde::{Deserialize, Deserializer};
+
+#[cfg(windows)]
+use crate::hotkey::Hotkey;
+use crate::{aliases, output::SortOrder, ColorMode, Format};
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Environment variables that override keys of the config file. Flags on the
+/// command line override both.
+pub const INCLUDE_DISCONNECTED_VAR: &str = "NOJOY_INCLUDE_DISCONNECTED";
+pub const COLOR_VAR: &str = "NOJOY_COLOR";
+pub const RETRIES_VAR: &str = "NOJOY_RETRIES";
+pub const RETRY_DELAY_VAR: &str = "NOJOY_RETRY_DELAY";
+
+/// https://no-color.org, only looked at when NOJOY_COLOR isn't set
+const NO_COLOR_VAR: &str = "NO_COLOR";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// %APPDATA%, or $HOME on Linux, isn't set, so we don't know where to
+    /// look for the file
+    NoConfigDir,
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    /// An environment variable has a value that can't be used
+    Env {
+        name: &'static str,
+        value: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NoConfigDir => write!(
+                f,
+                "{} environment variable is not set",
+                aliases::BASE_DIR_VAR
+            ),
+            ConfigError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Parse(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Env { name, value } => write!(f, "{}: invalid value \"{}\"", name, value),
+        }
+    }
+}
+
+/// Every key is optional, a flag given on the command line always wins. The
+/// keys of commands that are only there on Windows, like `[profiles]` and
+/// `hotkey`, are unknown elsewhere.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Output format of commands that support `--format`
+    pub format: Option<Format>,
+    /// Order of `list` output
+    pub sort: Option<SortOrder>,
+    /// Retry count of enable/disable
+    pub retries: Option<u32>,
+    /// Milliseconds before the first retry, doubled on every retry
+    pub retry_delay: Option<u64>,
+    pub include_disconnected: Option<bool>,
+    /// Whether `list` colors the status of devices
+    pub color: Option<ColorMode>,
+    /// Only let selectors like `name:` match devices of this USB vendor id
+    #[serde(default, deserialize_with = "hex_u16")]
+    pub vid: Option<u16>,
+    /// Key combination of the `hotkey` command
+    #[cfg(windows)]
+    #[serde(default, deserialize_with = "hotkey")]
+    pub hotkey: Option<Hotkey>,
+    /// Named sets of device states for `apply`
+    #[cfg(windows)]
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Executable name → profile to apply while it's in the foreground,
+    /// e.g. `"dcs.exe" = "flight"`
+    #[cfg(windows)]
+    #[serde(default)]
+    pub apps: BTreeMap<String, String>,
+    /// Write every change to a device to the Application event log
+    #[cfg(windows)]
+    pub audit: Option<bool>,
+    /// Times of day when the service enforces another profile than its own
+    #[cfg(windows)]
+    #[serde(default)]
+    pub schedule: Vec<Window>,
+    /// usb.ids file to name controllers that only have a generic name with
+    pub usb_ids: Option<PathBuf>,
+}
+
+/// Devices to enable and disable together, e.g.
+///
+/// ```toml
+/// [profiles.racing]
+/// disable = ["name:Xbox"]
+/// enable = ["name:wheel"]
+/// ```
+#[cfg(windows)]
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    /// Disable every connected controller before the lists are applied
+    #[serde(default)]
+    pub disable_all: bool,
+    /// Enable every connected controller before the lists are applied
+    #[serde(default)]
+    pub enable_all: bool,
+    /// Selectors of the devices to enable
+    #[serde(default)]
+    pub enable: Vec<String>,
+    /// Selectors of the devices to disable
+    #[serde(default)]
+    pub disable: Vec<String>,
+}
+
+/// A profile that applies between two times of the day, e.g.
+///
+/// ```toml
+/// [[schedule]]
+/// from = "22:00"
+/// to = "07:00"
+/// profile = "bedtime"
+/// ```
+///
+/// A window that ends before it starts goes over midnight.
+#[cfg(windows)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Window {
+    #[serde(deserialize_with = "time_of_day")]
+    pub from: TimeOfDay,
+    #[serde(deserialize_with = "time_of_day")]
+    pub to: TimeOfDay,
+    pub profile: String,
+}
+
+/// Minutes since midnight, local time
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay(pub u16);
+
+#[cfg(windows)]
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+impl Config {
+    /// Load the config file of the current user, an absent file or config
+    /// directory means no defaults
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = match path() {
+            Ok(path) => path,
+            Err(ConfigError::NoConfigDir) => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| ConfigError::Parse(path, e)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(ConfigError::Io(path, err)),
+        }
+    }
+
+    /// Override the keys that have an environment variable set, `var` looks
+    /// them up
+    pub fn with_env(mut self, var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        fn parse<T>(
+            var: &impl Fn(&str) -> Option<String>,
+            name: &'static str,
+            parse: impl Fn(&str) -> Option<T>,
+        ) -> Result<Option<T>, ConfigError> {
+            match var(name) {
+                Some(value) => match parse(value.trim()) {
+                    Some(parsed) => Ok(Some(parsed)),
+                    None => Err(ConfigError::Env { name, value }),
+                },
+                None => Ok(None),
+            }
+        }
+        let flag = |value: &str| match value.to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Some(true),
+            "0" | "false" | "no" | "off" => Some(false),
+            _ => None,
+        };
+        let color = |value: &str| clap::ValueEnum::from_str(value, true).ok();
+        if let Some(include) = parse(&var, INCLUDE_DISCONNECTED_VAR, flag)? {
+            self.include_disconnected = Some(include);
+        }
+        match parse(&var, COLOR_VAR, color)? {
+            Some(color) => self.color = Some(color),
+            None if var(NO_COLOR_VAR).is_some_and(|v| !v.is_empty()) => {
+                self.color = Some(ColorMode::Never)
+            }
+            None => {}
+        }
+        if let Some(retries) = parse(&var, RETRIES_VAR, |v| v.parse().ok())? {
+            self.retries = Some(retries);
+        }
+        if let Some(delay) = parse(&var, RETRY_DELAY_VAR, |v| v.parse().ok())? {
+            self.retry_delay = Some(delay);
+        }
+        Ok(self)
+    }
+}
+
+/// Where the config file is looked up
+pub fn path() -> Result<PathBuf, ConfigError> {
+    aliases::config_dir()
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .map_err(|_| ConfigError::NoConfigDir)
+}
+
+/// Vendor ids are written in hex like everywhere else, e.g. `vid = "045E"`
+fn hex_u16<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u16>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    u16::from_str_radix(&text, 16)
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("invalid vendor id \"{}\"", text)))
+}
+
+/// Hotkeys are written the same way as on the command line, e.g. `"Ctrl+Alt+J"`
+#[cfg(windows)]
+fn hotkey<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Hotkey>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    text.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+/// Times of day are written as "HH:MM" in 24-hour format
+#[cfg(windows)]
+fn time_of_day<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TimeOfDay, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    let invalid = || serde::de::Error::custom(format!("invalid time \"{}\", expected HH:MM", text));
+    let (hours, minutes) = text.split_once(':').ok_or_else(invalid)?;
+    let hours: u16 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u16 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+    Ok(TimeOfDay(hours * 60 + minutes))
+}
+
+/// A flag given on the command line, otherwise the configured value,
+/// otherwise the built-in default
+pub fn pick<T>(cli: Option<T>, config: Option<T>, default: T) -> T {
+    cli.or(config).unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &'static [(&'static str, &'static str)]) -> impl Fn(&str) -> Option<String> {
+        |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    fn configured() -> Config {
+        Config {
+            include_disconnected: Some(true),
+            color: Some(ColorMode::Always),
+            retries: Some(5),
+            retry_delay: Some(250),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn config_is_kept_without_env() {
+        let config = configured().with_env(env(&[])).unwrap();
+        assert_eq!(config.include_disconnected, Some(true));
+        assert_eq!(config.color, Some(ColorMode::Always));
+        assert_eq!(config.retries, Some(5));
+        assert_eq!(config.retry_delay, Some(250));
+    }
+
+    #[test]
+    fn env_overrides_config() {
+        let config = configured()
+            .with_env(env(&[
+                (INCLUDE_DISCONNECTED_VAR, "no"),
+                (COLOR_VAR, "never"),
+                (RETRIES_VAR, "1"),
+                (RETRY_DELAY_VAR, "500"),
+            ]))
+            .unwrap();
+        assert_eq!(config.include_disconnected, Some(false));
+        assert_eq!(config.color, Some(ColorMode::Never));
+        assert_eq!(config.retries, Some(1));
+        assert_eq!(config.retry_delay, Some(500));
+    }
+
+    #[test]
+    fn no_color_only_counts_without_nojoy_color() {
+        let config = configured().with_env(env(&[(NO_COLOR_VAR, "1")])).unwrap();
+        assert_eq!(config.color, Some(ColorMode::Never));
+        let config = configured()
+            .with_env(env(&[(NO_COLOR_VAR, "1"), (COLOR_VAR, "always")]))
+            .unwrap();
+        assert_eq!(config.color, Some(ColorMode::Always));
+        let config = configured().with_env(env(&[(NO_COLOR_VAR, "")])).unwrap();
+        assert_eq!(config.color, Some(ColorMode::Always));
+    }
+
+    #[test]
+    fn invalid_env_values_are_errors() {
+        for vars in [
+            &[(INCLUDE_DISCONNECTED_VAR, "maybe")],
+            &[(COLOR_VAR, "sometimes")],
+            &[(RETRY_DELAY_VAR, "-1")],
+        ] {
+            match Config::default().with_env(env(vars)) {
+                Err(ConfigError::Env { name, .. }) => assert_eq!(name, vars[0].0),
+                other => panic!("{:?} gave {:?}", vars, other),
+            }
+        }
+    }
+
+    #[test]
+    fn flags_win_over_config_and_defaults() {
+        assert_eq!(pick(Some(1), Some(2), 3), 1);
+        assert_eq!(pick(None, Some(2), 3), 2);
+        assert_eq!(pick(None, None, 3), 3);
+    }
+}
@@ -0,0 +1,619 @@
+//! Symbolic names for the numeric codes Windows returns
+//!
+//! Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use windows::{
+    core::HRESULT,
+    Win32::{
+        Devices::DeviceAndDriverInstallation::{
+            PNP_VetoAlreadyRemoved, PNP_VetoDevice, PNP_VetoDriver, PNP_VetoIllegalDeviceRequest,
+            PNP_VetoInsufficientPower, PNP_VetoInsufficientRights, PNP_VetoLegacyDevice,
+            PNP_VetoLegacyDriver, PNP_VetoNonDisableable, PNP_VetoOutstandingOpen,
+            PNP_VetoPendingClose, PNP_VetoTypeUnknown, PNP_VetoWindowsApp, PNP_VetoWindowsService,
+            CM_DEVNODE_STATUS_FLAGS, CM_PROB, CM_PROB_BIOS_TABLE, CM_PROB_BOOT_CONFIG_CONFLICT,
+            CM_PROB_CANT_SHARE_IRQ, CM_PROB_CONSOLE_LOCKED, CM_PROB_DEVICE_NOT_THERE,
+            CM_PROB_DEVICE_RESET, CM_PROB_DEVLOADER_FAILED, CM_PROB_DEVLOADER_NOT_FOUND,
+            CM_PROB_DEVLOADER_NOT_READY, CM_PROB_DISABLED, CM_PROB_DISABLED_SERVICE,
+            CM_PROB_DRIVER_BLOCKED, CM_PROB_DRIVER_FAILED_LOAD, CM_PROB_DRIVER_FAILED_PRIOR_UNLOAD,
+            CM_PROB_DRIVER_SERVICE_KEY_INVALID, CM_PROB_DUPLICATE_DEVICE,
+            CM_PROB_ENTRY_IS_WRONG_TYPE, CM_PROB_FAILED_ADD, CM_PROB_FAILED_DRIVER_ENTRY,
+            CM_PROB_FAILED_FILTER, CM_PROB_FAILED_INSTALL, CM_PROB_FAILED_POST_START,
+            CM_PROB_FAILED_START, CM_PROB_GUEST_ASSIGNMENT_FAILED, CM_PROB_HALTED,
+            CM_PROB_HARDWARE_DISABLED, CM_PROB_HELD_FOR_EJECT, CM_PROB_INVALID_DATA,
+            CM_PROB_IRQ_TRANSLATION_FAILED, CM_PROB_LACKED_ARBITRATOR,
+            CM_PROB_LEGACY_SERVICE_NO_DEVICES, CM_PROB_LIAR, CM_PROB_MOVED,
+            CM_PROB_NEED_CLASS_CONFIG, CM_PROB_NEED_RESTART, CM_PROB_NORMAL_CONFLICT,
+            CM_PROB_NOT_CONFIGURED, CM_PROB_NOT_VERIFIED, CM_PROB_NO_SOFTCONFIG,
+            CM_PROB_NO_VALID_LOG_CONF, CM_PROB_OUT_OF_MEMORY, CM_PROB_PARTIAL_LOG_CONF,
+            CM_PROB_PHANTOM, CM_PROB_REENUMERATION, CM_PROB_REGISTRY, CM_PROB_REGISTRY_TOO_LARGE,
+            CM_PROB_REINSTALL, CM_PROB_SETPROPERTIES_FAILED, CM_PROB_SYSTEM_SHUTDOWN,
+            CM_PROB_TOO_EARLY, CM_PROB_TRANSLATION_FAILED, CM_PROB_UNKNOWN_RESOURCE,
+            CM_PROB_UNSIGNED_DRIVER, CM_PROB_USED_BY_DEBUGGER, CM_PROB_VXDLDR,
+            CM_PROB_WAITING_ON_DEPENDENCY, CM_PROB_WILL_BE_REMOVED, CONFIGRET, CR_ACCESS_DENIED,
+            CR_ALREADY_SUCH_DEVNODE, CR_APM_VETOED, CR_BUFFER_SMALL, CR_CALL_NOT_IMPLEMENTED,
+            CR_CANT_SHARE_IRQ, CR_CREATE_BLOCKED, CR_DEFAULT, CR_DEVICE_INTERFACE_ACTIVE,
+            CR_DEVICE_NOT_THERE, CR_DEVLOADER_NOT_READY, CR_DEVNODE_HAS_REQS, CR_DLVXD_NOT_FOUND,
+            CR_FAILURE, CR_FREE_RESOURCES, CR_INVALID_API, CR_INVALID_ARBITRATOR,
+            CR_INVALID_CONFLICT_LIST, CR_INVALID_DATA, CR_INVALID_DEVICE_ID, CR_INVALID_DEVNODE,
+            CR_INVALID_FLAG, CR_INVALID_INDEX, CR_INVALID_LOAD_TYPE, CR_INVALID_LOG_CONF,
+            CR_INVALID_MACHINENAME, CR_INVALID_NODELIST, CR_INVALID_POINTER, CR_INVALID_PRIORITY,
+            CR_INVALID_PROPERTY, CR_INVALID_RANGE, CR_INVALID_RANGE_LIST,
+            CR_INVALID_REFERENCE_STRING, CR_INVALID_RESOURCEID, CR_INVALID_RES_DES,
+            CR_INVALID_STRUCTURE_SIZE, CR_MACHINE_UNAVAILABLE, CR_NEED_RESTART, CR_NOT_DISABLEABLE,
+            CR_NOT_SYSTEM_VM, CR_NO_ARBITRATOR, CR_NO_CM_SERVICES, CR_NO_DEPENDENT,
+            CR_NO_MORE_HW_PROFILES, CR_NO_MORE_LOG_CONF, CR_NO_MORE_RES_DES, CR_NO_REGISTRY_HANDLE,
+            CR_NO_SUCH_DEVICE_INTERFACE, CR_NO_SUCH_DEVNODE, CR_NO_SUCH_LOGICAL_DEV,
+            CR_NO_SUCH_REGISTRY_KEY, CR_NO_SUCH_VALUE, CR_OUT_OF_MEMORY, CR_QUERY_VETOED,
+            CR_REGISTRY_ERROR, CR_REMOTE_COMM_FAILURE, CR_REMOVE_VETOED, CR_SAME_RESOURCES,
+            CR_SUCCESS, CR_WRONG_TYPE, DN_APM_DRIVER, DN_APM_ENUMERATOR, DN_ARM_WAKEUP,
+            DN_BAD_PARTIAL, DN_BOOT_LOG_PROB, DN_CHILD_WITH_INVALID_ID, DN_DEVICE_DISCONNECTED,
+            DN_DISABLEABLE, DN_DRIVER_BLOCKED, DN_DRIVER_LOADED, DN_ENUM_LOADED, DN_FILTERED,
+            DN_HARDWARE_ENUM, DN_HAS_PROBLEM, DN_LEGACY_DRIVER, DN_MANUAL, DN_NEED_RESTART,
+            DN_NEED_TO_ENUM, DN_NOT_FIRST_TIMEE, DN_NO_SHOW_IN_DM, DN_NT_DRIVER, DN_NT_ENUMERATOR,
+            DN_PRIVATE_PROBLEM, DN_QUERY_REMOVE_ACTIVE, DN_QUERY_REMOVE_PENDING,
+            DN_REBAL_CANDIDATE, DN_REMOVABLE, DN_ROOT_ENUMERATED, DN_SILENT_INSTALL, DN_STARTED,
+            DN_STOP_FREE_RES, DN_WILL_BE_REMOVED, PNP_VETO_TYPE,
+        },
+        Foundation::{
+            ERROR_ACCESS_DENIED, ERROR_FILE_NOT_FOUND, ERROR_INSUFFICIENT_BUFFER,
+            ERROR_INVALID_DATA, ERROR_INVALID_FLAGS, ERROR_INVALID_HANDLE, ERROR_INVALID_PARAMETER,
+            ERROR_NOT_FOUND, ERROR_NO_MORE_ITEMS, ERROR_SUCCESS, WIN32_ERROR,
+        },
+    },
+};
+
+const CONFIGRET_NAMES: &[(CONFIGRET, &str)] = &[
+    (CR_SUCCESS, "CR_SUCCESS"),
+    (CR_DEFAULT, "CR_DEFAULT"),
+    (CR_OUT_OF_MEMORY, "CR_OUT_OF_MEMORY"),
+    (CR_INVALID_POINTER, "CR_INVALID_POINTER"),
+    (CR_INVALID_FLAG, "CR_INVALID_FLAG"),
+    (CR_INVALID_DEVNODE, "CR_INVALID_DEVNODE"),
+    (CR_INVALID_RES_DES, "CR_INVALID_RES_DES"),
+    (CR_INVALID_LOG_CONF, "CR_INVALID_LOG_CONF"),
+    (CR_INVALID_ARBITRATOR, "CR_INVALID_ARBITRATOR"),
+    (CR_INVALID_NODELIST, "CR_INVALID_NODELIST"),
+    (CR_DEVNODE_HAS_REQS, "CR_DEVNODE_HAS_REQS"),
+    (CR_INVALID_RESOURCEID, "CR_INVALID_RESOURCEID"),
+    (CR_DLVXD_NOT_FOUND, "CR_DLVXD_NOT_FOUND"),
+    (CR_NO_SUCH_DEVNODE, "CR_NO_SUCH_DEVNODE"),
+    (CR_NO_MORE_LOG_CONF, "CR_NO_MORE_LOG_CONF"),
+    (CR_NO_MORE_RES_DES, "CR_NO_MORE_RES_DES"),
+    (CR_ALREADY_SUCH_DEVNODE, "CR_ALREADY_SUCH_DEVNODE"),
+    (CR_INVALID_RANGE_LIST, "CR_INVALID_RANGE_LIST"),
+    (CR_INVALID_RANGE, "CR_INVALID_RANGE"),
+    (CR_FAILURE, "CR_FAILURE"),
+    (CR_NO_SUCH_LOGICAL_DEV, "CR_NO_SUCH_LOGICAL_DEV"),
+    (CR_CREATE_BLOCKED, "CR_CREATE_BLOCKED"),
+    (CR_NOT_SYSTEM_VM, "CR_NOT_SYSTEM_VM"),
+    (CR_REMOVE_VETOED, "CR_REMOVE_VETOED"),
+    (CR_APM_VETOED, "CR_APM_VETOED"),
+    (CR_INVALID_LOAD_TYPE, "CR_INVALID_LOAD_TYPE"),
+    (CR_BUFFER_SMALL, "CR_BUFFER_SMALL"),
+    (CR_NO_ARBITRATOR, "CR_NO_ARBITRATOR"),
+    (CR_NO_REGISTRY_HANDLE, "CR_NO_REGISTRY_HANDLE"),
+    (CR_REGISTRY_ERROR, "CR_REGISTRY_ERROR"),
+    (CR_INVALID_DEVICE_ID, "CR_INVALID_DEVICE_ID"),
+    (CR_INVALID_DATA, "CR_INVALID_DATA"),
+    (CR_INVALID_API, "CR_INVALID_API"),
+    (CR_DEVLOADER_NOT_READY, "CR_DEVLOADER_NOT_READY"),
+    (CR_NEED_RESTART, "CR_NEED_RESTART"),
+    (CR_NO_MORE_HW_PROFILES, "CR_NO_MORE_HW_PROFILES"),
+    (CR_DEVICE_NOT_THERE, "CR_DEVICE_NOT_THERE"),
+    (CR_NO_SUCH_VALUE, "CR_NO_SUCH_VALUE"),
+    (CR_WRONG_TYPE, "CR_WRONG_TYPE"),
+    (CR_INVALID_PRIORITY, "CR_INVALID_PRIORITY"),
+    (CR_NOT_DISABLEABLE, "CR_NOT_DISABLEABLE"),
+    (CR_FREE_RESOURCES, "CR_FREE_RESOURCES"),
+    (CR_QUERY_VETOED, "CR_QUERY_VETOED"),
+    (CR_CANT_SHARE_IRQ, "CR_CANT_SHARE_IRQ"),
+    (CR_NO_DEPENDENT, "CR_NO_DEPENDENT"),
+    (CR_SAME_RESOURCES, "CR_SAME_RESOURCES"),
+    (CR_NO_SUCH_REGISTRY_KEY, "CR_NO_SUCH_REGISTRY_KEY"),
+    (CR_INVALID_MACHINENAME, "CR_INVALID_MACHINENAME"),
+    (CR_REMOTE_COMM_FAILURE, "CR_REMOTE_COMM_FAILURE"),
+    (CR_MACHINE_UNAVAILABLE, "CR_MACHINE_UNAVAILABLE"),
+    (CR_NO_CM_SERVICES, "CR_NO_CM_SERVICES"),
+    (CR_ACCESS_DENIED, "CR_ACCESS_DENIED"),
+    (CR_CALL_NOT_IMPLEMENTED, "CR_CALL_NOT_IMPLEMENTED"),
+    (CR_INVALID_PROPERTY, "CR_INVALID_PROPERTY"),
+    (CR_DEVICE_INTERFACE_ACTIVE, "CR_DEVICE_INTERFACE_ACTIVE"),
+    (CR_NO_SUCH_DEVICE_INTERFACE, "CR_NO_SUCH_DEVICE_INTERFACE"),
+    (CR_INVALID_REFERENCE_STRING, "CR_INVALID_REFERENCE_STRING"),
+    (CR_INVALID_CONFLICT_LIST, "CR_INVALID_CONFLICT_LIST"),
+    (CR_INVALID_INDEX, "CR_INVALID_INDEX"),
+    (CR_INVALID_STRUCTURE_SIZE, "CR_INVALID_STRUCTURE_SIZE"),
+];
+
+/// What the CONFIGRET codes we are likely to run into mean for the user
+const CONFIGRET_EXPLANATIONS: &[(CONFIGRET, &str)] = &[
+    (CR_OUT_OF_MEMORY, "out of memory"),
+    (CR_INVALID_DEVNODE, "the device node handle is invalid"),
+    (CR_INVALID_DEVICE_ID, "the device instance id is invalid"),
+    (
+        CR_NO_SUCH_DEVNODE,
+        "the device node does not exist (device removed?)",
+    ),
+    (CR_FAILURE, "the operation failed"),
+    (CR_REMOVE_VETOED, "another program blocked the change"),
+    (CR_QUERY_VETOED, "another program blocked the change"),
+    (CR_NOT_DISABLEABLE, "the device can't be disabled"),
+    (CR_NEED_RESTART, "the change takes effect after a restart"),
+    (CR_DEVICE_NOT_THERE, "the device is not connected"),
+    (
+        CR_NO_SUCH_VALUE,
+        "the device doesn't have the requested value",
+    ),
+    (
+        CR_REGISTRY_ERROR,
+        "the device's registry data can't be accessed",
+    ),
+    (CR_ACCESS_DENIED, "insufficient privileges"),
+    (
+        CR_CALL_NOT_IMPLEMENTED,
+        "not supported on this version of Windows",
+    ),
+];
+
+/// What kind of component a PNP_VETO_TYPE says blocked a removal
+const VETO_TYPES: &[(PNP_VETO_TYPE, &str)] = &[
+    (PNP_VetoTypeUnknown, "unknown component"),
+    (PNP_VetoLegacyDevice, "legacy device"),
+    (PNP_VetoPendingClose, "pending close"),
+    (PNP_VetoWindowsApp, "application"),
+    (PNP_VetoWindowsService, "service"),
+    (PNP_VetoOutstandingOpen, "open handle to"),
+    (PNP_VetoDevice, "device"),
+    (PNP_VetoDriver, "driver"),
+    (PNP_VetoIllegalDeviceRequest, "illegal device request"),
+    (PNP_VetoInsufficientPower, "insufficient power"),
+    (PNP_VetoNonDisableable, "non-disableable device"),
+    (PNP_VetoLegacyDriver, "legacy driver"),
+    (PNP_VetoInsufficientRights, "insufficient rights"),
+    (PNP_VetoAlreadyRemoved, "device already removed"),
+];
+
+/// DN_xx bits in bit order. Some bits have several names in the SDK, the
+/// ones that apply to current Windows versions are used.
+const STATUS_FLAG_NAMES: &[(CM_DEVNODE_STATUS_FLAGS, &str)] = &[
+    (DN_ROOT_ENUMERATED, "root enumerated"),
+    (DN_DRIVER_LOADED, "driver loaded"),
+    (DN_ENUM_LOADED, "enumerator loaded"),
+    (DN_STARTED, "started"),
+    (DN_MANUAL, "manually installed"),
+    (DN_NEED_TO_ENUM, "needs enumeration"),
+    (DN_DRIVER_BLOCKED, "driver blocked"),
+    (DN_HARDWARE_ENUM, "hardware enumerated"),
+    (DN_NEED_RESTART, "needs restart"),
+    (DN_CHILD_WITH_INVALID_ID, "child with invalid id"),
+    (DN_HAS_PROBLEM, "has problem"),
+    (DN_FILTERED, "filtered"),
+    (DN_LEGACY_DRIVER, "legacy driver"),
+    (DN_DISABLEABLE, "disableable"),
+    (DN_REMOVABLE, "removable"),
+    (DN_PRIVATE_PROBLEM, "private problem"),
+    (DN_QUERY_REMOVE_PENDING, "query remove pending"),
+    (DN_QUERY_REMOVE_ACTIVE, "query remove active"),
+    (DN_WILL_BE_REMOVED, "will be removed"),
+    (DN_NOT_FIRST_TIMEE, "not first time"),
+    (DN_STOP_FREE_RES, "stopped to free resources"),
+    (DN_REBAL_CANDIDATE, "rebalance candidate"),
+    (DN_BAD_PARTIAL, "bad partial"),
+    (DN_NT_ENUMERATOR, "NT enumerator"),
+    (DN_NT_DRIVER, "NT driver"),
+    (DN_DEVICE_DISCONNECTED, "disconnected"),
+    (DN_ARM_WAKEUP, "armed for wake"),
+    (DN_APM_ENUMERATOR, "APM enumerator"),
+    (DN_APM_DRIVER, "APM driver"),
+    (DN_SILENT_INSTALL, "silent install"),
+    (DN_NO_SHOW_IN_DM, "hidden in Device Manager"),
+    (DN_BOOT_LOG_PROB, "boot log problem"),
+];
+
+/// CM_PROB_xx problem codes, the same numbers Device Manager shows as
+/// "Code 22" and so on
+const PROBLEM_NAMES: &[(CM_PROB, &str)] = &[
+    (CM_PROB_NOT_CONFIGURED, "CM_PROB_NOT_CONFIGURED"),
+    (CM_PROB_DEVLOADER_FAILED, "CM_PROB_DEVLOADER_FAILED"),
+    (CM_PROB_OUT_OF_MEMORY, "CM_PROB_OUT_OF_MEMORY"),
+    (CM_PROB_ENTRY_IS_WRONG_TYPE, "CM_PROB_ENTRY_IS_WRONG_TYPE"),
+    (CM_PROB_LACKED_ARBITRATOR, "CM_PROB_LACKED_ARBITRATOR"),
+    (CM_PROB_BOOT_CONFIG_CONFLICT, "CM_PROB_BOOT_CONFIG_CONFLICT"),
+    (CM_PROB_FAILED_FILTER, "CM_PROB_FAILED_FILTER"),
+    (CM_PROB_DEVLOADER_NOT_FOUND, "CM_PROB_DEVLOADER_NOT_FOUND"),
+    (CM_PROB_INVALID_DATA, "CM_PROB_INVALID_DATA"),
+    (CM_PROB_FAILED_START, "CM_PROB_FAILED_START"),
+    (CM_PROB_LIAR, "CM_PROB_LIAR"),
+    (CM_PROB_NORMAL_CONFLICT, "CM_PROB_NORMAL_CONFLICT"),
+    (CM_PROB_NOT_VERIFIED, "CM_PROB_NOT_VERIFIED"),
+    (CM_PROB_NEED_RESTART, "CM_PROB_NEED_RESTART"),
+    (CM_PROB_REENUMERATION, "CM_PROB_REENUMERATION"),
+    (CM_PROB_PARTIAL_LOG_CONF, "CM_PROB_PARTIAL_LOG_CONF"),
+    (CM_PROB_UNKNOWN_RESOURCE, "CM_PROB_UNKNOWN_RESOURCE"),
+    (CM_PROB_REINSTALL, "CM_PROB_REINSTALL"),
+    (CM_PROB_REGISTRY, "CM_PROB_REGISTRY"),
+    (CM_PROB_VXDLDR, "CM_PROB_VXDLDR"),
+    (CM_PROB_WILL_BE_REMOVED, "CM_PROB_WILL_BE_REMOVED"),
+    (CM_PROB_DISABLED, "CM_PROB_DISABLED"),
+    (CM_PROB_DEVLOADER_NOT_READY, "CM_PROB_DEVLOADER_NOT_READY"),
+    (CM_PROB_DEVICE_NOT_THERE, "CM_PROB_DEVICE_NOT_THERE"),
+    (CM_PROB_MOVED, "CM_PROB_MOVED"),
+    (CM_PROB_TOO_EARLY, "CM_PROB_TOO_EARLY"),
+    (CM_PROB_NO_VALID_LOG_CONF, "CM_PROB_NO_VALID_LOG_CONF"),
+    (CM_PROB_FAILED_INSTALL, "CM_PROB_FAILED_INSTALL"),
+    (CM_PROB_HARDWARE_DISABLED, "CM_PROB_HARDWARE_DISABLED"),
+    (CM_PROB_CANT_SHARE_IRQ, "CM_PROB_CANT_SHARE_IRQ"),
+    (CM_PROB_FAILED_ADD, "CM_PROB_FAILED_ADD"),
+    (CM_PROB_DISABLED_SERVICE, "CM_PROB_DISABLED_SERVICE"),
+    (CM_PROB_TRANSLATION_FAILED, "CM_PROB_TRANSLATION_FAILED"),
+    (CM_PROB_NO_SOFTCONFIG, "CM_PROB_NO_SOFTCONFIG"),
+    (CM_PROB_BIOS_TABLE, "CM_PROB_BIOS_TABLE"),
+    (
+        CM_PROB_IRQ_TRANSLATION_FAILED,
+        "CM_PROB_IRQ_TRANSLATION_FAILED",
+    ),
+    (CM_PROB_FAILED_DRIVER_ENTRY, "CM_PROB_FAILED_DRIVER_ENTRY"),
+    (
+        CM_PROB_DRIVER_FAILED_PRIOR_UNLOAD,
+        "CM_PROB_DRIVER_FAILED_PRIOR_UNLOAD",
+    ),
+    (CM_PROB_DRIVER_FAILED_LOAD, "CM_PROB_DRIVER_FAILED_LOAD"),
+    (
+        CM_PROB_DRIVER_SERVICE_KEY_INVALID,
+        "CM_PROB_DRIVER_SERVICE_KEY_INVALID",
+    ),
+    (
+        CM_PROB_LEGACY_SERVICE_NO_DEVICES,
+        "CM_PROB_LEGACY_SERVICE_NO_DEVICES",
+    ),
+    (CM_PROB_DUPLICATE_DEVICE, "CM_PROB_DUPLICATE_DEVICE"),
+    (CM_PROB_FAILED_POST_START, "CM_PROB_FAILED_POST_START"),
+    (CM_PROB_HALTED, "CM_PROB_HALTED"),
+    (CM_PROB_PHANTOM, "CM_PROB_PHANTOM"),
+    (CM_PROB_SYSTEM_SHUTDOWN, "CM_PROB_SYSTEM_SHUTDOWN"),
+    (CM_PROB_HELD_FOR_EJECT, "CM_PROB_HELD_FOR_EJECT"),
+    (CM_PROB_DRIVER_BLOCKED, "CM_PROB_DRIVER_BLOCKED"),
+    (CM_PROB_REGISTRY_TOO_LARGE, "CM_PROB_REGISTRY_TOO_LARGE"),
+    (CM_PROB_SETPROPERTIES_FAILED, "CM_PROB_SETPROPERTIES_FAILED"),
+    (
+        CM_PROB_WAITING_ON_DEPENDENCY,
+        "CM_PROB_WAITING_ON_DEPENDENCY",
+    ),
+    (CM_PROB_UNSIGNED_DRIVER, "CM_PROB_UNSIGNED_DRIVER"),
+    (CM_PROB_USED_BY_DEBUGGER, "CM_PROB_USED_BY_DEBUGGER"),
+    (CM_PROB_DEVICE_RESET, "CM_PROB_DEVICE_RESET"),
+    (CM_PROB_CONSOLE_LOCKED, "CM_PROB_CONSOLE_LOCKED"),
+    (CM_PROB_NEED_CLASS_CONFIG, "CM_PROB_NEED_CLASS_CONFIG"),
+    (
+        CM_PROB_GUEST_ASSIGNMENT_FAILED,
+        "CM_PROB_GUEST_ASSIGNMENT_FAILED",
+    ),
+];
+
+/// What the problem codes controllers usually end up with mean for the user
+const PROBLEM_EXPLANATIONS: &[(CM_PROB, &str)] = &[
+    (CM_PROB_FAILED_START, "the device couldn't be started"),
+    (CM_PROB_NEED_RESTART, "Windows needs to be restarted"),
+    (CM_PROB_REINSTALL, "the driver needs to be reinstalled"),
+    (CM_PROB_DISABLED, "the device is disabled"),
+    (CM_PROB_FAILED_INSTALL, "the driver isn't installed"),
+    (
+        CM_PROB_FAILED_ADD,
+        "the driver couldn't be loaded for the device",
+    ),
+    (CM_PROB_DISABLED_SERVICE, "the driver's service is disabled"),
+    (CM_PROB_DRIVER_FAILED_LOAD, "the driver couldn't be loaded"),
+    (CM_PROB_FAILED_POST_START, "the driver reported a failure"),
+    (CM_PROB_PHANTOM, "the device isn't connected"),
+    (CM_PROB_DRIVER_BLOCKED, "the driver is blocked from loading"),
+    (CM_PROB_UNSIGNED_DRIVER, "the driver isn't signed"),
+];
+
+/// Win32 errors SetupAPI calls are likely to come back with
+const WIN32_ERROR_NAMES: &[(WIN32_ERROR, &str)] = &[
+    (ERROR_SUCCESS, "ERROR_SUCCESS"),
+    (ERROR_FILE_NOT_FOUND, "ERROR_FILE_NOT_FOUND"),
+    (ERROR_ACCESS_DENIED, "ERROR_ACCESS_DENIED"),
+    (ERROR_INVALID_HANDLE, "ERROR_INVALID_HANDLE"),
+    (ERROR_INVALID_DATA, "ERROR_INVALID_DATA"),
+    (ERROR_INVALID_PARAMETER, "ERROR_INVALID_PARAMETER"),
+    (ERROR_INSUFFICIENT_BUFFER, "ERROR_INSUFFICIENT_BUFFER"),
+    (ERROR_NO_MORE_ITEMS, "ERROR_NO_MORE_ITEMS"),
+    (ERROR_INVALID_FLAGS, "ERROR_INVALID_FLAGS"),
+    (ERROR_NOT_FOUND, "ERROR_NOT_FOUND"),
+];
+
+/// Symbolic name of a CONFIGRET, e.g. "CR_NO_SUCH_DEVNODE"
+pub fn configret_name(result: CONFIGRET) -> Option<&'static str> {
+    CONFIGRET_NAMES
+        .iter()
+        .find(|(cr, _)| *cr == result)
+        .map(|(_, name)| *name)
+}
+
+/// Symbolic name of a Win32 error wrapped in an HRESULT, e.g. "ERROR_NOT_FOUND"
+pub fn hresult_name(code: HRESULT) -> Option<&'static str> {
+    WIN32_ERROR_NAMES
+        .iter()
+        .find(|(err, _)| HRESULT::from(*err) == code)
+        .map(|(_, name)| *name)
+}
+
+/// CONFIGRET for humans: "CR_ACCESS_DENIED (51)", or just the number
+/// when we don't know it
+pub fn configret(result: CONFIGRET) -> String {
+    match configret_name(result) {
+        Some(name) => format!("{} ({})", name, result.0),
+        None => format!("CONFIGRET {}", result.0),
+    }
+}
+
+/// One-line explanation of a CONFIGRET, if we have one
+pub fn configret_explanation(result: CONFIGRET) -> Option<&'static str> {
+    CONFIGRET_EXPLANATIONS
+        .iter()
+        .find(|(cr, _)| *cr == result)
+        .map(|(_, text)| *text)
+}
+
+/// CONFIGRET with its explanation for error messages:
+/// "CR_ACCESS_DENIED (51): insufficient privileges". Codes we don't explain
+/// get the system message of the Win32 error they map to, if there's one.
+pub fn explain_configret(result: CONFIGRET) -> String {
+    match configret_explanation(result) {
+        Some(text) => format!("{}: {}", configret(result), text),
+        None => match system_explanation(result) {
+            Some(text) => format!("{}: {}", configret(result), text),
+            None => configret(result),
+        },
+    }
+}
+
+/// Message of the Win32 error CM_MapCrToWin32Err maps the CONFIGRET to,
+/// without the trailing period
+#[cfg(windows)]
+fn system_explanation(result: CONFIGRET) -> Option<String> {
+    use windows::Win32::Devices::DeviceAndDriverInstallation::CM_MapCrToWin32Err;
+
+    let code = unsafe { CM_MapCrToWin32Err(result, ERROR_SUCCESS.0) };
+    if code == ERROR_SUCCESS.0 {
+        return None;
+    }
+    let message = windows::core::Error::from(HRESULT::from_win32(code))
+        .message()
+        .to_string();
+    let message = message.trim_end().trim_end_matches('.');
+    match message.is_empty() {
+        true => None,
+        false => Some(message.to_string()),
+    }
+}
+
+/// There are no system messages for CONFIGRETs off Windows
+#[cfg(not(windows))]
+fn system_explanation(_result: CONFIGRET) -> Option<String> {
+    None
+}
+
+/// Names of the DN_xx bits set in `flags`, unknown bits are left out
+pub fn status_flag_names(flags: CM_DEVNODE_STATUS_FLAGS) -> Vec<&'static str> {
+    STATUS_FLAG_NAMES
+        .iter()
+        .filter(|(flag, _)| (flags & *flag).0 != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// Status flags for humans: "driver loaded, started, disableable"
+pub fn status_flags(flags: CM_DEVNODE_STATUS_FLAGS) -> String {
+    match flags.0 {
+        0 => "none".to_string(),
+        _ => status_flag_names(flags).join(", "),
+    }
+}
+
+/// Symbolic name of a problem code, e.g. "CM_PROB_DISABLED"
+pub fn problem_name(problem: CM_PROB) -> Option<&'static str> {
+    PROBLEM_NAMES
+        .iter()
+        .find(|(code, _)| *code == problem)
+        .map(|(_, name)| *name)
+}
+
+/// One-line explanation of a problem code, if we have one
+pub fn problem_explanation(problem: CM_PROB) -> Option<&'static str> {
+    PROBLEM_EXPLANATIONS
+        .iter()
+        .find(|(code, _)| *code == problem)
+        .map(|(_, text)| *text)
+}
+
+/// Problem code for humans: "CM_PROB_DISABLED (22)", "none" when zero,
+/// or just the number when we don't know it
+pub fn problem(problem: CM_PROB) -> String {
+    match (problem.0, problem_name(problem)) {
+        (0, _) => "none".to_string(),
+        (_, Some(name)) => format!("{} ({})", name, problem.0),
+        (n, None) => format!("CM_PROB {}", n),
+    }
+}
+
+/// PNP_VETO_TYPE for humans: "application", "service", "driver"...
+pub fn veto_type(veto: PNP_VETO_TYPE) -> &'static str {
+    VETO_TYPES
+        .iter()
+        .find(|(v, _)| *v == veto)
+        .map_or("unknown component", |(_, text)| *text)
+}
+
+/// HRESULT for humans: "ERROR_NOT_FOUND (0x80070490)"
+pub fn hresult(code: HRESULT) -> String {
+    match hresult_name(code) {
+        Some(name) => format!("{} ({:#010x})", name, code.0),
+        None => format!("HRESULT {:#010x}", code.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devenum::Error;
+
+    #[test]
+    fn configret_names() {
+        assert_eq!(configret(CR_SUCCESS), "CR_SUCCESS (0)");
+        assert_eq!(configret(CR_NO_SUCH_DEVNODE), "CR_NO_SUCH_DEVNODE (13)");
+        assert_eq!(configret(CR_ACCESS_DENIED), "CR_ACCESS_DENIED (51)");
+        assert_eq!(configret(CONFIGRET(0xFFFF)), "CONFIGRET 65535");
+    }
+
+    #[test]
+    fn hresult_names() {
+        assert_eq!(
+            hresult(ERROR_NOT_FOUND.into()),
+            "ERROR_NOT_FOUND (0x80070490)"
+        );
+        assert_eq!(
+            hresult(ERROR_ACCESS_DENIED.into()),
+            "ERROR_ACCESS_DENIED (0x80070005)"
+        );
+        assert_eq!(
+            hresult(HRESULT(0x8000_4005_u32 as i32)),
+            "HRESULT 0x80004005"
+        );
+    }
+
+    #[test]
+    fn problem_names() {
+        assert_eq!(problem(CM_PROB(0)), "none");
+        assert_eq!(problem(CM_PROB_DISABLED), "CM_PROB_DISABLED (22)");
+        assert_eq!(problem(CM_PROB(0xFFFF)), "CM_PROB 65535");
+    }
+
+    #[test]
+    fn status_flags_are_listed_in_bit_order() {
+        assert_eq!(status_flags(CM_DEVNODE_STATUS_FLAGS(0)), "none");
+        assert_eq!(
+            status_flags(DN_DISABLEABLE | DN_STARTED | DN_DRIVER_LOADED),
+            "driver loaded, started, disableable"
+        );
+    }
+
+    #[test]
+    fn veto_types() {
+        assert_eq!(veto_type(PNP_VetoAlreadyRemoved), "device already removed");
+        assert_eq!(veto_type(PNP_VETO_TYPE(0xFF)), "unknown component");
+    }
+
+    #[test]
+    fn errors_are_shown_with_names() {
+        let err = Error::ConfigRet(CR_NO_SUCH_DEVNODE);
+        assert_eq!(
+            err.to_string(),
+            "configuration manager error CR_NO_SUCH_DEVNODE (13): \
+             the device node does not exist (device removed?)"
+        );
+        let err = Error::GaveUp {
+            result: CR_REMOVE_VETOED,
+            attempts: 4,
+        };
+        assert!(err
+            .to_string()
+            .starts_with("configuration manager error CR_REMOVE_VETOED (23): "));
+        assert!(err.to_string().ends_with(" after 4 attempts"));
+    }
+
+    /// Every entry of a table is found by its key, so no key is in it twice
+    fn assert_round_trip<K: Copy + PartialEq + std::fmt::Debug>(
+        table: &[(K, &str)],
+        lookup: impl Fn(K) -> Option<&'static str>,
+    ) {
+        for (key, text) in table {
+            assert_eq!(lookup(*key), Some(*text), "{:?} is in the table twice", key);
+        }
+    }
+
+    fn assert_unique(texts: impl IntoIterator<Item = &'static str>) {
+        let mut seen = Vec::new();
+        for text in texts {
+            assert!(!seen.contains(&text), "{} is in the table twice", text);
+            seen.push(text);
+        }
+    }
+
+    #[test]
+    fn every_configret_has_one_name() {
+        assert_round_trip(CONFIGRET_NAMES, configret_name);
+        assert_unique(CONFIGRET_NAMES.iter().map(|(_, name)| *name));
+        assert!(CONFIGRET_NAMES
+            .iter()
+            .all(|(_, name)| name.starts_with("CR_")));
+        for result in CR_SUCCESS.0..=CR_INVALID_STRUCTURE_SIZE.0 {
+            assert!(
+                configret_name(CONFIGRET(result)).is_some(),
+                "CONFIGRET {} has no name",
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn every_explained_configret_has_a_name() {
+        assert_round_trip(CONFIGRET_EXPLANATIONS, configret_explanation);
+        for (result, text) in CONFIGRET_EXPLANATIONS {
+            let name = configret_name(*result).unwrap();
+            assert_eq!(
+                explain_configret(*result),
+                format!("{} ({}): {}", name, result.0, text)
+            );
+        }
+    }
+
+    #[test]
+    fn every_status_bit_has_one_name() {
+        assert_eq!(STATUS_FLAG_NAMES.len(), 32);
+        for (bit, (flag, name)) in STATUS_FLAG_NAMES.iter().enumerate() {
+            assert_eq!(flag.0, 1 << bit, "{} is out of order", name);
+            assert_eq!(status_flag_names(*flag), [*name]);
+        }
+        assert_unique(STATUS_FLAG_NAMES.iter().map(|(_, name)| *name));
+        let all = status_flag_names(CM_DEVNODE_STATUS_FLAGS(u32::MAX));
+        let names: Vec<&str> = STATUS_FLAG_NAMES.iter().map(|(_, name)| *name).collect();
+        assert_eq!(all, names);
+    }
+
+    #[test]
+    fn every_problem_has_one_name() {
+        assert_round_trip(PROBLEM_NAMES, problem_name);
+        assert_unique(PROBLEM_NAMES.iter().map(|(_, name)| *name));
+        for (code, name) in PROBLEM_NAMES {
+            assert!(name.starts_with("CM_PROB_"));
+            assert_eq!(problem(*code), format!("{} ({})", name, code.0));
+        }
+        assert_round_trip(PROBLEM_EXPLANATIONS, problem_explanation);
+        for (code, _) in PROBLEM_EXPLANATIONS {
+            assert!(
+                problem_name(*code).is_some(),
+                "CM_PROB {} has no name",
+                code.0
+            );
+        }
+    }
+
+    #[test]
+    fn every_veto_type_has_one_text() {
+        for (veto, text) in VETO_TYPES {
+            assert_eq!(veto_type(*veto), *text);
+        }
+        assert_unique(VETO_TYPES.iter().map(|(_, text)| *text));
+    }
+
+    #[test]
+    fn every_win32_error_has_one_name() {
+        assert_round_trip(WIN32_ERROR_NAMES, |err| hresult_name(err.into()));
+        assert_unique(WIN32_ERROR_NAMES.iter().map(|(_, name)| *name));
+        for (err, name) in WIN32_ERROR_NAMES {
+            assert!(name.starts_with("ERROR_"));
+            assert!(hresult(HRESULT::from(*err)).starts_with(name));
+        }
+    }
+}
@@ -0,0 +1,83 @@
+/// Finding controllers that games see twice, once as themselves and once as
+/// the virtual pad a remapper like DS4Windows or reWASD feeds their input to
+///
+/// A virtual pad is the twin of a physical one with the same USB ids, e.g. a
+/// DualShock 4 that DS4Windows emulates as a DualShock 4. Remappers often
+/// emulate a different pad though, usually an Xbox 360 one, and then the ids
+/// can't tell anything. Such twins are only paired when exactly one physical
+/// and one virtual controller are left to pair. The devnodes of a physical
+/// controller are grouped by container id, so that all of its interfaces are
+/// handled together.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use nojoy_rs::devenum::{vid_pid, GameController, GameControllerStatus};
+
+/// A physical controller and the virtual pad it's duplicated by
+#[derive(Debug, Clone)]
+pub struct Duplicate<'a> {
+    /// Every devnode of the physical controller
+    pub original: Vec<&'a GameController>,
+    pub twin: &'a GameController,
+}
+
+/// Devnodes grouped by the physical device they belong to
+fn units<'a>(
+    controllers: impl Iterator<Item = &'a GameController>,
+) -> Vec<Vec<&'a GameController>> {
+    let mut units: Vec<Vec<&GameController>> = Vec::new();
+    for c in controllers {
+        let unit = c.container_id.as_ref().and_then(|id| {
+            units
+                .iter_mut()
+                .find(|unit| unit[0].container_id.as_ref() == Some(id))
+        });
+        match unit {
+            Some(unit) => unit.push(c),
+            None => units.push(vec![c]),
+        }
+    }
+    units
+}
+
+pub fn find(controllers: &[GameController]) -> Vec<Duplicate<'_>> {
+    let connected = controllers
+        .iter()
+        .filter(|c| c.status != GameControllerStatus::Disconnected);
+    let mut originals: Vec<Option<Vec<&GameController>>> =
+        units(connected.clone().filter(|c| !c.is_virtual))
+            .into_iter()
+            .map(Some)
+            .collect();
+    let mut twins: Vec<Option<&GameController>> =
+        connected.filter(|c| c.is_virtual).map(Some).collect();
+    let mut duplicates = Vec::new();
+    for slot in twins.iter_mut() {
+        let Some(twin) = *slot else { continue };
+        let Some(ids) = vid_pid(&twin.instance_id) else {
+            continue;
+        };
+        let mut matches = originals.iter_mut().filter(|unit| {
+            unit.as_ref()
+                .is_some_and(|unit| unit.iter().any(|c| vid_pid(&c.instance_id) == Some(ids)))
+        });
+        if let (Some(unit), None) = (matches.next(), matches.next()) {
+            duplicates.push(Duplicate {
+                original: unit.take().unwrap_or_default(),
+                twin,
+            });
+            *slot = None;
+        }
+    }
+    // the ones left can only be paired when there's no choice
+    let mut twins = twins.into_iter().flatten();
+    let mut originals = originals.into_iter().flatten();
+    if let (Some(twin), None, Some(original), None) = (
+        twins.next(),
+        twins.next(),
+        originals.next(),
+        originals.next(),
+    ) {
+        duplicates.push(Duplicate { original, twin });
+    }
+    duplicates
+}
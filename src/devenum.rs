@@ -1,261 +1,615 @@
 /// Device enumeration module
 ///
+/// The types and the functions to find and change devices are the same on
+/// every platform. On Windows they're backed by SetupAPI and the
+/// configuration manager, on Linux by the input devices in sysfs.
+///
 /// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
-#[cfg(target_os = "windows")]
-use core::slice::from_raw_parts;
-use std::{ffi::OsString, os::windows::ffi::OsStringExt};
+use std::{fmt, io, time::Duration};
+
 
+#[cfg(windows)]
+mod bus;
+#[cfg(windows)]
+mod devprop;
+#[cfg(windows)]
+mod emulated;
+#[cfg(windows)]
+mod propbuffer;
+#[cfg(windows)]
+mod properties;
+mod serial;
+#[cfg(windows)]
+mod setupapi;
+#[cfg(windows)]
 mod setupdienum;
 
-extern crate alloc;
+#[cfg(target_os = "linux")]
+mod linux;
+
+#[cfg(windows)]
+use propbuffer::PropBuffer;
+#[cfg(windows)]
+pub use properties::{DeviceProperties, DeviceProperty, PropertyValue};
+#[cfg(windows)]
+pub use setupapi::{
+    change_device_in_profile, class_guid, device_properties, device_property, device_services,
+    disable_device, enable_device, game_controllers, interface_guid, phantom_devices,
+    phantom_game_controllers, remove_device, remove_phantom_device, rescan, restart_device,
+    set_all_devices, set_devices_state, toggle_device, GameControllers,
+};
+#[cfg(windows)]
+pub use setupdienum::{DevInfoSet, DeviceRef, SetupDiEnum};
+
+#[cfg(target_os = "linux")]
+pub use linux::{
+    disable_device, enable_device, game_controllers, restart_device, set_all_devices,
+    set_devices_state, toggle_device,
+};
+
+use crate::{decode, timestamp::Timestamp};
+
+use serde::Serialize;
 use windows::{
-    core::PCWSTR,
-    Win32::{
-        Devices::{
-            DeviceAndDriverInstallation::{
-                CM_Disable_DevNode, CM_Enable_DevNode, CM_Get_DevNode_Status, SetupDiDestroyDeviceInfoList, SetupDiGetClassDevsW, SetupDiGetDeviceInstanceIdW, SetupDiGetDeviceRegistryPropertyW, CM_DEVNODE_STATUS_FLAGS, CM_PROB, CONFIGRET, CR_NO_SUCH_DEVNODE, CR_SUCCESS, DIGCF_DEVICEINTERFACE, DN_DISABLEABLE, DN_STARTED, HDEVINFO, SETUP_DI_REGISTRY_PROPERTY, SPDRP_DEVICEDESC, SPDRP_HARDWAREID, SPDRP_MFG, SP_DEVINFO_DATA
-            },
-            HumanInterfaceDevice::HidD_GetHidGuid,
-        },
-        Foundation::{ERROR_INSUFFICIENT_BUFFER, HWND},
-    },
+    core::GUID,
+    Win32::Devices::DeviceAndDriverInstallation::{CONFIGRET, PNP_VETO_TYPE},
 };
 
-#[derive(Debug, Clone, Copy)]
+/// State of a controller's devnode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum GameControllerStatus {
     Enabled,
     Disabled,
     Disconnected,
 }
 
+/// Result of a successful enable/disable request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Outcome {
+    Changed,
+    /// The device was already in the requested state, nothing was done
+    AlreadyInState,
+    /// The change was accepted but only takes effect after Windows is
+    /// restarted
+    RestartRequired,
+}
+
+/// Error of a failed Win32 call. Other platforms, where only the Linux
+/// backend runs, keep just its code, so that the crate links there without
+/// the Windows DLLs behind [`windows::core::Error`].
+#[cfg(windows)]
+pub type Win32Error = windows::core::Error;
+#[cfg(not(windows))]
+pub type Win32Error = windows::core::HRESULT;
+
+/// Everything that can go wrong in this crate
 #[derive(Debug, Clone)]
 pub enum Error {
+    /// No device with given instance id in the enumerated scope
     NotFound,
-    Win32(windows::core::Error),
+    /// The device is known but currently disconnected
+    NotPresent,
+    /// The device is connected, so it can't be pruned
+    Present,
+    /// The process needs to be elevated
+    AccessDenied,
+    /// Found out before trying, the process isn't elevated and changing
+    /// devices needs that
+    NotElevated,
+    /// The driver doesn't allow disabling the device with given name
+    NotDisableable(String),
+    /// Malformed device setup class GUID
+    InvalidClassGuid(String),
+    /// No device setup class with given name
+    UnknownClass(String),
+    /// Neither a device interface GUID nor a known interface name
+    UnknownInterface(String),
+    /// A required device property doesn't exist
+    PropertyMissing,
+    /// A device property has a different DEVPROP_TYPE_xx than expected
+    PropertyType { expected: u32, actual: u32 },
+    /// A fixed size device property has an unexpected length
+    PropertyLength(usize),
+    /// A SetupDi or other Win32 call failed
+    Win32(Win32Error),
+    /// A CM_xx call failed with given CONFIGRET
     ConfigRet(CONFIGRET),
+    /// A retryable CM_xx failure that persisted after all attempts
+    GaveUp { result: CONFIGRET, attempts: u32 },
+    /// Something that uses the device blocked disabling it. `vetoer` is the
+    /// application path, service or device name Windows gives, if any.
+    Vetoed { veto_type: PNP_VETO_TYPE, vetoer: String },
+    /// The devnode didn't reach the requested state after the CM_xx call
+    /// succeeded
+    StateTimeout,
+    /// The device can't do what was asked, like waking the computer
+    Unsupported(&'static str),
+    /// Reading or writing a sysfs attribute of the Linux backend failed
+    Sysfs { path: String, kind: io::ErrorKind },
+    /// Another error, with what was being done when it happened, like
+    /// "can't read manufacturer of HID\VID_045E&PID_02E0"
+    Context { context: String, source: Box<Error> },
+}
+
+impl Error {
+    /// Wrap the error with what was being done when it happened
+    pub fn context(self, context: impl Into<String>) -> Error {
+        Error::Context {
+            context: context.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// The error under any context, to match on
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Context { source, .. } => source.root(),
+            err => err,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound => write!(f, "device not found"),
+            Error::NotPresent => write!(f, "device not present"),
+            Error::Present => write!(f, "device is connected"),
+            Error::AccessDenied => write!(f, "access denied, try running as administrator"),
+            Error::NotElevated => {
+                write!(f, "changing devices needs administrator rights, run from an elevated prompt")
+            }
+            Error::NotDisableable(name) => {
+                write!(f, "{} can't be disabled, its driver doesn't allow that", name)
+            }
+            Error::InvalidClassGuid(guid) => write!(f, "invalid class GUID: {}", guid),
+            Error::UnknownClass(name) => write!(f, "unknown device class: {}", name),
+            Error::UnknownInterface(name) => write!(f, "unknown device interface: {}", name),
+            Error::PropertyMissing => write!(f, "device property is missing"),
+            Error::PropertyType { expected, actual } => write!(
+                f,
+                "device property has type {:#x} instead of {:#x}",
+                actual, expected
+            ),
+            Error::PropertyLength(len) => write!(f, "device property has invalid length {}", len),
+            #[cfg(windows)]
+            Error::Win32(err) => write!(f, "{} [{}]", err.message(), decode::hresult(err.code())),
+            #[cfg(not(windows))]
+            Error::Win32(code) => write!(f, "{}", decode::hresult(*code)),
+            Error::ConfigRet(result) => {
+                write!(f, "configuration manager error {}", decode::explain_configret(*result))
+            }
+            Error::GaveUp { result, attempts } => write!(
+                f,
+                "configuration manager error {} after {} attempts",
+                decode::explain_configret(*result),
+                attempts
+            ),
+            Error::Vetoed { veto_type, vetoer } => match vetoer.is_empty() {
+                true => write!(f, "blocked by {}", decode::veto_type(*veto_type)),
+                false => write!(f, "blocked by {} {}", decode::veto_type(*veto_type), vetoer),
+            },
+            Error::StateTimeout => write!(f, "device didn't reach the requested state in time"),
+            Error::Unsupported(what) => write!(f, "the device doesn't support {}", what),
+            Error::Sysfs { path, kind } => write!(f, "can't access {}: {}", path, kind),
+            Error::Context { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(windows)]
+            Error::Win32(err) => Some(err),
+            Error::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
 }
 
 impl From<windows::core::Error> for Error {
     fn from(err: windows::core::Error) -> Error {
-        Error::Win32(err)
+        #[cfg(windows)]
+        return Error::Win32(err);
+        #[cfg(not(windows))]
+        Error::Win32(err.code())
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct GameController {
-    pub manufacturer: String,
-    pub name: String,
-    pub instance_id: String,
-    pub status: GameControllerStatus,
-    pub disableable: bool,
+/// How often a transient enable/disable failure is retried.
+/// The delay doubles after every failed attempt.
+#[derive(Debug, Clone, Copy)]
+pub struct Retry {
+    pub retries: u32,
+    pub delay: Duration,
 }
 
-impl GameController {
-    /// Try to create an instance of GameController out of given devinfo data.
-    pub unsafe fn try_from_devinfo(
-        devinfo: HDEVINFO,
-        devinfo_data: &SP_DEVINFO_DATA,
-    ) -> Result<Self, Error> {
-        let name = device_prop_sz(devinfo, devinfo_data, SPDRP_DEVICEDESC)?;
-        let manufacturer = device_prop_sz(devinfo, devinfo_data, SPDRP_MFG)?;
-        let instance_id = device_instance_id(devinfo, devinfo_data)?;
-        let flags = device_status_flags(devinfo_data.DevInst)?;
-        let status = match flags {
-            CM_DEVNODE_STATUS_FLAGS(0) => GameControllerStatus::Disconnected,
-            x if (x & DN_STARTED).0 == 0 => GameControllerStatus::Disabled,
-            _ => GameControllerStatus::Enabled,
-        };
-        Ok(Self {
-            manufacturer,
-            name,
-            instance_id,
-            status,
-            disableable: (flags & DN_DISABLEABLE).0 != 0,
-        })
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            delay: Duration::from_millis(100),
+        }
     }
 }
 
-pub fn disable_device(id: &str) -> Result<(), Error> {
-    unsafe {
-        let devinfo = devinfo_hid()?;
-        match devinfo_data(devinfo, id) {
-            Some(data) => {
-                let result = CM_Disable_DevNode(data.DevInst, 0);
-                if result != CR_SUCCESS {
-                    return Err(Error::ConfigRet(result).into());
-                }
-                return Ok(());
-            },
-            None => Err(Error::NotFound)
+/// Which HID devices to enumerate, going by the HID_DEVICE_SYSTEM_xx
+/// hardware id Windows assigns to each top level collection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    #[default]
+    Gamepad,
+    Keyboard,
+    Mouse,
+    AllHid,
+}
+
+impl Kind {
+    const ALL: [Kind; 4] = [Kind::Gamepad, Kind::Keyboard, Kind::Mouse, Kind::AllHid];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Gamepad => "gamepad",
+            Kind::Keyboard => "keyboard",
+            Kind::Mouse => "mouse",
+            Kind::AllHid => "all-hid",
         }
     }
+
+    pub fn from_name(name: &str) -> Option<Kind> {
+        Kind::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
 }
 
-unsafe fn devinfo_data(devinfo: HDEVINFO, id: &str) -> Option<SP_DEVINFO_DATA> {
-    let mut result = enum_game_controllers(devinfo).filter(|d| {
-        let instance_id = device_instance_id(devinfo, &d).ok();
-        instance_id.is_some_and(|i| i == id)
-    });
-    result.next()
+/// How a device is connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Bus {
+    Usb,
+    Bluetooth,
+    /// Bluetooth Low Energy, HID over GATT
+    BluetoothLe,
 }
 
-pub fn enable_device(id: &str) -> Result<(), Error> {
-    unsafe {
-        let devinfo = devinfo_hid()?;
-        match devinfo_data(devinfo, id) {
-            Some(data) => {
-                let result = CM_Enable_DevNode(data.DevInst, 0);
-                if result != CR_SUCCESS {
-                    return Err(Error::ConfigRet(result).into());
-                }
-                return Ok(());
-            },
-            None => Err(Error::NotFound)
+impl Bus {
+    pub fn name(self) -> &'static str {
+        match self {
+            Bus::Usb => "USB",
+            Bus::Bluetooth => "Bluetooth",
+            Bus::BluetoothLe => "BTHLE",
         }
     }
 }
 
-unsafe fn enum_game_controllers(devinfo: HDEVINFO) -> impl Iterator<Item = SP_DEVINFO_DATA> {
-    setupdienum::SetupDiEnum::new(devinfo).filter(move |d| {
-        device_prop_multi_sz(devinfo, &d, SPDRP_HARDWAREID).is_ok_and(|d| is_game_controller(d))
-    })
+/// Charge of a controller's battery, as coarse as XInput reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Battery {
+    /// Powered over the cable, there's no level to report
+    Wired,
+    Empty,
+    Low,
+    Medium,
+    Full,
 }
 
-pub fn game_controllers() -> Result<Vec<GameController>, Error> {
-    unsafe {
-        let devinfo = devinfo_hid()?;
-        let result: Vec<GameController> = enum_game_controllers(devinfo)
-            .filter_map(|d| GameController::try_from_devinfo(devinfo, &d).ok())
-            .collect();
-
-        // must do this at the end
-        SetupDiDestroyDeviceInfoList(devinfo)?;
-        Ok(result)
+impl Battery {
+    pub fn name(self) -> &'static str {
+        match self {
+            Battery::Wired => "wired",
+            Battery::Empty => "empty",
+            Battery::Low => "low",
+            Battery::Medium => "medium",
+            Battery::Full => "full",
+        }
     }
 }
 
-unsafe fn devinfo_hid() -> Result<HDEVINFO, windows::core::Error> {
-    dev_info(HidD_GetHidGuid())
+/// Which devices to enumerate.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Scope {
+    /// Also enumerate phantom devnodes of devices that aren't connected
+    pub include_disconnected: bool,
+    /// Which HID devices to enumerate, unless a class or interface is given
+    pub kind: Kind,
+    /// Enumerate every device of this setup class instead of HID devices
+    pub class: Option<GUID>,
+    /// Enumerate every device that exposes this device interface instead of
+    /// HID devices
+    pub interface: Option<GUID>,
+}
+
+/// A device as reported by [`game_controllers`]
+#[derive(Debug, Clone, Serialize)]
+pub struct GameController {
+    pub manufacturer: String,
+    pub name: String,
+    /// Set by some drivers, or by the user in Device Manager
+    pub friendly_name: Option<String>,
+    /// Bus specific location like "Port_#0002.Hub_#0001"
+    pub location: Option<String>,
+    pub instance_id: String,
+    /// USB serial number or Bluetooth address, if the device has a real one
+    pub serial: Option<String>,
+    /// None if it's neither USB nor Bluetooth, or can't be told
+    pub bus: Option<Bus>,
+    pub parent_id: Option<String>,
+    /// Same for every devnode of one physical device, like the HID and XInput
+    /// interfaces of a composite controller
+    pub container_id: Option<String>,
+    pub status: GameControllerStatus,
+    pub disableable: bool,
+    /// Made by software like ViGEmBus, vJoy or Steam Input rather than
+    /// plugged in
+    #[serde(rename = "virtual")]
+    pub is_virtual: bool,
+    /// None for vendor-defined HID collections and devices that aren't HID
+    pub kind: Option<Kind>,
+    /// XInput user index, if it could be matched to this devnode
+    pub xinput_slot: Option<u32>,
+    /// Only known for controllers with an XInput slot
+    pub battery: Option<Battery>,
+    /// Raw DN_xx devnode status flags
+    pub status_flags: u32,
+    /// CM_PROB_xx problem code, zero if there is no problem
+    pub problem_code: u32,
+    pub installed_at: Option<Timestamp>,
+    /// Last time the device was connected or disconnected
+    pub last_seen: Option<Timestamp>,
+    /// None if the device has no driver, or none of its details could be read
+    pub driver: Option<Driver>,
 }
 
-fn is_game_controller(hwids: Vec<String>) -> bool {
-    const GAME_CONTROLLER_HARDWARE_ID: &str = "HID_DEVICE_SYSTEM_GAME";
+/// The driver of a device, as Device Manager shows it on the Driver tab
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Driver {
+    pub provider: Option<String>,
+    pub version: Option<String>,
+    pub date: Option<Timestamp>,
+    /// Name of the INF file it was installed from, like "oem42.inf"
+    pub inf: Option<String>,
+}
 
-    hwids.iter().any(|s| s == GAME_CONTROLLER_HARDWARE_ID)
+/// Outcome of a bulk enable/disable for one device
+#[derive(Debug, Clone)]
+pub struct BulkChange {
+    pub instance_id: String,
+    pub name: String,
+    pub result: Result<Outcome, Error>,
 }
 
-/// returns a HDEVINFO for given class GUID of a device
-unsafe fn dev_info(guid: windows::core::GUID) -> Result<HDEVINFO, windows::core::Error> {
-    SetupDiGetClassDevsW(
-        Some(&guid),
-        PCWSTR::null(),
-        HWND::default(),
-        DIGCF_DEVICEINTERFACE,
-    )
+/// What a bulk change leaves the devices in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Enabled,
+    Disabled,
 }
 
-unsafe fn device_status_flags(devinst: u32) -> Result<CM_DEVNODE_STATUS_FLAGS, Error> {
-    let mut flags: CM_DEVNODE_STATUS_FLAGS = CM_DEVNODE_STATUS_FLAGS(0);
-    let mut problem: CM_PROB = CM_PROB(0);
-    let result =
-        CM_Get_DevNode_Status(&mut flags, &mut problem, devinst, 0 /* must be zero */);
-    match result {
-        CR_SUCCESS => Ok(flags),
-        CR_NO_SUCH_DEVNODE => Ok(CM_DEVNODE_STATUS_FLAGS(0)),
-        x => Err(Error::ConfigRet(x)),
+impl State {
+    pub fn is_enabled(self) -> bool {
+        self == State::Enabled
     }
 }
 
-unsafe fn device_instance_id(
-    devinfo: HDEVINFO,
-    devinfo_data: &SP_DEVINFO_DATA,
-) -> Result<String, Error> {
-    let mut req_size = 0;
+impl From<bool> for State {
+    fn from(enabled: bool) -> State {
+        match enabled {
+            true => State::Enabled,
+            false => State::Disabled,
+        }
+    }
+}
 
-    assert_insufficient_buffer(SetupDiGetDeviceInstanceIdW(
-        devinfo,
-        devinfo_data,
-        None,
-        Some(&mut req_size),
-    ))?;
+/// Which devices a bulk change applies to, told by their instance ids alone
+/// so that they're matched without reading any other property
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// The device with this instance id, ignoring case
+    InstanceId(String),
+    /// Every device with this USB vendor id, and product id if given
+    VidPid { vid: u16, pid: Option<u16> },
+}
 
-    let mut buf = vec![0u16; req_size as usize];
-    SetupDiGetDeviceInstanceIdW(devinfo, devinfo_data, Some(&mut buf), Some(&mut req_size))?;
+impl DeviceSelector {
+    pub fn matches(&self, instance_id: &str) -> bool {
+        match self {
+            DeviceSelector::InstanceId(id) => id.eq_ignore_ascii_case(instance_id),
+            DeviceSelector::VidPid { vid, pid } => vid_pid(instance_id)
+                .is_some_and(|(v, p)| v == *vid && pid.is_none_or(|pid| p == pid)),
+        }
+    }
 
-    Ok(OsString::from_wide(&buf).to_string_lossy().into_owned())
+    fn same_as(&self, other: &DeviceSelector) -> bool {
+        match (self, other) {
+            (DeviceSelector::InstanceId(a), DeviceSelector::InstanceId(b)) => {
+                a.eq_ignore_ascii_case(b)
+            }
+            (a, b) => a == b,
+        }
+    }
 }
 
-unsafe fn device_prop_sz(
-    devinfo: HDEVINFO,
-    devinfo_data: &SP_DEVINFO_DATA,
-    prop: SETUP_DI_REGISTRY_PROPERTY,
-) -> Result<String, Error> {
-    let buflen = prop_bufsize(devinfo, devinfo_data, prop)?;
-
-    // get the contents
-    let mut buf = vec![0u8; buflen as usize];
-    SetupDiGetDeviceRegistryPropertyW(devinfo, devinfo_data, prop, None, Some(&mut buf), None)?;
-    Ok(from_utf16_in_u8(&buf))
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceSelector::InstanceId(id) => write!(f, "{}", id),
+            DeviceSelector::VidPid { vid, pid: None } => write!(f, "vid:{:04X}", vid),
+            DeviceSelector::VidPid {
+                vid,
+                pid: Some(pid),
+            } => write!(f, "vid:{:04X}:{:04X}", vid, pid),
+        }
+    }
 }
 
-unsafe fn device_prop_multi_sz(
-    devinfo: HDEVINFO,
-    devinfo_data: &SP_DEVINFO_DATA,
-    prop: SETUP_DI_REGISTRY_PROPERTY,
-) -> Result<Vec<String>, Error> {
-    let buflen = prop_bufsize(devinfo, devinfo_data, prop)?;
-
-    // get the contents
-    let mut buf = vec![0u8; buflen as usize];
-    SetupDiGetDeviceRegistryPropertyW(devinfo, devinfo_data, prop, None, Some(&mut buf), None)?;
-    Ok(multi_sz_from_utf16_in_u8(&buf))
+/// The devices the selectors match, in one pass over `devices`, which are
+/// instance ids with whatever else the backend has for them. Every device
+/// comes once, with the devices of the first selector that matches it, and
+/// selectors are grouped in the order they're given. A selector repeated
+/// ignoring case counts once, and one that matches nothing is an `Err` of
+/// its text where its devices would be. Stops early once every selector is
+/// an instance id that was found.
+pub(crate) fn select<T>(
+    selectors: &[DeviceSelector],
+    devices: impl IntoIterator<Item = (String, T)>,
+) -> Vec<Result<(String, T), String>> {
+    let mut unique: Vec<&DeviceSelector> = Vec::new();
+    for selector in selectors {
+        if !unique.iter().any(|s| s.same_as(selector)) {
+            unique.push(selector);
+        }
+    }
+    let only_ids = unique
+        .iter()
+        .all(|s| matches!(s, DeviceSelector::InstanceId(_)));
+    let mut matched = vec![false; unique.len()];
+    let mut found: Vec<Vec<(String, T)>> = unique.iter().map(|_| Vec::new()).collect();
+    for (id, device) in devices {
+        let mut first = None;
+        for (i, selector) in unique.iter().enumerate() {
+            if selector.matches(&id) {
+                matched[i] = true;
+                first.get_or_insert(i);
+            }
+        }
+        if let Some(i) = first {
+            found[i].push((id, device));
+        }
+        if only_ids && matched.iter().all(|m| *m) {
+            break;
+        }
+    }
+    unique
+        .into_iter()
+        .zip(matched)
+        .zip(found)
+        .flat_map(|((selector, matched), found)| {
+            let missing = (!matched).then(|| Err(selector.to_string()));
+            missing.into_iter().chain(found.into_iter().map(Ok))
+        })
+        .collect()
 }
 
-/// Query required buffer size
-unsafe fn prop_bufsize(
-    devinfo: HDEVINFO,
-    devinfo_data: &SP_DEVINFO_DATA,
-    prop: SETUP_DI_REGISTRY_PROPERTY,
-) -> Result<u32, Error> {
-    let mut buflen = 0;
-    // query buffer needed first - must return error
-    assert_insufficient_buffer(SetupDiGetDeviceRegistryPropertyW(
-        devinfo,
-        devinfo_data,
-        prop,
-        None,
-        None,
-        Some(&mut buflen),
-    ))?;
-    Ok(buflen)
+/// Extract USB vendor and product ids from an instance id like
+/// `HID\VID_045E&PID_02E0&IG_00\...`
+pub fn vid_pid(instance_id: &str) -> Option<(u16, u16)> {
+    let upper = instance_id.to_uppercase();
+    let hex_after = |tag: &str| {
+        let start = upper.find(tag)? + tag.len();
+        upper.get(start..start + 4)
+            .and_then(|s| u16::from_str_radix(s, 16).ok())
+    };
+    Some((hex_after("VID_")?, hex_after("PID_")?))
 }
 
-/// Make sure the last result is "ERROR_INSUFFICIENT_BUFFER" because
-/// it actually denotes success for when you need to get "required size" value
-/// in SetupDi calls. (CM_xx doesn't need this behavior)
-unsafe fn assert_insufficient_buffer(result: windows::core::Result<()>) -> Result<(), Error> {
-    match result {
-        Ok(_) => Ok(()),
-        Err(x) if x.code() == ERROR_INSUFFICIENT_BUFFER.into() => Ok(()),
-
-        // ERROR_INVALID_DATA means the property doesn't exist
-        Err(y) => Err(Error::Win32(y)),
+/// The USB ids with the interface and collection parts of an instance id,
+/// like `VID_045E&PID_0B13&MI_00&COL01`, which stay the same on every port
+pub fn hardware_function(instance_id: &str) -> Option<String> {
+    let (vid, pid) = vid_pid(instance_id)?;
+    let upper = instance_id.to_uppercase();
+    let mut function = format!("VID_{:04X}&PID_{:04X}", vid, pid);
+    for part in upper.split(['&', '\\']) {
+        if part.starts_with("MI_") || part.starts_with("COL") {
+            function += "&";
+            function += part;
+        }
     }
+    Some(function)
 }
 
-/// Cast a &[u8] to &[u16] according to where it's null terminator is positioned.
-unsafe fn from_utf16_in_u8(buf: &[u8]) -> String {
-    let slice: &[u16] = from_raw_parts(buf.as_ptr() as *const u16, buf.len() / 2);
-    let end = slice.iter().position(|&c| c == 0).unwrap_or(buf.len());
-    String::from_utf16(&slice[..end]).unwrap()
+/// What identifies the device wherever it's plugged in or paired, its
+/// [`hardware_function`] and serial number, like
+/// `VID_045E&PID_0B13&MI_00\3C:FA:06:41:92:7E`. None for devices without a
+/// serial number, those can't be told apart from another of the same model.
+pub fn identity(controller: &GameController) -> Option<String> {
+    let serial = controller.serial.as_deref()?;
+    Some(format!("{}\\{}", hardware_function(&controller.instance_id)?, serial.to_uppercase()))
 }
 
-/// Convert a UTF-16 encoded MULTI_SZ structure in a &[u8] into a Vec<String>
-unsafe fn multi_sz_from_utf16_in_u8(buf: &[u8]) -> Vec<String> {
-    let slice: &[u16] = from_raw_parts(buf.as_ptr() as *const u16, buf.len() / 2);
-    slice
-        .split(|&c| c == 0)
-        .filter(|p| !p.is_empty())
-        .map(|p| String::from_utf16(p).unwrap())
-        .collect()
+/// The [`identity`] of a device that may not be connected, from its
+/// instance id alone. None if the serial number is only in the instance id
+/// of its parent, like for HID children of USB devices.
+pub fn instance_identity(instance_id: &str) -> Option<String> {
+    let serial = serial::serial(instance_id, None)?;
+    Some(format!("{}\\{}", hardware_function(instance_id)?, serial.to_uppercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAD: &str = r"HID\VID_045E&PID_0B13\1";
+    const OTHER_PAD: &str = r"HID\VID_045E&PID_0B13\2";
+    const WHEEL: &str = r"HID\VID_046D&PID_C24F\1";
+
+    fn id(id: &str) -> DeviceSelector {
+        DeviceSelector::InstanceId(id.to_string())
+    }
+
+    fn selected(selectors: &[DeviceSelector]) -> Vec<Result<String, String>> {
+        let devices = [PAD, WHEEL, OTHER_PAD].map(|id| (id.to_string(), ()));
+        select(selectors, devices)
+            .into_iter()
+            .map(|found| found.map(|(id, ())| id))
+            .collect()
+    }
+
+    #[test]
+    fn selectors_are_matched_in_their_order() {
+        let vid = DeviceSelector::VidPid {
+            vid: 0x045e,
+            pid: None,
+        };
+        assert_eq!(
+            selected(&[id(WHEEL), vid]),
+            [
+                Ok(WHEEL.to_string()),
+                Ok(PAD.to_string()),
+                Ok(OTHER_PAD.to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn devices_come_once() {
+        let pads = DeviceSelector::VidPid {
+            vid: 0x045e,
+            pid: Some(0x0b13),
+        };
+        assert_eq!(
+            selected(&[id(PAD), pads, id(&PAD.to_lowercase())]),
+            [Ok(PAD.to_string()), Ok(OTHER_PAD.to_string())]
+        );
+    }
+
+    #[test]
+    fn selectors_without_devices_are_told() {
+        let none = DeviceSelector::VidPid {
+            vid: 0x054c,
+            pid: Some(0x0ce6),
+        };
+        assert_eq!(
+            selected(&[id(r"HID\NOPE"), id(PAD), none]),
+            [
+                Err(r"HID\NOPE".to_string()),
+                Ok(PAD.to_string()),
+                Err("vid:054C:0CE6".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn search_stops_once_every_id_is_found() {
+        let mut seen = 0;
+        let devices = [PAD, WHEEL, OTHER_PAD].into_iter().map(|id| {
+            seen += 1;
+            (id.to_string(), ())
+        });
+        assert_eq!(select(&[id(WHEEL)], devices).len(), 1);
+        assert_eq!(seen, 2);
+    }
 }
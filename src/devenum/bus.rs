@@ -0,0 +1,29 @@
+/// Telling how a controller is connected from its instance id
+///
+/// HID devnodes are always enumerated by `HID`. Bluetooth ones carry the
+/// Bluetooth service class in their device id, like
+/// `HID\{00001124-0000-1000-8000-00805F9B34FB}&VID_...` for classic HID and
+/// `HID\{00001812-0000-1000-8000-00805F9B34FB}_DEV_VID&...` for HID over GATT.
+/// The bus of anything else is the enumerator of its parent.
+use super::Bus;
+
+const BLUETOOTH_HID_SERVICE: &str = "{00001124-";
+const BLUETOOTH_LE_HID_SERVICE: &str = "{00001812-";
+
+pub(super) fn bus(instance_id: &str, parent_id: Option<&str>) -> Option<Bus> {
+    from_instance_id(instance_id).or_else(|| parent_id.and_then(from_instance_id))
+}
+
+fn from_instance_id(id: &str) -> Option<Bus> {
+    let mut parts = id.splitn(3, '\\');
+    let enumerator = parts.next()?.to_uppercase();
+    let device_id = parts.next().unwrap_or_default();
+    match enumerator.as_str() {
+        "USB" => Some(Bus::Usb),
+        "BTHENUM" => Some(Bus::Bluetooth),
+        "BTHLEDEVICE" => Some(Bus::BluetoothLe),
+        "HID" if device_id.starts_with(BLUETOOTH_HID_SERVICE) => Some(Bus::Bluetooth),
+        "HID" if device_id.starts_with(BLUETOOTH_LE_HID_SERVICE) => Some(Bus::BluetoothLe),
+        _ => None,
+    }
+}
@@ -0,0 +1,342 @@
+/// Typed access to DEVPKEY_xx device properties through SetupDiGetDevicePropertyW
+///
+/// Every getter returns Ok(None) when the device doesn't have the property,
+/// and an error when the property has a different type than the caller expects.
+/// [`get_any`] takes whatever type the property has.
+use windows::{
+    core::GUID,
+    Win32::{
+        Devices::{
+            DeviceAndDriverInstallation::{SetupDiGetDevicePropertyW, HDEVINFO, SP_DEVINFO_DATA},
+            Properties::{
+                DEVPROPKEY, DEVPROPTYPE, DEVPROP_TYPE_BOOLEAN, DEVPROP_TYPE_FILETIME,
+                DEVPROP_TYPE_GUID, DEVPROP_TYPE_STRING, DEVPROP_TYPE_STRING_LIST,
+                DEVPROP_TYPE_UINT32,
+            },
+        },
+        Foundation::ERROR_NOT_FOUND,
+    },
+};
+
+use super::{propbuffer::PropBuffer, setupapi::timed, Error, PropertyValue};
+use crate::{decode, timestamp::Timestamp};
+
+pub(crate) unsafe fn get_string(
+    devinfo: HDEVINFO,
+    devinfo_data: &SP_DEVINFO_DATA,
+    key: &DEVPROPKEY,
+    buffer: &mut PropBuffer<u8>,
+) -> Result<Option<String>, Error> {
+    get_raw(devinfo, devinfo_data, key, DEVPROP_TYPE_STRING, buffer)
+        .map(|b| b.map(from_utf16_in_u8))
+}
+
+pub(crate) unsafe fn get_string_list(
+    devinfo: HDEVINFO,
+    devinfo_data: &SP_DEVINFO_DATA,
+    key: &DEVPROPKEY,
+    buffer: &mut PropBuffer<u8>,
+) -> Result<Option<Vec<String>>, Error> {
+    get_raw(devinfo, devinfo_data, key, DEVPROP_TYPE_STRING_LIST, buffer)
+        .map(|b| b.map(multi_sz_from_utf16_in_u8))
+}
+
+pub(crate) unsafe fn get_u32(
+    devinfo: HDEVINFO,
+    devinfo_data: &SP_DEVINFO_DATA,
+    key: &DEVPROPKEY,
+    buffer: &mut PropBuffer<u8>,
+) -> Result<Option<u32>, Error> {
+    get_raw(devinfo, devinfo_data, key, DEVPROP_TYPE_UINT32, buffer)?
+        .map(|b| fixed::<4>(b).map(u32::from_le_bytes))
+        .transpose()
+}
+
+pub(crate) unsafe fn get_guid(
+    devinfo: HDEVINFO,
+    devinfo_data: &SP_DEVINFO_DATA,
+    key: &DEVPROPKEY,
+    buffer: &mut PropBuffer<u8>,
+) -> Result<Option<GUID>, Error> {
+    get_raw(devinfo, devinfo_data, key, DEVPROP_TYPE_GUID, buffer)?
+        .map(|b| fixed::<16>(b).map(guid_from_bytes))
+        .transpose()
+}
+
+/// Zero FILETIMEs are reported as absent too
+pub(crate) unsafe fn get_filetime(
+    devinfo: HDEVINFO,
+    devinfo_data: &SP_DEVINFO_DATA,
+    key: &DEVPROPKEY,
+    buffer: &mut PropBuffer<u8>,
+) -> Result<Option<Timestamp>, Error> {
+    let filetime = get_raw(devinfo, devinfo_data, key, DEVPROP_TYPE_FILETIME, buffer)?
+        .map(|b| fixed::<8>(b).map(u64::from_le_bytes))
+        .transpose()?;
+    Ok(filetime.and_then(Timestamp::from_filetime))
+}
+
+pub(crate) unsafe fn get_bool(
+    devinfo: HDEVINFO,
+    devinfo_data: &SP_DEVINFO_DATA,
+    key: &DEVPROPKEY,
+    buffer: &mut PropBuffer<u8>,
+) -> Result<Option<bool>, Error> {
+    // DEVPROP_TRUE is 0xFF, but anything non-zero is taken as true
+    get_raw(devinfo, devinfo_data, key, DEVPROP_TYPE_BOOLEAN, buffer)?
+        .map(|b| fixed::<1>(b).map(|[v]| v != 0))
+        .transpose()
+}
+
+/// The property decoded according to its own type, types other than the ones
+/// above as raw bytes
+pub(crate) unsafe fn get_any(
+    devinfo: HDEVINFO,
+    devinfo_data: &SP_DEVINFO_DATA,
+    key: &DEVPROPKEY,
+    buffer: &mut PropBuffer<u8>,
+) -> Result<Option<PropertyValue>, Error> {
+    match get_untyped(devinfo, devinfo_data, key, buffer)? {
+        Some((bytes, proptype)) => value(bytes, proptype),
+        None => Ok(None),
+    }
+}
+
+/// Decode a property according to its type, None for a zero FILETIME
+fn value(bytes: &[u8], proptype: DEVPROPTYPE) -> Result<Option<PropertyValue>, Error> {
+    Ok(Some(match proptype {
+        DEVPROP_TYPE_STRING => PropertyValue::String(from_utf16_in_u8(bytes)),
+        DEVPROP_TYPE_STRING_LIST => PropertyValue::List(multi_sz_from_utf16_in_u8(bytes)),
+        DEVPROP_TYPE_UINT32 => PropertyValue::U32(u32::from_le_bytes(fixed(bytes)?)),
+        DEVPROP_TYPE_GUID => PropertyValue::Guid(guid_from_bytes(fixed(bytes)?)),
+        DEVPROP_TYPE_BOOLEAN => PropertyValue::Bool(fixed::<1>(bytes)?[0] != 0),
+        DEVPROP_TYPE_FILETIME => {
+            match Timestamp::from_filetime(u64::from_le_bytes(fixed(bytes)?)) {
+                Some(time) => PropertyValue::Time(time),
+                None => return Ok(None),
+            }
+        }
+        _ => PropertyValue::Binary(bytes.to_vec()),
+    }))
+}
+
+/// Fetch the contents of a property into `buffer` and check its type
+unsafe fn get_raw<'b>(
+    devinfo: HDEVINFO,
+    devinfo_data: &SP_DEVINFO_DATA,
+    key: &DEVPROPKEY,
+    expected: DEVPROPTYPE,
+    buffer: &'b mut PropBuffer<u8>,
+) -> Result<Option<&'b [u8]>, Error> {
+    of_type(get_untyped(devinfo, devinfo_data, key, buffer)?, expected)
+}
+
+/// The contents of a property the device has, if it's of the expected type
+fn of_type(
+    property: Option<(&[u8], DEVPROPTYPE)>,
+    expected: DEVPROPTYPE,
+) -> Result<Option<&[u8]>, Error> {
+    match property {
+        Some((bytes, proptype)) => {
+            check_type(expected, proptype)?;
+            Ok(Some(bytes))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Fetch the contents of a property into `buffer` along with its type
+unsafe fn get_untyped<'b>(
+    devinfo: HDEVINFO,
+    devinfo_data: &SP_DEVINFO_DATA,
+    key: &DEVPROPKEY,
+    buffer: &'b mut PropBuffer<u8>,
+) -> Result<Option<(&'b [u8], DEVPROPTYPE)>, Error> {
+    let mut proptype = DEVPROPTYPE(0);
+    let result = buffer.read(|buf, required| {
+        timed("SetupDiGetDevicePropertyW", || {
+            SetupDiGetDevicePropertyW(
+                devinfo,
+                devinfo_data,
+                key,
+                &mut proptype,
+                Some(buf),
+                Some(required),
+                0,
+            )
+        })
+    });
+    log::debug!(
+        "SetupDiGetDevicePropertyW(devinst={}, key={}) -> {} type={:#x} size={}",
+        devinfo_data.DevInst,
+        key_name(key),
+        result
+            .as_ref()
+            .map_or_else(|e| decode::hresult(e.code()), |_| "ok".to_string()),
+        proptype.0,
+        result.as_ref().map_or(0, |b| b.len())
+    );
+    found(result, proptype)
+}
+
+/// The result of a property read, None if the device doesn't have it
+fn found(
+    result: windows::core::Result<&[u8]>,
+    proptype: DEVPROPTYPE,
+) -> Result<Option<(&[u8], DEVPROPTYPE)>, Error> {
+    match result {
+        Ok(bytes) => Ok(Some((bytes, proptype))),
+        Err(x) if x.code() == ERROR_NOT_FOUND.into() => Ok(None),
+        Err(x) => Err(x.into()),
+    }
+}
+
+/// "{fmtid} pid" like DEVPKEY_xx definitions are written
+fn key_name(key: &DEVPROPKEY) -> String {
+    format!("{{{:?}}} {}", key.fmtid, key.pid)
+}
+
+fn check_type(expected: DEVPROPTYPE, actual: DEVPROPTYPE) -> Result<(), Error> {
+    match expected == actual {
+        true => Ok(()),
+        false => Err(Error::PropertyType {
+            expected: expected.0,
+            actual: actual.0,
+        }),
+    }
+}
+
+/// Take exactly N bytes out of a fixed size property
+fn fixed<const N: usize>(buf: &[u8]) -> Result<[u8; N], Error> {
+    buf.try_into().map_err(|_| Error::PropertyLength(buf.len()))
+}
+
+fn guid_from_bytes(b: [u8; 16]) -> GUID {
+    GUID::from_values(
+        u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        u16::from_le_bytes([b[4], b[5]]),
+        u16::from_le_bytes([b[6], b[7]]),
+        [b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15]],
+    )
+}
+
+fn utf16_in_u8(buf: &[u8]) -> Vec<u16> {
+    buf.chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect()
+}
+
+/// Decode UTF-16 in a &[u8] up to where its null terminator is positioned.
+fn from_utf16_in_u8(buf: &[u8]) -> String {
+    let wide = utf16_in_u8(buf);
+    let end = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+    String::from_utf16_lossy(&wide[..end])
+}
+
+/// Convert a UTF-16 encoded MULTI_SZ structure in a &[u8] into a Vec<String>
+fn multi_sz_from_utf16_in_u8(buf: &[u8]) -> Vec<String> {
+    utf16_in_u8(buf)
+        .split(|&c| c == 0)
+        .filter(|p| !p.is_empty())
+        .map(String::from_utf16_lossy)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::{
+        Devices::Properties::DEVPROP_TYPE_BINARY, Foundation::ERROR_INVALID_DATA,
+    };
+
+    use super::*;
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn missing_property_is_none() {
+        let missing = found(Err(ERROR_NOT_FOUND.into()), DEVPROPTYPE(0)).unwrap();
+        assert!(missing.is_none());
+        assert!(of_type(missing, DEVPROP_TYPE_STRING).unwrap().is_none());
+    }
+
+    #[test]
+    fn other_read_errors_are_kept() {
+        let result = found(Err(ERROR_INVALID_DATA.into()), DEVPROPTYPE(0));
+        assert!(matches!(result, Err(Error::Win32(_))));
+    }
+
+    #[test]
+    fn wrong_type_is_an_error() {
+        let bytes = 7u32.to_le_bytes();
+        let result = of_type(Some((&bytes, DEVPROP_TYPE_UINT32)), DEVPROP_TYPE_STRING);
+        assert!(matches!(
+            result,
+            Err(Error::PropertyType { expected, actual })
+                if expected == DEVPROP_TYPE_STRING.0 && actual == DEVPROP_TYPE_UINT32.0
+        ));
+    }
+
+    #[test]
+    fn expected_type_is_returned() {
+        let bytes = 7u32.to_le_bytes();
+        let property = found(Ok(&bytes), DEVPROP_TYPE_UINT32).unwrap();
+        assert_eq!(
+            of_type(property, DEVPROP_TYPE_UINT32).unwrap(),
+            Some(&bytes[..])
+        );
+    }
+
+    #[test]
+    fn fixed_size_properties_need_their_size() {
+        assert_eq!(fixed::<4>(&[1, 0, 0, 0]).unwrap(), [1, 0, 0, 0]);
+        assert!(matches!(fixed::<4>(&[1, 0]), Err(Error::PropertyLength(2))));
+        assert!(matches!(
+            value(&[0; 3], DEVPROP_TYPE_UINT32),
+            Err(Error::PropertyLength(3))
+        ));
+    }
+
+    #[test]
+    fn strings_end_at_the_terminator() {
+        assert_eq!(from_utf16_in_u8(&utf16("Xbox\0junk")), "Xbox");
+        assert_eq!(from_utf16_in_u8(&utf16("no terminator")), "no terminator");
+        assert_eq!(
+            multi_sz_from_utf16_in_u8(&utf16("HID_DEVICE\0HID_DEVICE_SYSTEM_GAME\0\0")),
+            ["HID_DEVICE", "HID_DEVICE_SYSTEM_GAME"]
+        );
+        assert!(multi_sz_from_utf16_in_u8(&utf16("\0")).is_empty());
+    }
+
+    #[test]
+    fn values_are_decoded_by_their_type() {
+        let guid = GUID::from_u128(0x745a17a0_74d3_11d0_b6fe_00a0c90f57da);
+        let guid_bytes: Vec<u8> = [
+            &guid.data1.to_le_bytes()[..],
+            &guid.data2.to_le_bytes(),
+            &guid.data3.to_le_bytes(),
+            &guid.data4,
+        ]
+        .concat();
+        assert!(matches!(
+            value(&guid_bytes, DEVPROP_TYPE_GUID).unwrap(),
+            Some(PropertyValue::Guid(g)) if g == guid
+        ));
+        assert!(matches!(
+            value(&utf16("pad\0"), DEVPROP_TYPE_STRING).unwrap(),
+            Some(PropertyValue::String(s)) if s == "pad"
+        ));
+        assert!(matches!(
+            value(&[0xFF], DEVPROP_TYPE_BOOLEAN).unwrap(),
+            Some(PropertyValue::Bool(true))
+        ));
+        assert!(matches!(
+            value(&[1, 2], DEVPROP_TYPE_BINARY).unwrap(),
+            Some(PropertyValue::Binary(b)) if b == [1, 2]
+        ));
+        assert!(value(&0u64.to_le_bytes(), DEVPROP_TYPE_FILETIME)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,69 @@
+/// Telling virtual controllers from physical ones
+///
+/// Virtual pads are created by software buses that the PnP manager enumerates
+/// from the root of the device tree, like ViGEmBus (`ROOT\SYSTEM\0001`) that
+/// DS4Windows and many remappers feed, or vJoy (`ROOT\HIDCLASS\0000`). The
+/// pads themselves look like real ones: a ViGEm Xbox 360 pad has the
+/// `USB\VID_045E&PID_028E` ids of a wired Microsoft controller. So a device
+/// counts as virtual when one of its ancestors is root enumerated, or when it
+/// has the ids of a driver that only makes virtual devices.
+use windows::{
+    core::HSTRING,
+    Win32::Devices::DeviceAndDriverInstallation::{
+        CM_Get_Device_IDW, CM_Get_Parent, CM_Locate_DevNodeW, CM_LOCATE_DEVNODE_PHANTOM,
+        CR_SUCCESS, MAX_DEVICE_ID_LEN,
+    },
+};
+
+use super::vid_pid;
+
+/// USB ids only used by virtual devices
+const VIRTUAL_IDS: &[(u16, u16)] = &[
+    // vJoy
+    (0x1234, 0xBEAD),
+    // Steam Input's virtual gamepad, the Steam Controller itself is 28DE:1102
+    (0x28DE, 0x11FF),
+];
+
+/// Root enumerated devnodes are under `ROOT\`, except the root itself
+const ROOT_ENUMERATOR: &str = "ROOT\\";
+
+pub(super) fn is_virtual(instance_id: &str) -> bool {
+    if vid_pid(instance_id).is_some_and(|ids| VIRTUAL_IDS.contains(&ids)) {
+        return true;
+    }
+    ancestors(instance_id)
+        .iter()
+        .any(|id| id.to_uppercase().starts_with(ROOT_ENUMERATOR))
+}
+
+/// Instance ids of the parent, its parent, up to the root of the device tree
+fn ancestors(instance_id: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    let mut devinst = 0;
+    let result = unsafe {
+        // phantom, so that it works for disconnected devices too
+        CM_Locate_DevNodeW(
+            &mut devinst,
+            &HSTRING::from(instance_id),
+            CM_LOCATE_DEVNODE_PHANTOM,
+        )
+    };
+    if result != CR_SUCCESS {
+        log::debug!("CM_Locate_DevNodeW({}) -> {:?}", instance_id, result);
+        return ids;
+    }
+    loop {
+        let mut parent = 0;
+        if unsafe { CM_Get_Parent(&mut parent, devinst, 0) } != CR_SUCCESS {
+            return ids;
+        }
+        let mut buf = [0u16; MAX_DEVICE_ID_LEN as usize + 1];
+        if unsafe { CM_Get_Device_IDW(parent, &mut buf, 0) } != CR_SUCCESS {
+            return ids;
+        }
+        let end = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+        ids.push(String::from_utf16_lossy(&buf[..end]));
+        devinst = parent;
+    }
+}
@@ -0,0 +1,384 @@
+/// The Linux backend, game controllers through sysfs
+///
+/// Controllers are the input devices under `/sys/class/input` that have
+/// joystick or gamepad buttons. Disabling one sets its `inhibited`
+/// attribute (Linux 5.11 and later), so that the kernel stops delivering
+/// its events to every reader of its `js` and `event` nodes. Unbinding the
+/// driver would work on more devices, but the input device then goes away
+/// and couldn't be found again to enable it. Changing devices needs root, or
+/// a udev rule that makes `inhibited` writable.
+///
+/// Instance ids look like `INPUT\VID_045E&PID_028E\input12`, so that
+/// [`vid_pid`](super::vid_pid) and the selectors built on it work the same as
+/// on Windows. The input number changes when the device is plugged in again.
+/// There's nothing like a disconnected devnode, and changes aren't vetoed, so
+/// the scope's other settings and retries don't apply.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    BulkChange, Bus, DeviceSelector, Error, GameController, GameControllerStatus, Kind, Outcome,
+    Retry, Scope, State,
+};
+
+const INPUT_CLASS: &str = "/sys/class/input";
+
+/// BTN_JOYSTICK up to the last BTN_GAMEPAD button in linux/input-event-codes.h
+const CONTROLLER_BUTTONS: std::ops::RangeInclusive<usize> = 0x120..=0x13e;
+
+const BUS_USB: u16 = 0x03;
+const BUS_BLUETOOTH: u16 = 0x05;
+const BUS_VIRTUAL: u16 = 0x06;
+
+/// The connected game controllers. Only game controllers can be told apart
+/// from other input devices.
+pub fn game_controllers(scope: Scope) -> Result<Vec<GameController>, Error> {
+    if scope.kind != Kind::Gamepad || scope.class.is_some() || scope.interface.is_some() {
+        return Err(Error::Unsupported(
+            "other kinds of devices than game controllers on Linux",
+        ));
+    }
+    Ok(input_devices()?
+        .into_iter()
+        .filter_map(|dir| controller(&dir))
+        .collect())
+}
+
+/// Disable the device with given instance id until it's enabled again or
+/// unplugged
+pub fn disable_device(id: &str, _scope: Scope, _retry: Retry) -> Result<Outcome, Error> {
+    set_inhibited(id, true)
+}
+
+/// Enable the device with given instance id
+pub fn enable_device(id: &str, _scope: Scope, _retry: Retry) -> Result<Outcome, Error> {
+    set_inhibited(id, false)
+}
+
+/// Enable the device with given instance id if it's disabled, disable it
+/// otherwise. Returns whether the device was enabled, and the outcome.
+pub fn toggle_device(id: &str, scope: Scope, _retry: Retry) -> Result<(bool, Outcome), Error> {
+    let controller = game_controllers(scope)?
+        .into_iter()
+        .find(|c| c.instance_id.eq_ignore_ascii_case(id))
+        .ok_or(Error::NotFound)?;
+    let enable = controller.status == GameControllerStatus::Disabled;
+    Ok((enable, set_inhibited(id, !enable)?))
+}
+
+/// Disable the device with given instance id and enable it again, which
+/// makes readers of its event node see it come back
+pub fn restart_device(id: &str, _scope: Scope, _retry: Retry) -> Result<(), Error> {
+    set_inhibited(id, true)?;
+    set_inhibited(id, false)?;
+    Ok(())
+}
+
+/// Enable or disable every game controller. Controllers that can't be
+/// disabled are left out when disabling.
+pub fn set_all_devices(
+    scope: Scope,
+    _retry: Retry,
+    enable: bool,
+) -> Result<Vec<BulkChange>, Error> {
+    Ok(game_controllers(scope)?
+        .into_iter()
+        .filter(|c| enable || c.disableable)
+        .map(|c| BulkChange {
+            result: set_inhibited(&c.instance_id, !enable),
+            instance_id: c.instance_id,
+            name: c.name,
+        })
+        .collect())
+}
+
+/// Enable or disable the devices the selectors match. See
+/// [`select`](super::select) for the order of the results, selectors that
+/// match nothing get [`Error::NotFound`].
+pub fn set_devices_state(
+    selectors: &[DeviceSelector],
+    state: State,
+    scope: Scope,
+    _retry: Retry,
+) -> Result<Vec<BulkChange>, Error> {
+    let controllers = game_controllers(scope)?;
+    let devices = controllers.into_iter().map(|c| (c.instance_id.clone(), c));
+    Ok(super::select(selectors, devices)
+        .into_iter()
+        .map(|found| match found {
+            Ok((instance_id, controller)) => BulkChange {
+                result: set_inhibited(&instance_id, !state.is_enabled()),
+                instance_id,
+                name: controller.name,
+            },
+            Err(selector) => BulkChange {
+                instance_id: selector,
+                name: String::new(),
+                result: Err(Error::NotFound),
+            },
+        })
+        .collect())
+}
+
+fn set_inhibited(id: &str, inhibited: bool) -> Result<Outcome, Error> {
+    let (dir, controller) = input_devices()?
+        .into_iter()
+        .filter_map(|dir| controller(&dir).map(|c| (dir, c)))
+        .find(|(_, c)| c.instance_id.eq_ignore_ascii_case(id))
+        .ok_or(Error::NotFound)?;
+    let wanted = match inhibited {
+        true => GameControllerStatus::Disabled,
+        false => GameControllerStatus::Enabled,
+    };
+    if controller.status == wanted {
+        return Ok(Outcome::AlreadyInState);
+    }
+    if !controller.disableable {
+        return Err(Error::NotDisableable(controller.name));
+    }
+    let path = dir.join("inhibited");
+    log::debug!("writing {} to {}", inhibited as u8, path.display());
+    fs::write(&path, if inhibited { "1" } else { "0" }).map_err(|e| sysfs_error(&path, e))?;
+    Ok(Outcome::Changed)
+}
+
+/// Directories of the input devices like `/sys/class/input/input12`, in the
+/// order they were created
+fn input_devices() -> Result<Vec<PathBuf>, Error> {
+    let entries = fs::read_dir(INPUT_CLASS).map_err(|e| sysfs_error(Path::new(INPUT_CLASS), e))?;
+    let mut result: Vec<(u32, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let number = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("input")?
+                .parse()
+                .ok()?;
+            Some((number, entry.path()))
+        })
+        .collect();
+    result.sort_unstable();
+    Ok(result.into_iter().map(|(_, path)| path).collect())
+}
+
+/// The input device in `dir`, None if it isn't a game controller
+fn controller(dir: &Path) -> Option<GameController> {
+    let node = dir.file_name()?.to_str()?;
+    let buttons = attribute(dir, "capabilities/key")?;
+    if !CONTROLLER_BUTTONS
+        .into_iter()
+        .any(|button| has_bit(&buttons, button))
+    {
+        return None;
+    }
+    let name = attribute(dir, "name").unwrap_or_default();
+    let hex = |name| attribute(dir, name).and_then(|s| u16::from_str_radix(&s, 16).ok());
+    let bus_type = hex("id/bustype").unwrap_or_default();
+    let vendor = hex("id/vendor").unwrap_or_default();
+    let product = hex("id/product").unwrap_or_default();
+    let inhibited = attribute(dir, "inhibited");
+    let real_path = fs::canonicalize(dir).ok();
+    log::debug!("{} is a game controller: {}", node, name);
+    Some(GameController {
+        manufacturer: real_path
+            .as_deref()
+            .and_then(manufacturer)
+            .unwrap_or_default(),
+        name,
+        friendly_name: None,
+        location: attribute(dir, "phys").filter(|s| !s.is_empty()),
+        instance_id: format!("INPUT\\VID_{:04X}&PID_{:04X}\\{}", vendor, product, node),
+        serial: attribute(dir, "uniq").filter(|s| !s.is_empty()),
+        bus: match bus_type {
+            BUS_USB => Some(Bus::Usb),
+            BUS_BLUETOOTH => Some(Bus::Bluetooth),
+            _ => None,
+        },
+        parent_id: None,
+        container_id: None,
+        status: match inhibited.as_deref() {
+            Some("1") => GameControllerStatus::Disabled,
+            _ => GameControllerStatus::Enabled,
+        },
+        disableable: inhibited.is_some(),
+        is_virtual: bus_type == BUS_VIRTUAL
+            || real_path.is_some_and(|p| p.starts_with("/sys/devices/virtual")),
+        kind: Some(Kind::Gamepad),
+        xinput_slot: None,
+        battery: None,
+        status_flags: 0,
+        problem_code: 0,
+        installed_at: None,
+        last_seen: None,
+        driver: None,
+    })
+}
+
+/// A sysfs attribute without the trailing line break, None if it can't be
+/// read
+fn attribute(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name))
+        .ok()
+        .map(|s| s.trim_end().to_string())
+}
+
+/// Manufacturer of the closest USB device above the input device
+fn manufacturer(path: &Path) -> Option<String> {
+    path.ancestors()
+        .take_while(|p| p.starts_with("/sys/devices"))
+        .find_map(|p| attribute(p, "manufacturer"))
+}
+
+/// Whether `bit` is set in a bitmap the way the input subsystem prints it,
+/// hex words of a `long` each, the most significant first
+fn has_bit(bitmap: &str, bit: usize) -> bool {
+    let bits = usize::BITS as usize;
+    bitmap
+        .split_whitespace()
+        .rev()
+        .nth(bit / bits)
+        .and_then(|word| usize::from_str_radix(word, 16).ok())
+        .is_some_and(|word| word & (1 << (bit % bits)) != 0)
+}
+
+fn sysfs_error(path: &Path, err: io::Error) -> Error {
+    match err.kind() {
+        io::ErrorKind::PermissionDenied => Error::AccessDenied,
+        // unplugged while it was being changed
+        io::ErrorKind::NotFound => Error::NotFound,
+        kind => Error::Sysfs {
+            path: path.display().to_string(),
+            kind,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, env, process};
+
+    use super::*;
+
+    const BTN_SOUTH: usize = 0x130;
+    /// Keyboards have it, game controllers don't
+    const KEY_A: usize = 30;
+
+    type Attributes = BTreeMap<&'static str, String>;
+
+    /// A bitmap the way the input subsystem prints it, with given bits set
+    fn bitmap(bits: &[usize]) -> String {
+        let width = usize::BITS as usize;
+        let mut words = vec![0usize; bits.iter().max().map_or(1, |bit| bit / width + 1)];
+        for bit in bits {
+            words[bit / width] |= 1 << (bit % width);
+        }
+        let words: Vec<String> = words.iter().rev().map(|w| format!("{:x}", w)).collect();
+        words.join(" ")
+    }
+
+    /// `input7` of a made up sysfs with given attributes, in a directory of
+    /// its own for every test
+    fn device(test: &str, attributes: &Attributes) -> PathBuf {
+        let dir = env::temp_dir()
+            .join(format!("nojoy-sysfs-test-{}", process::id()))
+            .join(test)
+            .join("input7");
+        let _ = fs::remove_dir_all(&dir);
+        for (name, value) in attributes {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, format!("{}\n", value)).unwrap();
+        }
+        dir
+    }
+
+    /// The attributes of a wired Xbox 360 controller
+    fn gamepad() -> Attributes {
+        BTreeMap::from([
+            ("capabilities/key", bitmap(&[BTN_SOUTH])),
+            ("name", "Microsoft X-Box 360 pad".to_string()),
+            ("id/bustype", "0003".to_string()),
+            ("id/vendor", "045e".to_string()),
+            ("id/product", "028e".to_string()),
+            ("inhibited", "0".to_string()),
+            ("phys", "usb-0000:00:14.0-2/input0".to_string()),
+            ("uniq", String::new()),
+        ])
+    }
+
+    #[test]
+    fn bits_are_counted_from_the_last_word() {
+        let width = usize::BITS as usize;
+        assert!(has_bit("1", 0));
+        assert!(!has_bit("2", 0));
+        assert!(has_bit("2", 1));
+        assert!(has_bit("1 0", width));
+        assert!(!has_bit("1 0", 0));
+        assert!(has_bit(&bitmap(&[BTN_SOUTH]), BTN_SOUTH));
+        assert!(!has_bit(&bitmap(&[BTN_SOUTH]), BTN_SOUTH + 1));
+    }
+
+    #[test]
+    fn missing_and_malformed_words_are_unset() {
+        assert!(!has_bit("", 0));
+        assert!(!has_bit("1", usize::BITS as usize));
+        assert!(!has_bit("zz", 0));
+    }
+
+    #[test]
+    fn controllers_are_read_from_their_attributes() {
+        let found = controller(&device("gamepad", &gamepad())).unwrap();
+        assert_eq!(found.name, "Microsoft X-Box 360 pad");
+        assert_eq!(found.instance_id, r"INPUT\VID_045E&PID_028E\input7");
+        assert_eq!(found.bus, Some(Bus::Usb));
+        assert_eq!(found.status, GameControllerStatus::Enabled);
+        assert!(found.disableable);
+        assert!(!found.is_virtual);
+        assert_eq!(found.location.as_deref(), Some("usb-0000:00:14.0-2/input0"));
+        assert_eq!(found.serial, None);
+        assert_eq!(found.kind, Some(Kind::Gamepad));
+    }
+
+    #[test]
+    fn inhibited_controllers_are_disabled() {
+        let mut attributes = gamepad();
+        attributes.insert("inhibited", "1".to_string());
+        let found = controller(&device("inhibited", &attributes)).unwrap();
+        assert_eq!(found.status, GameControllerStatus::Disabled);
+    }
+
+    #[test]
+    fn controllers_without_inhibited_cant_be_disabled() {
+        let mut attributes = gamepad();
+        attributes.remove("inhibited");
+        let found = controller(&device("old-kernel", &attributes)).unwrap();
+        assert_eq!(found.status, GameControllerStatus::Enabled);
+        assert!(!found.disableable);
+    }
+
+    #[test]
+    fn buses() {
+        let mut attributes = gamepad();
+        attributes.insert("id/bustype", "0005".to_string());
+        attributes.insert("uniq", "a0:5a:5c:01:02:03".to_string());
+        let found = controller(&device("bluetooth", &attributes)).unwrap();
+        assert_eq!(found.bus, Some(Bus::Bluetooth));
+        assert_eq!(found.serial.as_deref(), Some("a0:5a:5c:01:02:03"));
+        attributes.insert("id/bustype", "0006".to_string());
+        let found = controller(&device("virtual", &attributes)).unwrap();
+        assert_eq!(found.bus, None);
+        assert!(found.is_virtual);
+    }
+
+    #[test]
+    fn other_input_devices_arent_controllers() {
+        let mut attributes = gamepad();
+        attributes.insert("capabilities/key", bitmap(&[KEY_A]));
+        assert!(controller(&device("keyboard", &attributes)).is_none());
+        attributes.remove("capabilities/key");
+        assert!(controller(&device("no-keys", &attributes)).is_none());
+    }
+}
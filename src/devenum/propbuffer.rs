@@ -0,0 +1,147 @@
+/// A growable buffer shared by the SetupDi reads of a device information set
+///
+/// Most properties fit in a few hundred bytes, so rather than asking for the
+/// size first and allocating for every read, the call is made straight into
+/// the buffer and repeated only after growing it when Windows reports
+/// ERROR_INSUFFICIENT_BUFFER. The allocation is kept for the next read.
+use core::mem::size_of;
+
+use windows::Win32::Foundation::ERROR_INSUFFICIENT_BUFFER;
+
+/// Initial size of a buffer in bytes
+const INITIAL_BYTES: usize = 512;
+
+pub(crate) struct PropBuffer<T> {
+    buf: Vec<T>,
+}
+
+impl<T: Copy + Default> PropBuffer<T> {
+    pub fn new() -> Self {
+        Self {
+            buf: vec![T::default(); INITIAL_BYTES / size_of::<T>()],
+        }
+    }
+
+    /// Run `read` with the buffer and a place to put the required size in
+    /// elements, growing the buffer until the result fits. Returns the part
+    /// of the buffer that was filled in.
+    pub fn read(
+        &mut self,
+        mut read: impl FnMut(&mut [T], &mut u32) -> windows::core::Result<()>,
+    ) -> windows::core::Result<&[T]> {
+        loop {
+            let mut required = 0;
+            match read(&mut self.buf, &mut required) {
+                Ok(()) => return Ok(&self.buf[..(required as usize).min(self.buf.len())]),
+                Err(err) if err.code() == ERROR_INSUFFICIENT_BUFFER.into() => {
+                    // never trust the reported size to make progress
+                    let size = (required as usize).max(self.buf.len() * 2);
+                    log::trace!("growing property buffer to {} elements", size);
+                    self.buf.resize(size, T::default());
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use windows::Win32::Foundation::ERROR_INVALID_DATA;
+
+    use super::*;
+
+    /// Behaves like a SetupDi getter returning `data`, and counts the calls.
+    /// `reported` is the size it tells when the buffer is too small.
+    fn fetch<'a, T: Copy>(
+        data: &'a [T],
+        reported: usize,
+        calls: &'a Cell<usize>,
+    ) -> impl FnMut(&mut [T], &mut u32) -> windows::core::Result<()> + 'a {
+        move |buf, required| {
+            calls.set(calls.get() + 1);
+            if buf.len() < data.len() {
+                *required = reported as u32;
+                return Err(ERROR_INSUFFICIENT_BUFFER.into());
+            }
+            buf[..data.len()].copy_from_slice(data);
+            *required = data.len() as u32;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn small_property_takes_one_call() {
+        let mut buffer = PropBuffer::<u8>::new();
+        let calls = Cell::new(0);
+        let data = [1, 2, 3];
+        assert_eq!(buffer.read(fetch(&data, 3, &calls)).unwrap(), data);
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn exactly_sized_property_takes_one_call() {
+        let mut buffer = PropBuffer::<u8>::new();
+        let calls = Cell::new(0);
+        let data = vec![7; INITIAL_BYTES];
+        assert_eq!(
+            buffer.read(fetch(&data, INITIAL_BYTES, &calls)).unwrap(),
+            data
+        );
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn oversized_property_grows_the_buffer_once() {
+        let mut buffer = PropBuffer::<u8>::new();
+        let calls = Cell::new(0);
+        let data: Vec<u8> = (0..=255).cycle().take(INITIAL_BYTES * 3).collect();
+        assert_eq!(buffer.read(fetch(&data, data.len(), &calls)).unwrap(), data);
+        assert_eq!(calls.get(), 2);
+        // the allocation is kept for the next read
+        calls.set(0);
+        assert_eq!(buffer.read(fetch(&data, data.len(), &calls)).unwrap(), data);
+        assert_eq!(calls.get(), 1);
+        assert_eq!(buffer.read(fetch(&[9], 1, &calls)).unwrap(), [9]);
+    }
+
+    #[test]
+    fn underreported_size_still_grows() {
+        let mut buffer = PropBuffer::<u8>::new();
+        let calls = Cell::new(0);
+        let data = vec![1; INITIAL_BYTES * 3];
+        assert_eq!(buffer.read(fetch(&data, 0, &calls)).unwrap(), data);
+        // 512, 1024, 2048
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn wide_buffers_are_sized_in_elements() {
+        let mut buffer = PropBuffer::<u16>::new();
+        let calls = Cell::new(0);
+        let data: Vec<u16> = "HID\\VID_045E&PID_02E0\0".encode_utf16().collect();
+        assert_eq!(buffer.read(fetch(&data, data.len(), &calls)).unwrap(), data);
+        let long = vec![0x41; INITIAL_BYTES];
+        assert_eq!(buffer.read(fetch(&long, long.len(), &calls)).unwrap(), long);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn multi_sz_keeps_its_terminators() {
+        let mut buffer = PropBuffer::<u8>::new();
+        let calls = Cell::new(0);
+        let data: Vec<u8> = "A\0B\0\0".bytes().chain([0; INITIAL_BYTES]).collect();
+        let read = buffer.read(fetch(&data, data.len(), &calls)).unwrap();
+        assert_eq!(read.len(), data.len());
+        assert_eq!(&read[..5], b"A\0B\0\0");
+    }
+
+    #[test]
+    fn other_errors_are_returned() {
+        let mut buffer = PropBuffer::<u8>::new();
+        let result = buffer.read(|_, _| Err(ERROR_INVALID_DATA.into()));
+        assert_eq!(result.unwrap_err().code(), ERROR_INVALID_DATA.into());
+    }
+}
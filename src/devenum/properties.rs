@@ -0,0 +1,184 @@
+/// Everything we can read about a device, for bug reports
+use std::fmt;
+
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use windows::{
+    core::GUID,
+    Win32::Devices::Properties::{
+        DEVPKEY_Device_BusReportedDeviceDesc, DEVPKEY_Device_Class, DEVPKEY_Device_ClassGuid,
+        DEVPKEY_Device_CompatibleIds, DEVPKEY_Device_ContainerId, DEVPKEY_Device_DeviceDesc,
+        DEVPKEY_Device_Driver, DEVPKEY_Device_DriverDate, DEVPKEY_Device_DriverInfPath,
+        DEVPKEY_Device_DriverProvider, DEVPKEY_Device_DriverVersion, DEVPKEY_Device_EnumeratorName,
+        DEVPKEY_Device_FriendlyName, DEVPKEY_Device_HardwareIds, DEVPKEY_Device_InstallDate,
+        DEVPKEY_Device_IsPresent, DEVPKEY_Device_LastArrivalDate, DEVPKEY_Device_LastRemovalDate,
+        DEVPKEY_Device_LocationInfo, DEVPKEY_Device_LocationPaths, DEVPKEY_Device_Manufacturer,
+        DEVPKEY_Device_Parent, DEVPKEY_Device_Service, DEVPROPKEY,
+    },
+};
+
+use super::DeviceRef;
+use crate::{decode, timestamp::Timestamp};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropertyValue {
+    String(String),
+    List(Vec<String>),
+    U32(u32),
+    Bool(bool),
+    Guid(GUID),
+    Time(Timestamp),
+    /// A property of a type that isn't decoded
+    Binary(Vec<u8>),
+}
+
+impl fmt::Display for PropertyValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PropertyValue::String(s) => write!(f, "{}", s),
+            PropertyValue::List(items) => write!(f, "{}", items.join(", ")),
+            PropertyValue::U32(n) => write!(f, "{}", n),
+            PropertyValue::Bool(b) => write!(f, "{}", b),
+            PropertyValue::Guid(guid) => write!(f, "{{{:?}}}", guid),
+            PropertyValue::Time(time) => write!(f, "{}", time),
+            PropertyValue::Binary(bytes) => {
+                for byte in bytes {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Serialize for PropertyValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            PropertyValue::String(s) => serializer.serialize_str(s),
+            PropertyValue::List(items) => items.serialize(serializer),
+            PropertyValue::U32(n) => serializer.serialize_u32(*n),
+            PropertyValue::Bool(b) => serializer.serialize_bool(*b),
+            PropertyValue::Guid(_) => serializer.collect_str(self),
+            PropertyValue::Time(time) => time.serialize(serializer),
+            PropertyValue::Binary(_) => serializer.collect_str(self),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceProperty {
+    /// Name of the property in JSON output
+    pub key: &'static str,
+    /// Name of the property in text output
+    pub label: &'static str,
+    pub value: PropertyValue,
+}
+
+/// Properties in the order they were read, serialized as a JSON object
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceProperties(pub Vec<DeviceProperty>);
+
+impl Serialize for DeviceProperties {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for prop in &self.0 {
+            map.serialize_entry(prop.key, &prop.value)?;
+        }
+        map.end()
+    }
+}
+
+const PROPERTIES: &[(&str, &str, &DEVPROPKEY)] = &[
+    ("description", "Description", &DEVPKEY_Device_DeviceDesc),
+    (
+        "bus_reported_description",
+        "Bus reported description",
+        &DEVPKEY_Device_BusReportedDeviceDesc,
+    ),
+    (
+        "friendly_name",
+        "Friendly name",
+        &DEVPKEY_Device_FriendlyName,
+    ),
+    ("manufacturer", "Manufacturer", &DEVPKEY_Device_Manufacturer),
+    ("hardware_ids", "Hardware IDs", &DEVPKEY_Device_HardwareIds),
+    (
+        "compatible_ids",
+        "Compatible IDs",
+        &DEVPKEY_Device_CompatibleIds,
+    ),
+    ("enumerator", "Enumerator", &DEVPKEY_Device_EnumeratorName),
+    ("class", "Class", &DEVPKEY_Device_Class),
+    ("class_guid", "Class GUID", &DEVPKEY_Device_ClassGuid),
+    ("driver_key", "Driver key", &DEVPKEY_Device_Driver),
+    (
+        "driver_provider",
+        "Driver provider",
+        &DEVPKEY_Device_DriverProvider,
+    ),
+    (
+        "driver_version",
+        "Driver version",
+        &DEVPKEY_Device_DriverVersion,
+    ),
+    ("driver_date", "Driver date", &DEVPKEY_Device_DriverDate),
+    ("driver_inf", "Driver INF", &DEVPKEY_Device_DriverInfPath),
+    ("service", "Service", &DEVPKEY_Device_Service),
+    ("location_info", "Location", &DEVPKEY_Device_LocationInfo),
+    (
+        "location_paths",
+        "Location paths",
+        &DEVPKEY_Device_LocationPaths,
+    ),
+    ("container_id", "Container ID", &DEVPKEY_Device_ContainerId),
+    ("parent", "Parent", &DEVPKEY_Device_Parent),
+    ("present", "Present", &DEVPKEY_Device_IsPresent),
+    ("installed_at", "Installed", &DEVPKEY_Device_InstallDate),
+    (
+        "last_arrival",
+        "Last arrival",
+        &DEVPKEY_Device_LastArrivalDate,
+    ),
+    (
+        "last_removal",
+        "Last removal",
+        &DEVPKEY_Device_LastRemovalDate,
+    ),
+];
+
+/// Every property in the table that the device has, followed by its devnode
+/// status. Properties that are missing or can't be read are left out.
+pub(super) fn properties(device: &DeviceRef) -> DeviceProperties {
+    let mut props = vec![DeviceProperty {
+        key: "instance_id",
+        label: "Instance ID",
+        value: match device.instance_id() {
+            Ok(id) => PropertyValue::String(id),
+            Err(err) => PropertyValue::String(format!("<{}>", err)),
+        },
+    }];
+    for (key, label, devpkey) in PROPERTIES {
+        match device.prop(devpkey) {
+            Ok(Some(value)) => props.push(DeviceProperty { key, label, value }),
+            Ok(None) => (),
+            Err(err) => log::debug!("{} can't be read: {}", label, err),
+        }
+    }
+    if let Ok((flags, problem)) = device.status() {
+        props.push(DeviceProperty {
+            key: "status_flags",
+            label: "Status flags",
+            value: PropertyValue::List(
+                decode::status_flag_names(flags)
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+            ),
+        });
+        props.push(DeviceProperty {
+            key: "problem_code",
+            label: "Problem code",
+            value: PropertyValue::U32(problem.0),
+        });
+    }
+    DeviceProperties(props)
+}
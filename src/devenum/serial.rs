@@ -0,0 +1,139 @@
+/// Telling identical controllers apart by the serial number or Bluetooth
+/// address buried in their instance ids
+///
+/// Instance ids have the shape `<enumerator>\<device id>\<instance>`. Only some
+/// of them carry something stable:
+///
+/// - `USB\VID_045E&PID_02EA\3032363030323637` has the USB serial number as its
+///   instance part, unless the device doesn't report one and Windows makes up
+///   something like `6&1a2b3c4d&0&2`
+/// - `BTHENUM\{00001124-...}_VID&0002054c_PID&09cc\8&2b6d5a0&0&A4AE12345678_C00000000`
+///   ends with the peer address of a Bluetooth classic device
+/// - `BTHLEDEVICE\{00001812-...}_DEV_VID&02045E_PID&0B13_REV&0509_C8B7C9D0E1F2\8&...`
+///   ends the device id with the address of a Bluetooth LE device, and so does
+///   the HID child of it
+///
+/// HID devnodes don't have a serial of their own, so the parent's instance id
+/// is looked at when the device's own doesn't have one.
+pub(super) fn serial(instance_id: &str, parent_id: Option<&str>) -> Option<String> {
+    from_instance_id(instance_id).or_else(|| parent_id.and_then(from_instance_id))
+}
+
+fn from_instance_id(id: &str) -> Option<String> {
+    let mut parts = id.splitn(3, '\\');
+    let enumerator = parts.next()?.to_uppercase();
+    let device_id = parts.next()?;
+    let instance = parts.next()?;
+    match enumerator.as_str() {
+        "USB" => usb_serial(instance),
+        "BTHENUM" => instance
+            .rsplit('&')
+            .next()
+            .and_then(|s| s.split('_').next())
+            .and_then(bluetooth_address),
+        "BTHLEDEVICE" | "HID" if device_id.to_uppercase().contains("_DEV_") => device_id
+            .rsplit('_')
+            .next()
+            .and_then(|s| s.split('&').next())
+            .and_then(bluetooth_address),
+        _ => None,
+    }
+}
+
+/// Generated instance parts always contain `&`, real serials never do
+fn usb_serial(instance: &str) -> Option<String> {
+    match instance.is_empty() || instance.contains('&') {
+        true => None,
+        false => Some(instance.to_string()),
+    }
+}
+
+/// `A4AE12345678` as `A4:AE:12:34:56:78`, all zeros means there's no address
+fn bluetooth_address(hex: &str) -> Option<String> {
+    if hex.len() != 12 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    if hex.chars().all(|c| c == '0') {
+        return None;
+    }
+    let upper = hex.to_uppercase();
+    let octets: Vec<&str> = (0..12).step_by(2).map(|i| &upper[i..i + 2]).collect();
+    Some(octets.join(":"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usb_serial_number() {
+        let id = r"USB\VID_045E&PID_02EA\3032363030323637";
+        assert_eq!(serial(id, None).as_deref(), Some("3032363030323637"));
+    }
+
+    #[test]
+    fn generated_usb_instance_is_not_a_serial() {
+        assert_eq!(serial(r"USB\VID_045E&PID_02EA\6&1a2b3c4d&0&2", None), None);
+        assert_eq!(serial(r"USB\VID_045E&PID_02EA\", None), None);
+    }
+
+    #[test]
+    fn hid_device_takes_the_serial_of_its_parent() {
+        let id = r"HID\VID_045E&PID_02EA\7&2f1e3d4c&0&0000";
+        let parent = r"USB\VID_045E&PID_02EA\3032363030323637";
+        assert_eq!(
+            serial(id, Some(parent)).as_deref(),
+            Some("3032363030323637")
+        );
+        assert_eq!(serial(id, None), None);
+    }
+
+    #[test]
+    fn bluetooth_classic_address() {
+        let id = r"BTHENUM\{00001124-0000-1000-8000-00805f9b34fb}_VID&0002054c_PID&09cc\8&2b6d5a0&0&A4AE12345678_C00000000";
+        assert_eq!(serial(id, None).as_deref(), Some("A4:AE:12:34:56:78"));
+        let hid =
+            r"HID\{00001124-0000-1000-8000-00805f9b34fb}_VID&0002054c_PID&09cc\9&1f2e3d4c&0&0000";
+        assert_eq!(serial(hid, Some(id)).as_deref(), Some("A4:AE:12:34:56:78"));
+    }
+
+    #[test]
+    fn bluetooth_le_address() {
+        let id = r"BTHLEDEVICE\{00001812-0000-1000-8000-00805f9b34fb}_Dev_VID&02045e_PID&0b13_REV&0509_c8b7c9d0e1f2\8&1a2b3c4d&0&0020";
+        assert_eq!(serial(id, None).as_deref(), Some("C8:B7:C9:D0:E1:F2"));
+    }
+
+    #[test]
+    fn hid_child_of_bluetooth_le_device() {
+        let id = r"HID\{00001812-0000-1000-8000-00805f9b34fb}_Dev_VID&02045e_PID&0b13_REV&0509_c8b7c9d0e1f2&Col01\9&3c4d5e6f&0&0000";
+        assert_eq!(serial(id, None).as_deref(), Some("C8:B7:C9:D0:E1:F2"));
+    }
+
+    #[test]
+    fn missing_bluetooth_addresses() {
+        let zero = r"BTHENUM\{00001124-0000-1000-8000-00805f9b34fb}_VID&0002054c_PID&09cc\8&2b6d5a0&0&000000000000_C00000000";
+        assert_eq!(serial(zero, None), None);
+        let short = r"BTHENUM\{00001124-0000-1000-8000-00805f9b34fb}_LOCALMFG&0000\7&1a2b3c4d&0&A4AE1234_C00000000";
+        assert_eq!(serial(short, None), None);
+    }
+
+    #[test]
+    fn other_enumerators_have_no_serial() {
+        assert_eq!(serial(r"ROOT\HIDCLASS\0000", None), None);
+        assert_eq!(
+            serial(r"HID\VID_054C&PID_0CE6&MI_03\7&1f2e3d4c&0&0000", None),
+            None
+        );
+        assert_eq!(serial("not an instance id", None), None);
+    }
+
+    #[test]
+    fn addresses_need_twelve_hex_digits() {
+        assert_eq!(
+            bluetooth_address("a4ae12345678").as_deref(),
+            Some("A4:AE:12:34:56:78")
+        );
+        assert_eq!(bluetooth_address("A4AE1234567G"), None);
+        assert_eq!(bluetooth_address("A4AE123456789"), None);
+    }
+}
@@ -0,0 +1,949 @@
+/// The Windows backend, SetupAPI for finding devices and reading their
+/// properties, and the configuration manager (CM_xx) for changing their state
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+extern crate alloc;
+use windows::{
+    core::{GUID, HSTRING, PCWSTR},
+    Win32::{
+        Devices::{
+            DeviceAndDriverInstallation::{
+                CM_Disable_DevNode, CM_Enable_DevNode, CM_Get_DevNode_Status, CM_Locate_DevNodeW, CM_Query_And_Remove_SubTreeW, CM_Reenumerate_DevNode, CM_Uninstall_DevNode, SetupDiCallClassInstaller, SetupDiClassGuidsFromNameW, SetupDiGetClassDevsW, SetupDiGetDeviceInstanceIdW, SetupDiSetClassInstallParamsW, CM_DEVNODE_STATUS_FLAGS, CM_LOCATE_DEVNODE_NORMAL, CM_PROB, CM_PROB_NEED_RESTART, CM_PROB_WILL_BE_REMOVED, CM_REENUMERATE_SYNCHRONOUS, CM_REMOVE_DISABLE, CM_REMOVE_NO_RESTART, CM_REMOVE_UI_NOT_OK, CONFIGRET, CR_ACCESS_DENIED, CR_FAILURE, CR_NEED_RESTART, CR_NO_SUCH_DEVNODE, CR_QUERY_VETOED, CR_REMOVE_VETOED, CR_SUCCESS, DICS_DISABLE, DICS_ENABLE, DICS_FLAG_CONFIGSPECIFIC, DICS_FLAG_GLOBAL, DIF_PROPERTYCHANGE, DIF_REMOVE, DIGCF_ALLCLASSES, DIGCF_DEVICEINTERFACE, DIGCF_PRESENT, DN_DISABLEABLE, DN_NEED_RESTART, DN_WILL_BE_REMOVED, SETUP_DI_GET_CLASS_DEVS_FLAGS, SETUP_DI_PROPERTY_CHANGE_SCOPE, DN_STARTED, HDEVINFO, PNP_VETO_TYPE, SP_CLASSINSTALL_HEADER, SP_DEVINFO_DATA, SP_PROPCHANGE_PARAMS
+            },
+            HumanInterfaceDevice::HidD_GetHidGuid,
+            Properties::{
+                DEVPKEY_Device_ContainerId, DEVPKEY_Device_DeviceDesc, DEVPKEY_Device_DriverDate,
+                DEVPKEY_Device_DriverInfPath, DEVPKEY_Device_DriverProvider, DEVPKEY_Device_DriverVersion, DEVPKEY_Device_HardwareIds, DEVPKEY_Device_InstallDate,
+                DEVPKEY_Device_FriendlyName, DEVPKEY_Device_IsPresent, DEVPKEY_Device_LocationInfo,
+                DEVPKEY_Device_LastArrivalDate, DEVPKEY_Device_LastRemovalDate, DEVPKEY_Device_Parent,
+                DEVPKEY_Device_Manufacturer, DEVPKEY_Device_Service, DEVPROPKEY,
+            },
+        },
+        Foundation::{ERROR_ACCESS_DENIED, HWND, MAX_PATH},
+    },
+};
+
+use super::{
+    bus, emulated, properties, serial, BulkChange, DevInfoSet, DeviceProperties, DeviceRef, DeviceSelector, Driver, Error,
+    GameController, GameControllerStatus, Kind, Outcome, PropBuffer, PropertyValue, Retry, Scope, SetupDiEnum, State,
+};
+use crate::{audit, decode, elevation, xinput};
+
+impl Kind {
+    fn hardware_id(self) -> &'static str {
+        match self {
+            Kind::Gamepad => "HID_DEVICE_SYSTEM_GAME",
+            Kind::Keyboard => "HID_DEVICE_SYSTEM_KEYBOARD",
+            Kind::Mouse => "HID_DEVICE_SYSTEM_MOUSE",
+            // every HID collection has this one
+            Kind::AllHid => "HID_DEVICE",
+        }
+    }
+
+    fn matches(self, hwids: &[String]) -> bool {
+        hwids.iter().any(|id| id == self.hardware_id())
+    }
+
+    /// Kind of a HID collection with given hardware ids, None if it's
+    /// neither a game controller, a keyboard nor a mouse
+    fn of(hwids: &[String]) -> Option<Kind> {
+        [Kind::Gamepad, Kind::Keyboard, Kind::Mouse]
+            .into_iter()
+            .find(|kind| kind.matches(hwids))
+    }
+}
+
+/// Container id of devices that aren't a part of a removable device
+const NULL_CONTAINER_ID: GUID = GUID::from_u128(0x00000000_0000_0000_ffff_ffffffffffff);
+
+impl GameController {
+    /// Try to create an instance of GameController out of given device.
+    pub fn try_from_device(device: &DeviceRef) -> Result<Self, Error> {
+        let instance_id = device.instance_id()?;
+        let required = |key, what| {
+            device
+                .prop_string(key)
+                .and_then(|value| value.ok_or(Error::PropertyMissing))
+                .map_err(|err| err.context(format!("can't read {} of {}", what, instance_id)))
+        };
+        let name = required(&DEVPKEY_Device_DeviceDesc, "description")?;
+        let manufacturer = required(&DEVPKEY_Device_Manufacturer, "manufacturer")?;
+        let friendly_name = device.prop_string(&DEVPKEY_Device_FriendlyName).ok().flatten();
+        let location = device.prop_string(&DEVPKEY_Device_LocationInfo).ok().flatten();
+        let parent_id = device.prop_string(&DEVPKEY_Device_Parent).ok().flatten();
+        let serial = serial::serial(&instance_id, parent_id.as_deref());
+        let bus = bus::bus(&instance_id, parent_id.as_deref());
+        let container_id = device
+            .prop_guid(&DEVPKEY_Device_ContainerId)
+            .ok()
+            .flatten()
+            // devices built into the computer share this one
+            .filter(|guid| *guid != NULL_CONTAINER_ID)
+            .map(|guid| format!("{{{:?}}}", guid));
+        let (flags, problem) = device.status()?;
+        let is_virtual = emulated::is_virtual(&instance_id);
+        let kind = device
+            .prop_string_list(&DEVPKEY_Device_HardwareIds)
+            .ok()
+            .flatten()
+            .and_then(|ids| Kind::of(&ids));
+        let date = |key| device.prop_filetime(key).ok().flatten();
+        let last_seen = [
+            date(&DEVPKEY_Device_LastArrivalDate),
+            date(&DEVPKEY_Device_LastRemovalDate),
+        ]
+        .into_iter()
+        .flatten()
+        .max();
+        let string = |key| device.prop_string(key).ok().flatten();
+        let driver = Driver {
+            provider: string(&DEVPKEY_Device_DriverProvider),
+            version: string(&DEVPKEY_Device_DriverVersion),
+            date: date(&DEVPKEY_Device_DriverDate),
+            inf: string(&DEVPKEY_Device_DriverInfPath),
+        };
+        let has_driver = driver.provider.is_some() || driver.version.is_some() || driver.inf.is_some();
+        let status = match flags {
+            CM_DEVNODE_STATUS_FLAGS(0) => GameControllerStatus::Disconnected,
+            x if (x & DN_STARTED).0 == 0 => GameControllerStatus::Disabled,
+            _ => GameControllerStatus::Enabled,
+        };
+        Ok(Self {
+            manufacturer,
+            name,
+            friendly_name,
+            location,
+            instance_id,
+            serial,
+            bus,
+            parent_id,
+            container_id,
+            status,
+            disableable: (flags & DN_DISABLEABLE).0 != 0,
+            is_virtual,
+            kind,
+            xinput_slot: None,
+            battery: None,
+            status_flags: flags.0,
+            problem_code: problem.0,
+            installed_at: date(&DEVPKEY_Device_InstallDate),
+            last_seen,
+            driver: has_driver.then_some(driver),
+        })
+    }
+}
+
+/// Every readable property of the device with given instance id
+pub fn device_properties(id: &str, scope: Scope) -> Result<DeviceProperties, Error> {
+    let set = DevInfoSet::open(scope)?;
+    let device = find_device(&set, scope, id).ok_or(Error::NotFound)?;
+    Ok(properties::properties(&device))
+}
+
+/// A single DEVPKEY_xx property of the device with given instance id, None if
+/// the device doesn't have it
+pub fn device_property(id: &str, scope: Scope, key: &DEVPROPKEY) -> Result<Option<PropertyValue>, Error> {
+    let set = DevInfoSet::open(scope)?;
+    let device = find_device(&set, scope, id).ok_or(Error::NotFound)?;
+    device.prop(key)
+}
+
+/// Disable the device with given instance id. Disabled devices stay that way
+/// across reboots until they are enabled again.
+pub fn disable_device(id: &str, scope: Scope, retry: Retry) -> Result<Outcome, Error> {
+    unsafe { change_device(id, scope, retry, false) }
+}
+
+/// Enable the device with given instance id
+pub fn enable_device(id: &str, scope: Scope, retry: Retry) -> Result<Outcome, Error> {
+    unsafe { change_device(id, scope, retry, true) }
+}
+
+/// Enable the device with given instance id if it's disabled, disable it
+/// otherwise. Returns whether the device was enabled, and the outcome.
+pub fn toggle_device(id: &str, scope: Scope, retry: Retry) -> Result<(bool, Outcome), Error> {
+    let set = DevInfoSet::open(scope)?;
+    let device = find_device(&set, scope, id).ok_or(Error::NotFound)?;
+    let enable = unsafe { (device_status_flags(device.devinst())? & DN_STARTED).0 == 0 };
+    let outcome = unsafe { change_devnode(&device, retry, enable)? };
+    Ok((enable, outcome))
+}
+
+/// How long a restart waits for the devnode to stop or start
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(10);
+const SETTLE_POLL: Duration = Duration::from_millis(50);
+
+/// Disable the device with given instance id and enable it again once it has
+/// stopped, like doing both from Device Manager. A disabled device is only
+/// enabled.
+pub fn restart_device(id: &str, scope: Scope, retry: Retry) -> Result<(), Error> {
+    let set = DevInfoSet::open(scope)?;
+    let device = find_device(&set, scope, id).ok_or(Error::NotFound)?;
+    unsafe {
+        change_devnode(&device, retry, false)?;
+        wait_for_state(&device, false)?;
+        change_devnode(&device, retry, true)?;
+        wait_for_state(&device, true)
+    }
+}
+
+/// Poll the devnode until it's started or stopped as requested
+unsafe fn wait_for_state(device: &DeviceRef, started: bool) -> Result<(), Error> {
+    let start = Instant::now();
+    loop {
+        let flags = device_status_flags(device.devinst())?;
+        if ((flags & DN_STARTED).0 != 0) == started {
+            log::debug!(
+                "devinst={} {} after {:?}",
+                device.devinst(),
+                if started { "started" } else { "stopped" },
+                start.elapsed()
+            );
+            return Ok(());
+        }
+        if start.elapsed() > SETTLE_TIMEOUT {
+            return Err(Error::StateTimeout);
+        }
+        thread::sleep(SETTLE_POLL);
+    }
+}
+
+/// What it takes to bring a device to the requested state
+#[derive(Debug, PartialEq, Eq)]
+enum Plan {
+    NotPresent,
+    AlreadyInState,
+    NotDisableable,
+    Change,
+}
+
+/// Decide on an enable/disable request from the devnode status flags alone
+fn plan_change(flags: CM_DEVNODE_STATUS_FLAGS, enable: bool) -> Plan {
+    let started = (flags & DN_STARTED).0 != 0;
+    match flags {
+        CM_DEVNODE_STATUS_FLAGS(0) => Plan::NotPresent,
+        _ if started == enable => Plan::AlreadyInState,
+        _ if !enable && (flags & DN_DISABLEABLE).0 == 0 => Plan::NotDisableable,
+        _ => Plan::Change,
+    }
+}
+
+/// Enable or disable the device with given instance id, retrying the CM_xx
+/// call as long as it fails with a transient error.
+unsafe fn change_device(id: &str, scope: Scope, retry: Retry, enable: bool) -> Result<Outcome, Error> {
+    let set = DevInfoSet::open(scope)?;
+    let device = find_device(&set, scope, id).ok_or(Error::NotFound)?;
+    change_devnode(&device, retry, enable)
+}
+
+/// Enable or disable the device with given instance id in the current
+/// hardware profile only (DICS_FLAG_CONFIGSPECIFIC), through its class
+/// installer the way Device Manager does, instead of in every profile
+pub fn change_device_in_profile(id: &str, scope: Scope, enable: bool) -> Result<Outcome, Error> {
+    let set = DevInfoSet::open(scope)?;
+    let device = find_device(&set, scope, id).ok_or(Error::NotFound)?;
+    unsafe {
+        let result = check_change(&device, enable).and_then(|checked| match checked {
+            Some(outcome) => Ok(outcome),
+            None => device
+                .change_state(enable, DICS_FLAG_CONFIGSPECIFIC)
+                .and_then(|()| restart_check(&device)),
+        });
+        audited(&device, enable, result)
+    }
+}
+
+unsafe fn change_devnode(device: &DeviceRef, retry: Retry, enable: bool) -> Result<Outcome, Error> {
+    audited(device, enable, try_change_devnode(device, retry, enable))
+}
+
+/// Record a change in the event log if one was tried
+fn audited(device: &DeviceRef, enable: bool, result: Result<Outcome, Error>) -> Result<Outcome, Error> {
+    if !matches!(result, Ok(Outcome::AlreadyInState)) {
+        let action = if enable { audit::Action::Enable } else { audit::Action::Disable };
+        audit::record(action, &device.instance_id().unwrap_or_default(), &result);
+    }
+    result
+}
+
+/// Whether the change can be made, Some if there's nothing to do
+unsafe fn check_change(device: &DeviceRef, enable: bool) -> Result<Option<Outcome>, Error> {
+    match plan_change(device_status_flags(device.devinst())?, enable) {
+        Plan::NotPresent => return Err(Error::NotPresent),
+        Plan::AlreadyInState => return Ok(Some(Outcome::AlreadyInState)),
+        Plan::NotDisableable => {
+            let name = match device.prop_string(&DEVPKEY_Device_DeviceDesc)? {
+                Some(name) => name,
+                None => device.instance_id()?,
+            };
+            return Err(Error::NotDisableable(name));
+        }
+        Plan::Change => (),
+    }
+    // an unknown elevation is left to the call making the change to sort out
+    if let Ok(false) = elevation::is_elevated() {
+        return Err(Error::NotElevated);
+    }
+    Ok(None)
+}
+
+unsafe fn try_change_devnode(device: &DeviceRef, retry: Retry, enable: bool) -> Result<Outcome, Error> {
+    if let Some(outcome) = check_change(device, enable)? {
+        return Ok(outcome);
+    }
+    match cm_change_devnode(device, retry, enable) {
+        // some drivers only go along with the class installer
+        Err(err @ (Error::ConfigRet(_) | Error::GaveUp { .. })) => match device.change_state(enable, DICS_FLAG_GLOBAL) {
+            Ok(()) => {
+                log::warn!(
+                    "devinst={} {} through the class installer after CM_xx failed with {}",
+                    device.devinst(),
+                    if enable { "enabled" } else { "disabled" },
+                    err
+                );
+                restart_check(device)
+            }
+            Err(fallback) => {
+                log::info!("class installer fallback failed too: {}", fallback);
+                Err(err)
+            }
+        },
+        result => result,
+    }
+}
+
+unsafe fn cm_change_devnode(device: &DeviceRef, retry: Retry, enable: bool) -> Result<Outcome, Error> {
+    let (call, change): (&str, unsafe fn(u32, u32) -> CONFIGRET) = match enable {
+        true => ("CM_Enable_DevNode", CM_Enable_DevNode),
+        false => ("CM_Disable_DevNode", CM_Disable_DevNode),
+    };
+    let mut delay = retry.delay;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = timed(call, || change(device.devinst(), 0));
+        log::info!(
+            "{}(devinst={}) attempt {} -> {}",
+            call,
+            device.devinst(),
+            attempts,
+            decode::configret(result)
+        );
+        match result {
+            CR_SUCCESS => return restart_check(device),
+            CR_NEED_RESTART => return Ok(Outcome::RestartRequired),
+            CR_NO_SUCH_DEVNODE => return Err(Error::NotPresent),
+            CR_ACCESS_DENIED => return Err(Error::AccessDenied),
+            CR_QUERY_VETOED if !enable => return disable_reporting_veto(device),
+            CR_REMOVE_VETOED if !enable && attempts > retry.retries => {
+                return disable_reporting_veto(device)
+            }
+            x if !is_retryable(x, device_status(device.devinst())?) => return Err(Error::ConfigRet(x)),
+            x if attempts > retry.retries => {
+                return Err(match attempts {
+                    1 => Error::ConfigRet(x),
+                    _ => Error::GaveUp {
+                        result: x,
+                        attempts,
+                    },
+                })
+            }
+            _ => {
+                log::warn!("{} failed, retrying in {:?}", call, delay);
+                thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+    }
+}
+
+/// Try a vetoed disable once more through CM_Query_And_Remove_SubTreeW,
+/// which tells what blocked it
+unsafe fn disable_reporting_veto(device: &DeviceRef) -> Result<Outcome, Error> {
+    let mut veto_type = PNP_VETO_TYPE::default();
+    let mut name = [0u16; MAX_PATH as usize];
+    let result = timed("CM_Query_And_Remove_SubTreeW", || {
+        CM_Query_And_Remove_SubTreeW(
+            device.devinst(),
+            Some(&mut veto_type),
+            Some(&mut name),
+            CM_REMOVE_UI_NOT_OK | CM_REMOVE_NO_RESTART | CM_REMOVE_DISABLE,
+        )
+    });
+    let end = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    let vetoer = String::from_utf16_lossy(&name[..end]);
+    log::info!(
+        "CM_Query_And_Remove_SubTreeW(devinst={}) -> {} veto={} {}",
+        device.devinst(),
+        decode::configret(result),
+        decode::veto_type(veto_type),
+        vetoer
+    );
+    match result {
+        CR_SUCCESS => restart_check(device),
+        CR_REMOVE_VETOED | CR_QUERY_VETOED => Err(Error::Vetoed { veto_type, vetoer }),
+        CR_ACCESS_DENIED => Err(Error::AccessDenied),
+        x => Err(Error::ConfigRet(x)),
+    }
+}
+
+/// A successful CM_xx call can still leave the device as it was until the
+/// next boot, which the devnode tells with a flag or a problem code
+unsafe fn restart_check(device: &DeviceRef) -> Result<Outcome, Error> {
+    let (flags, problem) = device_status(device.devinst())?;
+    if (flags & DN_NEED_RESTART).0 != 0 || problem == CM_PROB_NEED_RESTART {
+        log::warn!("devinst={} needs a restart for the change", device.devinst());
+        return Ok(Outcome::RestartRequired);
+    }
+    Ok(Outcome::Changed)
+}
+
+/// Enable or disable every connected game controller in scope, in a single
+/// pass over one device information set. Controllers that can't be disabled
+/// are left out when disabling, ones whose properties can't be read get the
+/// error as their result.
+pub fn set_all_devices(scope: Scope, retry: Retry, enable: bool) -> Result<Vec<BulkChange>, Error> {
+    let set = DevInfoSet::open(scope)?;
+    let mut changes = Vec::new();
+    for device in enum_devices(&set, scope) {
+        // one device that can't be read doesn't stop the others
+        let controller = match GameController::try_from_device(&device) {
+            Ok(controller) => controller,
+            Err(err) => match device.instance_id() {
+                Ok(instance_id) => {
+                    changes.push(BulkChange {
+                        instance_id,
+                        name: String::new(),
+                        result: Err(err),
+                    });
+                    continue;
+                }
+                Err(_) => {
+                    log::warn!("skipped a device that can't be read: {}", err);
+                    continue;
+                }
+            },
+        };
+        if controller.status == GameControllerStatus::Disconnected
+            || (!enable && !controller.disableable)
+        {
+            continue;
+        }
+        changes.push(BulkChange {
+            result: unsafe { change_devnode(&device, retry, enable) },
+            instance_id: controller.instance_id,
+            name: controller.name,
+        });
+    }
+    Ok(changes)
+}
+
+/// Enable or disable the devices the selectors match, in a single pass over
+/// one device information set. See [`select`](super::select) for the order
+/// of the results, selectors that match nothing get [`Error::NotFound`].
+pub fn set_devices_state(selectors: &[DeviceSelector], state: State, scope: Scope, retry: Retry) -> Result<Vec<BulkChange>, Error> {
+    let set = DevInfoSet::open(scope)?;
+    let devices = enum_devices(&set, scope).filter_map(|device| Some((device.instance_id().ok()?, device)));
+    Ok(super::select(selectors, devices)
+        .into_iter()
+        .map(|found| match found {
+            Ok((instance_id, device)) => BulkChange {
+                instance_id,
+                name: device.prop_string(&DEVPKEY_Device_DeviceDesc).ok().flatten().unwrap_or_default(),
+                result: unsafe { change_devnode(&device, retry, state.is_enabled()) },
+            },
+            Err(selector) => BulkChange {
+                instance_id: selector,
+                name: String::new(),
+                result: Err(Error::NotFound),
+            },
+        })
+        .collect())
+}
+
+/// Remove the devnode of a controller that isn't connected anymore.
+/// Connected devices are never touched.
+pub fn remove_phantom_device(id: &str, scope: Scope) -> Result<(), Error> {
+    unsafe {
+        let scope = Scope {
+            include_disconnected: true,
+            ..scope
+        };
+        let set = DevInfoSet::open(scope)?;
+        let device = find_device(&set, scope, id).ok_or(Error::NotFound)?;
+        let present = device.prop_bool(&DEVPKEY_Device_IsPresent)?;
+        if present == Some(true) || device_status_flags(device.devinst())? != CM_DEVNODE_STATUS_FLAGS(0) {
+            return Err(Error::Present);
+        }
+        let result = timed("CM_Uninstall_DevNode", || CM_Uninstall_DevNode(device.devinst(), 0));
+        log::info!(
+            "CM_Uninstall_DevNode(devinst={}) -> {}",
+            device.devinst(),
+            decode::configret(result)
+        );
+        let result = match result {
+            CR_SUCCESS => Ok(()),
+            CR_ACCESS_DENIED => Err(Error::AccessDenied),
+            x => Err(Error::ConfigRet(x)),
+        };
+        audit::record(audit::Action::Remove, id, &result);
+        result
+    }
+}
+
+/// Uninstall the device with given instance id, connected or not, the way
+/// Device Manager's "Uninstall device" does. The devnode comes back on the
+/// next hardware scan if the device is still there.
+pub fn remove_device(id: &str, scope: Scope) -> Result<(), Error> {
+    let set = DevInfoSet::open(scope)?;
+    let device = find_device(&set, scope, id).ok_or(Error::NotFound)?;
+    let result = unsafe { device.remove() };
+    audit::record(audit::Action::Remove, id, &result);
+    result
+}
+
+pub(super) unsafe fn remove_devnode(devinfo: HDEVINFO, devinfo_data: &SP_DEVINFO_DATA) -> Result<(), Error> {
+    let result = timed("SetupDiCallClassInstaller", || {
+        SetupDiCallClassInstaller(DIF_REMOVE, devinfo, Some(devinfo_data))
+    });
+    match &result {
+        Ok(()) => log::info!("SetupDiCallClassInstaller(DIF_REMOVE, devinst={}) -> ok", devinfo_data.DevInst),
+        Err(err) => log::warn!(
+            "SetupDiCallClassInstaller(DIF_REMOVE, devinst={}) -> {}",
+            devinfo_data.DevInst,
+            decode::hresult(err.code())
+        ),
+    }
+    match result {
+        Ok(()) => Ok(()),
+        Err(err) if err.code() == ERROR_ACCESS_DENIED.to_hresult() => Err(Error::AccessDenied),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Enable or disable the device the way Device Manager does, through its
+/// class installer with DIF_PROPERTYCHANGE, in all hardware profiles or the
+/// current one
+pub(super) unsafe fn property_change_devnode(
+    devinfo: HDEVINFO,
+    devinfo_data: &SP_DEVINFO_DATA,
+    enable: bool,
+    profiles: SETUP_DI_PROPERTY_CHANGE_SCOPE,
+) -> Result<(), Error> {
+    let params = SP_PROPCHANGE_PARAMS {
+        ClassInstallHeader: SP_CLASSINSTALL_HEADER {
+            cbSize: size_of::<SP_CLASSINSTALL_HEADER>() as u32,
+            InstallFunction: DIF_PROPERTYCHANGE,
+        },
+        StateChange: if enable { DICS_ENABLE } else { DICS_DISABLE },
+        Scope: profiles,
+        // the current profile
+        HwProfile: 0,
+    };
+    let result = SetupDiSetClassInstallParamsW(
+        devinfo,
+        Some(devinfo_data),
+        Some(&params.ClassInstallHeader),
+        size_of::<SP_PROPCHANGE_PARAMS>() as u32,
+    )
+    .and_then(|()| {
+        timed("SetupDiCallClassInstaller", || {
+            SetupDiCallClassInstaller(DIF_PROPERTYCHANGE, devinfo, Some(devinfo_data))
+        })
+    });
+    match &result {
+        Ok(()) => log::info!(
+            "SetupDiCallClassInstaller(DIF_PROPERTYCHANGE, devinst={}) -> ok",
+            devinfo_data.DevInst
+        ),
+        Err(err) => log::warn!(
+            "SetupDiCallClassInstaller(DIF_PROPERTYCHANGE, devinst={}) -> {}",
+            devinfo_data.DevInst,
+            decode::hresult(err.code())
+        ),
+    }
+    match result {
+        Ok(()) => Ok(()),
+        Err(err) if err.code() == ERROR_ACCESS_DENIED.to_hresult() => Err(Error::AccessDenied),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Scan for hardware changes from the root of the device tree, so that
+/// removed devices that are still there get detected again. Returns when the
+/// scan is finished.
+pub fn rescan() -> Result<(), Error> {
+    unsafe {
+        let mut root = 0;
+        let result = CM_Locate_DevNodeW(&mut root, PCWSTR::null(), CM_LOCATE_DEVNODE_NORMAL);
+        log::debug!("CM_Locate_DevNodeW(root) -> {} devinst={}", decode::configret(result), root);
+        if result != CR_SUCCESS {
+            return Err(Error::ConfigRet(result));
+        }
+        let result = timed("CM_Reenumerate_DevNode", || {
+            CM_Reenumerate_DevNode(root, CM_REENUMERATE_SYNCHRONOUS)
+        });
+        log::info!("CM_Reenumerate_DevNode(devinst={}) -> {}", root, decode::configret(result));
+        match result {
+            CR_SUCCESS => Ok(()),
+            CR_ACCESS_DENIED => Err(Error::AccessDenied),
+            x => Err(Error::ConfigRet(x)),
+        }
+    }
+}
+
+/// Whether a failed CM_xx call might succeed when tried again shortly after:
+/// when an application still holds the device open, or when the generic
+/// CR_FAILURE comes from a device whose removal is still pending. Any other
+/// CR_FAILURE will fail the same way again.
+fn is_retryable(result: CONFIGRET, (flags, problem): (CM_DEVNODE_STATUS_FLAGS, CM_PROB)) -> bool {
+    match result {
+        CR_REMOVE_VETOED => true,
+        CR_FAILURE => (flags & DN_WILL_BE_REMOVED).0 != 0 || problem == CM_PROB_WILL_BE_REMOVED,
+        _ => false,
+    }
+}
+
+fn find_device<'a>(set: &'a DevInfoSet, scope: Scope, id: &str) -> Option<DeviceRef<'a>> {
+    enum_devices(set, scope).find(|d| d.instance_id().is_ok_and(|i| i.eq_ignore_ascii_case(id)))
+}
+
+/// HID devices of the scope's kind in given set, or all of its devices if a
+/// specific class or interface was requested
+fn enum_devices(set: &DevInfoSet, scope: Scope) -> impl Iterator<Item = DeviceRef<'_>> {
+    set.iter().filter(move |d| in_scope(d, scope))
+}
+
+fn in_scope(device: &DeviceRef, scope: Scope) -> bool {
+    if scope.class.is_some() || scope.interface.is_some() {
+        return true;
+    }
+    let matched = device
+        .prop_string_list(&DEVPKEY_Device_HardwareIds)
+        .is_ok_and(|ids| ids.is_some_and(|ids| scope.kind.matches(&ids)));
+    if !matched {
+        log::debug!("devinst={} skipped, not a {}", device.devinst(), scope.kind.name());
+    }
+    matched
+}
+
+/// The devices of [`game_controllers`] one at a time, so that a search can
+/// stop at the first match without reading the properties of the rest.
+/// XInput slots and batteries aren't assigned, that needs all controllers.
+///
+/// ```no_run
+/// use nojoy_rs::devenum::{DevInfoSet, GameControllers, Scope};
+///
+/// let scope = Scope::default();
+/// let set = DevInfoSet::open(scope).unwrap();
+/// let xbox = GameControllers::new(&set, scope).find(|c| c.name.contains("Xbox"));
+/// ```
+pub struct GameControllers<'a> {
+    devices: SetupDiEnum<'a>,
+    scope: Scope,
+}
+
+impl<'a> GameControllers<'a> {
+    /// Controllers in `set`, which should be opened with the same scope
+    pub fn new(set: &'a DevInfoSet, scope: Scope) -> Self {
+        Self {
+            devices: set.iter(),
+            scope,
+        }
+    }
+}
+
+impl Iterator for GameControllers<'_> {
+    type Item = GameController;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let scope = self.scope;
+        self.devices.by_ref().filter(|d| in_scope(d, scope)).find_map(|d| {
+            GameController::try_from_device(&d)
+                .inspect_err(|err| log::debug!("devinst={} skipped, {}", d.devinst(), err))
+                .ok()
+        })
+    }
+}
+
+/// HID devices of the scope's kind, game controllers by default, or every
+/// device of the scope's class or interface
+pub fn game_controllers(scope: Scope) -> Result<Vec<GameController>, Error> {
+    let set = DevInfoSet::open(scope)?;
+    let mut result: Vec<GameController> = GameControllers::new(&set, scope).collect();
+    xinput::assign_slots(&mut result);
+    Ok(result)
+}
+
+/// Controllers that have a devnode but aren't connected
+pub fn phantom_game_controllers() -> Result<Vec<GameController>, Error> {
+    phantom_devices(Scope::default())
+}
+
+/// Devices in given scope that have a devnode but aren't connected
+pub fn phantom_devices(scope: Scope) -> Result<Vec<GameController>, Error> {
+    let scope = Scope {
+        include_disconnected: true,
+        ..scope
+    };
+    let mut result = game_controllers(scope)?;
+    result.retain(|c| c.status == GameControllerStatus::Disconnected);
+    Ok(result)
+}
+
+/// Names of the services (drivers) of all present devices in the system
+pub fn device_services() -> Result<Vec<String>, Error> {
+    let set = unsafe {
+        DevInfoSet::from_handle(SetupDiGetClassDevsW(
+            None,
+            PCWSTR::null(),
+            HWND::default(),
+            DIGCF_ALLCLASSES | DIGCF_PRESENT,
+        )?)
+    };
+    let mut result: Vec<String> = set
+        .iter()
+        .filter_map(|d| d.prop_string(&DEVPKEY_Device_Service).ok().flatten())
+        .collect();
+    result.sort_unstable();
+    result.dedup();
+    Ok(result)
+}
+
+/// Resolve a device setup class given either as a GUID or as a class name
+/// like "Mouse" or "MEDIA"
+pub fn class_guid(class: &str) -> Result<GUID, Error> {
+    if class.starts_with('{') || class.contains('-') {
+        return parse_guid(class).ok_or_else(|| Error::InvalidClassGuid(class.to_string()));
+    }
+    let name = HSTRING::from(class);
+    let mut guids = PropBuffer::<GUID>::new();
+    // more than one class can have the same name
+    let guids = guids.read(|buf, required| unsafe { SetupDiClassGuidsFromNameW(&name, buf, required) })?;
+    guids.first().copied().ok_or_else(|| Error::UnknownClass(class.to_string()))
+}
+
+/// Device interfaces that can be given by name instead of GUID
+const INTERFACE_NAMES: &[(&str, u128)] = &[
+    ("keyboard", 0x884b96c3_56ef_11d1_bc8c_00a0c91405dd),
+    ("mouse", 0x378de44c_56ef_11d1_bc8c_00a0c91405dd),
+];
+
+/// Resolve a device interface class given either as a GUID or as one of
+/// "hid", "keyboard" or "mouse"
+pub fn interface_guid(interface: &str) -> Result<GUID, Error> {
+    if interface.eq_ignore_ascii_case("hid") {
+        return Ok(unsafe { HidD_GetHidGuid() });
+    }
+    INTERFACE_NAMES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(interface))
+        .map(|(_, guid)| GUID::from_u128(*guid))
+        .or_else(|| parse_guid(interface))
+        .ok_or_else(|| Error::UnknownInterface(interface.to_string()))
+}
+
+/// Parse a GUID in "{xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx}" form, braces optional
+fn parse_guid(s: &str) -> Option<GUID> {
+    let s = s.strip_prefix('{').and_then(|s| s.strip_suffix('}')).unwrap_or(s);
+    let groups: Vec<&str> = s.split('-').collect();
+    let lengths: Vec<usize> = groups.iter().map(|g| g.len()).collect();
+    if lengths != [8, 4, 4, 4, 12] || !s.chars().all(|c| c == '-' || c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u128::from_str_radix(&groups.concat(), 16)
+        .ok()
+        .map(GUID::from_u128)
+}
+
+pub(super) unsafe fn devinfo_scope(scope: Scope) -> Result<HDEVINFO, windows::core::Error> {
+    let result = timed("SetupDiGetClassDevsW", || devinfo_scope_raw(scope));
+    match &result {
+        Ok(_) => log::debug!("SetupDiGetClassDevsW({:?}) -> ok", scope),
+        Err(err) => log::warn!(
+            "SetupDiGetClassDevsW({:?}) -> {}",
+            scope,
+            decode::hresult(err.code())
+        ),
+    }
+    result
+}
+
+unsafe fn devinfo_scope_raw(scope: Scope) -> Result<HDEVINFO, windows::core::Error> {
+    if let Some(class) = scope.class {
+        return dev_info_class(class, scope.include_disconnected);
+    }
+    if let Some(interface) = scope.interface {
+        return dev_info_interface(interface, scope.include_disconnected);
+    }
+    if scope.include_disconnected {
+        // phantom devnodes have no active interfaces, so we have to walk
+        // every device and rely on the hardware id filter instead
+        return dev_info_all();
+    }
+    dev_info(HidD_GetHidGuid())
+}
+
+/// returns a HDEVINFO for given class GUID of a device
+unsafe fn dev_info(guid: windows::core::GUID) -> Result<HDEVINFO, windows::core::Error> {
+    SetupDiGetClassDevsW(
+        Some(&guid),
+        PCWSTR::null(),
+        HWND::default(),
+        DIGCF_DEVICEINTERFACE,
+    )
+}
+
+/// returns a HDEVINFO for devices of given setup class
+unsafe fn dev_info_class(
+    class: GUID,
+    include_disconnected: bool,
+) -> Result<HDEVINFO, windows::core::Error> {
+    let flags = match include_disconnected {
+        true => SETUP_DI_GET_CLASS_DEVS_FLAGS(0),
+        false => DIGCF_PRESENT,
+    };
+    SetupDiGetClassDevsW(Some(&class), PCWSTR::null(), HWND::default(), flags)
+}
+
+/// returns a HDEVINFO for devices that expose given interface class
+unsafe fn dev_info_interface(
+    interface: GUID,
+    include_disconnected: bool,
+) -> Result<HDEVINFO, windows::core::Error> {
+    let flags = match include_disconnected {
+        true => DIGCF_DEVICEINTERFACE,
+        false => DIGCF_DEVICEINTERFACE | DIGCF_PRESENT,
+    };
+    SetupDiGetClassDevsW(Some(&interface), PCWSTR::null(), HWND::default(), flags)
+}
+
+/// returns a HDEVINFO for all devices in the system, including the ones
+/// that aren't present
+unsafe fn dev_info_all() -> Result<HDEVINFO, windows::core::Error> {
+    SetupDiGetClassDevsW(None, PCWSTR::null(), HWND::default(), DIGCF_ALLCLASSES)
+}
+
+unsafe fn device_status_flags(devinst: u32) -> Result<CM_DEVNODE_STATUS_FLAGS, Error> {
+    device_status(devinst).map(|(flags, _)| flags)
+}
+
+/// Returns both the status flags and the problem code of a devnode
+pub(super) unsafe fn device_status(devinst: u32) -> Result<(CM_DEVNODE_STATUS_FLAGS, CM_PROB), Error> {
+    let mut flags: CM_DEVNODE_STATUS_FLAGS = CM_DEVNODE_STATUS_FLAGS(0);
+    let mut problem: CM_PROB = CM_PROB(0);
+    let result = timed("CM_Get_DevNode_Status", || {
+        CM_Get_DevNode_Status(&mut flags, &mut problem, devinst, 0 /* must be zero */)
+    });
+    log::debug!(
+        "CM_Get_DevNode_Status(devinst={}) -> {} flags={:#x} ({}) problem={}",
+        devinst,
+        decode::configret(result),
+        flags.0,
+        decode::status_flags(flags),
+        problem.0
+    );
+    match result {
+        CR_SUCCESS => Ok((flags, problem)),
+        CR_NO_SUCH_DEVNODE => Ok((CM_DEVNODE_STATUS_FLAGS(0), CM_PROB(0))),
+        x => Err(Error::ConfigRet(x)),
+    }
+}
+
+pub(super) unsafe fn device_instance_id(
+    devinfo: HDEVINFO,
+    devinfo_data: &SP_DEVINFO_DATA,
+    buffer: &mut PropBuffer<u16>,
+) -> Result<String, Error> {
+    let wide = buffer.read(|buf, required| {
+        timed("SetupDiGetDeviceInstanceIdW", || {
+            SetupDiGetDeviceInstanceIdW(devinfo, devinfo_data, Some(buf), Some(required))
+        })
+    })?;
+
+    // the required size includes the null terminator
+    let end = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+    let id = String::from_utf16_lossy(&wide[..end]);
+    log::debug!(
+        "SetupDiGetDeviceInstanceIdW(devinst={}) -> {} chars: {}",
+        devinfo_data.DevInst,
+        wide.len(),
+        id
+    );
+    Ok(id)
+}
+
+/// Run a Win32 call, logging how long it took at the highest verbosity
+pub(super) fn timed<T>(call: &str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    log::trace!("{} took {:?}", call, start.elapsed());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::Devices::DeviceAndDriverInstallation::{
+        CM_PROB_DISABLED, CR_INVALID_DEVNODE, DN_DISABLEABLE, DN_DRIVER_LOADED, DN_HAS_PROBLEM,
+        DN_REMOVABLE, DN_STARTED,
+    };
+
+    use super::*;
+
+    const STARTED: (CM_DEVNODE_STATUS_FLAGS, CM_PROB) = (DN_STARTED, CM_PROB(0));
+
+    #[test]
+    fn held_open_devices_are_retried() {
+        assert!(is_retryable(CR_REMOVE_VETOED, STARTED));
+    }
+
+    #[test]
+    fn generic_failure_is_retried_only_while_removal_is_pending() {
+        assert!(!is_retryable(CR_FAILURE, STARTED));
+        assert!(!is_retryable(CR_FAILURE, (DN_DISABLEABLE, CM_PROB_DISABLED)));
+        assert!(is_retryable(CR_FAILURE, (DN_STARTED | DN_WILL_BE_REMOVED, CM_PROB(0))));
+        assert!(is_retryable(CR_FAILURE, (CM_DEVNODE_STATUS_FLAGS(0), CM_PROB_WILL_BE_REMOVED)));
+    }
+
+    #[test]
+    fn other_failures_are_not_retried() {
+        let removing = (DN_WILL_BE_REMOVED, CM_PROB_WILL_BE_REMOVED);
+        for result in [CR_ACCESS_DENIED, CR_NO_SUCH_DEVNODE, CR_INVALID_DEVNODE, CR_QUERY_VETOED] {
+            assert!(!is_retryable(result, removing), "{}", decode::configret(result));
+        }
+    }
+
+    #[test]
+    fn plan_for_missing_devnode() {
+        let none = CM_DEVNODE_STATUS_FLAGS(0);
+        assert_eq!(plan_change(none, true), Plan::NotPresent);
+        assert_eq!(plan_change(none, false), Plan::NotPresent);
+    }
+
+    #[test]
+    fn plan_for_started_device() {
+        let started = DN_DRIVER_LOADED | DN_STARTED | DN_DISABLEABLE | DN_REMOVABLE;
+        assert_eq!(plan_change(started, true), Plan::AlreadyInState);
+        assert_eq!(plan_change(started, false), Plan::Change);
+    }
+
+    #[test]
+    fn plan_for_disabled_device() {
+        let disabled = DN_DISABLEABLE | DN_HAS_PROBLEM;
+        assert_eq!(plan_change(disabled, true), Plan::Change);
+        assert_eq!(plan_change(disabled, false), Plan::AlreadyInState);
+    }
+
+    #[test]
+    fn plan_for_device_that_cant_be_disabled() {
+        let started = DN_DRIVER_LOADED | DN_STARTED;
+        assert_eq!(plan_change(started, false), Plan::NotDisableable);
+        assert_eq!(plan_change(started, true), Plan::AlreadyInState);
+        // a stopped one can still be enabled
+        assert_eq!(plan_change(DN_DRIVER_LOADED, true), Plan::Change);
+        assert_eq!(plan_change(DN_DRIVER_LOADED, false), Plan::AlreadyInState);
+    }
+}
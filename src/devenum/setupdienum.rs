@@ -1,21 +1,193 @@
-use windows::Win32::Devices::DeviceAndDriverInstallation::SetupDiEnumDeviceInfo;
+use core::{cell::RefCell, mem::size_of};
 
-use core::mem::size_of;
+use windows::{
+    core::{GUID, HSTRING},
+    Win32::{
+        Devices::{
+            DeviceAndDriverInstallation::{
+                SetupDiCreateDeviceInfoList, SetupDiDestroyDeviceInfoList, SetupDiEnumDeviceInfo,
+                SetupDiOpenDevRegKey, SetupDiOpenDeviceInfoW, CM_DEVNODE_STATUS_FLAGS, CM_PROB,
+                DICS_FLAG_GLOBAL, DIREG_DEV, HDEVINFO, SETUP_DI_PROPERTY_CHANGE_SCOPE,
+                SP_DEVINFO_DATA,
+            },
+            Properties::DEVPROPKEY,
+        },
+        Foundation::HWND,
+        System::Registry::{HKEY, REG_SAM_FLAGS},
+    },
+};
+
+use super::{devprop, propbuffer::PropBuffer, Error, PropertyValue, Scope};
+use crate::{decode, timestamp::Timestamp};
+
+/// A SetupDi device information set, destroyed when dropped.
+///
+/// Devices enumerated from the set borrow it, so they can't be used after
+/// the set is gone:
+///
+// Only a borrow error where the devenum items exist, anywhere else it'd fail
+// for the wrong reason
+#[cfg_attr(windows, doc = "```compile_fail")]
+#[cfg_attr(not(windows), doc = "```ignore")]
+/// use nojoy_rs::devenum::{DevInfoSet, Scope};
+///
+/// let device = {
+///     let set = DevInfoSet::open(Scope::default()).unwrap();
+///     set.iter().next().unwrap()
+/// }; // `set` is destroyed here
+/// println!("{:?}", device.instance_id());
+/// ```
+pub struct DevInfoSet {
+    handle: HDEVINFO,
+    /// Reused by every property read on devices of the set
+    props: RefCell<PropBuffer<u8>>,
+    /// Reused by every instance id read on devices of the set
+    ids: RefCell<PropBuffer<u16>>,
+}
+
+impl DevInfoSet {
+    /// Open the devices in given scope
+    pub fn open(scope: Scope) -> Result<Self, Error> {
+        let handle = unsafe { super::setupapi::devinfo_scope(scope) };
+        log::debug!("SetupDiGetClassDevsW({:?}) -> {:?}", scope, handle);
+        match handle {
+            Ok(handle) => Ok(unsafe { Self::from_handle(handle) }),
+            Err(err) => Err(Error::from(err).context("can't enumerate devices")),
+        }
+    }
+
+    /// Open the one present device with given instance id, whatever its
+    /// class is, like the USB parent of a controller
+    pub fn open_device(id: &str) -> Result<Self, Error> {
+        unsafe {
+            let handle = SetupDiCreateDeviceInfoList(None, HWND::default())?;
+            let set = Self::from_handle(handle);
+            let result = SetupDiOpenDeviceInfoW(set.handle, &HSTRING::from(id), HWND::default(), 0, None);
+            log::debug!("SetupDiOpenDeviceInfoW({}) -> {:?}", id, result);
+            match result {
+                Ok(()) => Ok(set),
+                Err(_) => Err(Error::NotFound),
+            }
+        }
+    }
+
+    /// Take ownership of a handle returned by SetupDiGetClassDevsW
+    pub(crate) unsafe fn from_handle(handle: HDEVINFO) -> Self {
+        Self {
+            handle,
+            props: RefCell::new(PropBuffer::new()),
+            ids: RefCell::new(PropBuffer::new()),
+        }
+    }
+
+    pub fn iter(&self) -> SetupDiEnum<'_> {
+        SetupDiEnum::new(self)
+    }
+}
+
+impl Drop for DevInfoSet {
+    fn drop(&mut self) {
+        unsafe {
+            // nothing sensible to do if this fails
+            let _ = SetupDiDestroyDeviceInfoList(self.handle);
+        }
+    }
+}
+
+/// A device in a [`DevInfoSet`]. Reading devices doesn't need elevation, so
+/// this runs on any machine, with or without controllers:
+///
+/// ```
+/// use nojoy_rs::devenum::{DevInfoSet, Scope};
+///
+/// let set = DevInfoSet::open(Scope::default()).unwrap();
+/// for device in set.iter() {
+///     let id = device.instance_id().unwrap();
+///     assert!(!id.is_empty());
+///     assert_ne!(device.devinst(), 0, "{}", id);
+/// }
+/// ```
+#[derive(Clone, Copy)]
+pub struct DeviceRef<'a> {
+    set: &'a DevInfoSet,
+    data: SP_DEVINFO_DATA,
+}
 
-use windows::Win32::Devices::DeviceAndDriverInstallation::SP_DEVINFO_DATA;
+impl DeviceRef<'_> {
+    /// Devnode handle for CM_xx functions
+    pub fn devinst(&self) -> u32 {
+        self.data.DevInst
+    }
+
+    pub fn instance_id(&self) -> Result<String, Error> {
+        unsafe { super::setupapi::device_instance_id(self.set.handle, &self.data, &mut self.set.ids.borrow_mut()) }
+    }
+
+    /// Devnode status flags and problem code
+    pub fn status(&self) -> Result<(CM_DEVNODE_STATUS_FLAGS, CM_PROB), Error> {
+        unsafe { super::setupapi::device_status(self.data.DevInst) }
+    }
+
+    /// Any property, whatever its type is
+    pub fn prop(&self, key: &DEVPROPKEY) -> Result<Option<PropertyValue>, Error> {
+        unsafe { devprop::get_any(self.set.handle, &self.data, key, &mut self.set.props.borrow_mut()) }
+    }
+
+    pub fn prop_string(&self, key: &DEVPROPKEY) -> Result<Option<String>, Error> {
+        unsafe { devprop::get_string(self.set.handle, &self.data, key, &mut self.set.props.borrow_mut()) }
+    }
+
+    pub fn prop_string_list(&self, key: &DEVPROPKEY) -> Result<Option<Vec<String>>, Error> {
+        unsafe { devprop::get_string_list(self.set.handle, &self.data, key, &mut self.set.props.borrow_mut()) }
+    }
+
+    pub fn prop_u32(&self, key: &DEVPROPKEY) -> Result<Option<u32>, Error> {
+        unsafe { devprop::get_u32(self.set.handle, &self.data, key, &mut self.set.props.borrow_mut()) }
+    }
 
-use windows::Win32::Devices::DeviceAndDriverInstallation::HDEVINFO;
+    pub fn prop_guid(&self, key: &DEVPROPKEY) -> Result<Option<GUID>, Error> {
+        unsafe { devprop::get_guid(self.set.handle, &self.data, key, &mut self.set.props.borrow_mut()) }
+    }
+
+    pub fn prop_bool(&self, key: &DEVPROPKEY) -> Result<Option<bool>, Error> {
+        unsafe { devprop::get_bool(self.set.handle, &self.data, key, &mut self.set.props.borrow_mut()) }
+    }
+
+    pub fn prop_filetime(&self, key: &DEVPROPKEY) -> Result<Option<Timestamp>, Error> {
+        unsafe { devprop::get_filetime(self.set.handle, &self.data, key, &mut self.set.props.borrow_mut()) }
+    }
+
+    /// Enable or disable the device through its class installer
+    pub(super) unsafe fn change_state(&self, enable: bool, profiles: SETUP_DI_PROPERTY_CHANGE_SCOPE) -> Result<(), Error> {
+        super::setupapi::property_change_devnode(self.set.handle, &self.data, enable, profiles)
+    }
+
+    /// The hardware key of the device, where its `Device Parameters` are.
+    /// The caller closes it.
+    pub(crate) fn open_hardware_key(&self, access: REG_SAM_FLAGS) -> Result<HKEY, Error> {
+        let result = unsafe {
+            SetupDiOpenDevRegKey(self.set.handle, &self.data, DICS_FLAG_GLOBAL.0, 0, DIREG_DEV, access.0)
+        };
+        log::debug!("SetupDiOpenDevRegKey(devinst={}) -> {:?}", self.data.DevInst, result.as_ref().map(|_| ()));
+        Ok(result?)
+    }
+
+    /// Uninstall the device through its class installer
+    pub(super) unsafe fn remove(&self) -> Result<(), Error> {
+        super::setupapi::remove_devnode(self.set.handle, &self.data)
+    }
+}
 
-pub(crate) struct SetupDiEnum {
-    pub(crate) devinfo: HDEVINFO,
-    pub(crate) data: SP_DEVINFO_DATA,
-    pub(crate) index: u32,
+pub struct SetupDiEnum<'a> {
+    set: &'a DevInfoSet,
+    data: SP_DEVINFO_DATA,
+    index: u32,
 }
 
-impl SetupDiEnum {
-    pub fn new(devinfo: HDEVINFO) -> SetupDiEnum {
+impl<'a> SetupDiEnum<'a> {
+    pub fn new(set: &'a DevInfoSet) -> SetupDiEnum<'a> {
         SetupDiEnum {
-            devinfo: devinfo,
+            set,
             data: SP_DEVINFO_DATA {
                 cbSize: size_of::<SP_DEVINFO_DATA>() as u32,
                 ..Default::default()
@@ -25,17 +197,25 @@ impl SetupDiEnum {
     }
 }
 
-impl Iterator for SetupDiEnum {
-    type Item = SP_DEVINFO_DATA;
+impl<'a> Iterator for SetupDiEnum<'a> {
+    type Item = DeviceRef<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
         unsafe {
-            let result = SetupDiEnumDeviceInfo(self.devinfo, self.index, &mut self.data);
+            let result = SetupDiEnumDeviceInfo(self.set.handle, self.index, &mut self.data);
+            log::trace!(
+                "SetupDiEnumDeviceInfo(index={}) -> {}",
+                self.index,
+                result.as_ref().map_or_else(|e| decode::hresult(e.code()), |_| "ok".to_string())
+            );
             if result.is_ok() {
                 self.index += 1;
-                return Some(self.data);
+                return Some(DeviceRef {
+                    set: self.set,
+                    data: self.data,
+                });
             }
         }
-        return None;
+        None
     }
 }
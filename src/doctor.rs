@@ -0,0 +1,296 @@
+/// Environment and permission diagnostics
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::fmt;
+
+use serde::Serialize;
+
+use nojoy_rs::devenum::{device_services, game_controllers, Error, GameController, Scope};
+use nojoy_rs::elevation::is_elevated;
+
+/// CM_PROB_NEED_RESTART
+const PROBLEM_NEED_RESTART: u32 = 14;
+
+/// DN_NEED_RESTART
+const STATUS_NEED_RESTART: u32 = 0x100;
+
+/// Services of virtual controller drivers that are known to interfere with
+/// controller toggling, and what they belong to.
+const CONFLICTING_SERVICES: &[(&str, &str)] = &[
+    ("ViGEmBus", "ViGEm virtual gamepad bus"),
+    ("vjoy", "vJoy virtual joystick"),
+    ("HidHide", "HidHide device filter"),
+];
+
+/// Result of a single diagnostic check
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    /// Whether a failure means the tool can't do its job
+    pub critical: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, passed: bool, critical: bool, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            passed,
+            critical,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Run all checks against the live system
+pub fn run() -> Vec<Check> {
+    let mut checks = vec![check_elevated(is_elevated())];
+    let controllers = game_controllers(Scope::default());
+    checks.push(check_enumeration(&controllers));
+    if let Ok(controllers) = &controllers {
+        checks.push(check_controllers_found(controllers));
+        checks.extend(controllers.iter().map(check_problem));
+        checks.extend(controllers.iter().map(check_disableable));
+        checks.push(check_pending_reboot(controllers));
+    }
+    checks.extend(check_conflicting_drivers(&device_services()));
+    checks
+}
+
+/// The names of the critical checks that failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CriticalFailure(pub Vec<String>);
+
+impl fmt::Display for CriticalFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "critical checks failed: {}", self.0.join(", "))
+    }
+}
+
+/// Fails if any critical check has failed
+pub fn verdict(checks: &[Check]) -> Result<(), CriticalFailure> {
+    let failed: Vec<String> = checks
+        .iter()
+        .filter(|c| c.critical && !c.passed)
+        .map(|c| c.name.clone())
+        .collect();
+    match failed.is_empty() {
+        true => Ok(()),
+        false => Err(CriticalFailure(failed)),
+    }
+}
+
+pub fn check_elevated(elevated: Result<bool, windows::core::Error>) -> Check {
+    match elevated {
+        Ok(true) => Check::new("elevated", true, true, "running as administrator"),
+        Ok(false) => Check::new(
+            "elevated",
+            false,
+            true,
+            "not running as administrator, enable/disable will fail",
+        ),
+        Err(err) => Check::new("elevated", false, true, format!("can't query elevation: {}", err)),
+    }
+}
+
+pub fn check_enumeration(controllers: &Result<Vec<GameController>, Error>) -> Check {
+    match controllers {
+        Ok(_) => Check::new("enumeration", true, true, "HID devices can be enumerated"),
+        Err(err) => Check::new(
+            "enumeration",
+            false,
+            true,
+            format!("HID enumeration failed: {}", err),
+        ),
+    }
+}
+
+pub fn check_controllers_found(controllers: &[GameController]) -> Check {
+    Check::new(
+        "controllers",
+        !controllers.is_empty(),
+        false,
+        format!("{} game controller(s) found", controllers.len()),
+    )
+}
+
+pub fn check_problem(controller: &GameController) -> Check {
+    let name = format!("problem: {}", controller.instance_id);
+    match controller.problem_code {
+        0 => Check::new(name, true, false, format!("{} has no problems", controller.name)),
+        code => Check::new(
+            name,
+            false,
+            false,
+            format!("{} reports problem code {}", controller.name, code),
+        ),
+    }
+}
+
+pub fn check_disableable(controller: &GameController) -> Check {
+    let name = format!("disableable: {}", controller.instance_id);
+    match controller.disableable {
+        true => Check::new(name, true, false, format!("{} can be disabled", controller.name)),
+        false => Check::new(name, false, false, format!("{} can't be disabled", controller.name)),
+    }
+}
+
+pub fn check_pending_reboot(controllers: &[GameController]) -> Check {
+    let pending: Vec<&str> = controllers
+        .iter()
+        .filter(|c| {
+            c.problem_code == PROBLEM_NEED_RESTART || c.status_flags & STATUS_NEED_RESTART != 0
+        })
+        .map(|c| c.instance_id.as_str())
+        .collect();
+    match pending.is_empty() {
+        true => Check::new("reboot", true, false, "no device is waiting for a reboot"),
+        false => Check::new(
+            "reboot",
+            false,
+            false,
+            format!("reboot required for: {}", pending.join(", ")),
+        ),
+    }
+}
+
+pub fn check_conflicting_drivers(services: &Result<Vec<String>, Error>) -> Vec<Check> {
+    let services = match services {
+        Ok(services) => services,
+        Err(err) => {
+            return vec![Check::new(
+                "drivers",
+                false,
+                false,
+                format!("can't enumerate device drivers: {}", err),
+            )]
+        }
+    };
+    CONFLICTING_SERVICES
+        .iter()
+        .map(|(service, description)| {
+            let found = services.iter().any(|s| s.eq_ignore_ascii_case(service));
+            let name = format!("driver: {}", service);
+            match found {
+                true => Check::new(name, false, false, format!("{} is installed", description)),
+                false => Check::new(name, true, false, format!("{} is not installed", description)),
+            }
+        })
+        .collect()
+}
+
+/// Render checks as one line per check
+pub fn render_text(checks: &[Check]) -> String {
+    checks
+        .iter()
+        .map(|c| {
+            let mark = if c.passed { "PASS" } else { "FAIL" };
+            format!("[{}] {}: {}\n", mark, c.name, c.detail)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use nojoy_rs::provider::{DeviceProvider, FakeProvider};
+    use windows::Win32::Foundation::E_ACCESSDENIED;
+
+    use super::*;
+
+    fn controllers() -> Vec<GameController> {
+        FakeProvider::sample().controllers().unwrap()
+    }
+
+    #[test]
+    fn elevation() {
+        assert!(check_elevated(Ok(true)).passed);
+        let check = check_elevated(Ok(false));
+        assert!(!check.passed && check.critical);
+        let check = check_elevated(Err(E_ACCESSDENIED.into()));
+        assert!(!check.passed && check.critical);
+        assert!(check.detail.starts_with("can't query elevation"));
+    }
+
+    #[test]
+    fn enumeration() {
+        assert!(check_enumeration(&Ok(Vec::new())).passed);
+        let check = check_enumeration(&Err(Error::AccessDenied));
+        assert!(!check.passed && check.critical);
+    }
+
+    #[test]
+    fn no_controllers_is_not_critical() {
+        let check = check_controllers_found(&[]);
+        assert!(!check.passed && !check.critical);
+        assert!(check_controllers_found(&controllers()).passed);
+    }
+
+    #[test]
+    fn problem_codes_fail() {
+        let mut controller = controllers().remove(0);
+        assert!(check_problem(&controller).passed);
+        controller.problem_code = 22;
+        let check = check_problem(&controller);
+        assert!(!check.passed);
+        assert_eq!(check.detail, "Xbox Wireless Controller reports problem code 22");
+    }
+
+    #[test]
+    fn controllers_that_cant_be_disabled_fail() {
+        let results: Vec<bool> = controllers().iter().map(|c| check_disableable(c).passed).collect();
+        assert_eq!(results, [true, true, false, true]);
+    }
+
+    #[test]
+    fn pending_reboot_is_found_by_problem_or_status() {
+        let mut controllers = controllers();
+        assert!(check_pending_reboot(&controllers).passed);
+        controllers[1].problem_code = PROBLEM_NEED_RESTART;
+        controllers[2].status_flags = STATUS_NEED_RESTART | 0x8;
+        let check = check_pending_reboot(&controllers);
+        assert!(!check.passed);
+        let ids = format!("{}, {}", controllers[1].instance_id, controllers[2].instance_id);
+        assert_eq!(check.detail, format!("reboot required for: {}", ids));
+    }
+
+    #[test]
+    fn conflicting_drivers_are_found_ignoring_case() {
+        let services = Ok(vec!["HidUsb".to_string(), "vigembus".to_string()]);
+        let failed: Vec<String> = check_conflicting_drivers(&services)
+            .into_iter()
+            .filter(|c| !c.passed)
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(failed, ["driver: ViGEmBus"]);
+    }
+
+    #[test]
+    fn unreadable_drivers_are_one_failed_check() {
+        let checks = check_conflicting_drivers(&Err(Error::AccessDenied));
+        assert_eq!(checks.len(), 1);
+        assert!(!checks[0].passed && !checks[0].critical);
+    }
+
+    #[test]
+    fn only_critical_failures_fail_the_verdict() {
+        let mut checks = vec![check_elevated(Ok(true)), check_controllers_found(&[])];
+        assert_eq!(verdict(&checks), Ok(()));
+        checks.push(check_enumeration(&Err(Error::AccessDenied)));
+        checks.push(check_elevated(Ok(false)));
+        assert_eq!(
+            verdict(&checks),
+            Err(CriticalFailure(vec!["enumeration".to_string(), "elevated".to_string()]))
+        );
+    }
+
+    #[test]
+    fn text_has_a_line_per_check() {
+        let checks = [check_elevated(Ok(true)), check_controllers_found(&[])];
+        assert_eq!(
+            render_text(&checks),
+            "[PASS] elevated: running as administrator\n\
+             [FAIL] controllers: 0 game controller(s) found\n"
+        );
+    }
+}
@@ -0,0 +1,105 @@
+/// Process elevation (UAC) queries, and running ourselves again as
+/// administrator
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use core::{ffi::c_void, mem::size_of};
+
+use windows::{
+    core::{w, HRESULT, HSTRING, PCWSTR},
+    Win32::{
+        Foundation::{CloseHandle, ERROR_PATH_NOT_FOUND, HANDLE},
+        Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY},
+        System::Threading::{
+            GetCurrentProcess, GetExitCodeProcess, OpenProcessToken, WaitForSingleObject, INFINITE,
+        },
+        UI::{
+            Shell::{ShellExecuteExW, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW},
+            WindowsAndMessaging::SW_SHOWNORMAL,
+        },
+    },
+};
+
+/// Whether the current process runs with administrative privileges
+pub fn is_elevated() -> Result<bool, windows::core::Error> {
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)?;
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut size = 0;
+        let result = GetTokenInformation(
+            token,
+            TokenElevation,
+            Some(&mut elevation as *mut TOKEN_ELEVATION as *mut c_void),
+            size_of::<TOKEN_ELEVATION>() as u32,
+            &mut size,
+        );
+        CloseHandle(token)?;
+        result?;
+        Ok(elevation.TokenIsElevated != 0)
+    }
+}
+
+/// Quote an argument so that CommandLineToArgvW gives it back as is
+pub fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted += &"\\".repeat(backslashes * 2 + 1);
+                backslashes = 0;
+            }
+            _ => {
+                quoted += &"\\".repeat(backslashes);
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+    quoted += &"\\".repeat(backslashes * 2);
+    quoted.push('"');
+    quoted
+}
+
+/// Run this executable again with the same arguments after the UAC prompt,
+/// wait for it and return its exit code. Its output goes to a console window
+/// of its own.
+pub fn relaunch_elevated() -> Result<i32, windows::core::Error> {
+    let exe = std::env::current_exe().map_err(|e| {
+        let code = e
+            .raw_os_error()
+            .map_or(ERROR_PATH_NOT_FOUND.0, |code| code as u32);
+        windows::core::Error::from(HRESULT::from_win32(code))
+    })?;
+    #[cfg(windows)]
+    let exe = HSTRING::from(exe.as_os_str());
+    // the library also builds for the Linux backend, where this isn't called
+    #[cfg(not(windows))]
+    let exe = HSTRING::from(exe.to_string_lossy().as_ref());
+    let params: Vec<String> = std::env::args().skip(1).map(|arg| quote(&arg)).collect();
+    let params = HSTRING::from(params.join(" "));
+    let mut info = SHELLEXECUTEINFOW {
+        cbSize: size_of::<SHELLEXECUTEINFOW>() as u32,
+        fMask: SEE_MASK_NOCLOSEPROCESS,
+        lpVerb: w!("runas"),
+        lpFile: PCWSTR(exe.as_ptr()),
+        lpParameters: PCWSTR(params.as_ptr()),
+        nShow: SW_SHOWNORMAL.0,
+        ..Default::default()
+    };
+    unsafe {
+        ShellExecuteExW(&mut info)?;
+        WaitForSingleObject(info.hProcess, INFINITE);
+        let mut code = 0;
+        let result = GetExitCodeProcess(info.hProcess, &mut code);
+        CloseHandle(info.hProcess)?;
+        result?;
+        Ok(code as i32)
+    }
+}
@@ -0,0 +1,271 @@
+/// Process exit codes, so that scripts can tell what went wrong
+///
+/// Errors are printed to stderr as they happen, and the first one decides
+/// the exit code of the process.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{
+    fmt,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+use nojoy_rs::devenum;
+
+#[cfg(windows)]
+use crate::doctor::CriticalFailure;
+use crate::{aliases::AliasError, config::ConfigError, selector::SelectError};
+
+pub const SUCCESS: i32 = 0;
+/// Anything that doesn't have a code of its own
+pub const FAILURE: i32 = 1;
+/// No such device, alias or profile
+pub const NOT_FOUND: i32 = 2;
+pub const ACCESS_DENIED: i32 = 3;
+/// A configuration manager call failed
+pub const CONFIGRET: i32 = 4;
+/// The device isn't connected
+pub const NOT_PRESENT: i32 = 5;
+pub const NOT_DISABLEABLE: i32 = 6;
+/// A SetupDi or other Win32 call failed
+pub const WIN32: i32 = 7;
+/// A selector or another argument that can't be used as given
+pub const INVALID_ARGUMENT: i32 = 8;
+/// The config, alias or snapshot file can't be read or written
+pub const CONFIG: i32 = 9;
+/// Everything worked, but Windows has to be restarted for a change
+pub const RESTART_REQUIRED: i32 = 10;
+/// Nothing was changed, the confirmation was declined or there was no
+/// terminal to ask on
+pub const DECLINED: i32 = 11;
+/// A critical `doctor` check failed
+#[cfg(windows)]
+pub const DOCTOR: i32 = 12;
+
+static CODE: AtomicI32 = AtomicI32::new(SUCCESS);
+
+pub trait Failure: fmt::Display {
+    fn exit_code(&self) -> i32;
+}
+
+impl Failure for devenum::Error {
+    fn exit_code(&self) -> i32 {
+        use devenum::Error::*;
+        match self.root() {
+            NotFound => NOT_FOUND,
+            NotPresent => NOT_PRESENT,
+            AccessDenied | NotElevated => ACCESS_DENIED,
+            NotDisableable(_) => NOT_DISABLEABLE,
+            ConfigRet(_) | GaveUp { .. } | Vetoed { .. } => CONFIGRET,
+            Win32(_) => WIN32,
+            InvalidClassGuid(_) | UnknownClass(_) | UnknownInterface(_) => INVALID_ARGUMENT,
+            Present
+            | PropertyMissing
+            | PropertyType { .. }
+            | PropertyLength(_)
+            | StateTimeout
+            | Unsupported(_)
+            | Sysfs { .. }
+            | Context { .. } => FAILURE,
+        }
+    }
+}
+
+impl Failure for SelectError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            SelectError::Alias(err) => err.exit_code(),
+            SelectError::Device(err) => err.exit_code(),
+            SelectError::Invalid(_) | SelectError::Ambiguous { .. } => INVALID_ARGUMENT,
+            SelectError::NoMatch(_)
+            | SelectError::StaleAlias { .. }
+            | SelectError::NoSuchRow(_) => NOT_FOUND,
+        }
+    }
+}
+
+impl Failure for AliasError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            AliasError::UnknownAlias(_) => NOT_FOUND,
+            AliasError::InvalidName(_) | AliasError::InvalidSelector(_) => INVALID_ARGUMENT,
+            AliasError::NoConfigDir | AliasError::Io(..) | AliasError::Parse(..) => CONFIG,
+        }
+    }
+}
+
+impl Failure for ConfigError {
+    fn exit_code(&self) -> i32 {
+        CONFIG
+    }
+}
+
+#[cfg(windows)]
+impl Failure for CriticalFailure {
+    fn exit_code(&self) -> i32 {
+        DOCTOR
+    }
+}
+
+/// An error message that isn't one of the error types above
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub code: i32,
+    pub text: String,
+}
+
+impl Message {
+    pub fn new(code: i32, text: impl Into<String>) -> Self {
+        Self {
+            code,
+            text: text.into(),
+        }
+    }
+
+    /// Keep the text and code of another error
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn of(err: &impl Failure) -> Self {
+        Self::new(err.exit_code(), err.to_string())
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Failure for Message {
+    fn exit_code(&self) -> i32 {
+        self.code
+    }
+}
+
+/// Print the error to stderr and remember its code for the end of the
+/// command
+pub fn report(err: &impl Failure) {
+    eprintln!("Error: {}", err);
+    set(err.exit_code());
+}
+
+/// Like `report`, for an error about something specific like a device
+pub fn report_for(subject: &impl fmt::Display, err: &impl Failure) {
+    eprintln!("Error: {}: {}", subject, err);
+    set(err.exit_code());
+}
+
+/// Print the error to stderr and exit right away
+pub fn fail(err: &impl Failure) -> ! {
+    report(err);
+    std::process::exit(code())
+}
+
+/// Set the exit code unless an earlier error already did
+pub fn set(code: i32) {
+    let _ = CODE.compare_exchange(SUCCESS, code, Ordering::SeqCst, Ordering::SeqCst);
+}
+
+pub fn code() -> i32 {
+    CODE.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use nojoy_rs::devenum::Error;
+    use windows::Win32::Devices::DeviceAndDriverInstallation::{CR_FAILURE, CR_REMOVE_VETOED};
+
+    use super::*;
+    use crate::selector::Selector;
+
+    #[test]
+    fn device_errors_have_codes_of_their_own() {
+        let cases = [
+            (Error::NotFound, NOT_FOUND),
+            (Error::NotPresent, NOT_PRESENT),
+            (Error::AccessDenied, ACCESS_DENIED),
+            (Error::NotElevated, ACCESS_DENIED),
+            (Error::NotDisableable("Wheel".to_string()), NOT_DISABLEABLE),
+            (Error::ConfigRet(CR_FAILURE), CONFIGRET),
+            (
+                Error::GaveUp {
+                    result: CR_REMOVE_VETOED,
+                    attempts: 4,
+                },
+                CONFIGRET,
+            ),
+            (Error::UnknownClass("Nope".to_string()), INVALID_ARGUMENT),
+            (Error::StateTimeout, FAILURE),
+        ];
+        for (err, code) in cases {
+            assert_eq!(err.exit_code(), code, "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn context_keeps_the_code_of_the_error() {
+        let err = Error::NotPresent
+            .context("can't read")
+            .context("can't list");
+        assert_eq!(err.exit_code(), NOT_PRESENT);
+    }
+
+    #[test]
+    fn selection_errors() {
+        let name = || Selector::Name("pad".to_string());
+        let ambiguous = SelectError::Ambiguous {
+            selector: name(),
+            count: 2,
+        };
+        assert_eq!(ambiguous.exit_code(), INVALID_ARGUMENT);
+        assert_eq!(SelectError::NoMatch(name()).exit_code(), NOT_FOUND);
+        assert_eq!(SelectError::NoSuchRow(3).exit_code(), NOT_FOUND);
+        let stale = SelectError::StaleAlias {
+            alias: "pad".to_string(),
+            selector: "name:pad".to_string(),
+        };
+        assert_eq!(stale.exit_code(), NOT_FOUND);
+        assert_eq!(
+            SelectError::Device(Error::AccessDenied).exit_code(),
+            ACCESS_DENIED
+        );
+        let alias = SelectError::Alias(AliasError::UnknownAlias("pad".to_string()));
+        assert_eq!(alias.exit_code(), NOT_FOUND);
+    }
+
+    #[test]
+    fn messages_keep_the_code_they_are_made_of() {
+        let message = Message::of(&Error::NotFound);
+        assert_eq!(message.code, NOT_FOUND);
+        assert_eq!(message.text, Error::NotFound.to_string());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn failed_doctor_checks() {
+        let err = CriticalFailure(vec!["elevated".to_string()]);
+        assert_eq!(err.exit_code(), DOCTOR);
+        assert_eq!(err.to_string(), "critical checks failed: elevated");
+    }
+
+    #[test]
+    fn codes_are_distinct() {
+        let codes = [
+            SUCCESS,
+            FAILURE,
+            NOT_FOUND,
+            ACCESS_DENIED,
+            CONFIGRET,
+            NOT_PRESENT,
+            NOT_DISABLEABLE,
+            WIN32,
+            INVALID_ARGUMENT,
+            CONFIG,
+            RESTART_REQUIRED,
+            DECLINED,
+            #[cfg(windows)]
+            DOCTOR,
+        ];
+        for (i, code) in codes.iter().enumerate() {
+            assert!(!codes[i + 1..].contains(code), "{} is used twice", code);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+/// C ABI for using nojoy from other languages, see `include/nojoy.h`, which
+/// build.rs generates from this file.
+///
+/// All functions return a `NOJOY_` status code. Strings going out are either
+/// written into caller-provided buffers or allocated by us and must be
+/// released with `nojoy_free_string`, or `nojoy_free_list` for lists.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use core::ffi::c_char;
+#[cfg(windows)]
+use std::{collections::VecDeque, panic::AssertUnwindSafe, time::Duration};
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    panic::{catch_unwind, UnwindSafe},
+    ptr,
+};
+
+use serde::Serialize;
+
+use crate::devenum::{self, Error, Outcome, Retry, Scope};
+#[cfg(windows)]
+use crate::{
+    devenum::GameController,
+    timestamp::Timestamp,
+    watch::{self, ControllerEvent, Notifications},
+};
+
+pub const NOJOY_OK: i32 = 0;
+pub const NOJOY_NOT_FOUND: i32 = 1;
+pub const NOJOY_NOT_PRESENT: i32 = 2;
+pub const NOJOY_ACCESS_DENIED: i32 = 3;
+pub const NOJOY_CONFIGRET: i32 = 4;
+pub const NOJOY_WIN32: i32 = 5;
+pub const NOJOY_INVALID_ARGUMENT: i32 = 6;
+pub const NOJOY_BUFFER_TOO_SMALL: i32 = 7;
+pub const NOJOY_PANIC: i32 = 8;
+pub const NOJOY_OTHER: i32 = 9;
+pub const NOJOY_NOT_DISABLEABLE: i32 = 10;
+pub const NOJOY_RESTART_REQUIRED: i32 = 11;
+pub const NOJOY_TIMEOUT: i32 = 12;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn status_code(err: &Error) -> i32 {
+    match err.root() {
+        Error::NotFound => NOJOY_NOT_FOUND,
+        Error::NotPresent => NOJOY_NOT_PRESENT,
+        Error::AccessDenied | Error::NotElevated => NOJOY_ACCESS_DENIED,
+        Error::NotDisableable(_) => NOJOY_NOT_DISABLEABLE,
+        Error::ConfigRet(_) | Error::GaveUp { .. } | Error::Vetoed { .. } => NOJOY_CONFIGRET,
+        Error::Win32(_) => NOJOY_WIN32,
+        Error::Present
+        | Error::InvalidClassGuid(_)
+        | Error::UnknownClass(_)
+        | Error::UnknownInterface(_)
+        | Error::PropertyMissing
+        | Error::PropertyType { .. }
+        | Error::PropertyLength(_)
+        | Error::StateTimeout
+        | Error::Unsupported(_)
+        | Error::Sysfs { .. }
+        | Error::Context { .. } => NOJOY_OTHER,
+    }
+}
+
+fn set_last_error(message: Option<String>) {
+    LAST_ERROR.with(|e| *e.borrow_mut() = message);
+}
+
+/// Run `f` with panics caught and the thread's last error updated
+fn guard(f: impl FnOnce() -> Result<(), (i32, String)> + UnwindSafe) -> i32 {
+    match catch_unwind(f) {
+        Ok(Ok(())) => {
+            set_last_error(None);
+            NOJOY_OK
+        }
+        Ok(Err((code, message))) => {
+            set_last_error(Some(message));
+            code
+        }
+        Err(_) => {
+            set_last_error(Some("internal error (panic)".to_string()));
+            NOJOY_PANIC
+        }
+    }
+}
+
+fn device_error(err: Error) -> (i32, String) {
+    (status_code(&err), err.to_string())
+}
+
+/// A change that waits for a restart is reported with its own code, so that
+/// callers can tell the user
+fn change_result(outcome: Outcome) -> Result<(), (i32, String)> {
+    match outcome {
+        Outcome::RestartRequired => Err((
+            NOJOY_RESTART_REQUIRED,
+            "Windows has to be restarted for the change to take effect".to_string(),
+        )),
+        Outcome::Changed | Outcome::AlreadyInState => Ok(()),
+    }
+}
+
+unsafe fn instance_id<'a>(id: *const c_char) -> Result<&'a str, (i32, String)> {
+    if id.is_null() {
+        return Err((NOJOY_INVALID_ARGUMENT, "instance id is null".to_string()));
+    }
+    CStr::from_ptr(id)
+        .to_str()
+        .map_err(|_| (NOJOY_INVALID_ARGUMENT, "instance id is not valid UTF-8".to_string()))
+}
+
+/// Write `value` as UTF-8 JSON the way `nojoy_list` describes
+unsafe fn write_json(
+    value: &impl Serialize,
+    out_buf: *mut u8,
+    out_len: *mut usize,
+) -> Result<(), (i32, String)> {
+    if out_len.is_null() {
+        return Err((NOJOY_INVALID_ARGUMENT, "out_len is null".to_string()));
+    }
+    let json = serde_json::to_vec(value).expect("values are serializable");
+    let capacity = *out_len;
+    *out_len = json.len();
+    if out_buf.is_null() || capacity < json.len() {
+        return Err((
+            NOJOY_BUFFER_TOO_SMALL,
+            format!("buffer needs to be {} bytes", json.len()),
+        ));
+    }
+    ptr::copy_nonoverlapping(json.as_ptr(), out_buf, json.len());
+    Ok(())
+}
+
+/// Write the connected controllers as UTF-8 JSON (not null terminated) into
+/// `out_buf`.
+///
+/// `*out_len` must hold the size of `out_buf` on entry and receives the
+/// size of the JSON on return. Pass a null `out_buf` to query the size;
+/// `NOJOY_BUFFER_TOO_SMALL` is returned when the buffer can't hold it.
+///
+/// # Safety
+/// `out_len` must be valid, and `out_buf` null or valid for `*out_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn nojoy_list(out_buf: *mut u8, out_len: *mut usize) -> i32 {
+    guard(|| {
+        let controllers = devenum::game_controllers(Scope::default()).map_err(device_error)?;
+        write_json(&controllers, out_buf, out_len)
+    })
+}
+
+/// Allocate the connected controllers as null terminated UTF-8 JSON like
+/// `nojoy_list` writes them, for callers that would rather not size a
+/// buffer. `*out_json` receives the JSON, to be released with
+/// `nojoy_free_list`, and `*out_len` its length without the terminator
+/// unless `out_len` is null.
+///
+/// # Safety
+/// `out_json` must be valid, and `out_len` null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn nojoy_list_alloc(out_json: *mut *mut c_char, out_len: *mut usize) -> i32 {
+    guard(|| {
+        if out_json.is_null() {
+            return Err((NOJOY_INVALID_ARGUMENT, "out_json is null".to_string()));
+        }
+        *out_json = ptr::null_mut();
+        let controllers = devenum::game_controllers(Scope::default()).map_err(device_error)?;
+        let json = serde_json::to_string(&controllers).expect("values are serializable");
+        if !out_len.is_null() {
+            *out_len = json.len();
+        }
+        // JSON escapes control characters, it has no null byte in it
+        *out_json = CString::new(json).expect("JSON has no null byte").into_raw();
+        Ok(())
+    })
+}
+
+/// Release a list from `nojoy_list_alloc`. Null is ignored.
+///
+/// # Safety
+/// `json` must come from `nojoy_list_alloc` and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn nojoy_free_list(json: *mut c_char) {
+    if !json.is_null() {
+        drop(CString::from_raw(json));
+    }
+}
+
+/// Enable the device with given null terminated UTF-8 instance id.
+///
+/// # Safety
+/// `instance_id` must be null or a valid null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn nojoy_enable(instance_id_utf8: *const c_char) -> i32 {
+    guard(|| {
+        let id = instance_id(instance_id_utf8)?;
+        devenum::enable_device(id, Scope::default(), Retry::default())
+            .map_err(device_error)
+            .and_then(change_result)
+    })
+}
+
+/// Disable the device with given null terminated UTF-8 instance id.
+///
+/// # Safety
+/// `instance_id` must be null or a valid null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn nojoy_disable(instance_id_utf8: *const c_char) -> i32 {
+    guard(|| {
+        let id = instance_id(instance_id_utf8)?;
+        devenum::disable_device(id, Scope::default(), Retry::default())
+            .map_err(device_error)
+            .and_then(change_result)
+    })
+}
+
+/// Message of the last failed call on the calling thread, or null if the last
+/// call succeeded. Release the result with `nojoy_free_string`.
+#[no_mangle]
+pub extern "C" fn nojoy_last_error_message() -> *mut c_char {
+    LAST_ERROR.with(|e| match e.borrow().as_deref() {
+        Some(message) => CString::new(message.replace('\0', " "))
+            .map(CString::into_raw)
+            .unwrap_or(ptr::null_mut()),
+        None => ptr::null_mut(),
+    })
+}
+
+/// Release a string returned by this library. Null is ignored.
+///
+/// # Safety
+/// `s` must come from this library and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn nojoy_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Registration for controller events, created by `nojoy_watch_open`.
+/// Watching is only there on Windows.
+#[cfg(windows)]
+pub struct NojoyWatch {
+    notifications: Notifications,
+    known: Vec<GameController>,
+    /// Events found but not returned yet
+    pending: VecDeque<ControllerEvent>,
+}
+
+/// Start watching the game controllers, events are read with
+/// `nojoy_watch_next`.
+///
+/// # Safety
+/// `out_watch` must be valid. The watch it receives must be released with
+/// `nojoy_watch_close`.
+#[cfg(windows)]
+#[no_mangle]
+pub unsafe extern "C" fn nojoy_watch_open(out_watch: *mut *mut NojoyWatch) -> i32 {
+    guard(AssertUnwindSafe(|| {
+        if out_watch.is_null() {
+            return Err((NOJOY_INVALID_ARGUMENT, "out_watch is null".to_string()));
+        }
+        let notifications = Notifications::register().map_err(device_error)?;
+        let known = devenum::game_controllers(Scope::default()).map_err(device_error)?;
+        let watch = NojoyWatch {
+            notifications,
+            known,
+            pending: VecDeque::new(),
+        };
+        *out_watch = Box::into_raw(Box::new(watch));
+        Ok(())
+    }))
+}
+
+/// Wait up to `timeout_ms` for the next event and write it as UTF-8 JSON
+/// like `nojoy_list` does, an object with `time`, `kind` and `controller`.
+/// Returns `NOJOY_TIMEOUT` if nothing happened. An event that doesn't fit
+/// `out_buf` is kept for the next call.
+///
+/// # Safety
+/// `watch` must come from `nojoy_watch_open` and not be closed, the buffer
+/// arguments are as in `nojoy_list`.
+#[cfg(windows)]
+#[no_mangle]
+pub unsafe extern "C" fn nojoy_watch_next(
+    watch: *mut NojoyWatch,
+    timeout_ms: u32,
+    out_buf: *mut u8,
+    out_len: *mut usize,
+) -> i32 {
+    guard(AssertUnwindSafe(|| {
+        let Some(watch) = watch.as_mut() else {
+            return Err((NOJOY_INVALID_ARGUMENT, "watch is null".to_string()));
+        };
+        if watch.pending.is_empty() {
+            let timeout = Duration::from_millis(timeout_ms.into());
+            if !watch.notifications.wait_timeout(timeout) {
+                return Err((NOJOY_TIMEOUT, "no event before the timeout".to_string()));
+            }
+            let current = devenum::game_controllers(Scope::default()).map_err(device_error)?;
+            watch
+                .pending
+                .extend(watch::diff(&watch.known, &current, Timestamp::now()));
+            watch.known = current;
+        }
+        // device tree changes don't always change a controller
+        let Some(event) = watch.pending.front() else {
+            return Err((NOJOY_TIMEOUT, "no event before the timeout".to_string()));
+        };
+        write_json(event, out_buf, out_len)?;
+        watch.pending.pop_front();
+        Ok(())
+    }))
+}
+
+/// Stop watching. Null is ignored.
+///
+/// # Safety
+/// `watch` must come from `nojoy_watch_open` and must not be used
+/// afterwards.
+#[cfg(windows)]
+#[no_mangle]
+pub unsafe extern "C" fn nojoy_watch_close(watch: *mut NojoyWatch) {
+    if !watch.is_null() {
+        drop(Box::from_raw(watch));
+    }
+}
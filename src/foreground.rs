@@ -0,0 +1,110 @@
+/// Following which application the user is working with
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{cell::RefCell, path::Path};
+
+use nojoy_rs::devenum::Error;
+use windows::{
+    core::PWSTR,
+    Win32::{
+        Foundation::{CloseHandle, HWND},
+        System::Threading::{
+            OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+            PROCESS_QUERY_LIMITED_INFORMATION,
+        },
+        UI::{
+            Accessibility::{SetWinEventHook, UnhookWinEvent, HWINEVENTHOOK},
+            WindowsAndMessaging::{
+                DispatchMessageW, GetForegroundWindow, GetMessageW, GetWindowThreadProcessId,
+                TranslateMessage, EVENT_SYSTEM_FOREGROUND, MSG, WINEVENT_OUTOFCONTEXT,
+            },
+        },
+    },
+};
+
+type Callback = Box<dyn FnMut(&str)>;
+
+thread_local! {
+    /// The hook procedure has no other way to reach the caller's closure
+    static ON_CHANGE: RefCell<Option<Callback>> = RefCell::new(None);
+}
+
+/// File name of the executable that owns given window, like `dcs.exe`
+fn process_name(hwnd: HWND) -> Option<String> {
+    unsafe {
+        let mut pid = 0;
+        GetWindowThreadProcessId(hwnd, Some(&mut pid));
+        if pid == 0 {
+            return None;
+        }
+        // elevated and protected processes can't be opened, those are skipped
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let mut buf = [0u16; 1024];
+        let mut len = buf.len() as u32;
+        let result = QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(buf.as_mut_ptr()),
+            &mut len,
+        );
+        let _ = CloseHandle(process);
+        result.ok()?;
+        let path = String::from_utf16_lossy(&buf[..len as usize]);
+        Path::new(&path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    }
+}
+
+fn notify(hwnd: HWND) {
+    let Some(name) = process_name(hwnd) else {
+        return;
+    };
+    log::debug!("foreground process: {}", name);
+    ON_CHANGE.with(|f| {
+        if let Some(on_change) = f.borrow_mut().as_mut() {
+            on_change(&name);
+        }
+    });
+}
+
+unsafe extern "system" fn hook_proc(
+    _hook: HWINEVENTHOOK,
+    _event: u32,
+    hwnd: HWND,
+    _id_object: i32,
+    _id_child: i32,
+    _event_thread: u32,
+    _event_time: u32,
+) {
+    notify(hwnd);
+}
+
+/// Call `on_change` with the executable name of the foreground window, once
+/// at the start and then every time another window comes to the foreground
+pub fn watch(on_change: impl FnMut(&str) + 'static) -> Result<(), Error> {
+    ON_CHANGE.with(|f| *f.borrow_mut() = Some(Box::new(on_change)));
+    unsafe {
+        notify(GetForegroundWindow());
+        let hook = SetWinEventHook(
+            EVENT_SYSTEM_FOREGROUND,
+            EVENT_SYSTEM_FOREGROUND,
+            None,
+            Some(hook_proc),
+            0,
+            0,
+            WINEVENT_OUTOFCONTEXT,
+        );
+        if hook.is_invalid() {
+            return Err(windows::core::Error::from_win32().into());
+        }
+        let mut msg = MSG::default();
+        while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+        let _ = UnhookWinEvent(hook);
+    }
+    ON_CHANGE.with(|f| f.borrow_mut().take());
+    Ok(())
+}
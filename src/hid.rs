@@ -0,0 +1,393 @@
+//! Talking to a HID collection itself, through its device interface
+//!
+//! Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use serde::Serialize;
+use windows::{
+    core::{HSTRING, PCWSTR},
+    Win32::{
+        Devices::{
+            DeviceAndDriverInstallation::{
+                CM_Get_Device_Interface_ListW, CM_Get_Device_Interface_List_SizeW,
+                CM_GET_DEVICE_INTERFACE_LIST_PRESENT, CR_SUCCESS,
+            },
+            HumanInterfaceDevice::{
+                HidD_FreePreparsedData, HidD_GetHidGuid, HidD_GetPreparsedData, HidP_GetButtonCaps,
+                HidP_GetCaps, HidP_GetUsageValue, HidP_GetUsages, HidP_GetValueCaps, HidP_Input,
+                HIDP_BUTTON_CAPS, HIDP_CAPS, HIDP_VALUE_CAPS, PHIDP_PREPARSED_DATA,
+            },
+        },
+        Foundation::{CloseHandle, GENERIC_READ, GENERIC_WRITE, HANDLE, NTSTATUS},
+        Storage::FileSystem::{
+            CreateFileW, ReadFile, WriteFile, FILE_FLAGS_AND_ATTRIBUTES, FILE_SHARE_READ,
+            FILE_SHARE_WRITE, OPEN_EXISTING,
+        },
+    },
+};
+
+use crate::devenum::Error;
+
+/// What a [`HidDevice`] is opened for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Only reading its attributes and report descriptor, which works for
+    /// keyboards and mice too, Windows keeps those to itself otherwise
+    Query,
+    Read,
+    ReadWrite,
+}
+
+/// An open HID collection, closed when dropped
+pub struct HidDevice {
+    handle: HANDLE,
+    /// Device interface path it was opened with
+    pub path: String,
+}
+
+impl Drop for HidDevice {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.handle);
+        }
+    }
+}
+
+impl HidDevice {
+    /// Open the connected HID collection with given instance id
+    pub fn open(instance_id: &str, access: Access) -> Result<Self, Error> {
+        let path = interface_path(instance_id)?;
+        let access = match access {
+            Access::Query => 0,
+            Access::Read => GENERIC_READ.0,
+            Access::ReadWrite => (GENERIC_READ | GENERIC_WRITE).0,
+        };
+        let handle = unsafe {
+            CreateFileW(
+                &HSTRING::from(&path),
+                access,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                None,
+                OPEN_EXISTING,
+                FILE_FLAGS_AND_ATTRIBUTES(0),
+                None,
+            )
+        };
+        log::debug!("CreateFileW({}) -> {:?}", path, handle);
+        match handle {
+            Ok(handle) => Ok(Self { handle, path }),
+            Err(err) => Err(Error::from(err).context(format!("can't open {}", instance_id))),
+        }
+    }
+
+    /// Parsed report descriptor of the collection
+    pub fn preparsed(&self) -> Result<Preparsed, Error> {
+        let mut data = PHIDP_PREPARSED_DATA::default();
+        match unsafe { HidD_GetPreparsedData(self.handle, &mut data) }.as_bool() {
+            true => Ok(Preparsed(data)),
+            false => Err(Error::from(windows::core::Error::from_win32())),
+        }
+    }
+
+    /// Send an output report, the first byte is the report id
+    pub fn write(&self, report: &[u8]) -> Result<(), Error> {
+        let result = unsafe { WriteFile(self.handle, Some(report), None, None) };
+        log::debug!("WriteFile({} bytes) -> {:?}", report.len(), result);
+        Ok(result?)
+    }
+
+    /// Wait for the next input report and read it into `report`, which has
+    /// to hold `InputReportByteLength` bytes. Returns the report's length.
+    pub fn read(&self, report: &mut [u8]) -> Result<usize, Error> {
+        let mut read = 0;
+        unsafe { ReadFile(self.handle, Some(report), Some(&mut read), None)? };
+        Ok(read as usize)
+    }
+}
+
+/// A control of a HID collection, like a button or an axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Usage {
+    pub page: u16,
+    pub usage: u16,
+}
+
+impl Usage {
+    /// Name of the control in the HID usage tables, the numbers if it isn't
+    /// one that game controllers have
+    pub fn name(&self) -> String {
+        let name = match (self.page, self.usage) {
+            (GENERIC_DESKTOP, 0x02) => "Mouse",
+            (GENERIC_DESKTOP, 0x04) => "Joystick",
+            (GENERIC_DESKTOP, 0x05) => "Gamepad",
+            (GENERIC_DESKTOP, 0x06) => "Keyboard",
+            (GENERIC_DESKTOP, 0x08) => "Multi-axis controller",
+            (GENERIC_DESKTOP, 0x30) => "X",
+            (GENERIC_DESKTOP, 0x31) => "Y",
+            (GENERIC_DESKTOP, 0x32) => "Z",
+            (GENERIC_DESKTOP, 0x33) => "Rx",
+            (GENERIC_DESKTOP, 0x34) => "Ry",
+            (GENERIC_DESKTOP, 0x35) => "Rz",
+            (GENERIC_DESKTOP, 0x36) => "Slider",
+            (GENERIC_DESKTOP, 0x37) => "Dial",
+            (GENERIC_DESKTOP, 0x38) => "Wheel",
+            (GENERIC_DESKTOP, 0x39) => "Hat switch",
+            (GENERIC_DESKTOP, 0x90) => "D-pad up",
+            (GENERIC_DESKTOP, 0x91) => "D-pad down",
+            (GENERIC_DESKTOP, 0x92) => "D-pad right",
+            (GENERIC_DESKTOP, 0x93) => "D-pad left",
+            (SIMULATION, 0xbb) => "Throttle",
+            (SIMULATION, 0xba) => "Rudder",
+            (SIMULATION, 0xc4) => "Accelerator",
+            (SIMULATION, 0xc5) => "Brake",
+            (SIMULATION, 0xc8) => "Steering",
+            (BUTTON, usage) => return format!("Button {}", usage),
+            (page, usage) => return format!("{:#06x}:{:#06x}", page, usage),
+        };
+        name.to_string()
+    }
+}
+
+const GENERIC_DESKTOP: u16 = 0x01;
+const SIMULATION: u16 = 0x02;
+const BUTTON: u16 = 0x09;
+
+/// Buttons of an input report, with consecutive usages
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Buttons {
+    pub report_id: u8,
+    pub page: u16,
+    pub first: u16,
+    pub last: u16,
+}
+
+/// A value of an input report, like an axis
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Value {
+    pub report_id: u8,
+    pub usage: Usage,
+    #[serde(skip)]
+    link_collection: u16,
+    pub min: i32,
+    pub max: i32,
+    pub bits: u16,
+}
+
+/// Controls of an input report as they are at the time of the report
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct InputState {
+    /// Buttons that are down
+    pub buttons: Vec<Usage>,
+    pub values: Vec<(Usage, u32)>,
+}
+
+/// Preparsed data of a HID collection, released when dropped
+pub struct Preparsed(PHIDP_PREPARSED_DATA);
+
+impl Drop for Preparsed {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = HidD_FreePreparsedData(self.0);
+        }
+    }
+}
+
+impl Preparsed {
+    pub fn caps(&self) -> Result<HIDP_CAPS, Error> {
+        let mut caps = HIDP_CAPS::default();
+        check(unsafe { HidP_GetCaps(self.0, &mut caps) })?;
+        Ok(caps)
+    }
+
+    pub fn input_buttons(&self) -> Result<Vec<Buttons>, Error> {
+        let mut len = self.caps()?.NumberInputButtonCaps;
+        let mut caps = vec![HIDP_BUTTON_CAPS::default(); len.into()];
+        check(unsafe { HidP_GetButtonCaps(HidP_Input, caps.as_mut_ptr(), &mut len, self.0) })?;
+        caps.truncate(len.into());
+        Ok(caps
+            .iter()
+            .map(|c| {
+                // the union is whichever IsRange says
+                let (first, last) = unsafe {
+                    match c.IsRange.as_bool() {
+                        true => (c.Anonymous.Range.UsageMin, c.Anonymous.Range.UsageMax),
+                        false => (c.Anonymous.NotRange.Usage, c.Anonymous.NotRange.Usage),
+                    }
+                };
+                Buttons {
+                    report_id: c.ReportID,
+                    page: c.UsagePage,
+                    first,
+                    last,
+                }
+            })
+            .collect())
+    }
+
+    pub fn input_values(&self) -> Result<Vec<Value>, Error> {
+        let mut len = self.caps()?.NumberInputValueCaps;
+        let mut caps = vec![HIDP_VALUE_CAPS::default(); len.into()];
+        check(unsafe { HidP_GetValueCaps(HidP_Input, caps.as_mut_ptr(), &mut len, self.0) })?;
+        caps.truncate(len.into());
+        let mut values = Vec::new();
+        for c in &caps {
+            let (first, last) = unsafe {
+                match c.IsRange.as_bool() {
+                    true => (c.Anonymous.Range.UsageMin, c.Anonymous.Range.UsageMax),
+                    false => (c.Anonymous.NotRange.Usage, c.Anonymous.NotRange.Usage),
+                }
+            };
+            values.extend((first..=last).map(|usage| Value {
+                report_id: c.ReportID,
+                usage: Usage {
+                    page: c.UsagePage,
+                    usage,
+                },
+                link_collection: c.LinkCollection,
+                min: c.LogicalMin,
+                max: c.LogicalMax,
+                bits: c.BitSize,
+            }));
+        }
+        Ok(values)
+    }
+
+    /// The controls in an input report read with [`HidDevice::read`]. Those
+    /// that aren't in this report are left out.
+    pub fn decode(&self, report: &mut [u8], buttons: &[Buttons], values: &[Value]) -> InputState {
+        let Some(&report_id) = report.first() else {
+            return InputState::default();
+        };
+        let mut state = InputState::default();
+        let mut pages: Vec<u16> = buttons
+            .iter()
+            .filter(|b| b.report_id == report_id)
+            .map(|b| b.page)
+            .collect();
+        pages.sort_unstable();
+        pages.dedup();
+        for page in pages {
+            let mut usages = [0u16; MAX_BUTTONS];
+            let mut len = usages.len() as u32;
+            let status = unsafe {
+                HidP_GetUsages(
+                    HidP_Input,
+                    page,
+                    0,
+                    usages.as_mut_ptr(),
+                    &mut len,
+                    self.0,
+                    report,
+                )
+            };
+            if check(status).is_ok() {
+                state.buttons.extend(
+                    usages[..len as usize]
+                        .iter()
+                        .map(|&usage| Usage { page, usage }),
+                );
+            }
+        }
+        for value in values.iter().filter(|v| v.report_id == report_id) {
+            let mut data = 0;
+            let status = unsafe {
+                HidP_GetUsageValue(
+                    HidP_Input,
+                    value.usage.page,
+                    value.link_collection,
+                    value.usage.usage,
+                    &mut data,
+                    self.0,
+                    report,
+                )
+            };
+            if check(status).is_ok() {
+                state.values.push((value.usage, data));
+            }
+        }
+        state.buttons.sort_unstable();
+        state
+    }
+}
+
+/// What a HID collection reports, as Windows parsed it from the report
+/// descriptor. The raw descriptor is only available to drivers.
+#[derive(Debug, Clone, Serialize)]
+pub struct Description {
+    /// Top level usage of the collection, like Gamepad
+    pub usage: Usage,
+    /// Whether the usage is one of a joystick, gamepad or multi-axis
+    /// controller, which is what Windows puts HID_DEVICE_SYSTEM_GAME on
+    pub is_game_controller: bool,
+    pub input_report_length: u16,
+    pub output_report_length: u16,
+    pub feature_report_length: u16,
+    pub buttons: Vec<Buttons>,
+    pub values: Vec<Value>,
+}
+
+/// Capabilities of the connected HID collection with given instance id
+pub fn describe(instance_id: &str) -> Result<Description, Error> {
+    let device = HidDevice::open(instance_id, Access::Query)?;
+    let preparsed = device.preparsed()?;
+    let caps = preparsed.caps()?;
+    let usage = Usage {
+        page: caps.UsagePage,
+        usage: caps.Usage,
+    };
+    Ok(Description {
+        usage,
+        is_game_controller: usage.page == GENERIC_DESKTOP
+            && [0x04, 0x05, 0x08].contains(&usage.usage),
+        input_report_length: caps.InputReportByteLength,
+        output_report_length: caps.OutputReportByteLength,
+        feature_report_length: caps.FeatureReportByteLength,
+        buttons: preparsed.input_buttons()?,
+        values: preparsed.input_values()?,
+    })
+}
+
+/// Most buttons of one usage page to read from a report
+const MAX_BUTTONS: usize = 128;
+
+/// Result of a HidP_xx call
+pub(crate) fn check(status: NTSTATUS) -> Result<(), Error> {
+    match status.is_ok() {
+        true => Ok(()),
+        false => Err(windows::core::Error::from(status.to_hresult()).into()),
+    }
+}
+
+/// Path of the HID interface of the device with given instance id
+fn interface_path(instance_id: &str) -> Result<String, Error> {
+    let guid = unsafe { HidD_GetHidGuid() };
+    let id = HSTRING::from(instance_id);
+    let mut len = 0;
+    let result = unsafe {
+        CM_Get_Device_Interface_List_SizeW(
+            &mut len,
+            &guid,
+            PCWSTR(id.as_ptr()),
+            CM_GET_DEVICE_INTERFACE_LIST_PRESENT,
+        )
+    };
+    if result != CR_SUCCESS {
+        return Err(Error::ConfigRet(result));
+    }
+    let mut buffer = vec![0u16; len as usize];
+    let result = unsafe {
+        CM_Get_Device_Interface_ListW(
+            &guid,
+            PCWSTR(id.as_ptr()),
+            &mut buffer,
+            CM_GET_DEVICE_INTERFACE_LIST_PRESENT,
+        )
+    };
+    if result != CR_SUCCESS {
+        return Err(Error::ConfigRet(result));
+    }
+    // a list of paths, there's one for a HID collection
+    let end = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    match end {
+        0 => Err(Error::NotPresent),
+        _ => Ok(String::from_utf16_lossy(&buffer[..end])),
+    }
+}
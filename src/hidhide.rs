@@ -0,0 +1,208 @@
+/// Hiding devices from applications with the HidHide filter driver
+///
+/// HidHide (https://github.com/nefarius/HidHide) keeps a list of hidden
+/// device instance ids and a list of applications that still see them, and
+/// hides the devices from everything else while "cloaking" is active. The
+/// device itself keeps running, unlike a disabled one.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{
+    ffi::c_void,
+    path::{Component, Path, Prefix},
+    ptr,
+};
+
+use windows::{
+    core::{w, HSTRING},
+    Win32::{
+        Foundation::{CloseHandle, GENERIC_READ, HANDLE},
+        Storage::FileSystem::{
+            CreateFileW, QueryDosDeviceW, FILE_ATTRIBUTE_NORMAL, FILE_SHARE_READ, FILE_SHARE_WRITE,
+            OPEN_EXISTING,
+        },
+        System::IO::DeviceIoControl,
+    },
+};
+
+use crate::devenum::Error;
+
+const FILE_DEVICE_HIDHIDE: u32 = 32769;
+const METHOD_BUFFERED: u32 = 0;
+const FILE_READ_DATA: u32 = 1;
+
+const fn ioctl(function: u32) -> u32 {
+    (FILE_DEVICE_HIDHIDE << 16) | (FILE_READ_DATA << 14) | (function << 2) | METHOD_BUFFERED
+}
+
+const IOCTL_GET_WHITELIST: u32 = ioctl(2048);
+const IOCTL_SET_WHITELIST: u32 = ioctl(2049);
+const IOCTL_GET_BLACKLIST: u32 = ioctl(2050);
+const IOCTL_SET_BLACKLIST: u32 = ioctl(2051);
+const IOCTL_GET_ACTIVE: u32 = ioctl(2052);
+const IOCTL_SET_ACTIVE: u32 = ioctl(2053);
+
+/// Control device of the driver, closed when dropped
+pub struct HidHide(HANDLE);
+
+impl Drop for HidHide {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
+
+impl HidHide {
+    pub fn open() -> Result<Self, Error> {
+        let handle = unsafe {
+            CreateFileW(
+                w!("\\\\.\\HidHide"),
+                GENERIC_READ.0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                None,
+                OPEN_EXISTING,
+                FILE_ATTRIBUTE_NORMAL,
+                None,
+            )
+        };
+        match handle {
+            Ok(handle) => Ok(Self(handle)),
+            Err(err) => Err(Error::from(err).context("can't open HidHide, is it installed?")),
+        }
+    }
+
+    /// Instance ids of the hidden devices
+    pub fn hidden(&self) -> Result<Vec<String>, Error> {
+        self.get_list(IOCTL_GET_BLACKLIST)
+    }
+
+    pub fn set_hidden(&self, ids: &[String]) -> Result<(), Error> {
+        self.set_list(IOCTL_SET_BLACKLIST, ids)
+    }
+
+    /// Applications that see hidden devices, as NT paths like
+    /// `\Device\HarddiskVolume3\Games\game.exe`
+    pub fn allowed(&self) -> Result<Vec<String>, Error> {
+        self.get_list(IOCTL_GET_WHITELIST)
+    }
+
+    pub fn set_allowed(&self, paths: &[String]) -> Result<(), Error> {
+        self.set_list(IOCTL_SET_WHITELIST, paths)
+    }
+
+    /// Whether hidden devices are actually hidden right now
+    pub fn is_active(&self) -> Result<bool, Error> {
+        let mut active = 0u8;
+        self.control(
+            IOCTL_GET_ACTIVE,
+            None,
+            Some(std::slice::from_mut(&mut active)),
+        )?;
+        Ok(active != 0)
+    }
+
+    pub fn set_active(&self, active: bool) -> Result<(), Error> {
+        self.control(IOCTL_SET_ACTIVE, Some(&[active as u8]), None)?;
+        Ok(())
+    }
+
+    fn get_list(&self, code: u32) -> Result<Vec<String>, Error> {
+        // the first call only tells the size
+        let size = self.control(code, None, None)?;
+        let mut buf = vec![0u8; size];
+        let len = self.control(code, None, Some(&mut buf))?;
+        let wide: Vec<u16> = buf[..len]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        Ok(wide
+            .split(|&c| c == 0)
+            .filter(|s| !s.is_empty())
+            .map(String::from_utf16_lossy)
+            .collect())
+    }
+
+    fn set_list(&self, code: u32, items: &[String]) -> Result<(), Error> {
+        let mut wide: Vec<u16> = Vec::new();
+        for item in items {
+            wide.extend(item.encode_utf16());
+            wide.push(0);
+        }
+        wide.push(0);
+        let bytes: Vec<u8> = wide.iter().flat_map(|c| c.to_le_bytes()).collect();
+        self.control(code, Some(&bytes), None)?;
+        Ok(())
+    }
+
+    /// DeviceIoControl with optional buffers, returns the number of bytes
+    /// written to the output
+    fn control(
+        &self,
+        code: u32,
+        input: Option<&[u8]>,
+        output: Option<&mut [u8]>,
+    ) -> Result<usize, Error> {
+        let (in_ptr, in_len) = match input {
+            Some(buf) => (buf.as_ptr() as *const c_void, buf.len() as u32),
+            None => (ptr::null(), 0),
+        };
+        let (out_ptr, out_len) = match output {
+            Some(buf) => (buf.as_mut_ptr() as *mut c_void, buf.len() as u32),
+            None => (ptr::null_mut(), 0),
+        };
+        let mut returned = 0u32;
+        let result = unsafe {
+            DeviceIoControl(
+                self.0,
+                code,
+                (!in_ptr.is_null()).then_some(in_ptr),
+                in_len,
+                (!out_ptr.is_null()).then_some(out_ptr),
+                out_len,
+                Some(&mut returned),
+                None,
+            )
+        };
+        log::debug!(
+            "DeviceIoControl(HidHide, {:#x}) -> {:?} returned={}",
+            code,
+            result,
+            returned
+        );
+        result?;
+        Ok(returned as usize)
+    }
+}
+
+/// `C:\Games\game.exe` as `\Device\HarddiskVolume3\Games\game.exe`, the form
+/// the allowlist takes. None if the path isn't on a drive with a letter.
+pub fn nt_path(path: &Path) -> Option<String> {
+    let path = std::path::absolute(path).ok()?;
+    let mut components = path.components();
+    let drive = match components.next()? {
+        Component::Prefix(prefix) => match prefix.kind() {
+            Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => format!("{}:", letter as char),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let mut target = [0u16; 1024];
+    let len = unsafe { QueryDosDeviceW(&HSTRING::from(drive.as_str()), Some(&mut target)) };
+    if len == 0 {
+        log::debug!(
+            "QueryDosDeviceW({}) -> {}",
+            drive,
+            windows::core::Error::from_win32()
+        );
+        return None;
+    }
+    let end = target.iter().position(|&c| c == 0).unwrap_or(target.len());
+    let mut result = String::from_utf16_lossy(&target[..end]);
+    for component in components {
+        if let Component::Normal(name) = component {
+            result.push('\\');
+            result.push_str(&name.to_string_lossy());
+        }
+    }
+    Some(result)
+}
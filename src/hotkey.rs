@@ -0,0 +1,120 @@
+/// System-wide key combinations that work while another window has focus
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{fmt, str::FromStr};
+
+use nojoy_rs::devenum::Error;
+use windows::Win32::UI::{
+    Input::KeyboardAndMouse::{
+        RegisterHotKey, UnregisterHotKey, HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT,
+        MOD_SHIFT, MOD_WIN,
+    },
+    WindowsAndMessaging::{DispatchMessageW, GetMessageW, TranslateMessage, MSG, WM_HOTKEY},
+};
+
+const HOTKEY_ID: i32 = 1;
+
+const VK_PAUSE: u32 = 0x13;
+const VK_F1: u32 = 0x70;
+
+/// A key with modifiers, written like `Ctrl+Alt+J`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    modifiers: HOT_KEY_MODIFIERS,
+    key: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidHotkey(pub String);
+
+impl fmt::Display for InvalidHotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid hotkey \"{}\", expected something like Ctrl+Alt+J",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidHotkey {}
+
+/// Virtual key code of a letter, digit, F1-F24 or Pause
+fn virtual_key(name: &str) -> Option<u32> {
+    let upper = name.to_ascii_uppercase();
+    if let [c] = upper.as_bytes() {
+        return c.is_ascii_alphanumeric().then_some(u32::from(*c));
+    }
+    if upper == "PAUSE" {
+        return Some(VK_PAUSE);
+    }
+    match upper.strip_prefix('F')?.parse::<u32>().ok()? {
+        n @ 1..=24 => Some(VK_F1 + n - 1),
+        _ => None,
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = InvalidHotkey;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidHotkey(s.to_string());
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key = parts.pop().and_then(virtual_key).ok_or_else(invalid)?;
+        let mut modifiers = HOT_KEY_MODIFIERS::default();
+        for part in parts {
+            modifiers |= match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => MOD_CONTROL,
+                "alt" => MOD_ALT,
+                "shift" => MOD_SHIFT,
+                "win" => MOD_WIN,
+                _ => return Err(invalid()),
+            };
+        }
+        // a bare letter would stop working everywhere else
+        if modifiers == HOT_KEY_MODIFIERS::default() {
+            return Err(invalid());
+        }
+        Ok(Self { modifiers, key })
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (MOD_CONTROL, "Ctrl"),
+            (MOD_ALT, "Alt"),
+            (MOD_SHIFT, "Shift"),
+            (MOD_WIN, "Win"),
+        ] {
+            if self.modifiers & modifier == modifier {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match self.key {
+            VK_PAUSE => write!(f, "Pause"),
+            n if (VK_F1..VK_F1 + 24).contains(&n) => write!(f, "F{}", n - VK_F1 + 1),
+            n => write!(f, "{}", char::from_u32(n).unwrap_or('?')),
+        }
+    }
+}
+
+/// Call `on_press` every time the hotkey is pressed, until the message loop
+/// is ended
+pub fn run(hotkey: Hotkey, mut on_press: impl FnMut()) -> Result<(), Error> {
+    unsafe {
+        // holding the keys down would otherwise flip the devices repeatedly
+        RegisterHotKey(None, HOTKEY_ID, hotkey.modifiers | MOD_NOREPEAT, hotkey.key)?;
+        let mut msg = MSG::default();
+        while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+            if msg.message == WM_HOTKEY && msg.wParam.0 == HOTKEY_ID as usize {
+                on_press();
+                continue;
+            }
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+        let _ = UnregisterHotKey(None, HOTKEY_ID);
+    }
+    Ok(())
+}
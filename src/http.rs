@@ -0,0 +1,281 @@
+/// The requests of the named pipe over HTTP, for home automation and other
+/// things that can't open a pipe
+///
+/// ```text
+/// GET  /controllers
+/// POST /controllers/{device}/enable
+/// POST /controllers/{device}/disable
+/// POST /controllers/{device}/toggle
+/// ```
+///
+/// `{device}` is an instance id, alias or selector, percent-encoded, like
+/// `name%3Axbox`. Bodies are the JSON responses of the pipe, and failures
+/// get a status that matches their exit code. With a token, every request
+/// has to have an `Authorization: Bearer <token>` header. Connections are
+/// served one at a time and closed after the response.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    time::Duration,
+};
+
+use nojoy_rs::provider::DeviceProvider;
+
+use crate::{
+    exit,
+    pipe::{self, Request, Response},
+};
+
+/// How long a client can take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest request line or header that's read
+const MAX_LINE: usize = 8 * 1024;
+
+/// Serve requests on `address` until an error occurs
+pub fn serve(
+    address: SocketAddr,
+    token: Option<&str>,
+    provider: &impl DeviceProvider,
+    vid: Option<u16>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::warn!("can't accept a connection: {}", err);
+                continue;
+            }
+        };
+        if let Err(err) = serve_client(stream, token, provider, vid) {
+            log::warn!("HTTP client failed: {}", err);
+        }
+    }
+    Ok(())
+}
+
+fn serve_client(
+    stream: TcpStream,
+    token: Option<&str>,
+    provider: &impl DeviceProvider,
+    vid: Option<u16>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let request_line = read_line(&mut reader)?;
+    let mut authorization = None;
+    loop {
+        let header = read_line(&mut reader)?;
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+    }
+    log::info!("HTTP {}", request_line);
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let (status, response) = match token {
+        Some(token) if !authorized(authorization.as_deref(), token) => (
+            "401 Unauthorized",
+            failure(exit::ACCESS_DENIED, "missing or wrong token"),
+        ),
+        _ => route(method, target)
+            .map(|request| {
+                let response = pipe::handle(provider, vid, request);
+                (status(&response), response)
+            })
+            .unwrap_or_else(|(status, text)| (status, failure(exit::INVALID_ARGUMENT, text))),
+    };
+    let body = serde_json::to_string(&response).unwrap();
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// A line of the request head without the line break
+fn read_line(reader: &mut BufReader<&TcpStream>) -> io::Result<String> {
+    let mut line = String::new();
+    reader.by_ref().take(MAX_LINE as u64).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "request line too long or cut short",
+        ));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+fn failure(code: i32, text: &str) -> Response {
+    Response::failure(&exit::Message::new(code, text))
+}
+
+/// Compares all of the token regardless of where it differs, so that timing
+/// doesn't tell how much of a guess is right
+fn authorized(authorization: Option<&str>, token: &str) -> bool {
+    // the scheme isn't case sensitive, the token is
+    let Some(given) = authorization
+        .and_then(|a| a.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("Bearer"))
+        .map(|(_, token)| token)
+    else {
+        return false;
+    };
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// The request a method and path stand for, or the status and reason they
+/// don't stand for any
+fn route(method: &str, target: &str) -> Result<Request, (&'static str, &'static str)> {
+    let path = target.split('?').next().unwrap_or("");
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let request = match segments.as_slice() {
+        ["controllers"] => (method == "GET").then_some(Request::List),
+        ["controllers", device, action] => {
+            let device = percent_decode(device).ok_or(("400 Bad Request", "invalid device"))?;
+            let request = match *action {
+                "enable" => Request::Enable { device },
+                "disable" => Request::Disable { device },
+                "toggle" => Request::Toggle { device },
+                _ => return Err(("404 Not Found", "unknown action")),
+            };
+            (method == "POST").then_some(request)
+        }
+        _ => return Err(("404 Not Found", "unknown path")),
+    };
+    request.ok_or(("405 Method Not Allowed", "wrong method for the path"))
+}
+
+/// HTTP status of a response of the pipe
+fn status(response: &Response) -> &'static str {
+    match (response.ok, response.code) {
+        (true, _) => "200 OK",
+        (_, Some(exit::NOT_FOUND)) => "404 Not Found",
+        (_, Some(exit::INVALID_ARGUMENT)) => "400 Bad Request",
+        (_, Some(exit::ACCESS_DENIED)) => "403 Forbidden",
+        (_, Some(exit::NOT_PRESENT | exit::NOT_DISABLEABLE)) => "409 Conflict",
+        _ => "500 Internal Server Error",
+    }
+}
+
+/// Decode `%XX` escapes, None if they're malformed or not UTF-8
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b != b'%' {
+            bytes.push(b);
+            rest = tail;
+            continue;
+        }
+        let hex = tail
+            .get(..2)
+            .filter(|h| h.iter().all(u8::is_ascii_hexdigit))?;
+        bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+        rest = &tail[2..];
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_right_bearer_token_is_authorized() {
+        assert!(authorized(Some("Bearer s3cret"), "s3cret"));
+        assert!(authorized(Some("bearer s3cret"), "s3cret"));
+        assert!(!authorized(Some("Bearer S3CRET"), "s3cret"));
+        assert!(!authorized(Some("Bearer s3cre"), "s3cret"));
+        assert!(!authorized(Some("Bearer s3cret2"), "s3cret"));
+        assert!(!authorized(Some("Basic s3cret"), "s3cret"));
+        assert!(!authorized(Some("s3cret"), "s3cret"));
+        assert!(!authorized(Some("Bearer "), "s3cret"));
+        assert!(!authorized(None, "s3cret"));
+    }
+
+    #[test]
+    fn escapes_are_decoded() {
+        assert_eq!(percent_decode("name%3Axbox").unwrap(), "name:xbox");
+        assert_eq!(
+            percent_decode("HID%5CVID_045E%5c1").unwrap(),
+            r"HID\VID_045E\1"
+        );
+        assert_eq!(percent_decode("caf%C3%A9").unwrap(), "café");
+        assert_eq!(percent_decode("plain").unwrap(), "plain");
+    }
+
+    #[test]
+    fn malformed_escapes_are_refused() {
+        assert_eq!(percent_decode("xbox%"), None);
+        assert_eq!(percent_decode("xbox%4"), None);
+        assert_eq!(percent_decode("%G1"), None);
+        assert_eq!(percent_decode("%+1"), None);
+        assert_eq!(percent_decode("%FF"), None);
+        assert_eq!(percent_decode("%C3"), None);
+    }
+
+    #[test]
+    fn paths_are_routed() {
+        assert_eq!(route("GET", "/controllers"), Ok(Request::List));
+        assert_eq!(route("GET", "/controllers/"), Ok(Request::List));
+        assert_eq!(
+            route("POST", "/controllers/name%3Axbox/disable"),
+            Ok(Request::Disable {
+                device: "name:xbox".to_string()
+            })
+        );
+        assert_eq!(
+            route("POST", "/controllers/pad/toggle"),
+            Ok(Request::Toggle {
+                device: "pad".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn query_strings_are_ignored() {
+        assert_eq!(route("GET", "/controllers?all=1"), Ok(Request::List));
+        assert_eq!(
+            route("POST", "/controllers/pad/enable?now"),
+            Ok(Request::Enable {
+                device: "pad".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_paths_and_wrong_methods_are_told_apart() {
+        let status = |method, target| route(method, target).unwrap_err().0;
+        assert_eq!(status("GET", "/"), "404 Not Found");
+        assert_eq!(status("GET", "/devices"), "404 Not Found");
+        assert_eq!(status("POST", "/controllers/pad/remove"), "404 Not Found");
+        assert_eq!(status("POST", "/controllers/pad"), "404 Not Found");
+        assert_eq!(status("POST", "/controllers"), "405 Method Not Allowed");
+        assert_eq!(
+            status("GET", "/controllers/pad/disable"),
+            "405 Method Not Allowed"
+        );
+        assert_eq!(
+            status("POST", "/controllers/%ZZ/disable"),
+            "400 Bad Request"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Making a controller rumble or flash, to find out which physical pad a
+//! devnode is
+//!
+//! XInput controllers rumble through their XInput slot, whose player light
+//! also tells which one it is. Sony pads get a rumble and a flashing light
+//! bar through an output report, only over USB since Bluetooth reports need
+//! a checksum and a different layout. Other controllers have no common way
+//! of doing either.
+//!
+//! Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{thread, time::Duration};
+
+use serde::Serialize;
+
+use crate::{
+    devenum::{vid_pid, Error, GameController},
+    hid::{Access, HidDevice},
+    xinput,
+};
+
+const SONY: u16 = 0x054c;
+const DUALSHOCK4: &[u16] = &[0x05c4, 0x09cc, 0x0ba0];
+const DUALSENSE: &[u16] = &[0x0ce6, 0x0df2];
+
+/// Output report sizes over USB, with the report id
+const DUALSHOCK4_REPORT_LENGTH: usize = 32;
+const DUALSENSE_REPORT_LENGTH: usize = 48;
+
+/// How the controller was made to show itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Signal {
+    /// Rumbled through the XInput slot, 0 to 3
+    XInput { slot: u32 },
+    /// Rumbled and flashed its light bar through an output report
+    OutputReport,
+}
+
+/// Make the controller rumble, or flash too where it can, for about
+/// `duration`
+pub fn identify(controller: &GameController, duration: Duration) -> Result<Signal, Error> {
+    if let Some(slot) = controller.xinput_slot {
+        return match xinput::rumble(slot, duration) {
+            true => Ok(Signal::XInput { slot }),
+            false => Err(Error::NotPresent),
+        };
+    }
+    match vid_pid(&controller.instance_id) {
+        Some((SONY, pid)) if DUALSHOCK4.contains(&pid) => sony(
+            controller,
+            duration,
+            DUALSHOCK4_REPORT_LENGTH,
+            dualshock4_report,
+        ),
+        Some((SONY, pid)) if DUALSENSE.contains(&pid) => sony(
+            controller,
+            duration,
+            DUALSENSE_REPORT_LENGTH,
+            dualsense_report,
+        ),
+        _ if controller.instance_id.to_uppercase().contains("&IG_") => Err(Error::Unsupported(
+            "rumbling while identical XInput controllers are connected",
+        )),
+        _ => Err(Error::Unsupported(
+            "identifying, only XInput and Sony controllers can rumble or flash",
+        )),
+    }
+}
+
+fn sony(
+    controller: &GameController,
+    duration: Duration,
+    length: usize,
+    report: fn(bool) -> Vec<u8>,
+) -> Result<Signal, Error> {
+    let device = HidDevice::open(&controller.instance_id, Access::ReadWrite)?;
+    let caps = device.preparsed()?.caps()?;
+    if caps.OutputReportByteLength as usize != length {
+        return Err(Error::Unsupported("identifying over Bluetooth"));
+    }
+    device.write(&report(true))?;
+    thread::sleep(duration);
+    device.write(&report(false))?;
+    Ok(Signal::OutputReport)
+}
+
+/// Rumble at full speed with a white flashing light bar, or stop and go back
+/// to the blue of the first player
+fn dualshock4_report(on: bool) -> Vec<u8> {
+    let mut report = vec![0u8; DUALSHOCK4_REPORT_LENGTH];
+    report[0] = 0x05;
+    // rumble, light bar and flash are set
+    report[1] = 0x07;
+    let (motor, color, flash) = match on {
+        true => (0xff, [0xff, 0xff, 0xff], 0x10),
+        false => (0, [0, 0, 0x40], 0),
+    };
+    report[4] = motor;
+    report[5] = motor;
+    report[6..9].copy_from_slice(&color);
+    report[9] = flash;
+    report[10] = flash;
+    report
+}
+
+/// Like [`dualshock4_report`] without the flashing, which DualSense doesn't
+/// do on its own
+fn dualsense_report(on: bool) -> Vec<u8> {
+    let mut report = vec![0u8; DUALSENSE_REPORT_LENGTH];
+    report[0] = 0x02;
+    // rumble the way DualShock 4 does, instead of the haptics
+    report[1] = 0x03;
+    // light bar is set
+    report[2] = 0x04;
+    let (motor, color) = match on {
+        true => (0xff, [0xff, 0xff, 0xff]),
+        false => (0, [0, 0, 0x40]),
+    };
+    report[3] = motor;
+    report[4] = motor;
+    report[45..48].copy_from_slice(&color);
+    report
+}
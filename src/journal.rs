@@ -0,0 +1,467 @@
+/// The changes made to devices, so that `undo` can turn them back
+///
+/// Every device a command enables, disables, hides or changes the power
+/// settings of gets a line in journal.jsonl in the config directory, with
+/// the state it was in before. All changes of one command share a run, and
+/// `undo` turns back whole runs, the last one first. Commands that keep
+/// running, `auto`, `tui`, `tray`, `hotkey` and `serve`, start a run for
+/// every change they're asked for: a profile applied or restored, a toggle,
+/// a hotkey press or a request. So do `run` when it applies its profile and
+/// when it restores the devices. Only the timer of `disable --for` isn't
+/// recorded, it turns back a change its `disable` already recorded.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{Mutex, Once, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use nojoy_rs::{
+    devenum::{self, Error, GameController, Kind, Scope},
+    provider::{
+        BulkResult, DeviceChange, DeviceProvider, HardwareProfile, HidHideProvider, SetupApi,
+    },
+    timestamp::Timestamp,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::aliases;
+
+const JOURNAL_FILE_NAME: &str = "journal.jsonl";
+
+/// Older entries are dropped once there are more than this
+const MAX_ENTRIES: usize = 1000;
+
+/// How the device was changed, which is also how it's changed back
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Method {
+    Device,
+    /// Only in the current hardware profile
+    Profile,
+    HidHide,
+    Wake,
+    SelectiveSuspend,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Milliseconds since the Unix epoch when the command made its first
+    /// change
+    pub run: u64,
+    pub time: String,
+    pub method: Method,
+    pub instance_id: String,
+    /// Only there to make the journal readable
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// Whether the device was enabled, visible, allowed to wake the
+    /// computer, or had selective suspend on, before the change
+    pub before: bool,
+    /// The scope the device was found in, to find it again
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+    /// Older entries don't have it, they were found among connected devices
+    #[serde(default)]
+    pub include_disconnected: bool,
+}
+
+impl Entry {
+    pub fn scope(&self) -> Result<Scope, Error> {
+        Ok(Scope {
+            kind: Kind::from_name(&self.kind).unwrap_or_default(),
+            class: self.class.as_deref().map(devenum::class_guid).transpose()?,
+            interface: self
+                .interface
+                .as_deref()
+                .map(devenum::interface_guid)
+                .transpose()?,
+            include_disconnected: self.include_disconnected,
+        })
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    aliases::config_dir()
+        .ok()
+        .map(|dir| dir.join(JOURNAL_FILE_NAME))
+}
+
+struct Runs {
+    /// The run changes are recorded in, None until the next change
+    current: Option<u64>,
+    last: u64,
+}
+
+static RUNS: Mutex<Runs> = Mutex::new(Runs {
+    current: None,
+    last: 0,
+});
+
+fn run() -> u64 {
+    let mut runs = RUNS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(run) = runs.current {
+        return run;
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    // two runs in the same millisecond are still two
+    let run = now.max(runs.last + 1);
+    runs.last = run;
+    runs.current = Some(run);
+    run
+}
+
+/// Record the changes from here on in a run of their own, which `undo`
+/// turns back separately from the ones before
+pub fn start_run() {
+    RUNS.lock().unwrap_or_else(PoisonError::into_inner).current = None;
+}
+
+/// The entries, the oldest first. Lines that can't be read are skipped.
+pub fn load() -> io::Result<Vec<Entry>> {
+    let Some(path) = path() else {
+        return Ok(Vec::new());
+    };
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            serde_json::from_str(line)
+                .map_err(|err| log::warn!("journal entry skipped: {}", err))
+                .ok()
+        })
+        .collect())
+}
+
+/// Replace the journal with given entries
+pub fn save(entries: &[Entry]) -> io::Result<()> {
+    let Some(path) = path() else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let skip = entries.len().saturating_sub(MAX_ENTRIES);
+    let mut text = String::new();
+    for entry in &entries[skip..] {
+        text += &serde_json::to_string(entry)?;
+        text += "\n";
+    }
+    fs::write(path, text)
+}
+
+/// Add the entry at the end of the journal. The first entry of a run drops
+/// the entries over `MAX_ENTRIES` first, the rest are only appended.
+fn append(entry: &Entry) -> io::Result<()> {
+    static TRIM: Once = Once::new();
+    let Some(path) = path() else {
+        return Ok(());
+    };
+    let mut trimmed = Ok(());
+    TRIM.call_once(|| trimmed = load().and_then(|entries| save(&entries)));
+    trimmed?;
+    let mut line = serde_json::to_string(entry)?;
+    line += "\n";
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
+
+/// Write down that the device was changed from the state it was in
+pub fn record(method: Method, scope: Scope, id: &str, name: &str, before: bool) {
+    let entry = Entry {
+        run: run(),
+        time: Timestamp::now().to_iso8601(),
+        method,
+        instance_id: id.to_string(),
+        name: name.to_string(),
+        before,
+        kind: scope.kind.name().to_string(),
+        class: scope.class.map(|guid| format!("{:?}", guid)),
+        interface: scope.interface.map(|guid| format!("{:?}", guid)),
+        include_disconnected: scope.include_disconnected,
+    };
+    if let Err(err) = append(&entry) {
+        log::warn!(
+            "the change to {} can't be written to the journal: {}",
+            id,
+            err
+        );
+    }
+}
+
+/// Turn the device of the entry back to the state it was in before
+pub fn revert(entry: &Entry, provider: &impl DeviceProvider) -> Result<DeviceChange, Error> {
+    provider.set_enabled(&entry.instance_id, entry.before)
+}
+
+/// The entries of the last `count` runs, the newest first, and the rest
+pub fn split_last_runs(entries: Vec<Entry>, count: usize) -> (Vec<Entry>, Vec<Entry>) {
+    let mut runs: Vec<u64> = entries.iter().map(|e| e.run).collect();
+    runs.dedup();
+    let first = runs.len().saturating_sub(count);
+    let runs = &runs[first..];
+    let (mut last, rest): (Vec<Entry>, Vec<Entry>) =
+        entries.into_iter().partition(|e| runs.contains(&e.run));
+    last.reverse();
+    (last, rest)
+}
+
+/// Providers that can change a device back
+pub trait Reversible: DeviceProvider {
+    fn method(&self) -> Method;
+    fn scope(&self) -> Scope;
+}
+
+impl Reversible for SetupApi {
+    fn method(&self) -> Method {
+        Method::Device
+    }
+
+    fn scope(&self) -> Scope {
+        self.scope
+    }
+}
+
+impl Reversible for HardwareProfile {
+    fn method(&self) -> Method {
+        Method::Profile
+    }
+
+    fn scope(&self) -> Scope {
+        self.scope
+    }
+}
+
+impl Reversible for HidHideProvider {
+    fn method(&self) -> Method {
+        Method::HidHide
+    }
+
+    fn scope(&self) -> Scope {
+        self.scope
+    }
+}
+
+/// Writes every change the provider makes to the journal
+pub struct Journaled<P> {
+    inner: P,
+}
+
+impl<P: Reversible> Journaled<P> {
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+
+    fn record(&self, id: &str, name: &str, change: &Result<DeviceChange, Error>) {
+        let before = match change {
+            Ok(DeviceChange::Enabled | DeviceChange::EnabledAfterRestart) => false,
+            Ok(DeviceChange::Disabled | DeviceChange::DisabledAfterRestart) => true,
+            _ => return,
+        };
+        record(self.inner.method(), self.inner.scope(), id, name, before);
+    }
+
+    fn record_bulk(&self, results: &Result<Vec<BulkResult>, Error>) {
+        for result in results.iter().flatten() {
+            self.record(&result.instance_id, &result.name, &result.result);
+        }
+    }
+}
+
+impl<P: Reversible> DeviceProvider for Journaled<P> {
+    fn controllers(&self) -> Result<Vec<GameController>, Error> {
+        self.inner.controllers()
+    }
+
+    fn set_enabled(&self, id: &str, enabled: bool) -> Result<DeviceChange, Error> {
+        let change = self.inner.set_enabled(id, enabled);
+        self.record(id, "", &change);
+        change
+    }
+
+    fn toggle(&self, id: &str) -> Result<DeviceChange, Error> {
+        let change = self.inner.toggle(id);
+        self.record(id, "", &change);
+        change
+    }
+
+    fn restart(&self, id: &str) -> Result<(), Error> {
+        self.inner.restart(id)
+    }
+
+    fn set_all_enabled(&self, enabled: bool) -> Result<Vec<BulkResult>, Error> {
+        let results = self.inner.set_all_enabled(enabled);
+        self.record_bulk(&results);
+        results
+    }
+
+    fn set_many_enabled(&self, ids: &[String], enabled: bool) -> Result<Vec<BulkResult>, Error> {
+        let results = self.inner.set_many_enabled(ids, enabled);
+        self.record_bulk(&results);
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process, sync::MutexGuard};
+
+    use nojoy_rs::{devenum::GameControllerStatus, provider::FakeProvider};
+
+    use super::*;
+
+    const XBOX: &str =
+        r"HID\{00001124-0000-1000-8000-00805F9B34FB}&VID_045E&PID_0B13&IG_00\8&2A1B3C4D&0&0000";
+    const DUALSENSE: &str = r"HID\VID_054C&PID_0CE6&MI_03\7&1F2E3D4C&0&0000";
+    const WHEEL: &str = r"HID\VID_046D&PID_C24F\6&3B4C5D6E&0&0000";
+
+    impl Reversible for FakeProvider {
+        fn method(&self) -> Method {
+            Method::Device
+        }
+
+        fn scope(&self) -> Scope {
+            Scope::default()
+        }
+    }
+
+    /// An empty journal in a config directory of the tests' own, which the
+    /// tests take turns with
+    fn empty_journal() -> MutexGuard<'static, ()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        let guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let dir = env::temp_dir().join(format!("nojoy-journal-test-{}", process::id()));
+        env::set_var(aliases::CONFIG_DIR_VAR, dir);
+        save(&[]).unwrap();
+        start_run();
+        guard
+    }
+
+    fn statuses(provider: &impl DeviceProvider) -> Vec<GameControllerStatus> {
+        provider
+            .controllers()
+            .unwrap()
+            .iter()
+            .map(|c| c.status)
+            .collect()
+    }
+
+    fn entry(run: u64, instance_id: &str) -> Entry {
+        Entry {
+            run,
+            time: String::new(),
+            method: Method::Device,
+            instance_id: instance_id.to_string(),
+            name: String::new(),
+            before: true,
+            kind: "gamepad".to_string(),
+            class: None,
+            interface: None,
+            include_disconnected: false,
+        }
+    }
+
+    fn runs(entries: &[Entry]) -> Vec<(u64, &str)> {
+        entries
+            .iter()
+            .map(|e| (e.run, e.instance_id.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn undo_restores_the_state_before_each_run() {
+        let _journal = empty_journal();
+        let journaled = Journaled::new(FakeProvider::sample());
+        let original = statuses(&journaled);
+        let ids = [XBOX, DUALSENSE].map(String::from);
+        journaled.set_many_enabled(&ids, false).unwrap();
+        start_run();
+        journaled.toggle(XBOX).unwrap();
+
+        let (last, rest) = split_last_runs(load().unwrap(), 1);
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0].instance_id, XBOX);
+        assert!(!last[0].before);
+        for entry in &last {
+            revert(entry, &journaled.inner).unwrap();
+        }
+        assert_eq!(
+            statuses(&journaled)[..2],
+            [
+                GameControllerStatus::Disabled,
+                GameControllerStatus::Disabled
+            ]
+        );
+
+        // both devices of the first run are turned back together
+        let (last, rest) = split_last_runs(rest, 1);
+        let ids: Vec<&str> = last.iter().map(|e| e.instance_id.as_str()).collect();
+        assert_eq!(ids, [DUALSENSE, XBOX]);
+        assert!(rest.is_empty());
+        for entry in &last {
+            revert(entry, &journaled.inner).unwrap();
+        }
+        assert_eq!(statuses(&journaled), original);
+    }
+
+    #[test]
+    fn only_changes_are_recorded() {
+        let _journal = empty_journal();
+        let journaled = Journaled::new(FakeProvider::sample());
+        assert!(journaled.set_enabled(WHEEL, false).is_err());
+        journaled.set_enabled(DUALSENSE, true).unwrap();
+        journaled.set_all_enabled(true).unwrap();
+        assert!(load().unwrap().is_empty());
+    }
+
+    #[test]
+    fn every_run_started_is_a_run_of_its_own() {
+        let _journal = empty_journal();
+        let journaled = Journaled::new(FakeProvider::sample());
+        journaled.toggle(DUALSENSE).unwrap();
+        start_run();
+        journaled.toggle(DUALSENSE).unwrap();
+        let entries = load().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].run < entries[1].run);
+    }
+
+    #[test]
+    fn last_runs_are_split_off_newest_first() {
+        let entries = || vec![entry(1, "a"), entry(2, "b"), entry(2, "c"), entry(3, "d")];
+        let (last, rest) = split_last_runs(entries(), 2);
+        assert_eq!(runs(&last), [(3, "d"), (2, "c"), (2, "b")]);
+        assert_eq!(runs(&rest), [(1, "a")]);
+        let (last, rest) = split_last_runs(entries(), 0);
+        assert!(last.is_empty());
+        assert_eq!(rest.len(), 4);
+        let (last, rest) = split_last_runs(entries(), 10);
+        assert_eq!(last.len(), 4);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn old_entries_are_found_among_connected_devices() {
+        let line = r#"{"run":1,"time":"","method":"device","instance_id":"a","before":true,"kind":"gamepad"}"#;
+        let entry: Entry = serde_json::from_str(line).unwrap();
+        let scope = entry.scope().unwrap();
+        assert!(!scope.include_disconnected);
+        assert_eq!(scope.kind, Kind::Gamepad);
+    }
+}
@@ -0,0 +1,35 @@
+/// Running a program while we wait to clean up after it
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{
+    io,
+    process::{Command, ExitStatus},
+};
+
+use windows::Win32::{
+    Foundation::{BOOL, FALSE, TRUE},
+    System::Console::{SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT},
+};
+
+/// Ctrl+C reaches every process on the console. The child decides what to do
+/// with it while we keep waiting, so that there's always a chance to clean up.
+unsafe extern "system" fn ignore_interrupt(ctrl_type: u32) -> BOOL {
+    match ctrl_type {
+        CTRL_C_EVENT | CTRL_BREAK_EVENT => TRUE,
+        _ => FALSE,
+    }
+}
+
+/// Start `command` with the rest of the arguments and wait for it to exit,
+/// surviving Ctrl+C in the meantime
+pub fn run(command: &[String]) -> io::Result<ExitStatus> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no program given"))?;
+    unsafe { SetConsoleCtrlHandler(Some(ignore_interrupt), TRUE)? };
+    let status = Command::new(program).args(args).status();
+    unsafe {
+        let _ = SetConsoleCtrlHandler(Some(ignore_interrupt), FALSE);
+    }
+    status
+}
@@ -0,0 +1,53 @@
+//! Enumerating and toggling game controllers on Windows and Linux
+//!
+//! The command line tool is a thin layer over this crate, so anything
+//! it does can be done from another program too:
+//!
+//! ```no_run
+//! use nojoy_rs::devenum::{self, Outcome, Retry, Scope};
+//!
+//! let scope = Scope::default();
+//! for controller in devenum::game_controllers(scope).unwrap() {
+//!     match devenum::disable_device(&controller.instance_id, scope, Retry::default()) {
+//!         Ok(Outcome::Changed) => println!("disabled {}", controller.name),
+//!         Ok(Outcome::AlreadyInState) => println!("{} was already disabled", controller.name),
+//!         Ok(Outcome::RestartRequired) => println!("{} is disabled after a restart", controller.name),
+//!         Err(err) => println!("{}: {}", controller.name, err),
+//!     }
+//! }
+//! ```
+//!
+//! [`provider::DeviceProvider`] abstracts the same operations for code that
+//! also wants to run without real devices.
+//!
+//! On Linux, [`devenum`] finds and disables controllers through sysfs
+//! instead, with the same functions and types. The modules that only make
+//! sense on Windows, like HidHide or power management, aren't built
+//! there.
+//!
+//! Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+#[cfg(windows)]
+pub mod audit;
+pub mod decode;
+pub mod devenum;
+#[cfg(windows)]
+pub mod elevation;
+#[cfg(windows)]
+pub mod hid;
+#[cfg(windows)]
+pub mod hidhide;
+#[cfg(windows)]
+pub mod identify;
+#[cfg(windows)]
+pub mod power;
+pub mod provider;
+pub mod timestamp;
+#[cfg(windows)]
+pub mod watch;
+#[cfg(windows)]
+pub mod xinput;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+mod python;
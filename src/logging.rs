@@ -0,0 +1,63 @@
+/// Diagnostic logging to stderr, so it never mixes with the output on stdout,
+/// or to a file given with `--log-file`
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use nojoy_rs::timestamp::Timestamp;
+
+struct Logger;
+
+/// Where the log goes instead of stderr
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match FILE.lock().unwrap().as_mut() {
+            // files are read later, so they get the time too
+            Some(file) => {
+                let _ = writeln!(file, "{} [{}] {}", Timestamp::now(), record.level(), record.args());
+            }
+            None => {
+                let _ = writeln!(io::stderr(), "[{}] {}", record.level(), record.args());
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = FILE.lock().unwrap().as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Warnings only by default, each -v adds a level up to trace. The log is
+/// appended to `file` if it's given.
+pub fn init(verbosity: u8, file: Option<&Path>) -> io::Result<()> {
+    let level = match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    if let Some(path) = file {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        *FILE.lock().unwrap() = Some(file);
+    }
+    log::set_logger(&Logger).expect("logger is only set once");
+    log::set_max_level(level);
+    Ok(())
+}
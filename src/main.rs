@@ -1,7 +1,84 @@
-mod devenum;
+mod aliases;
+mod config;
+#[cfg(windows)]
+mod dedupe;
+#[cfg(windows)]
+mod doctor;
+mod exit;
+#[cfg(windows)]
+mod foreground;
+#[cfg(windows)]
+mod hotkey;
+#[cfg(windows)]
+mod http;
+#[cfg(windows)]
+mod journal;
+#[cfg(windows)]
+mod launch;
+mod logging;
+#[cfg(windows)]
+mod notify;
+mod ordinal;
+mod output;
+mod pipe;
+#[cfg(windows)]
+mod policy;
+mod selector;
+#[cfg(windows)]
+mod service;
+#[cfg(windows)]
+mod startup;
+#[cfg(windows)]
+mod tray;
+#[cfg(windows)]
+mod tui;
+mod usbids;
+#[cfg(windows)]
+mod why;
 
-use devenum::{disable_device, enable_device, game_controllers};
+use std::{
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+#[cfg(windows)]
+use std::{net::SocketAddr, path::Path, process::Command};
+
+use nojoy_rs::{
+    devenum::{self, GameController, GameControllerStatus, Kind, Retry, Scope},
+    provider::{BulkResult, DeviceChange, DeviceProvider, DryRun, FakeProvider},
+};
+#[cfg(windows)]
+use nojoy_rs::{
+    audit,
+    elevation,
+    hidhide::{self, HidHide},
+    devenum::{phantom_devices, remove_phantom_device},
+    hid::{self, HidDevice},
+    identify,
+    power,
+    timestamp::Timestamp,
+    provider::{Cached, HardwareProfile, HidHideProvider, SetupApi},
+    watch,
+};
+#[cfg(target_os = "linux")]
+use nojoy_rs::provider::Sysfs;
+use aliases::Aliases;
+use config::Config;
+#[cfg(windows)]
+use config::{ConfigError, Profile, TimeOfDay};
+#[cfg(windows)]
+use notify::Notifier;
+use output::{ListFormat, SortOrder};
+use selector::{Pick, SelectError};
 use clap::{Parser, Subcommand};
+use windows::core::GUID;
+#[cfg(windows)]
+use windows::Win32::System::Console::{GetConsoleMode, GetStdHandle, SetConsoleMode, CONSOLE_MODE, ENABLE_VIRTUAL_TERMINAL_PROCESSING, STD_OUTPUT_HANDLE};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Message {
@@ -11,18 +88,1013 @@ pub enum Message {
 
 #[derive(Debug, Clone, Subcommand)]
 pub enum MainCommand {
-    List,
+    List {
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+
+        /// List every HID device, not only game controllers, with the kind
+        /// of each (same as --kind all-hid)
+        #[arg(long, conflicts_with_all = ["kind", "class", "interface"])]
+        all_hid: bool,
+
+        /// Also show friendly names and locations, which tell identical
+        /// devices apart
+        #[arg(short, long)]
+        long: bool,
+
+        /// Print only instance ids, one per line
+        #[arg(long, conflicts_with = "format")]
+        id_only: bool,
+
+        #[arg(long, value_enum)]
+        sort: Option<SortOrder>,
+
+        #[arg(long, value_enum)]
+        format: Option<ListFormat>,
+    },
     Enable {
-        id: String,
+        #[command(flatten)]
+        targets: TargetsArgs,
+
+        #[command(flatten)]
+        pick: PickArgs,
+
+        /// Go on with the other devices when one of them fails
+        #[arg(long)]
+        continue_on_error: bool,
+
+        /// Don't ask for confirmation when a selector matches more than one
+        /// device
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Change the device in the current hardware profile only, through
+        /// its class installer like Device Manager, instead of in all of them
+        #[cfg(windows)]
+        #[arg(long)]
+        this_profile: bool,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
     },
     Disable {
+        #[command(flatten)]
+        targets: TargetsArgs,
+
+        #[command(flatten)]
+        pick: PickArgs,
+
+        /// Go on with the other devices when one of them fails
+        #[arg(long)]
+        continue_on_error: bool,
+
+        /// Don't ask for confirmation when a selector matches more than one
+        /// device
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Enable the device again after this long, e.g. 30m, 2h or 1h30m.
+        /// Restarting Windows before then keeps it disabled.
+        #[cfg(windows)]
+        #[arg(long = "for", value_parser = parse_duration)]
+        duration: Option<Duration>,
+
+        /// Change the device in the current hardware profile only, through
+        /// its class installer like Device Manager, instead of in all of them
+        #[cfg(windows)]
+        #[arg(long, conflicts_with = "duration")]
+        this_profile: bool,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Wait, then enable the device, started in the background by
+    /// `disable --for`
+    #[cfg(windows)]
+    #[command(hide = true)]
+    EnableAfter {
+        seconds: u64,
+
         id: String,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Enable and disable the devices of a profile in the config file
+    #[cfg(windows)]
+    Apply {
+        profile: String,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Apply profiles while the applications in the config file are in the
+    /// foreground, and put the devices back when they lose focus
+    #[cfg(windows)]
+    Auto {
+        /// Show a notification when a profile is applied or the devices are
+        /// restored
+        #[arg(long)]
+        notify: bool,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Disable controllers, run a program, and restore the controllers when
+    /// it exits, e.g. `run -- game.exe -fullscreen`
+    #[cfg(windows)]
+    Run {
+        /// Profile to apply instead of disabling all connected controllers
+        #[arg(long)]
+        profile: Option<String>,
+
+        /// Program to run and its arguments
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Interactive controller table
+    #[cfg(windows)]
+    Tui {
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Stay in the notification area with a menu to toggle controllers
+    #[cfg(windows)]
+    Tray {
+        /// Show a notification when a controller comes, goes, is enabled or
+        /// disabled
+        #[arg(long)]
+        notify: bool,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Wait in the background for a hotkey that disables the given devices,
+    /// or enables them again if they're already disabled
+    #[cfg(windows)]
+    Hotkey {
+        /// Devices to switch, all connected controllers when none given
+        devices: Vec<String>,
+
+        /// Key combination to listen for [default: Ctrl+Alt+J]
+        #[arg(long)]
+        key: Option<hotkey::Hotkey>,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Print controller events as they happen until interrupted
+    #[cfg(windows)]
+    Watch {
+        /// Show a notification when a controller comes, goes, is enabled or
+        /// disabled
+        #[arg(long)]
+        notify: bool,
+
+        /// json prints one object per line for every event
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Show every property of a device, for bug reports
+    #[cfg(windows)]
+    Info {
+        #[command(flatten)]
+        target: TargetArgs,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+    },
+    /// Explain why a device can't be disabled, and what to do instead
+    #[cfg(windows)]
+    Why {
+        #[command(flatten)]
+        target: TargetArgs,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+    },
+    /// Show the devnode status flags and problem code of a device
+    #[cfg(windows)]
+    Status {
+        #[command(flatten)]
+        target: TargetArgs,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+    },
+    /// Disable the device if it's enabled, enable it otherwise
+    Toggle {
+        #[command(flatten)]
+        target: TargetArgs,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Make the device rumble, or flash its lights, to tell which physical
+    /// controller it is
+    #[cfg(windows)]
+    Identify {
+        #[command(flatten)]
+        target: TargetArgs,
+
+        /// How long to rumble, like 5s
+        #[arg(long = "for", value_parser = parse_duration, default_value = "1s")]
+        duration: Duration,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Show the usage, buttons and axes the device's report descriptor
+    /// declares, to tell whether it really is a game controller
+    #[cfg(windows)]
+    Describe {
+        #[command(flatten)]
+        target: TargetArgs,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+    },
+    /// Print the buttons and axes of the device as they change, until
+    /// Ctrl+C, to see whether input comes from it
+    #[cfg(windows)]
+    Test {
+        #[command(flatten)]
+        target: TargetArgs,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Disable the device and enable it again, to unstick it
+    #[cfg(windows)]
+    Restart {
+        #[command(flatten)]
+        target: TargetArgs,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Enable all connected controllers
+    #[cfg(windows)]
+    EnableAll {
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Disable all connected controllers that can be disabled
+    #[cfg(windows)]
+    DisableAll {
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Write the enabled state of every connected controller to a file
+    #[cfg(windows)]
+    Save {
+        /// JSON file to write [default: snapshot.json in the config directory]
+        file: Option<PathBuf>,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Put controllers back to the states written by `save`
+    #[cfg(windows)]
+    Restore {
+        /// JSON file to read [default: snapshot.json in the config directory]
+        file: Option<PathBuf>,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Uninstall a device, connected or not, to clear out broken driver
+    /// leftovers
+    #[cfg(windows)]
+    Remove {
+        #[command(flatten)]
+        target: TargetArgs,
+
+        /// Work on all devices of a setup class (GUID or name like "Mouse")
+        /// instead of game controllers
+        #[arg(long, value_parser = parse_class)]
+        class: Option<GUID>,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Scan for hardware changes, to detect removed devices again
+    #[cfg(windows)]
+    Rescan,
+    /// Hide physical controllers that a remapper like DS4Windows or reWASD
+    /// also exposes as a virtual one, so games see each pad once
+    #[cfg(windows)]
+    Dedupe {
+        /// Disable the originals instead of hiding them with HidHide. Most
+        /// remappers stop getting input from a disabled controller.
+        #[arg(long)]
+        disable: bool,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Remove leftover devnodes of controllers that aren't connected, like
+    /// the ones every pairing of a Bluetooth pad leaves behind
+    #[cfg(windows)]
+    #[command(visible_alias = "cleanup")]
+    Prune {
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Check the environment for common problems
+    #[cfg(windows)]
+    Doctor {
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+    },
+    /// Manage device aliases
+    Alias {
+        #[command(subcommand)]
+        command: AliasCommand,
+    },
+    /// Hide controllers from applications with the HidHide driver instead
+    /// of disabling them
+    #[cfg(windows)]
+    #[command(name = "hidhide")]
+    HidHide {
+        #[command(subcommand)]
+        command: HidHideCommand,
+    },
+    /// Apply a profile at every logon, so devices that Windows Update or a
+    /// driver reinstall enabled again get disabled
+    #[cfg(windows)]
+    Startup {
+        #[command(subcommand)]
+        command: StartupCommand,
+    },
+    /// Change whether a controller can wake the computer, or be turned off
+    /// while it's idle, without disabling it
+    #[cfg(windows)]
+    Power {
+        #[command(subcommand)]
+        command: PowerCommand,
+    },
+    /// Turn back the changes of the last command, or of the last <COUNT>
+    /// commands. Every change auto, tui, tray, hotkey and serve make counts
+    /// as a command of its own, the timer of disable --for isn't recorded.
+    #[cfg(windows)]
+    Undo {
+        #[arg(default_value_t = 1)]
+        count: usize,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Set up the event source of --audit
+    #[cfg(windows)]
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+    /// Enable and disable devices for other applications that send requests
+    /// to a named pipe, so that only this has to run as administrator
+    #[cfg(windows)]
+    Serve {
+        /// Serve a REST API on this address, like 127.0.0.1:8080, instead
+        /// of the named pipe
+        #[arg(long, value_name = "ADDRESS")]
+        http: Option<SocketAddr>,
+
+        /// Require `Authorization: Bearer <TOKEN>` on every HTTP request
+        #[arg(long, requires = "http")]
+        token: Option<String>,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Send a request to a running `serve`
+    #[cfg(windows)]
+    Client {
+        #[command(subcommand)]
+        request: pipe::Request,
+    },
+    /// Keep a profile applied from a Windows service
+    #[cfg(windows)]
+    Service {
+        #[command(subcommand)]
+        command: ServiceCommand,
+    },
+    /// Inspect the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum AliasCommand {
+    /// Add or replace an alias
+    Add {
+        name: String,
+        /// Instance id or selector the alias stands for
+        selector: String,
+    },
+    Remove {
+        name: String,
+    },
+    List,
+}
+
+#[cfg(windows)]
+#[derive(Debug, Clone, Subcommand)]
+pub enum HidHideCommand {
+    /// Hide the device from all applications that aren't allowed to see it
+    Hide {
+        #[command(flatten)]
+        target: TargetArgs,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Make the device visible to all applications again
+    Unhide {
+        #[command(flatten)]
+        target: TargetArgs,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Let the application see hidden devices
+    Allow {
+        /// Path of the executable
+        program: PathBuf,
+    },
+    /// Hide hidden devices from the application again
+    Disallow {
+        /// Path of the executable
+        program: PathBuf,
+    },
+    /// Print the hidden devices and the allowed applications
+    Status,
+}
+
+#[cfg(windows)]
+#[derive(Debug, Clone, Subcommand)]
+pub enum StartupCommand {
+    /// Create the logon task, or replace the one there is
+    Register {
+        /// Profile to apply
+        profile: String,
+    },
+    /// Remove the logon task
+    Unregister,
+}
+
+#[cfg(windows)]
+#[derive(Debug, Clone, Subcommand)]
+pub enum PowerCommand {
+    /// Allow the device to wake the computer from sleep, or stop it. This is
+    /// set on the USB or Bluetooth parent when the device itself can't wake
+    /// the computer.
+    Wake {
+        #[arg(value_enum)]
+        setting: Setting,
+
+        #[command(flatten)]
+        target: TargetArgs,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Let Windows turn the USB device of the controller off while it's
+    /// idle, or keep it on. Takes effect when it's plugged in again.
+    SelectiveSuspend {
+        #[arg(value_enum)]
+        setting: Setting,
+
+        #[command(flatten)]
+        target: TargetArgs,
+
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+}
+
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Setting {
+    On,
+    Off,
+}
+
+#[cfg(windows)]
+#[derive(Debug, Clone, Subcommand)]
+pub enum AuditCommand {
+    /// Register the event source, so that Event Viewer shows the events
+    /// properly
+    Register,
+    /// Remove the event source
+    Unregister,
+}
+
+#[cfg(windows)]
+#[derive(Debug, Clone, Subcommand)]
+pub enum ServiceCommand {
+    /// Install and start the service, it reads the config file of the
+    /// current user
+    Install {
+        /// Profile to enforce while no [[schedule]] window is open
+        profile: String,
+    },
+    /// Stop and remove the service
+    Uninstall,
+    /// Entry point of the service, started by the service manager
+    #[command(hide = true)]
+    Run {
+        profile: String,
+
+        #[arg(long)]
+        config_dir: Option<PathBuf>,
     },
 }
 
+#[derive(Debug, Clone, Subcommand)]
+pub enum ConfigCommand {
+    /// Print where the config file is looked up
+    Path,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Text,
+    Json,
+}
+
+/// Whether to color the output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// When printing to a console
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+#[group(required = true, multiple = false)]
+pub struct TargetArgs {
+    /// Instance id, row number in the last list, alias, or selector
+    /// (name:<text>, manufacturer:<text>, vid:<vid>[:<pid>], serial:<value>,
+    /// device:<identity>, match:<regex>)
+    pub id: Option<String>,
+
+    /// USB serial number or Bluetooth address of the device
+    #[arg(long)]
+    pub serial: Option<String>,
+
+    /// Case-insensitive part of the device name
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Case-insensitive part of the manufacturer name
+    #[arg(long)]
+    pub manufacturer: Option<String>,
+
+    /// Case-insensitive regular expression matched against the instance id,
+    /// the name and the manufacturer
+    #[arg(long = "match", value_name = "REGEX", value_parser = parse_regex)]
+    pub regex: Option<String>,
+}
+
+impl TargetArgs {
+    /// The device to work on as a selector string
+    fn selector(self) -> String {
+        if let Some(serial) = self.serial {
+            return format!("serial:{}", serial);
+        }
+        if let Some(name) = self.name {
+            return format!("name:{}", name);
+        }
+        if let Some(manufacturer) = self.manufacturer {
+            return format!("manufacturer:{}", manufacturer);
+        }
+        if let Some(regex) = self.regex {
+            return format!("match:{}", regex);
+        }
+        self.id.expect("clap requires one of the arguments")
+    }
+}
+
+/// Like `TargetArgs`, for commands that work on several devices at once
+#[derive(Debug, Clone, clap::Args)]
+#[group(required = true, multiple = false)]
+pub struct TargetsArgs {
+    /// Instance ids, row numbers in the last list, aliases, or selectors
+    /// (name:<text>, manufacturer:<text>, vid:<vid>[:<pid>], serial:<value>,
+    /// device:<identity>, match:<regex>). A single - reads one of them per
+    /// line from stdin.
+    pub ids: Vec<String>,
+
+    /// USB serial number or Bluetooth address of the device
+    #[arg(long)]
+    pub serial: Option<String>,
+
+    /// Case-insensitive part of the device name
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Case-insensitive part of the manufacturer name
+    #[arg(long)]
+    pub manufacturer: Option<String>,
+
+    /// Case-insensitive regular expression matched against the instance id,
+    /// the name and the manufacturer
+    #[arg(long = "match", value_name = "REGEX", value_parser = parse_regex)]
+    pub regex: Option<String>,
+}
+
+impl TargetsArgs {
+    /// The devices to work on as selector strings
+    fn selectors(self) -> Vec<String> {
+        let target = TargetArgs {
+            id: None,
+            serial: self.serial,
+            name: self.name,
+            manufacturer: self.manufacturer,
+            regex: self.regex,
+        };
+        match self.ids.is_empty() {
+            true => vec![target.selector()],
+            false => self.ids,
+        }
+    }
+}
+
+/// What to do when a selector matches more than one device
+#[derive(Debug, Clone, clap::Args)]
+pub struct PickArgs {
+    /// Use the first device that matches
+    #[arg(long, conflicts_with = "all")]
+    pub first: bool,
+
+    /// Use every device that matches
+    #[arg(long)]
+    pub all: bool,
+}
+
+impl PickArgs {
+    fn pick(self) -> Pick {
+        match (self.first, self.all) {
+            (true, _) => Pick::First,
+            (_, true) => Pick::All,
+            _ => Pick::One,
+        }
+    }
+}
+
+/// Which of the enumerated controllers to list, all given filters have to
+/// match
+#[derive(Debug, Clone, clap::Args)]
+pub struct FilterArgs {
+    /// Only enabled controllers
+    #[arg(long)]
+    pub enabled: bool,
+
+    /// Only disabled controllers
+    #[arg(long)]
+    pub disabled: bool,
+
+    /// Only connected controllers, useful with --include-disconnected
+    #[arg(long)]
+    pub connected: bool,
+
+    /// Only controllers that can be disabled
+    #[arg(long)]
+    pub disableable: bool,
+
+    /// Only controllers that are plugged in, not made by software like
+    /// ViGEmBus, vJoy or Steam Input
+    #[arg(long, conflicts_with = "virtual_only")]
+    pub physical_only: bool,
+
+    /// Only virtual controllers
+    #[arg(long)]
+    pub virtual_only: bool,
+}
+
+impl FilterArgs {
+    fn matches(&self, controller: &GameController) -> bool {
+        (!self.enabled || controller.status == GameControllerStatus::Enabled)
+            && (!self.disabled || controller.status == GameControllerStatus::Disabled)
+            && (!self.connected || controller.status != GameControllerStatus::Disconnected)
+            && (!self.disableable || controller.disableable)
+            && (!self.physical_only || !controller.is_virtual)
+            && (!self.virtual_only || controller.is_virtual)
+    }
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct ScopeArgs {
+    /// Include controllers that are paired or installed but not connected
+    #[arg(long, overrides_with = "no_include_disconnected")]
+    pub include_disconnected: bool,
+
+    /// Leave disconnected controllers out even if the config file or
+    /// NOJOY_INCLUDE_DISCONNECTED includes them
+    #[arg(long, overrides_with = "include_disconnected")]
+    pub no_include_disconnected: bool,
+
+    /// Kind of HID devices to work on: gamepad, keyboard, mouse or all-hid
+    #[arg(long, value_parser = parse_kind, default_value = "gamepad", conflicts_with_all = ["class", "interface"])]
+    pub kind: Kind,
+
+    /// Work on all devices of a setup class (GUID or name like "Mouse")
+    /// instead of game controllers
+    #[arg(long, value_parser = parse_class, conflicts_with = "interface")]
+    pub class: Option<GUID>,
+
+    /// Work on all devices with a device interface (GUID, "hid", "keyboard"
+    /// or "mouse") instead of game controllers
+    #[arg(long, value_parser = parse_interface)]
+    pub interface: Option<GUID>,
+}
+
+#[cfg(windows)]
+fn parse_class(class: &str) -> Result<GUID, String> {
+    devenum::class_guid(class).map_err(|e| e.to_string())
+}
+
+/// Setup classes only exist on Windows
+#[cfg(not(windows))]
+fn parse_class(_class: &str) -> Result<GUID, String> {
+    Err("setup classes are only there on Windows".to_string())
+}
+
+fn parse_kind(kind: &str) -> Result<Kind, String> {
+    Kind::from_name(kind).ok_or_else(|| "expected one of gamepad, keyboard, mouse or all-hid".to_string())
+}
+
+#[cfg(windows)]
+fn parse_interface(interface: &str) -> Result<GUID, String> {
+    devenum::interface_guid(interface).map_err(|e| e.to_string())
+}
+
+/// Device interfaces only exist on Windows
+#[cfg(not(windows))]
+fn parse_interface(_interface: &str) -> Result<GUID, String> {
+    Err("device interfaces are only there on Windows".to_string())
+}
+
+/// Check `--match` while parsing the arguments, so that clap can tell what's
+/// wrong with it
+fn parse_regex(text: &str) -> Result<String, regex::Error> {
+    selector::Pattern::new(text).map(|_| text.to_string())
+}
+
+/// A duration like 90s, 30m, 2h or 1h30m
+#[cfg(windows)]
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration \"{}\", expected something like 30m, 2h or 1h30m", text);
+    let mut seconds = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                number.push(c);
+                continue;
+            },
+            'h' | 'H' => 3600,
+            'm' | 'M' => 60,
+            's' | 'S' => 1,
+            _ => return Err(invalid()),
+        };
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        seconds += value * unit;
+        number.clear();
+    }
+    if !number.is_empty() || seconds == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+/// The duration the way `parse_duration` takes it
+#[cfg(windows)]
+fn duration_text(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    [(secs / 3600, "h"), (secs / 60 % 60, "m"), (secs % 60, "s")]
+        .iter()
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{}{}", value, unit))
+        .collect()
+}
+
+/// Start a copy of this process in the background that enables the device
+/// after `duration` with the same scope and retries. It keeps running after
+/// this one exits.
+#[cfg(windows)]
+fn enable_after(id: &str, duration: Duration, scope: Scope, retry: Retry) -> io::Result<()> {
+    use std::os::windows::process::CommandExt;
+    use windows::Win32::System::Threading::{CREATE_NEW_PROCESS_GROUP, DETACHED_PROCESS};
+
+    let mut args = vec!["enable-after".to_string(), duration.as_secs().to_string(), id.to_string()];
+    // given either way, so that the config file can't change it
+    args.push(match scope.include_disconnected {
+        true => "--include-disconnected".to_string(),
+        false => "--no-include-disconnected".to_string(),
+    });
+    args.extend([
+        "--retries".to_string(),
+        retry.retries.to_string(),
+        "--retry-delay".to_string(),
+        retry.delay.as_millis().to_string(),
+    ]);
+    if let Some(class) = scope.class {
+        args.extend(["--class".to_string(), format!("{:?}", class)]);
+    } else if let Some(interface) = scope.interface {
+        args.extend(["--interface".to_string(), format!("{:?}", interface)]);
+    } else {
+        args.extend(["--kind".to_string(), scope.kind.name().to_string()]);
+    }
+    Command::new(std::env::current_exe()?)
+        .args(args)
+        .creation_flags((DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP).0)
+        .spawn()?;
+    Ok(())
+}
+
+impl ScopeArgs {
+    /// What the command line says about disconnected devices, if anything
+    fn include_disconnected(&self) -> Option<bool> {
+        match (self.include_disconnected, self.no_include_disconnected) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        }
+    }
+
+    fn scope(self, config: &Config) -> Scope {
+        Scope {
+            include_disconnected: config::pick(self.include_disconnected(), config.include_disconnected, false),
+            kind: self.kind,
+            class: self.class,
+            interface: self.interface,
+        }
+    }
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct RetryArgs {
+    /// Number of times to retry when the device is busy [default: 3]
+    #[arg(long)]
+    pub retries: Option<u32>,
+
+    /// Delay before the first retry in milliseconds, doubled on every retry
+    /// [default: 100]
+    #[arg(long)]
+    pub retry_delay: Option<u64>,
+}
+
+impl RetryArgs {
+    fn retry(self, config: &Config) -> Retry {
+        Retry {
+            retries: config::pick(self.retries, config.retries, Retry::default().retries),
+            delay: config::pick(self.retry_delay.map(Duration::from_millis), config.retry_delay.map(Duration::from_millis), Retry::default().delay),
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
+    /// Log Win32 calls and why devices are skipped, repeat for more detail
+    /// (-vvv includes timings)
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Append the log to this file instead of writing it to stderr
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Ignore the config file
+    #[arg(long, global = true)]
+    pub no_config: bool,
+
+    /// Only show what would be enabled, disabled or removed, after the same
+    /// selection, elevation and disableability checks as doing it
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Work on a few made up controllers instead of the real ones, to try
+    /// commands out without hardware or administrator rights. Nothing is
+    /// changed, every run starts with the same controllers.
+    #[arg(long, global = true)]
+    pub simulate: bool,
+
+    /// Print only data and errors, for scripts
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Print JSON for ConvertFrom-Json and the like: lists and other data
+    /// as with --format json, and what happened to each changed device as
+    /// an array of objects at the end. Errors are still on stderr.
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Write every enable, disable and remove to the Application event log
+    #[cfg(windows)]
+    #[arg(long, global = true)]
+    pub audit: bool,
+
+    /// Offer to restart Windows when a change only takes effect after one
+    #[cfg(windows)]
+    #[arg(long, global = true)]
+    pub reboot_prompt: bool,
+
+    /// Color the status of devices, overrides NOJOY_COLOR, NO_COLOR and the
+    /// config file [default: auto]
+    #[arg(long, value_enum, global = true)]
+    pub color: Option<ColorMode>,
+
+    /// Run the command again as administrator when it's denied access
+    #[cfg(windows)]
+    #[arg(long, global = true)]
+    pub elevate: bool,
 
     #[command(subcommand)]
     pub command: MainCommand,
@@ -31,40 +1103,1869 @@ pub struct Args {
 // example output:
 // GameController { manufacturer: "(Standard system devices)", name: "HID-compliant game controller", instance_id: "HID\\{00001124-0000-1000-8000-00805F9B34FB}&VID_045E&PID_02E0&IG_00\\D&5688A0B&0&0000", status: Enabled, disableable: true }
 
-fn main() {
-    let args = Args::parse();
-    match args.command {
-        MainCommand::List => {
-            let controllers = game_controllers().unwrap();
-            if controllers.is_empty() {
-                println!("No controllers found");
-                return;
-            }
-            for item in controllers {
-                println!("{:?}", item);
-            }        
-        },
+/// Set when any change printed so far needs a restart to take effect
+static RESTART_REQUIRED: AtomicBool = AtomicBool::new(false);
 
-        MainCommand::Enable { id } => {
-            match enable_device(&id) {
-                Ok(()) => {
-                    println!("Device {} disabled successfully", &id)
-                },
-                Err(err) => {
-                    println!("Error: {:?}", err);
-                }
-            }
-        },
+/// Set by --dry-run, changes are only checked and printed
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
 
-        MainCommand::Disable { id } => {
-            match disable_device(&id) {
-                Ok(()) => {
-                    println!("Device {} disabled successfully", &id)
+/// Set by --simulate, devices come from FakeProvider::sample
+static SIMULATE: AtomicBool = AtomicBool::new(false);
+
+/// Set for commands that only look at devices, which don't move aliases to
+/// where their devices are now
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Set by --quiet, only data and errors are printed
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Set by --json
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Set when output is colored, from --color and the like
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// What happened to each device that was changed, printed at the end with
+/// --json
+static OUTCOMES: Mutex<Vec<pipe::Response>> = Mutex::new(Vec::new());
+
+fn json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+fn color() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// Whether to color stdout. Consoles have to be told to take VT sequences,
+/// ones too old for that aren't colored unless it's forced.
+fn use_color(mode: ColorMode) -> bool {
+    match mode {
+        ColorMode::Never => false,
+        ColorMode::Always => {
+            vt();
+            true
+        },
+        ColorMode::Auto => io::stdout().is_terminal() && vt(),
+    }
+}
+
+/// Have the console take VT sequences, false if it's too old for them
+#[cfg(windows)]
+fn vt() -> bool {
+    unsafe {
+        let Ok(output) = GetStdHandle(STD_OUTPUT_HANDLE) else {
+            return false;
+        };
+        let mut console_mode = CONSOLE_MODE::default();
+        GetConsoleMode(output, &mut console_mode).is_ok()
+            && SetConsoleMode(output, console_mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING).is_ok()
+    }
+}
+
+/// Terminals everywhere else take them as they are
+#[cfg(not(windows))]
+fn vt() -> bool {
+    true
+}
+
+/// Remember that the device was changed for --json. `change` is None for
+/// changes other than enabling and disabling, like removing.
+fn succeeded(id: &str, change: Option<DeviceChange>) {
+    if json() {
+        OUTCOMES.lock().unwrap().push(pipe::Response {
+            ok: true,
+            instance_id: Some(id.to_string()),
+            change,
+            ..Default::default()
+        });
+    }
+}
+
+/// Report an error about the device, and remember it for --json
+fn failed(id: &str, err: &impl exit::Failure) {
+    exit::report_for(&id, err);
+    if json() {
+        OUTCOMES.lock().unwrap().push(pipe::Response {
+            instance_id: Some(id.to_string()),
+            ..pipe::Response::failure(err)
+        });
+    }
+}
+
+/// Format of a command's output, --json decides over the config file
+#[cfg(windows)]
+fn output_format(format: Option<Format>, config: &Config) -> Format {
+    config::pick(format, json().then_some(Format::Json).or(config.format), Format::Text)
+}
+
+/// `println!` for what's said about the data instead of the data itself,
+/// e.g. "Device ... disabled successfully", which --quiet leaves out
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
+fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+fn simulated() -> bool {
+    SIMULATE.load(Ordering::Relaxed)
+}
+
+fn read_only() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
+
+/// The provider to make changes with, one that only checks them with
+/// --dry-run, or the made up devices of --simulate
+#[cfg(windows)]
+fn devices<P: journal::Reversible + 'static>(provider: P) -> Box<dyn DeviceProvider> {
+    match (simulated(), dry_run()) {
+        (true, _) => Box::new(FakeProvider::sample()),
+        (false, true) => Box::new(DryRun::new(provider)),
+        (false, false) => Box::new(journal::Journaled::new(provider)),
+    }
+}
+
+/// Same as on Windows, except that there's no `undo` to journal the changes
+/// for
+#[cfg(not(windows))]
+fn devices<P: DeviceProvider + 'static>(provider: P) -> Box<dyn DeviceProvider> {
+    match (simulated(), dry_run()) {
+        (true, _) => Box::new(FakeProvider::sample()),
+        (false, true) => Box::new(DryRun::new(provider)),
+        (false, false) => Box::new(provider),
+    }
+}
+
+/// The real devices, through SetupAPI
+#[cfg(windows)]
+fn system(scope: Scope, retry: Retry) -> SetupApi {
+    SetupApi { scope, retry }
+}
+
+/// The real devices, through sysfs, where changes aren't retried
+#[cfg(target_os = "linux")]
+fn system(scope: Scope, _retry: Retry) -> Sysfs {
+    Sysfs { scope }
+}
+
+/// Whether the command only shows devices and what's known about them
+fn reads_only(command: &MainCommand) -> bool {
+    match command {
+        MainCommand::List { .. } => true,
+        #[cfg(windows)]
+        MainCommand::Status { .. }
+        | MainCommand::Info { .. }
+        | MainCommand::Why { .. }
+        | MainCommand::Describe { .. }
+        | MainCommand::Test { .. }
+        | MainCommand::Watch { .. } => true,
+        _ => false,
+    }
+}
+
+/// Whether the command only goes through [`devices`], so that it can be run
+/// with --simulate
+fn simulatable(command: &MainCommand) -> bool {
+    match command {
+        MainCommand::List { .. }
+        | MainCommand::Enable { .. }
+        | MainCommand::Disable { .. }
+        | MainCommand::Toggle { .. } => true,
+        #[cfg(windows)]
+        MainCommand::Status { .. }
+        | MainCommand::Restart { .. }
+        | MainCommand::EnableAll { .. }
+        | MainCommand::DisableAll { .. }
+        | MainCommand::Apply { .. }
+        | MainCommand::Restore { .. } => true,
+        _ => false,
+    }
+}
+
+/// What `simulatable` says yes to, for the error message
+#[cfg(windows)]
+const SIMULATABLE: &str = "list, status, enable, disable, toggle, restart, enable-all, disable-all, apply and restore";
+#[cfg(not(windows))]
+const SIMULATABLE: &str = "list, enable, disable and toggle";
+
+/// Name of the command if it can't be run with --dry-run. Commands that
+/// don't change anything ignore it.
+fn without_dry_run(command: &MainCommand) -> Option<&'static str> {
+    match command {
+        #[cfg(windows)]
+        MainCommand::EnableAfter { .. } => Some("enable-after"),
+        #[cfg(windows)]
+        MainCommand::Auto { .. } => Some("auto"),
+        #[cfg(windows)]
+        MainCommand::Run { .. } => Some("run"),
+        #[cfg(windows)]
+        MainCommand::Tui { .. } => Some("tui"),
+        #[cfg(windows)]
+        MainCommand::Tray { .. } => Some("tray"),
+        #[cfg(windows)]
+        MainCommand::Hotkey { .. } => Some("hotkey"),
+        #[cfg(windows)]
+        MainCommand::Save { .. } => Some("save"),
+        #[cfg(windows)]
+        MainCommand::Rescan => Some("rescan"),
+        MainCommand::Alias { .. } => Some("alias"),
+        #[cfg(windows)]
+        MainCommand::Startup { .. } => Some("startup"),
+        #[cfg(windows)]
+        MainCommand::Audit { .. } => Some("audit"),
+        #[cfg(windows)]
+        MainCommand::Service { .. } => Some("service"),
+        #[cfg(windows)]
+        MainCommand::Serve { .. } => Some("serve"),
+        #[cfg(windows)]
+        MainCommand::Client { .. } => Some("client"),
+        _ => None,
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    #[cfg(windows)]
+    let (reboot_prompt, elevate) = (args.reboot_prompt, args.elevate);
+    if let Err(err) = logging::init(args.verbose, args.log_file.as_deref()) {
+        exit::fail(&exit::Message::new(exit::FAILURE, format!("can't open the log file: {}", err)));
+    }
+    let config = match args.no_config {
+        true => Ok(Config::default()),
+        false => Config::load(),
+    };
+    let config = match config.and_then(|config| config.with_env(|name| std::env::var(name).ok())) {
+        Ok(config) => config,
+        Err(err) => exit::fail(&err),
+    };
+    #[cfg(windows)]
+    if args.audit || config.audit == Some(true) {
+        audit::enable();
+    }
+    READ_ONLY.store(reads_only(&args.command), Ordering::Relaxed);
+    QUIET.store(args.quiet || args.json, Ordering::Relaxed);
+    JSON.store(args.json, Ordering::Relaxed);
+    COLOR.store(!args.json && use_color(config::pick(args.color, config.color, ColorMode::Auto)), Ordering::Relaxed);
+    if args.dry_run {
+        if let Some(name) = without_dry_run(&args.command) {
+            exit::fail(&exit::Message::new(exit::INVALID_ARGUMENT, format!("{} can't be run with --dry-run", name)));
+        }
+        DRY_RUN.store(true, Ordering::Relaxed);
+    }
+    if args.simulate {
+        if !simulatable(&args.command) {
+            exit::fail(&exit::Message::new(exit::INVALID_ARGUMENT, format!("--simulate works with {} only", SIMULATABLE)));
+        }
+        SIMULATE.store(true, Ordering::Relaxed);
+    }
+    run(args.command, config);
+    let outcomes = OUTCOMES.lock().unwrap();
+    if !outcomes.is_empty() {
+        println!("{}", serde_json::to_string_pretty(&*outcomes).unwrap());
+    }
+    #[cfg(windows)]
+    if exit::code() == exit::ACCESS_DENIED && !elevation::is_elevated().unwrap_or(true) {
+        match elevate {
+            true => match elevation::relaunch_elevated() {
+                Ok(code) => std::process::exit(code),
+                Err(err) => exit::report_for(&"elevation", &exit::Message::new(exit::ACCESS_DENIED, err.to_string())),
+            },
+            false => eprintln!("Run the command with --elevate to do it as administrator"),
+        }
+    }
+    if RESTART_REQUIRED.load(Ordering::Relaxed) {
+        say!("Windows has to be restarted for the changes to take effect");
+        #[cfg(windows)]
+        if reboot_prompt && confirm("Restart now?") {
+            if let Err(err) = Command::new("shutdown").args(["/r", "/t", "0"]).status() {
+                exit::report_for(&"shutdown", &exit::Message::new(exit::FAILURE, err.to_string()));
+            }
+        }
+        exit::set(exit::RESTART_REQUIRED);
+    }
+    std::process::exit(exit::code());
+}
+
+fn run(command: MainCommand, config: Config) {
+    match command {
+        MainCommand::List { scope, filter, all_hid, long, id_only, sort, format } => {
+            let mut scope = scope.scope(&config);
+            if all_hid {
+                scope.kind = Kind::AllHid;
+            }
+            let columns = output::Columns {
+                kind: scope.kind == Kind::AllHid && scope.class.is_none() && scope.interface.is_none(),
+                details: long,
+                color: color(),
+            };
+            let provider = devices(system(scope, Retry::default()));
+            match provider.controllers() {
+                Ok(mut controllers) => {
+                    controllers.retain(|c| filter.matches(c));
+                    output::sort(&mut controllers, config::pick(sort, config.sort, SortOrder::None));
+                    let configured = json().then_some(Format::Json).or(config.format).map(|format| match format {
+                        Format::Text => ListFormat::Table,
+                        Format::Json => ListFormat::Json,
+                    });
+                    let format = config::pick(format, configured, ListFormat::Table);
+                    if format == ListFormat::Table {
+                        usbids::rename(&mut controllers, &usbids::UsbIds::load(config.usb_ids.as_deref()));
+                    }
+                    print_list(&controllers, id_only, format, columns)
+                },
+                Err(err) => exit::report(&err),
+            }
+        },
+
+        #[cfg(not(windows))]
+        MainCommand::Enable { targets, pick, continue_on_error, yes, scope, retry } => {
+            let provider = devices(system(scope.scope(&config), retry.retry(&config)));
+            set_states(&provider, config.vid, targets.selectors(), pick.pick(), true, continue_on_error, yes);
+        },
+
+        #[cfg(windows)]
+        MainCommand::Enable { targets, pick, continue_on_error, yes, this_profile, scope, retry } => {
+            let scope = scope.scope(&config);
+            let provider = match this_profile {
+                true => devices(HardwareProfile { scope }),
+                false => devices(system(scope, retry.retry(&config))),
+            };
+            set_states(&provider, config.vid, targets.selectors(), pick.pick(), true, continue_on_error, yes);
+        },
+
+        #[cfg(not(windows))]
+        MainCommand::Disable { targets, pick, continue_on_error, yes, scope, retry } => {
+            let provider = devices(system(scope.scope(&config), retry.retry(&config)));
+            set_states(&provider, config.vid, targets.selectors(), pick.pick(), false, continue_on_error, yes);
+        },
+
+        #[cfg(windows)]
+        MainCommand::Disable { targets, pick, continue_on_error, yes, duration, this_profile, scope, retry } => {
+            let scope = scope.scope(&config);
+            let retry = retry.retry(&config);
+            let provider = match this_profile {
+                true => devices(HardwareProfile { scope }),
+                false => devices(system(scope, retry)),
+            };
+            let ids = set_states(&provider, config.vid, targets.selectors(), pick.pick(), false, continue_on_error, yes);
+            let Some(duration) = duration else {
+                return;
+            };
+            for id in ids {
+                if dry_run() || simulated() {
+                    say!("Device {} would be enabled again in {}", id, duration_text(duration));
+                    continue;
+                }
+                match enable_after(&id, duration, scope, retry) {
+                    Ok(()) => say!("Device {} will be enabled again in {}", id, duration_text(duration)),
+                    Err(err) => exit::report(&exit::Message::new(exit::FAILURE, format!("can't start the timer: {}", err))),
+                }
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::EnableAfter { seconds, id, scope, retry } => {
+            std::thread::sleep(Duration::from_secs(seconds));
+            let provider = SetupApi {
+                scope: scope.scope(&config),
+                retry: retry.retry(&config),
+            };
+            if let Err(err) = provider.set_enabled(&id, true) {
+                failed(&id, &err);
+            }
+        },
+
+        MainCommand::Toggle { target, scope, retry } => {
+            let provider = devices(system(scope.scope(&config), retry.retry(&config)));
+            let id = target.selector();
+            print_change(with_aliases(|aliases| {
+                let id = selector::select(&provider, aliases, config.vid, &id)?;
+                let change = provider.toggle(&id)?;
+                Ok((id, change))
+            }))
+        },
+
+        #[cfg(windows)]
+        MainCommand::Identify { target, duration, scope } => {
+            let provider = SetupApi {
+                scope: scope.scope(&config),
+                ..Default::default()
+            };
+            let id = target.selector();
+            let result = with_aliases(|aliases| {
+                let id = selector::select(&provider, aliases, config.vid, &id)?;
+                let controller = provider
+                    .controllers()?
+                    .into_iter()
+                    .find(|c| c.instance_id == id)
+                    .ok_or(devenum::Error::NotFound)?;
+                Ok(controller)
+            });
+            let controller = match result {
+                Ok(controller) => controller,
+                Err(err) => return exit::report(&err),
+            };
+            match identify::identify(&controller, duration) {
+                Ok(identify::Signal::XInput { slot }) => {
+                    say!("{} rumbled, it's player {} on the ring light", controller.instance_id, slot + 1)
+                },
+                Ok(identify::Signal::OutputReport) => say!("{} rumbled and flashed", controller.instance_id),
+                Err(err) => exit::report(&err),
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Describe { target, scope, format } => {
+            let provider = SetupApi {
+                scope: scope.scope(&config),
+                ..Default::default()
+            };
+            let id = target.selector();
+            let id = match with_aliases(|aliases| selector::select(&provider, aliases, config.vid, &id)) {
+                Ok(id) => id,
+                Err(err) => return exit::report(&err),
+            };
+            let description = match hid::describe(&id) {
+                Ok(description) => description,
+                Err(err) => return exit::report(&err),
+            };
+            match output_format(format, &config) {
+                Format::Text => print!("{}", output::render_description(&description)),
+                Format::Json => println!("{}", serde_json::to_string_pretty(&description).unwrap()),
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Test { target, scope } => {
+            let provider = SetupApi {
+                scope: scope.scope(&config),
+                ..Default::default()
+            };
+            let id = target.selector();
+            let id = match with_aliases(|aliases| selector::select(&provider, aliases, config.vid, &id)) {
+                Ok(id) => id,
+                Err(err) => return exit::report(&err),
+            };
+            if let Err(err) = monitor_input(&id) {
+                exit::report(&err);
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Restart { target, scope, retry } => {
+            let provider = devices(SetupApi {
+                scope: scope.scope(&config),
+                retry: retry.retry(&config),
+            });
+            let id = target.selector();
+            let result = with_aliases(|aliases| {
+                let id = selector::select(&provider, aliases, config.vid, &id)?;
+                provider.restart(&id)?;
+                Ok(id)
+            });
+            match result {
+                Ok(id) if dry_run() => {
+                    succeeded(&id, None);
+                    say!("Device {} would be restarted", id)
+                },
+                Ok(id) => {
+                    succeeded(&id, None);
+                    say!("Device {} restarted successfully", id)
+                },
+                Err(err) => exit::report(&err),
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::EnableAll { scope, retry } => {
+            let provider = devices(SetupApi {
+                scope: scope.scope(&config),
+                retry: retry.retry(&config),
+            });
+            print_bulk(provider.set_all_enabled(true))
+        },
+
+        #[cfg(windows)]
+        MainCommand::DisableAll { yes, scope, retry } => {
+            let provider = devices(SetupApi {
+                scope: scope.scope(&config),
+                retry: retry.retry(&config),
+            });
+            if !yes && !dry_run() {
+                let controllers = match provider.controllers() {
+                    Ok(controllers) => controllers,
+                    Err(err) => return exit::report(&err),
+                };
+                let ids: Vec<String> = controllers
+                    .into_iter()
+                    .filter(|c| c.status == GameControllerStatus::Enabled && c.disableable)
+                    .map(|c| c.instance_id)
+                    .collect();
+                if !ids.is_empty() && !confirm_devices(&provider, &ids, "Disable") {
+                    return;
+                }
+            }
+            print_bulk(provider.set_all_enabled(false))
+        },
+
+        #[cfg(windows)]
+        MainCommand::Apply { profile, scope, retry } => {
+            let profile = find_profile(&config, &profile);
+            let provider = devices(SetupApi {
+                scope: scope.scope(&config),
+                retry: retry.retry(&config),
+            });
+            match with_aliases(|aliases| Ok(policy::apply(&provider, aliases, config.vid, profile)?)) {
+                Ok(changes) => print_changes(changes),
+                Err(err) => exit::report(&err),
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Auto { notify, scope, retry } => {
+            if config.apps.is_empty() {
+                exit::fail(&exit::Message::new(exit::CONFIG, "there are no [apps] rules in the config file"));
+            }
+            for profile in config.apps.values() {
+                find_profile(&config, profile);
+            }
+            let provider = devices(SetupApi {
+                scope: scope.scope(&config),
+                retry: retry.retry(&config),
+            });
+            let notifier = notify.then(Notifier::new).transpose().unwrap_or_else(|err| exit::fail(&err));
+            let mut active = None;
+            let result = foreground::watch(move |exe| {
+                on_foreground(&provider, &config, &mut active, exe, notifier.as_ref())
+            });
+            if let Err(err) = result {
+                exit::report(&err);
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Run { profile, command, scope, retry } => {
+            let profile = match profile {
+                Some(name) => find_profile(&config, &name).clone(),
+                None => Profile {
+                    disable_all: true,
+                    ..Default::default()
+                },
+            };
+            let provider = devices(SetupApi {
+                scope: scope.scope(&config),
+                retry: retry.retry(&config),
+            });
+            let snapshot = match policy::snapshot(&provider) {
+                Ok(snapshot) => snapshot,
+                Err(err) => {
+                    exit::fail(&err);
+                },
+            };
+            match with_aliases(|aliases| Ok(policy::apply(&provider, aliases, config.vid, &profile)?)) {
+                Ok(changes) => print_changes(changes),
+                Err(err) => exit::report(&err),
+            }
+            let status = launch::run(&command);
+            journal::start_run();
+            print_changes(policy::restore(&provider, &snapshot));
+            match status {
+                Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+                Err(err) => {
+                    exit::report_for(&command[0], &exit::Message::new(exit::FAILURE, err.to_string()));
+                    std::process::exit(exit::code());
+                },
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Tui { scope, retry } => {
+            let provider = devices(SetupApi {
+                scope: scope.scope(&config),
+                retry: retry.retry(&config),
+            });
+            if let Err(err) = tui::run(&Cached::new(provider)) {
+                exit::report(&err);
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Tray { notify, scope, retry } => {
+            let scope = scope.scope(&config);
+            let provider = devices(SetupApi {
+                scope,
+                retry: retry.retry(&config),
+            });
+            let notify = notify.then_some(scope);
+            if let Err(err) = tray::run(Box::new(Cached::new(provider)), notify) {
+                exit::report(&err);
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Hotkey { devices: selectors, key, scope, retry } => {
+            let provider = devices(SetupApi {
+                scope: scope.scope(&config),
+                retry: retry.retry(&config),
+            });
+            let key = config::pick(key, config.hotkey, "Ctrl+Alt+J".parse().unwrap());
+            say!("Press {} to switch controllers, Ctrl+C to quit", key);
+            let result = hotkey::run(key, || {
+                journal::start_run();
+                match with_aliases(|aliases| flip_devices(&provider, aliases, config.vid, &selectors)) {
+                    Ok(results) => print_bulk(Ok(results)),
+                    Err(err) => exit::report(&err),
+                }
+            });
+            if let Err(err) = result {
+                exit::report(&err);
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Watch { notify, format, scope } => {
+            let notifier = notify.then(Notifier::new).transpose().unwrap_or_else(|err| exit::fail(&err));
+            let format = output_format(format, &config);
+            let result = watch::watch(scope.scope(&config), |event| {
+                match format {
+                    Format::Text => println!("{}", output::render_event(event)),
+                    Format::Json => println!("{}", serde_json::to_string(event).unwrap()),
+                }
+                if let Some(notifier) = &notifier {
+                    notifier.event(event);
+                }
+            });
+            if let Err(err) = result {
+                exit::report(&err);
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Info { target, scope, format } => {
+            let scope = scope.scope(&config);
+            let provider = SetupApi {
+                scope,
+                ..Default::default()
+            };
+            let id = target.selector();
+            let result = with_aliases(|aliases| {
+                let id = selector::select(&provider, aliases, config.vid, &id)?;
+                Ok(devenum::device_properties(&id, scope)?)
+            });
+            match (result, output_format(format, &config)) {
+                (Ok(props), Format::Text) => print!("{}", output::render_properties(&props)),
+                (Ok(props), Format::Json) => {
+                    println!("{}", serde_json::to_string_pretty(&props).unwrap())
+                },
+                (Err(err), _) => exit::report(&err),
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Save { file, scope } => {
+            let provider = SetupApi {
+                scope: scope.scope(&config),
+                ..Default::default()
+            };
+            let Some(path) = file.or_else(policy::default_snapshot_path) else {
+                return exit::report(&ConfigError::NoConfigDir);
+            };
+            let result = policy::snapshot(&provider)
+                .map_err(|e| exit::Message::of(&e))
+                .and_then(|snapshot| {
+                    snapshot
+                        .save(&path)
+                        .map_err(|e| exit::Message::new(exit::CONFIG, format!("{}: {}", path.display(), e)))?;
+                    Ok(snapshot.0.len())
+                });
+            match result {
+                Ok(count) => say!("States of {} device(s) saved to {}", count, path.display()),
+                Err(err) => exit::report(&err),
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Restore { file, scope, retry } => {
+            let provider = devices(SetupApi {
+                scope: scope.scope(&config),
+                retry: retry.retry(&config),
+            });
+            let Some(path) = file.or_else(policy::default_snapshot_path) else {
+                return exit::report(&ConfigError::NoConfigDir);
+            };
+            match policy::Snapshot::load(&path) {
+                Ok(snapshot) => {
+                    let changes = policy::restore(&provider, &snapshot);
+                    if changes.is_empty() {
+                        say!("All devices are already in their saved states");
+                    }
+                    print_changes(changes)
+                },
+                Err(err) => exit::report_for(&path.display(), &exit::Message::new(exit::CONFIG, err.to_string())),
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Remove { target, class, yes } => {
+            // disconnected devices are as good a target as connected ones
+            let scope = Scope {
+                include_disconnected: true,
+                class,
+                ..Default::default()
+            };
+            let provider = SetupApi {
+                scope,
+                ..Default::default()
+            };
+            let id = target.selector();
+            let id = match with_aliases(|aliases| selector::select(&provider, aliases, config.vid, &id)) {
+                Ok(id) => id,
+                Err(err) => return exit::report(&err),
+            };
+            if dry_run() {
+                return match elevation::is_elevated() {
+                    Ok(false) => failed(&id, &devenum::Error::NotElevated),
+                    _ => {
+                        succeeded(&id, None);
+                        say!("Device {} would be removed", id)
+                    },
+                };
+            }
+            if !yes && !confirm_change(&format!("Uninstall device {}?", id)) {
+                return;
+            }
+            match devenum::remove_device(&id, scope) {
+                Ok(()) => {
+                    succeeded(&id, None);
+                    say!("Device {} removed successfully", id)
+                },
+                Err(err) => failed(&id, &err),
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Rescan => match devenum::rescan() {
+            Ok(()) => say!("Hardware scan finished"),
+            Err(err) => exit::report(&err),
+        },
+
+        #[cfg(windows)]
+        MainCommand::Why { target, scope, format } => {
+            let scope = scope.scope(&config);
+            let provider = SetupApi {
+                scope,
+                ..Default::default()
+            };
+            let id = target.selector();
+            let result = with_aliases(|aliases| {
+                let id = selector::select(&provider, aliases, config.vid, &id)?;
+                let controller = provider
+                    .controllers()?
+                    .into_iter()
+                    .find(|c| c.instance_id == id)
+                    .ok_or(devenum::Error::NotFound)?;
+                Ok(controller)
+            });
+            let explanation = match result {
+                Ok(controller) => why::explain(&controller, scope),
+                Err(err) => return exit::report(&err),
+            };
+            match output_format(format, &config) {
+                Format::Text => print!("{}", why::render_text(&explanation)),
+                Format::Json => println!("{}", serde_json::to_string_pretty(&explanation).unwrap()),
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Status { target, scope, format } => {
+            let provider = devices(SetupApi {
+                scope: scope.scope(&config),
+                ..Default::default()
+            });
+            let id = target.selector();
+            let result = with_aliases(|aliases| {
+                let id = selector::select(&provider, aliases, config.vid, &id)?;
+                let controller = provider
+                    .controllers()?
+                    .into_iter()
+                    .find(|c| c.instance_id == id)
+                    .ok_or(devenum::Error::NotFound)?;
+                Ok(controller)
+            });
+            let controller = match result {
+                Ok(controller) => controller,
+                Err(err) => return exit::report(&err),
+            };
+            let mut status = output::DeviceStatus::new(&controller);
+            if !simulated() && controller.status != GameControllerStatus::Disconnected {
+                status.power = power::power_info(&controller)
+                    .map_err(|err| log::debug!("no power info for {}: {}", controller.instance_id, err))
+                    .ok();
+            }
+            match output_format(format, &config) {
+                Format::Text => print!("{}", output::render_status(&status)),
+                Format::Json => println!("{}", serde_json::to_string_pretty(&status).unwrap()),
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Dedupe { disable, scope, retry } => {
+            let scope = scope.scope(&config);
+            let disabler = SetupApi { scope, retry: retry.retry(&config) };
+            let provider = match disable {
+                true => devices(disabler),
+                false => devices(HidHideProvider { scope }),
+            };
+            dedupe(&disabler, &provider, disable);
+        },
+
+        #[cfg(windows)]
+        MainCommand::Prune { yes, scope } => prune(scope.scope(&config), yes),
+
+        #[cfg(windows)]
+        MainCommand::Doctor { format } => {
+            let checks = doctor::run();
+            match output_format(format, &config) {
+                Format::Text => print!("{}", doctor::render_text(&checks)),
+                Format::Json => println!("{}", serde_json::to_string_pretty(&checks).unwrap()),
+            }
+            if let Err(err) = doctor::verdict(&checks) {
+                exit::report(&err);
+            }
+        },
+
+        MainCommand::Alias { command } => {
+            if let Err(err) = alias(command) {
+                exit::report(&err);
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::HidHide { command } => {
+            if let Err(err) = run_hidhide(command, config) {
+                exit::fail(&err);
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Startup { command } => {
+            if let Err(err) = run_startup(command, config) {
+                exit::fail(&err);
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Power { command } => {
+            if let Err(err) = run_power(command, config) {
+                exit::fail(&err);
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Undo { count, retry } => {
+            if let Err(err) = undo(count, retry.retry(&config)) {
+                exit::fail(&err);
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Audit { command: AuditCommand::Register } => match audit::register() {
+            Ok(()) => say!("Event source registered"),
+            Err(err) => exit::report(&err),
+        },
+
+        #[cfg(windows)]
+        MainCommand::Audit { command: AuditCommand::Unregister } => match audit::unregister() {
+            Ok(()) => say!("Event source removed"),
+            Err(err) => exit::report(&err),
+        },
+
+        #[cfg(windows)]
+        MainCommand::Serve { http: Some(address), token, scope, retry } => {
+            let provider = devices(SetupApi {
+                scope: scope.scope(&config),
+                retry: retry.retry(&config),
+            });
+            if token.is_none() && !address.ip().is_loopback() {
+                eprintln!("Warning: anyone who can reach {} can change devices, consider --token", address);
+            }
+            say!("Serving requests on http://{}, Ctrl+C to quit", address);
+            if let Err(err) = http::serve(address, token.as_deref(), &provider, config.vid) {
+                exit::report(&exit::Message::new(exit::FAILURE, format!("{}: {}", address, err)));
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Serve { http: None, scope, retry, .. } => {
+            let provider = devices(SetupApi {
+                scope: scope.scope(&config),
+                retry: retry.retry(&config),
+            });
+            say!("Serving requests on {}, Ctrl+C to quit", pipe::PIPE_NAME);
+            if let Err(err) = pipe::serve(&provider, config.vid) {
+                exit::report(&err);
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Client { request } => {
+            let response = match pipe::request(&request) {
+                Ok(response) => response,
+                Err(err) => return exit::report(&err),
+            };
+            if let Some(error) = response.error {
+                return exit::report(&exit::Message::new(response.code.unwrap_or(exit::FAILURE), error));
+            }
+            if let Some(controllers) = response.controllers {
+                println!("{}", serde_json::to_string_pretty(&controllers).unwrap());
+            }
+            if let (Some(id), Some(change)) = (response.instance_id, response.change) {
+                print_change(Ok((id, change)));
+            }
+        },
+
+        #[cfg(windows)]
+        MainCommand::Service { command } => {
+            if let Err(err) = run_service(command, config) {
+                exit::fail(&err);
+            }
+        },
+
+        MainCommand::Config { command: ConfigCommand::Path } => match config::path() {
+            Ok(path) => println!("{}", path.display()),
+            Err(err) => exit::report(&err),
+        },
+    }
+}
+
+fn print_list(controllers: &[GameController], id_only: bool, format: ListFormat, columns: output::Columns) {
+    if id_only || format == ListFormat::Ids {
+        print!("{}", output::render_ids(controllers));
+        return;
+    }
+    if format == ListFormat::Json {
+        println!("{}", serde_json::to_string_pretty(controllers).unwrap());
+        return;
+    }
+    if format == ListFormat::Csv {
+        print!("{}", output::render_csv(controllers));
+        return;
+    }
+    if controllers.is_empty() {
+        say!("No controllers found");
+        return;
+    }
+    let ids: Vec<&str> = controllers.iter().map(|c| c.instance_id.as_str()).collect();
+    if let Err(err) = ordinal::save(&ids) {
+        log::warn!("row numbers can't be saved: {}", err);
+    }
+    if format == ListFormat::Table {
+        print!("{}", output::render_table(controllers, columns));
+        return;
+    }
+    for (i, item) in controllers.iter().enumerate() {
+        println!("{:>2}  {:?}", i + 1, item);
+    }
+}
+
+/// The profile with given name, exits if there is none
+#[cfg(windows)]
+fn find_profile<'a>(config: &'a Config, name: &str) -> &'a Profile {
+    match config.profiles.get(name) {
+        Some(profile) => profile,
+        None => exit::fail(&exit::Message::new(
+            exit::NOT_FOUND,
+            format!("there is no profile \"{}\" in the config file", name),
+        )),
+    }
+}
+
+/// Run `f` with the user's aliases loaded
+fn with_aliases<T>(f: impl FnOnce(&Aliases) -> Result<T, SelectError>) -> Result<T, SelectError> {
+    f(&Aliases::load()?)
+}
+
+/// Device argument that stands for the lines of stdin
+const STDIN_ARG: &str = "-";
+
+/// Enable or disable the devices given on the command line, or on stdin
+/// one per line if the only one is `-`, printing the result of each. Stops
+/// at the first failure unless `continue_on_error`. Asks before changing the
+/// devices of a selector that matches more than one, unless `yes`. Returns
+/// the ids of the devices that are in the requested state now.
+fn set_states(
+    provider: &impl DeviceProvider,
+    vid: Option<u16>,
+    args: Vec<String>,
+    pick: Pick,
+    enable: bool,
+    continue_on_error: bool,
+    yes: bool,
+) -> Vec<String> {
+    let single = matches!(args.as_slice(), [arg] if arg != STDIN_ARG);
+    let args: Box<dyn Iterator<Item = String>> = match args.as_slice() {
+        [arg] if arg == STDIN_ARG => Box::new(io::stdin().lines().map_while(|line| {
+            line.inspect_err(|err| {
+                exit::report(&exit::Message::new(exit::FAILURE, format!("can't read stdin: {}", err)))
+            })
+            .ok()
+        })),
+        _ => Box::new(args.into_iter()),
+    };
+    let aliases = match Aliases::load() {
+        Ok(aliases) => aliases,
+        Err(err) => {
+            exit::report(&err);
+            return Vec::new();
+        },
+    };
+    // selectors are all resolved against a single enumeration, a single
+    // instance id doesn't need one at all
+    let snapshot;
+    let devices: &dyn DeviceProvider = match single {
+        true => provider,
+        false => match provider.controllers() {
+            Ok(controllers) => {
+                snapshot = FakeProvider::new(controllers);
+                &snapshot
+            },
+            Err(err) => {
+                exit::report(&err);
+                return Vec::new();
+            },
+        },
+    };
+    let mut ids = Vec::new();
+    let mut failed = 0;
+    for arg in args {
+        let arg = arg.trim();
+        if arg.is_empty() {
+            continue;
+        }
+        let matched = match selector::select_all(devices, &aliases, vid, arg, pick) {
+            Ok(matched) => matched,
+            Err(err) => {
+                self::failed(arg, &err);
+                failed += 1;
+                Vec::new()
+            },
+        };
+        // patterns always say how many they matched, they can easily catch
+        // more or fewer devices than thought
+        match matched.len() {
+            1 if arg.starts_with("match:") => say!("1 device matches \"{}\"", arg),
+            0 | 1 => {},
+            count => say!("{} devices match \"{}\"", count, arg),
+        }
+        let verb = if enable { "Enable" } else { "Disable" };
+        if matched.len() > 1 && !yes && !dry_run() && !confirm_devices(devices, &matched, verb) {
+            continue;
+        }
+        let results = match matched.as_slice() {
+            [] => Vec::new(),
+            [id] => vec![BulkResult {
+                instance_id: id.clone(),
+                name: String::new(),
+                result: provider.set_enabled(id, enable),
+            }],
+            _ => match provider.set_many_enabled(&matched, enable) {
+                Ok(results) => results,
+                Err(err) => {
+                    self::failed(arg, &err);
+                    failed += matched.len();
+                    Vec::new()
+                },
+            },
+        };
+        for BulkResult { instance_id: id, result, .. } in results {
+            match result {
+                Ok(change) => {
+                    ids.push(id.clone());
+                    print_change(Ok((id, change)));
                 },
                 Err(err) => {
-                    println!("Error: {:?}", err);
+                    self::failed(&id, &err);
+                    failed += 1;
+                },
+            }
+        }
+        if failed > 0 && !continue_on_error {
+            break;
+        }
+    }
+    if !single {
+        let state = if enable { "enabled" } else { "disabled" };
+        let would = if dry_run() { "would be " } else { "" };
+        say!("{} device(s) {}{}, {} failed", ids.len(), would, state, failed);
+    }
+    ids
+}
+
+/// Resolve the device given on the command line and enable or disable it
+#[cfg(windows)]
+fn set_state(
+    provider: &impl DeviceProvider,
+    aliases: &Aliases,
+    vid: Option<u16>,
+    arg: &str,
+    enable: bool,
+) -> Result<(String, DeviceChange), SelectError> {
+    let id = selector::select(provider, aliases, vid, arg)?;
+    let change = provider.set_enabled(&id, enable)?;
+    Ok((id, change))
+}
+
+/// Disable the devices if any of them is enabled, enable them all otherwise
+#[cfg(windows)]
+fn flip_devices(
+    provider: &impl DeviceProvider,
+    aliases: &Aliases,
+    vid: Option<u16>,
+    selectors: &[String],
+) -> Result<Vec<BulkResult>, SelectError> {
+    let controllers = provider.controllers()?;
+    if selectors.is_empty() {
+        let any_enabled = controllers.iter().any(|c| c.status == GameControllerStatus::Enabled);
+        return Ok(provider.set_all_enabled(!any_enabled)?);
+    }
+    let mut targets = Vec::new();
+    for arg in selectors {
+        let id = selector::select(provider, aliases, vid, arg)?;
+        if let Some(controller) = controllers.iter().find(|c| c.instance_id == id) {
+            targets.push(controller);
+        }
+    }
+    let enable = !targets.iter().any(|c| c.status == GameControllerStatus::Enabled);
+    Ok(targets
+        .into_iter()
+        .map(|c| BulkResult {
+            instance_id: c.instance_id.clone(),
+            name: c.name.clone(),
+            result: provider.set_enabled(&c.instance_id, enable),
+        })
+        .collect())
+}
+
+/// Restore the devices when the application whose profile was applied loses
+/// focus, and apply the profile of the new one if it has a rule
+#[cfg(windows)]
+fn on_foreground(
+    provider: &impl DeviceProvider,
+    config: &Config,
+    active: &mut Option<(String, policy::Snapshot)>,
+    exe: &str,
+    notifier: Option<&Notifier>,
+) {
+    if active.as_ref().is_some_and(|(app, _)| app.eq_ignore_ascii_case(exe)) {
+        return;
+    }
+    if let Some((app, snapshot)) = active.take() {
+        say!("{} lost focus, restoring devices", app);
+        journal::start_run();
+        let changes = policy::restore(provider, &snapshot);
+        if let Some(notifier) = notifier.filter(|_| !changes.is_empty()) {
+            notifier.show("Controllers restored", &format!("{} lost focus", app));
+        }
+        print_changes(changes);
+    }
+    let Some((app, name)) = config.apps.iter().find(|(app, _)| app.eq_ignore_ascii_case(exe)) else {
+        return;
+    };
+    let snapshot = match policy::snapshot(provider) {
+        Ok(snapshot) => snapshot,
+        Err(err) => return exit::report(&err),
+    };
+    say!("{} focused, applying profile {}", app, name);
+    journal::start_run();
+    let result = with_aliases(|aliases| {
+        Ok(policy::apply(provider, aliases, config.vid, &config.profiles[name])?)
+    });
+    match result {
+        Ok(changes) => {
+            if let Some(notifier) = notifier.filter(|_| !changes.is_empty()) {
+                notifier.show(&format!("Profile {} applied", name), &format!("{} is in the foreground", app));
+            }
+            print_changes(changes)
+        },
+        Err(err) => exit::report(&err),
+    }
+    *active = Some((app.clone(), snapshot));
+}
+
+#[cfg(windows)]
+fn print_changes(changes: Vec<policy::Change>) {
+    for (id, result) in changes {
+        match result {
+            Ok(change) => print_change(Ok((id, change))),
+            Err(err) => failed(&id, &err),
+        }
+    }
+}
+
+fn print_change(result: Result<(String, DeviceChange), SelectError>) {
+    match &result {
+        Ok((id, change)) => succeeded(id, Some(*change)),
+        Err(err) if json() => OUTCOMES.lock().unwrap().push(pipe::Response::failure(err)),
+        Err(_) => (),
+    }
+    match result {
+        Ok((id, DeviceChange::Enabled)) if dry_run() => say!("Device {} would be enabled", id),
+        Ok((id, DeviceChange::Disabled)) if dry_run() => say!("Device {} would be disabled", id),
+        Ok((id, DeviceChange::Enabled)) => say!("Device {} enabled successfully", id),
+        Ok((id, DeviceChange::Disabled)) => say!("Device {} disabled successfully", id),
+        Ok((id, DeviceChange::AlreadyEnabled)) => say!("Device {} already enabled", id),
+        Ok((id, DeviceChange::AlreadyDisabled)) => say!("Device {} already disabled", id),
+        Ok((id, DeviceChange::EnabledAfterRestart)) => {
+            RESTART_REQUIRED.store(true, Ordering::Relaxed);
+            say!("Device {} will be enabled after a restart", id)
+        },
+        Ok((id, DeviceChange::DisabledAfterRestart)) => {
+            RESTART_REQUIRED.store(true, Ordering::Relaxed);
+            say!("Device {} will be disabled after a restart", id)
+        },
+        Err(err) => exit::report(&err),
+    }
+}
+
+#[cfg(windows)]
+fn print_bulk(results: Result<Vec<BulkResult>, devenum::Error>) {
+    let results = match results {
+        Ok(results) => results,
+        Err(err) => return exit::report(&err),
+    };
+    if results.is_empty() {
+        say!("No controllers found");
+    }
+    for BulkResult { instance_id, result, .. } in results {
+        match result {
+            Ok(change) => print_change(Ok((instance_id, change))),
+            Err(err) => failed(&instance_id, &err),
+        }
+    }
+}
+
+fn alias(command: AliasCommand) -> Result<(), aliases::AliasError> {
+    let mut aliases = Aliases::load()?;
+    match command {
+        AliasCommand::Add { name, selector } => {
+            let selector = durable_selector(selector);
+            aliases.add(&name, &selector)?;
+            aliases.save()?;
+            say!("Alias {} added for {}", name, selector);
+        },
+        AliasCommand::Remove { name } => {
+            aliases.remove(&name)?;
+            aliases.save()?;
+            say!("Alias {} removed", name);
+        },
+        AliasCommand::List => {
+            for (name, selector) in aliases.iter() {
+                println!("{} = {}", name, selector);
+            }
+        },
+    }
+    Ok(())
+}
+
+/// The identity of a connected device instead of its instance id, so that
+/// an alias keeps working when the device is plugged into another port
+fn durable_selector(selector: String) -> String {
+    if !matches!(selector.parse(), Ok(selector::Selector::InstanceId(_))) {
+        return selector;
+    }
+    let provider = devices(system(Scope::default(), Retry::default()));
+    let identity = provider
+        .controllers()
+        .ok()
+        .and_then(|controllers| controllers.into_iter().find(|c| c.instance_id.eq_ignore_ascii_case(&selector)))
+        .and_then(|controller| devenum::identity(&controller));
+    match identity {
+        Some(identity) => selector::Selector::Device(identity).to_string(),
+        None => selector,
+    }
+}
+
+#[cfg(windows)]
+fn run_hidhide(command: HidHideCommand, config: Config) -> Result<(), exit::Message> {
+    match command {
+        HidHideCommand::Hide { target, scope } => hide_device(target, scope, &config, true),
+        HidHideCommand::Unhide { target, scope } => hide_device(target, scope, &config, false),
+        HidHideCommand::Allow { program } => allow_program(&program, true),
+        HidHideCommand::Disallow { program } => allow_program(&program, false),
+        HidHideCommand::Status => {
+            let hidhide = HidHide::open().map_err(|e| exit::Message::of(&e))?;
+            let active = hidhide.is_active().map_err(|e| exit::Message::of(&e))?;
+            println!("Hiding:  {}", if active { "on" } else { "off" });
+            println!("Hidden devices:");
+            for id in hidhide.hidden().map_err(|e| exit::Message::of(&e))? {
+                println!("  {}", id);
+            }
+            println!("Allowed applications:");
+            for path in hidhide.allowed().map_err(|e| exit::Message::of(&e))? {
+                println!("  {}", path);
+            }
+            Ok(())
+        },
+    }
+}
+
+#[cfg(windows)]
+fn hide_device(target: TargetArgs, scope: ScopeArgs, config: &Config, hide: bool) -> Result<(), exit::Message> {
+    let provider = devices(HidHideProvider { scope: scope.scope(config) });
+    let id = target.selector();
+    let (id, change) = with_aliases(|aliases| set_state(&provider, aliases, config.vid, &id, !hide))
+        .map_err(|e| exit::Message::of(&e))?;
+    match change {
+        DeviceChange::AlreadyDisabled => say!("Device {} is already hidden", id),
+        DeviceChange::AlreadyEnabled => say!("Device {} is already visible", id),
+        _ if dry_run() && hide => say!("Device {} would be hidden", id),
+        _ if dry_run() => say!("Device {} would be visible again", id),
+        _ if hide => say!("Device {} hidden", id),
+        _ => say!("Device {} visible again", id),
+    }
+    Ok(())
+}
+
+/// Add the program to the HidHide allowlist, or remove it
+#[cfg(windows)]
+fn allow_program(program: &Path, allow: bool) -> Result<(), exit::Message> {
+    let path = hidhide::nt_path(program).ok_or_else(|| {
+        exit::Message::new(
+            exit::INVALID_ARGUMENT,
+            format!("{} isn't on a drive with a letter", program.display()),
+        )
+    })?;
+    let hidhide = HidHide::open().map_err(|e| exit::Message::of(&e))?;
+    let mut allowed = hidhide.allowed().map_err(|e| exit::Message::of(&e))?;
+    if allowed.iter().any(|p| p.eq_ignore_ascii_case(&path)) == allow {
+        say!("{} is {} allowed", program.display(), if allow { "already" } else { "not" });
+        return Ok(());
+    }
+    if dry_run() {
+        let change = if allow { "allowed" } else { "disallowed" };
+        say!("{} would be {}", program.display(), change);
+        return Ok(());
+    }
+    allowed.retain(|p| !p.eq_ignore_ascii_case(&path));
+    if allow {
+        allowed.push(path);
+    }
+    hidhide.set_allowed(&allowed).map_err(|e| exit::Message::of(&e))?;
+    match allow {
+        true => say!("{} can see hidden devices", program.display()),
+        false => say!("{} can't see hidden devices anymore", program.display()),
+    }
+    Ok(())
+}
+
+/// Print the controls of the device every time one changes
+#[cfg(windows)]
+fn monitor_input(id: &str) -> Result<(), devenum::Error> {
+    let device = HidDevice::open(id, hid::Access::Read)?;
+    let preparsed = device.preparsed()?;
+    let (buttons, values) = (preparsed.input_buttons()?, preparsed.input_values()?);
+    let mut report = vec![0u8; preparsed.caps()?.InputReportByteLength.into()];
+    eprintln!("Reading {}, press Ctrl+C to stop", id);
+    let mut last = None;
+    loop {
+        let len = device.read(&mut report)?;
+        let state = preparsed.decode(&mut report[..len], &buttons, &values);
+        if last.as_ref() == Some(&state) {
+            continue;
+        }
+        let pressed: Vec<String> = state.buttons.iter().map(|b| b.name()).collect();
+        let values: Vec<String> = state.values.iter().map(|(usage, value)| format!("{}={}", usage.name(), value)).collect();
+        match pressed.is_empty() {
+            true => println!("{} {}", Timestamp::now(), values.join(" ")),
+            false => println!("{} [{}] {}", Timestamp::now(), pressed.join(", "), values.join(" ")),
+        }
+        last = Some(state);
+    }
+}
+
+#[cfg(windows)]
+fn run_power(command: PowerCommand, config: Config) -> Result<(), exit::Message> {
+    let (wake, setting, target, scope) = match command {
+        PowerCommand::Wake { setting, target, scope } => (true, setting, target, scope),
+        PowerCommand::SelectiveSuspend { setting, target, scope } => (false, setting, target, scope),
+    };
+    let provider = SetupApi {
+        scope: scope.scope(&config),
+        ..Default::default()
+    };
+    let id = target.selector();
+    let controller = with_aliases(|aliases| {
+        let id = selector::select(&provider, aliases, config.vid, &id)?;
+        let controller = provider
+            .controllers()?
+            .into_iter()
+            .find(|c| c.instance_id == id)
+            .ok_or(devenum::Error::NotFound)?;
+        Ok(controller)
+    })
+    .map_err(|e| exit::Message::of(&e))?;
+    let on = setting == Setting::On;
+    if dry_run() {
+        match wake {
+            true => say!("{} would {} wake the computer", controller.instance_id, if on { "be allowed to" } else { "no longer" }),
+            false => say!("Selective suspend would be turned {} for {}", if on { "on" } else { "off" }, controller.instance_id),
+        }
+        return Ok(());
+    }
+    let (id, outcome) = match wake {
+        true => power::set_wake_armed(&controller, on),
+        false => power::set_selective_suspend(&controller, on),
+    }
+    .map_err(|e| exit::Message::of(&e))?;
+    if outcome == devenum::Outcome::Changed {
+        let method = if wake { journal::Method::Wake } else { journal::Method::SelectiveSuspend };
+        journal::record(method, provider.scope, &controller.instance_id, &controller.name, !on);
+    }
+    match (wake, outcome, on) {
+        (true, devenum::Outcome::AlreadyInState, true) => say!("{} is already allowed to wake the computer", id),
+        (true, devenum::Outcome::AlreadyInState, false) => say!("{} already can't wake the computer", id),
+        (true, _, true) => say!("{} can wake the computer now", id),
+        (true, _, false) => say!("{} can't wake the computer anymore", id),
+        (false, devenum::Outcome::AlreadyInState, _) => {
+            say!("Selective suspend is already {} for {}", if on { "on" } else { "off" }, id)
+        },
+        (false, _, _) => say!(
+            "Selective suspend turned {} for {}, it takes effect when the controller is plugged in again",
+            if on { "on" } else { "off" },
+            id
+        ),
+    }
+    Ok(())
+}
+
+/// Change the devices in the journal back to their states before the last
+/// `count` commands, the last change first. Changes that can't be turned
+/// back stay in the journal.
+#[cfg(windows)]
+fn undo(count: usize, retry: Retry) -> Result<(), exit::Message> {
+    let journal_error = |e: std::io::Error| exit::Message::new(exit::CONFIG, format!("can't read the journal: {}", e));
+    let (entries, mut rest) = journal::split_last_runs(journal::load().map_err(journal_error)?, count);
+    if entries.is_empty() {
+        say!("There's nothing to undo");
+        return Ok(());
+    }
+    let mut failures = Vec::new();
+    for entry in entries {
+        let result = entry.scope().and_then(|scope| undo_entry(&entry, scope, retry));
+        match result {
+            Ok(()) => (),
+            Err(err) => {
+                failed(&entry.instance_id, &err);
+                failures.push(entry);
+            },
+        }
+    }
+    if dry_run() {
+        return Ok(());
+    }
+    // failures go back in the order they were made
+    failures.reverse();
+    rest.extend(failures);
+    journal::save(&rest).map_err(|e| exit::Message::new(exit::CONFIG, format!("can't write the journal: {}", e)))
+}
+
+#[cfg(windows)]
+fn undo_entry(entry: &journal::Entry, scope: Scope, retry: Retry) -> Result<(), devenum::Error> {
+    let id = &entry.instance_id;
+    let provider: Box<dyn DeviceProvider> = match entry.method {
+        journal::Method::Device | journal::Method::Wake | journal::Method::SelectiveSuspend => {
+            Box::new(SetupApi { scope, retry })
+        },
+        journal::Method::Profile => Box::new(HardwareProfile { scope }),
+        journal::Method::HidHide => Box::new(HidHideProvider { scope }),
+    };
+    let provider: Box<dyn DeviceProvider> = match dry_run() {
+        true => Box::new(DryRun::new(provider)),
+        false => provider,
+    };
+    let on = entry.before;
+    let wake = match entry.method {
+        journal::Method::Wake => true,
+        journal::Method::SelectiveSuspend => false,
+        _ => {
+            let change = journal::revert(entry, &provider)?;
+            print_change(Ok((id.clone(), change)));
+            return Ok(());
+        },
+    };
+    let controller = provider
+        .controllers()?
+        .into_iter()
+        .find(|c| c.instance_id == *id)
+        .ok_or(devenum::Error::NotFound)?;
+    if dry_run() {
+        match wake {
+            true => say!("{} would {} wake the computer", id, if on { "be allowed to" } else { "no longer" }),
+            false => say!("Selective suspend would be turned {} for {}", if on { "on" } else { "off" }, id),
+        }
+        return Ok(());
+    }
+    match wake {
+        true => power::set_wake_armed(&controller, on)?,
+        false => power::set_selective_suspend(&controller, on)?,
+    };
+    match wake {
+        true if on => say!("{} can wake the computer again", id),
+        true => say!("{} can't wake the computer anymore", id),
+        false => say!("Selective suspend turned {} again for {}", if on { "on" } else { "off" }, id),
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn run_startup(command: StartupCommand, config: Config) -> Result<(), exit::Message> {
+    match command {
+        StartupCommand::Register { profile } => {
+            find_profile(&config, &profile);
+            let exe = std::env::current_exe().map_err(|e| exit::Message::new(exit::FAILURE, e.to_string()))?;
+            startup::register(&exe, &profile)?;
+            say!("Profile {} will be applied at every logon", profile);
+        },
+        StartupCommand::Unregister => {
+            startup::unregister()?;
+            say!("Logon task removed");
+        },
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn run_service(command: ServiceCommand, config: Config) -> Result<(), exit::Message> {
+    match command {
+        ServiceCommand::Install { profile } => {
+            find_profile(&config, &profile);
+            for window in &config.schedule {
+                find_profile(&config, &window.profile);
+            }
+            let exe = std::env::current_exe().map_err(|e| exit::Message::new(exit::FAILURE, e.to_string()))?;
+            let dir = aliases::config_dir().map_err(|e| exit::Message::of(&e))?;
+            service::install(&exe, &profile, &dir).map_err(|e| exit::Message::of(&e))?;
+            say!("Service installed and started");
+        },
+        ServiceCommand::Uninstall => {
+            service::uninstall().map_err(|e| exit::Message::of(&e))?;
+            say!("Service removed");
+        },
+        ServiceCommand::Run { profile, config_dir } => {
+            // the service account has a different %APPDATA%
+            if let Some(dir) = config_dir {
+                std::env::set_var(aliases::CONFIG_DIR_VAR, dir);
+            }
+            let config = Config::load().map_err(|e| exit::Message::of(&e))?;
+            if config.audit == Some(true) {
+                audit::enable();
+            }
+            find_profile(&config, &profile);
+            for window in &config.schedule {
+                find_profile(&config, &window.profile);
+            }
+            let provider = SetupApi {
+                scope: Scope {
+                    include_disconnected: config.include_disconnected.unwrap_or(false),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut current = String::new();
+            service::run(move |changed| {
+                let name = policy::scheduled(&config.schedule, TimeOfDay::now()).unwrap_or(&profile);
+                if !changed && name == current {
+                    return;
+                }
+                if name != current {
+                    log::info!("enforcing profile {}", name);
+                    current = name.to_string();
                 }
+                let result = with_aliases(|aliases| {
+                    Ok(policy::apply(&provider, aliases, config.vid, &config.profiles[name])?)
+                });
+                match result {
+                    Ok(changes) => {
+                        for (id, result) in changes {
+                            match result {
+                                Ok(change) => log::info!("{}: {:?}", id, change),
+                                Err(err) => log::warn!("{}: {}", id, err),
+                            }
+                        }
+                    },
+                    Err(err) => log::warn!("{}", err),
+                }
+            })
+            .map_err(|e| exit::Message::of(&e))?;
+        },
+    }
+    Ok(())
+}
+
+/// Hide or disable (`disable`) the originals of the duplicates found by
+/// `devices`, which has to see hidden controllers as they are
+#[cfg(windows)]
+fn dedupe(devices: &SetupApi, provider: &dyn DeviceProvider, disable: bool) {
+    let controllers = match devices.controllers() {
+        Ok(controllers) => controllers,
+        Err(err) => return exit::report(&err),
+    };
+    let duplicates = dedupe::find(&controllers);
+    if duplicates.is_empty() {
+        say!("No duplicate controllers found");
+        return;
+    }
+    for duplicate in &duplicates {
+        say!("{} is duplicated by virtual {} ({})", duplicate.original[0].name, duplicate.twin.name, duplicate.twin.instance_id);
+        for c in &duplicate.original {
+            match provider.set_enabled(&c.instance_id, false) {
+                Ok(change) if disable => print_change(Ok((c.instance_id.clone(), change))),
+                Ok(DeviceChange::AlreadyDisabled) => {
+                    succeeded(&c.instance_id, Some(DeviceChange::AlreadyDisabled));
+                    say!("Device {} is already hidden", c.instance_id)
+                },
+                Ok(change) => {
+                    succeeded(&c.instance_id, Some(change));
+                    match dry_run() {
+                        true => say!("Device {} would be hidden", c.instance_id),
+                        false => say!("Device {} hidden", c.instance_id),
+                    }
+                },
+                Err(err) => failed(&c.instance_id, &err),
             }
         }
     }
 }
+
+#[cfg(windows)]
+fn prune(scope: Scope, yes: bool) {
+    let phantoms = match phantom_devices(scope) {
+        Ok(phantoms) => phantoms,
+        Err(err) => return exit::report(&err),
+    };
+    if phantoms.is_empty() {
+        say!("No disconnected controllers found");
+        return;
+    }
+    let ask = !yes && !dry_run();
+    for item in &phantoms {
+        match ask {
+            true => eprintln!("{} ({})", item.name, item.instance_id),
+            false => say!("{} ({})", item.name, item.instance_id),
+        }
+    }
+    if dry_run() {
+        say!("{} device(s) would be removed", phantoms.len());
+        return;
+    }
+    if ask && !confirm_change(&format!("Remove {} device(s)?", phantoms.len())) {
+        return;
+    }
+    for item in &phantoms {
+        match remove_phantom_device(&item.instance_id, scope) {
+            Ok(()) => {
+                succeeded(&item.instance_id, None);
+                say!("Device {} removed successfully", &item.instance_id)
+            },
+            Err(err) => failed(&item.instance_id, &err),
+        }
+    }
+}
+
+/// List the devices with given ids and ask whether to `verb` them
+fn confirm_devices(provider: &(impl DeviceProvider + ?Sized), ids: &[String], verb: &str) -> bool {
+    let controllers = provider.controllers().unwrap_or_default();
+    for id in ids {
+        match controllers.iter().find(|c| c.instance_id.eq_ignore_ascii_case(id)) {
+            Some(c) => eprintln!("  {} ({})", c.name, id),
+            None => eprintln!("  {}", id),
+        }
+    }
+    confirm_change(&format!("{} {} device(s)?", verb, ids.len()))
+}
+
+/// Ask before changing devices. No for an answer, or no terminal to answer
+/// on, fails the command with its own exit code.
+fn confirm_change(question: &str) -> bool {
+    let confirmed = confirm(question);
+    if !confirmed {
+        exit::set(exit::DECLINED);
+    }
+    confirmed
+}
+
+/// Ask a yes/no question on the terminal, defaulting to no. Without a
+/// terminal, e.g. when stdin is a list of devices, the answer is no. The
+/// question goes to stderr, so that it doesn't end up in piped output.
+fn confirm(question: &str) -> bool {
+    if !io::stdin().is_terminal() {
+        eprintln!("{} No terminal to answer on, use --yes", question);
+        return false;
+    }
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush().unwrap();
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The scope and retry settings of `list` or `enable` with given flags
+    fn parse(flags: &[&str]) -> MainCommand {
+        Args::try_parse_from(["nojoy-rs"].iter().chain(flags)).unwrap().command
+    }
+
+    fn scope(flags: &[&str], config: &Config) -> Scope {
+        let mut args = vec!["list"];
+        args.extend(flags);
+        match parse(&args) {
+            MainCommand::List { scope, .. } => scope.scope(config),
+            _ => unreachable!(),
+        }
+    }
+
+    fn retry(flags: &[&str], config: &Config) -> Retry {
+        let mut args = vec!["enable", "1"];
+        args.extend(flags);
+        match parse(&args) {
+            MainCommand::Enable { retry, .. } => retry.retry(config),
+            _ => unreachable!(),
+        }
+    }
+
+    fn config(include_disconnected: Option<bool>, env: &[(&str, &str)]) -> Config {
+        let config = Config {
+            include_disconnected,
+            retry_delay: Some(250),
+            ..Default::default()
+        };
+        config
+            .with_env(|name| env.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string()))
+            .unwrap()
+    }
+
+    #[test]
+    fn disconnected_devices_are_left_out_by_default() {
+        assert!(!scope(&[], &Config::default()).include_disconnected);
+    }
+
+    #[test]
+    fn config_includes_disconnected_devices() {
+        assert!(scope(&[], &config(Some(true), &[])).include_disconnected);
+    }
+
+    #[test]
+    fn env_overrides_config_for_disconnected_devices() {
+        let config = config(Some(true), &[(config::INCLUDE_DISCONNECTED_VAR, "0")]);
+        assert!(!scope(&[], &config).include_disconnected);
+    }
+
+    #[test]
+    fn flags_override_env_and_config_for_disconnected_devices() {
+        let included = config(Some(false), &[(config::INCLUDE_DISCONNECTED_VAR, "1")]);
+        assert!(!scope(&["--no-include-disconnected"], &included).include_disconnected);
+        let excluded = config(Some(true), &[(config::INCLUDE_DISCONNECTED_VAR, "0")]);
+        assert!(scope(&["--include-disconnected"], &excluded).include_disconnected);
+    }
+
+    #[test]
+    fn the_last_disconnected_flag_wins() {
+        let flags = ["--include-disconnected", "--no-include-disconnected"];
+        assert!(!scope(&flags, &Config::default()).include_disconnected);
+        let flags = ["--no-include-disconnected", "--include-disconnected"];
+        assert!(scope(&flags, &Config::default()).include_disconnected);
+    }
+
+    /// Names of the sample controllers `list` shows with given flags
+    fn listed(flags: &[&str]) -> Vec<String> {
+        let mut args = vec!["list"];
+        args.extend(flags);
+        let filter = match parse(&args) {
+            MainCommand::List { filter, .. } => filter,
+            _ => unreachable!(),
+        };
+        let mut controllers = FakeProvider::sample().controllers().unwrap();
+        controllers[0].is_virtual = true;
+        controllers.retain(|c| filter.matches(c));
+        controllers.into_iter().map(|c| c.name).collect()
+    }
+
+    #[test]
+    fn list_filters() {
+        let all = ["Xbox Wireless Controller", "DualSense Wireless Controller", "Racing Wheel", "USB Gamepad"];
+        assert_eq!(listed(&[]), all);
+        assert_eq!(listed(&["--enabled"]), all[..3]);
+        assert_eq!(listed(&["--connected"]), all[..3]);
+        assert_eq!(listed(&["--disabled"]), Vec::<String>::new());
+        assert_eq!(listed(&["--disableable"]), [all[0], all[1], all[3]]);
+        assert_eq!(listed(&["--virtual-only"]), all[..1]);
+        assert_eq!(listed(&["--physical-only", "--disableable"]), [all[1], all[3]]);
+    }
+
+    #[test]
+    fn id_only_conflicts_with_format() {
+        assert!(Args::try_parse_from(["nojoy-rs", "list", "--id-only", "--format", "json"]).is_err());
+        assert!(Args::try_parse_from(["nojoy-rs", "list", "--id-only", "--enabled", "--sort", "name"]).is_ok());
+    }
+
+    #[test]
+    fn physical_and_virtual_only_conflict() {
+        assert!(Args::try_parse_from(["nojoy-rs", "list", "--physical-only", "--virtual-only"]).is_err());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn flipping_disables_all_if_any_is_enabled() {
+        let provider = FakeProvider::sample();
+        let results = flip_devices(&provider, &Aliases::default(), None, &[]).unwrap();
+        let changes: Vec<_> = results.into_iter().map(|r| r.result.unwrap()).collect();
+        assert_eq!(changes, [DeviceChange::Disabled, DeviceChange::Disabled]);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn flipping_enables_all_if_none_is_enabled() {
+        let provider = FakeProvider::sample();
+        for id in ["name:xbox", "name:dualsense"] {
+            flip_devices(&provider, &Aliases::default(), None, &[id.to_string()]).unwrap();
+        }
+        let selectors = ["name:xbox".to_string(), "name:dualsense".to_string()];
+        let results = flip_devices(&provider, &Aliases::default(), None, &selectors).unwrap();
+        let changes: Vec<_> = results.into_iter().map(|r| r.result.unwrap()).collect();
+        assert_eq!(changes, [DeviceChange::Enabled, DeviceChange::Enabled]);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn flipping_selected_devices_fails_on_the_first_bad_selector() {
+        let provider = FakeProvider::sample();
+        let selectors = ["name:dualsense".to_string(), "name:wireless".to_string()];
+        let result = flip_devices(&provider, &Aliases::default(), None, &selectors);
+        assert!(matches!(result, Err(SelectError::Ambiguous { count: 2, .. })));
+        let selectors = ["name:dualsense".to_string(), "vid:045E".to_string()];
+        let results = flip_devices(&provider, &Aliases::default(), None, &selectors).unwrap();
+        let changes: Vec<_> = results.into_iter().map(|r| r.result.unwrap()).collect();
+        assert_eq!(changes, [DeviceChange::Disabled, DeviceChange::Disabled]);
+    }
+
+    #[test]
+    fn retry_delay_comes_from_flags_then_env_then_config() {
+        assert_eq!(retry(&[], &Config::default()).delay, Retry::default().delay);
+        assert_eq!(retry(&[], &config(None, &[])).delay, Duration::from_millis(250));
+        let env = config(None, &[(config::RETRY_DELAY_VAR, "500")]);
+        assert_eq!(retry(&[], &env).delay, Duration::from_millis(500));
+        assert_eq!(retry(&["--retry-delay", "50"], &env).delay, Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,132 @@
+/// Telling the user what happened to their controllers while nojoy runs in
+/// the background
+///
+/// These are notification area balloons, which Windows 10 and later show as
+/// toasts. A balloon has to come from an icon, so modes that don't have one
+/// of their own add one for as long as they run.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::mem::size_of;
+
+use nojoy_rs::{
+    devenum::Error,
+    watch::{ControllerEvent, EventKind},
+};
+use windows::{
+    core::w,
+    Win32::{
+        Foundation::HWND,
+        UI::{
+            Shell::{
+                Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_TIP, NIIF_INFO, NIM_ADD, NIM_DELETE,
+                NIM_MODIFY, NOTIFYICONDATAW,
+            },
+            WindowsAndMessaging::{
+                CreateWindowExW, DestroyWindow, LoadIconW, HWND_MESSAGE, IDI_APPLICATION,
+                WINDOW_EX_STYLE, WINDOW_STYLE,
+            },
+        },
+    },
+};
+
+/// Id of the icon, there's never more than one per window
+pub const ICON_ID: u32 = 1;
+
+/// Shows notifications from an icon, and removes the icon when dropped if
+/// it added it
+pub struct Notifier {
+    hwnd: HWND,
+    owned: bool,
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        if !self.owned {
+            return;
+        }
+        unsafe {
+            let _ = Shell_NotifyIconW(NIM_DELETE, &self.icon());
+            let _ = DestroyWindow(self.hwnd);
+        }
+    }
+}
+
+/// Copy the text to a fixed size buffer, cut to fit with the terminating
+/// zero
+fn copy(dst: &mut [u16], text: &str) {
+    let len = dst.len() - 1;
+    for (dst, src) in dst[..len].iter_mut().zip(text.encode_utf16()) {
+        *dst = src;
+    }
+}
+
+impl Notifier {
+    /// Add an icon of our own to show the notifications from
+    pub fn new() -> Result<Self, Error> {
+        unsafe {
+            // a message-only window of a system class, nothing is ever sent
+            // to it
+            let hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                w!("STATIC"),
+                w!("nojoy"),
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                None,
+                None,
+                None,
+            );
+            let notifier = Self { hwnd, owned: true };
+            let mut icon = notifier.icon();
+            icon.uFlags = NIF_ICON | NIF_TIP;
+            icon.hIcon = LoadIconW(None, IDI_APPLICATION)?;
+            copy(&mut icon.szTip, "nojoy");
+            Shell_NotifyIconW(NIM_ADD, &icon).ok()?;
+            Ok(notifier)
+        }
+    }
+
+    /// Show the notifications from the icon that `hwnd` has added already
+    pub fn for_icon(hwnd: HWND) -> Self {
+        Self { hwnd, owned: false }
+    }
+
+    fn icon(&self) -> NOTIFYICONDATAW {
+        NOTIFYICONDATAW {
+            cbSize: size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: self.hwnd,
+            uID: ICON_ID,
+            ..Default::default()
+        }
+    }
+
+    pub fn show(&self, title: &str, text: &str) {
+        let mut icon = self.icon();
+        icon.uFlags = NIF_INFO;
+        icon.dwInfoFlags = NIIF_INFO;
+        copy(&mut icon.szInfoTitle, title);
+        copy(&mut icon.szInfo, text);
+        if !unsafe { Shell_NotifyIconW(NIM_MODIFY, &icon) }.as_bool() {
+            log::warn!("notification \"{}\" can't be shown", title);
+        }
+    }
+
+    pub fn event(&self, event: &ControllerEvent) {
+        let title = match event.kind {
+            EventKind::Connected => "Controller connected",
+            EventKind::Disconnected => "Controller disconnected",
+            EventKind::Enabled => "Controller enabled",
+            EventKind::Disabled => "Controller disabled",
+            EventKind::Removed => "Controller removed",
+            EventKind::ProblemChanged if event.controller.problem_code == 0 => {
+                "Controller problem solved"
+            }
+            EventKind::ProblemChanged => "Controller has a problem",
+        };
+        self.show(title, &event.controller.name);
+    }
+}
@@ -0,0 +1,36 @@
+/// Numbers shown in `list` output that stand for devices in later commands
+///
+/// The instance ids are kept in the order `list` last printed them, so `2`
+/// means the same device the user saw in the second row.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{fs, io, path::PathBuf};
+
+use crate::aliases;
+
+const LAST_LIST_FILE_NAME: &str = "last-list.txt";
+
+fn path() -> Option<PathBuf> {
+    aliases::config_dir()
+        .ok()
+        .map(|dir| dir.join(LAST_LIST_FILE_NAME))
+}
+
+/// Remember the instance ids in the order they were listed
+pub fn save(ids: &[&str]) -> io::Result<()> {
+    let Some(path) = path() else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, ids.iter().map(|id| format!("{}\n", id)).collect::<String>())
+}
+
+/// Instance id of the device in given 1-based row of the last list
+pub fn lookup(ordinal: usize) -> Option<String> {
+    let text = fs::read_to_string(path()?).ok()?;
+    text.lines()
+        .nth(ordinal.checked_sub(1)?)
+        .map(str::to_string)
+}
@@ -0,0 +1,565 @@
+/// Rendering controller lists for the terminal and for scripts
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use nojoy_rs::devenum::{Battery, Bus, Driver, GameController, GameControllerStatus, Kind};
+#[cfg(windows)]
+use nojoy_rs::{
+    decode,
+    devenum::{self, DeviceProperties, PropertyValue},
+    hid::{Description, Usage},
+    power::PowerInfo,
+    watch::{ControllerEvent, EventKind},
+};
+use serde::Deserialize;
+#[cfg(windows)]
+use serde::Serialize;
+#[cfg(windows)]
+use windows::Win32::Devices::DeviceAndDriverInstallation::{CM_DEVNODE_STATUS_FLAGS, CM_PROB};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Enumeration order
+    None,
+    Name,
+    Id,
+    /// Enabled first, then disabled, then disconnected
+    Status,
+    /// Devnodes of the same physical device next to each other
+    Device,
+}
+
+pub fn sort(controllers: &mut [GameController], order: SortOrder) {
+    let status_rank = |c: &GameController| match c.status {
+        GameControllerStatus::Enabled => 0,
+        GameControllerStatus::Disabled => 1,
+        GameControllerStatus::Disconnected => 2,
+    };
+    match order {
+        SortOrder::None => (),
+        SortOrder::Name => controllers.sort_by_key(|c| c.name.to_lowercase()),
+        SortOrder::Id => controllers.sort_by(|a, b| a.instance_id.cmp(&b.instance_id)),
+        SortOrder::Status => controllers.sort_by_key(status_rank),
+        SortOrder::Device => {
+            let units = units(controllers);
+            let mut rows: Vec<(usize, GameController)> =
+                units.into_iter().zip(controllers.iter().cloned()).collect();
+            rows.sort_by_key(|(unit, _)| *unit);
+            for (slot, (_, controller)) in controllers.iter_mut().zip(rows) {
+                *slot = controller;
+            }
+        }
+    }
+}
+
+/// Number of the physical device each row belongs to, in the order the
+/// devices first appear. Devnodes without a container id are on their own.
+fn units(controllers: &[GameController]) -> Vec<usize> {
+    let mut containers: Vec<&str> = Vec::new();
+    controllers
+        .iter()
+        .map(|c| {
+            let container = c.container_id.as_deref().unwrap_or(&c.instance_id);
+            match containers.iter().position(|known| *known == container) {
+                Some(unit) => unit + 1,
+                None => {
+                    containers.push(container);
+                    containers.len()
+                }
+            }
+        })
+        .collect()
+}
+
+/// How `list` prints controllers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ListFormat {
+    /// Aligned columns
+    #[value(alias = "text")]
+    Table,
+    /// Every field of every controller, as Rust debug output
+    Debug,
+    Json,
+    /// Comma separated values with a header row, see [`render_csv`]
+    Csv,
+    /// Instance ids only, same as `--id-only`
+    Ids,
+}
+
+/// Longest instance id shown in full in the table
+const MAX_ID_WIDTH: usize = 40;
+
+pub fn status_text(status: GameControllerStatus) -> &'static str {
+    match status {
+        GameControllerStatus::Enabled => "enabled",
+        GameControllerStatus::Disabled => "disabled",
+        GameControllerStatus::Disconnected => "disconnected",
+    }
+}
+
+/// Keep the start and the end of a long instance id, which is where the bus,
+/// the USB ids and the instance number are
+fn shorten_id(id: &str) -> String {
+    let chars: Vec<char> = id.chars().collect();
+    if chars.len() <= MAX_ID_WIDTH {
+        return id.to_string();
+    }
+    let head = (MAX_ID_WIDTH - 1) / 2;
+    let tail = MAX_ID_WIDTH - 1 - head;
+    let mut short: String = chars[..head].iter().collect();
+    short.push('…');
+    short.extend(&chars[chars.len() - tail..]);
+    short
+}
+
+/// "game controller", "keyboard", "mouse", or "vendor-defined" for anything
+/// else
+pub fn kind_text(kind: Option<Kind>) -> &'static str {
+    match kind {
+        Some(Kind::Gamepad) => "game controller",
+        Some(Kind::Keyboard) => "keyboard",
+        Some(Kind::Mouse) => "mouse",
+        Some(Kind::AllHid) | None => "vendor-defined",
+    }
+}
+
+/// Optional columns of [`render_table`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Columns {
+    pub kind: bool,
+    /// Friendly name, location and driver
+    pub details: bool,
+    /// The status in color
+    pub color: bool,
+}
+
+/// SGR parameters of the status in color: enabled is green, disabled is
+/// yellow and disconnected devices are dim
+fn status_color(status: GameControllerStatus) -> &'static str {
+    match status {
+        GameControllerStatus::Enabled => "32",
+        GameControllerStatus::Disabled => "33",
+        GameControllerStatus::Disconnected => "2",
+    }
+}
+
+/// Numbered rows with name, manufacturer, status, whether the device can be
+/// disabled, the bus, the physical device if some have more than one devnode,
+/// the optional columns, the XInput slot and battery if any device has one,
+/// and a shortened instance id
+pub fn render_table(controllers: &[GameController], columns: Columns) -> String {
+    let with_slot = controllers.iter().any(|c| c.xinput_slot.is_some());
+    let with_virtual = controllers.iter().any(|c| c.is_virtual);
+    let units = units(controllers);
+    let with_unit = (1..=units.len()).any(|unit| units.iter().filter(|u| **u == unit).count() > 1);
+    let mut header = vec!["#", "Name", "Manufacturer", "Status", "Disableable", "Bus"];
+    if with_unit {
+        header.push("Device");
+    }
+    if with_virtual {
+        header.push("Virtual");
+    }
+    if columns.kind {
+        header.push("Kind");
+    }
+    if columns.details {
+        header.push("Friendly name");
+        header.push("Location");
+        header.push("Driver");
+    }
+    if with_slot {
+        header.push("XInput");
+        header.push("Battery");
+    }
+    header.push("ID");
+    let rows: Vec<Vec<String>> = controllers
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let mut row = vec![
+                (i + 1).to_string(),
+                c.name.clone(),
+                c.manufacturer.clone(),
+                status_text(c.status).to_string(),
+                if c.disableable { "yes" } else { "no" }.to_string(),
+                c.bus.map(Bus::name).unwrap_or_default().to_string(),
+            ];
+            if with_unit {
+                row.push(units[i].to_string());
+            }
+            if with_virtual {
+                row.push(if c.is_virtual { "yes" } else { "no" }.to_string());
+            }
+            if columns.kind {
+                row.push(kind_text(c.kind).to_string());
+            }
+            if columns.details {
+                row.push(c.friendly_name.clone().unwrap_or_default());
+                row.push(c.location.clone().unwrap_or_default());
+                row.push(c.driver.as_ref().map(driver_text).unwrap_or_default());
+            }
+            if with_slot {
+                row.push(c.xinput_slot.map(|s| s.to_string()).unwrap_or_default());
+                row.push(c.battery.map(Battery::name).unwrap_or_default().to_string());
+            }
+            row.push(shorten_id(&c.instance_id));
+            row
+        })
+        .collect();
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let last = header.len() - 1;
+    // the status is the fourth column
+    let line = |cells: Vec<&str>, status: Option<GameControllerStatus>| {
+        let mut line = String::new();
+        for (i, (cell, width)) in cells.iter().zip(&widths).enumerate() {
+            // the row number is right aligned, the last column isn't padded
+            let cell = match i {
+                0 => format!("{:>width$}", cell, width = width),
+                _ if i == last => cell.to_string(),
+                _ => format!("{:width$}", cell, width = width),
+            };
+            match status {
+                Some(status) if i == 3 && columns.color => {
+                    line += &format!("\x1b[{}m{}\x1b[0m", status_color(status), cell)
+                }
+                _ => line += &cell,
+            }
+            if i < last {
+                line += "  ";
+            }
+        }
+        line + "\n"
+    };
+    let mut text = line(header.clone(), None);
+    for (row, c) in rows.iter().zip(controllers) {
+        text += &line(row.iter().map(String::as_str).collect(), Some(c.status));
+    }
+    text
+}
+
+/// Provider, version, date and INF file of a driver, the ones that are known,
+/// like "Microsoft 10.0.19041.1 2006-06-21 input.inf"
+fn driver_text(driver: &Driver) -> String {
+    let date = driver.date.map(|t| t.to_iso8601()[..10].to_string());
+    [driver.provider.clone(), driver.version.clone(), date, driver.inf.clone()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// CSV columns in output order. New columns are only ever added at the end.
+const CSV_COLUMNS: &[&str] = &[
+    "instance_id",
+    "name",
+    "manufacturer",
+    "serial",
+    "status",
+    "disableable",
+    "status_flags",
+    "problem_code",
+    "installed_at",
+    "last_seen",
+    "kind",
+    "xinput_slot",
+    "battery",
+    "bus",
+    "parent_id",
+    "container_id",
+    "friendly_name",
+    "location",
+    "virtual",
+    "driver_provider",
+    "driver_version",
+    "driver_date",
+    "driver_inf",
+];
+
+/// Quote a CSV field if it has to be, doubling the quotes in it (RFC 4180)
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\r', '\n']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+/// A header row followed by one row per controller, empty fields for
+/// missing values
+pub fn render_csv(controllers: &[GameController]) -> String {
+    let mut text = CSV_COLUMNS.join(",") + "\r\n";
+    for c in controllers {
+        let optional = |t: Option<String>| t.unwrap_or_default();
+        let fields = [
+            c.instance_id.clone(),
+            c.name.clone(),
+            c.manufacturer.clone(),
+            optional(c.serial.clone()),
+            status_text(c.status).to_string(),
+            c.disableable.to_string(),
+            c.status_flags.to_string(),
+            c.problem_code.to_string(),
+            optional(c.installed_at.map(|t| t.to_string())),
+            optional(c.last_seen.map(|t| t.to_string())),
+            optional(c.kind.map(|k| k.name().to_string())),
+            optional(c.xinput_slot.map(|s| s.to_string())),
+            optional(c.battery.map(|b| b.name().to_string())),
+            optional(c.bus.map(|b| b.name().to_string())),
+            optional(c.parent_id.clone()),
+            optional(c.container_id.clone()),
+            optional(c.friendly_name.clone()),
+            optional(c.location.clone()),
+            c.is_virtual.to_string(),
+            optional(c.driver.as_ref().and_then(|d| d.provider.clone())),
+            optional(c.driver.as_ref().and_then(|d| d.version.clone())),
+            optional(c.driver.as_ref().and_then(|d| d.date.map(|t| t.to_string()))),
+            optional(c.driver.as_ref().and_then(|d| d.inf.clone())),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        text += &(row.join(",") + "\r\n");
+    }
+    text
+}
+
+/// One instance id per line, nothing else, so the output can be fed to
+/// `for /f` loops and PowerShell pipelines as is
+pub fn render_ids(controllers: &[GameController]) -> String {
+    controllers
+        .iter()
+        .map(|c| format!("{}\n", c.instance_id.trim_end()))
+        .collect()
+}
+
+/// One "Label: value" line per property, list items on lines of their own
+#[cfg(windows)]
+pub fn render_properties(props: &DeviceProperties) -> String {
+    let width = props.0.iter().map(|p| p.label.len()).max().unwrap_or(0) + 1;
+    let mut text = String::new();
+    for prop in &props.0 {
+        let label = format!("{}:", prop.label);
+        match &prop.value {
+            PropertyValue::List(items) => {
+                text += &format!("{}\n", label);
+                for item in items {
+                    text += &format!("    {}\n", item);
+                }
+            }
+            value => text += &format!("{:width$} {}\n", label, value, width = width),
+        }
+    }
+    text
+}
+
+/// "2024-01-02T03:04:05Z disabled     Xbox Controller (USB\...)"
+#[cfg(windows)]
+pub fn render_event(event: &ControllerEvent) -> String {
+    let kind = match event.kind {
+        EventKind::Connected => "connected",
+        EventKind::Disconnected => "disconnected",
+        EventKind::Enabled => "enabled",
+        EventKind::Disabled => "disabled",
+        EventKind::Removed => "removed",
+        EventKind::ProblemChanged => "problem",
+    };
+    format!(
+        "{} {:12} {} ({})",
+        event.time, kind, event.controller.name, event.controller.instance_id
+    )
+}
+
+/// Devnode state of one device with the numbers decoded
+#[cfg(windows)]
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceStatus<'a> {
+    pub instance_id: &'a str,
+    pub name: &'a str,
+    /// [`devenum::identity`], None without a serial number
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    pub status: GameControllerStatus,
+    pub status_flags: u32,
+    pub flag_names: Vec<&'static str>,
+    pub problem_code: u32,
+    pub problem: Option<&'static str>,
+    pub problem_explanation: Option<&'static str>,
+    pub battery: Option<Battery>,
+    /// None if it couldn't be read, or the device isn't connected
+    pub power: Option<PowerInfo>,
+}
+
+#[cfg(windows)]
+impl<'a> DeviceStatus<'a> {
+    pub fn new(controller: &'a GameController) -> Self {
+        let problem = CM_PROB(controller.problem_code);
+        Self {
+            instance_id: &controller.instance_id,
+            name: &controller.name,
+            identity: devenum::identity(controller),
+            status: controller.status,
+            status_flags: controller.status_flags,
+            flag_names: decode::status_flag_names(CM_DEVNODE_STATUS_FLAGS(controller.status_flags)),
+            problem_code: controller.problem_code,
+            problem: decode::problem_name(problem),
+            problem_explanation: decode::problem_explanation(problem),
+            battery: controller.battery,
+            power: None,
+        }
+    }
+}
+
+#[cfg(windows)]
+pub fn render_status(status: &DeviceStatus) -> String {
+    let problem = CM_PROB(status.problem_code);
+    let mut text = format!("Instance ID:  {}\n", status.instance_id);
+    text += &format!("Name:         {}\n", status.name);
+    if let Some(identity) = &status.identity {
+        text += &format!("Identity:     {}\n", identity);
+    }
+    text += &format!("Status:       {:?}\n", status.status);
+    text += &format!("Status flags: {:#010x}\n", status.status_flags);
+    for name in &status.flag_names {
+        text += &format!("    {}\n", name);
+    }
+    text += &match status.problem_explanation {
+        Some(explanation) => format!(
+            "Problem:      {}: {}\n",
+            decode::problem(problem),
+            explanation
+        ),
+        None => format!("Problem:      {}\n", decode::problem(problem)),
+    };
+    if let Some(battery) = status.battery {
+        text += &format!("Battery:      {}\n", battery.name());
+    }
+    if let Some(power) = &status.power {
+        text += &format!("Power:        {}\n", power.state.name());
+        text += &match (&power.wake_device, power.wake_armed) {
+            (None, _) => "Wake:         can't wake the computer\n".to_string(),
+            (Some(_), false) => "Wake:         not allowed to wake the computer\n".to_string(),
+            (Some(id), true) if id == status.instance_id => {
+                "Wake:         allowed to wake the computer\n".to_string()
+            }
+            (Some(id), true) => format!("Wake:         allowed to wake the computer, through {}\n", id),
+        };
+    }
+    text
+}
+
+/// "Usage:        Gamepad (game controller)" and the controls below it
+#[cfg(windows)]
+pub fn render_description(description: &Description) -> String {
+    let kind = match description.is_game_controller {
+        true => "game controller",
+        false => "not a game controller",
+    };
+    let mut text = format!("Usage:        {} ({})\n", description.usage.name(), kind);
+    text += &format!(
+        "Reports:      input {} bytes, output {} bytes, feature {} bytes\n",
+        description.input_report_length, description.output_report_length, description.feature_report_length
+    );
+    let count: usize = description
+        .buttons
+        .iter()
+        .map(|b| usize::from(b.last.saturating_sub(b.first)) + 1)
+        .sum();
+    text += &format!("Buttons:      {}\n", count);
+    for buttons in &description.buttons {
+        let first = Usage { page: buttons.page, usage: buttons.first };
+        let last = Usage { page: buttons.page, usage: buttons.last };
+        match buttons.first == buttons.last {
+            true => text += &format!("    {} (report {})\n", first.name(), buttons.report_id),
+            false => text += &format!("    {} to {} (report {})\n", first.name(), last.name(), buttons.report_id),
+        }
+    }
+    text += &format!("Values:       {}\n", description.values.len());
+    for value in &description.values {
+        text += &format!(
+            "    {}: {} to {}, {} bits (report {})\n",
+            value.usage.name(),
+            value.min,
+            value.max,
+            value.bits,
+            value.report_id
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use nojoy_rs::provider::{DeviceProvider, FakeProvider};
+
+    use super::*;
+
+    fn controllers() -> Vec<GameController> {
+        FakeProvider::sample().controllers().unwrap()
+    }
+
+    #[test]
+    fn ids_are_one_per_line() {
+        let mut controllers = controllers();
+        controllers[1].instance_id += " \t";
+        let text = render_ids(&controllers[..2]);
+        assert_eq!(
+            text,
+            format!("{}\n{}\n", controllers[0].instance_id, controllers[1].instance_id.trim_end())
+        );
+        assert!(text.lines().all(|line| line == line.trim_end()));
+    }
+
+    #[test]
+    fn no_ids_is_no_output() {
+        assert_eq!(render_ids(&[]), "");
+    }
+
+    #[test]
+    fn long_ids_keep_both_ends() {
+        let id = r"HID\{00001124-0000-1000-8000-00805F9B34FB}&VID_045E&PID_0B13&IG_00\8&2A1B3C4D&0&0000";
+        let short = shorten_id(id);
+        assert_eq!(short, r"HID\{00001124-0000-…00\8&2A1B3C4D&0&0000");
+        assert_eq!(short.chars().count(), MAX_ID_WIDTH);
+        let id = r"HID\VID_046D&PID_C24F\6&3B4C5D6E&0&0000";
+        assert_eq!(shorten_id(id), id);
+    }
+
+    #[test]
+    fn table_columns_line_up() {
+        let text = render_table(&controllers(), Columns::default());
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("#  Name  "));
+        assert!(lines[0].ends_with("  ID"));
+        let id_column = lines[0].find("ID").unwrap();
+        for line in &lines[1..] {
+            assert_eq!(line.find("HID\\"), Some(id_column), "{}", line);
+            assert_eq!(*line, line.trim_end());
+        }
+        assert!(lines[3].contains("Racing Wheel") && lines[3].contains("  no  "));
+        assert!(lines[4].contains("disconnected"));
+    }
+
+    #[test]
+    fn only_the_status_is_colored() {
+        let color = Columns {
+            color: true,
+            ..Default::default()
+        };
+        let text = render_table(&controllers()[..1], color);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(!lines[0].contains('\x1b'));
+        assert_eq!(lines[1].matches("\x1b[0m").count(), 1);
+        assert!(lines[1].contains(&format!("\x1b[{}menabled", status_color(GameControllerStatus::Enabled))));
+        assert!(!render_table(&controllers(), Columns::default()).contains('\x1b'));
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,304 @@
+/// Changing devices on behalf of other processes over a named pipe
+///
+/// An elevated `serve` can enable and disable devices for applications that
+/// aren't elevated, like a tray helper or a Stream Deck plugin, without a UAC
+/// prompt for every change. Requests and responses are JSON objects, one per
+/// line:
+///
+/// ```text
+/// {"command":"disable","device":"name:xbox"}
+/// {"ok":true,"instance_id":"HID\\VID_045E&PID_02E0\\...","change":"Disabled"}
+/// ```
+///
+/// `list` responds with the controllers the way `list --format json` prints
+/// them. Devices are the same selectors and aliases `disable` takes, with the
+/// aliases of the user running the server. Failed requests respond with the
+/// error and the exit code the command would have exited with. The pipe
+/// accepts interactive users of this machine only. Named pipes are a
+/// Windows thing, only [`Response`] is built elsewhere, for `--json`.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+#[cfg(windows)]
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    mem::{size_of, ManuallyDrop},
+    os::windows::io::{FromRawHandle, RawHandle},
+    time::Duration,
+};
+
+#[cfg(windows)]
+use clap::Subcommand;
+use nojoy_rs::provider::DeviceChange;
+#[cfg(windows)]
+use nojoy_rs::{devenum::Error, provider::DeviceProvider};
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
+use windows::{
+    core::{w, PCWSTR},
+    Win32::{
+        Foundation::{
+            CloseHandle, LocalFree, ERROR_FILE_NOT_FOUND, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED,
+            HLOCAL,
+        },
+        Security::{
+            Authorization::{
+                ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+            },
+            PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES,
+        },
+        Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX},
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, WaitNamedPipeW,
+            PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_WAIT,
+        },
+    },
+};
+
+use crate::exit::Failure;
+#[cfg(windows)]
+use crate::{aliases::Aliases, exit, journal, selector};
+
+#[cfg(windows)]
+pub const PIPE_NAME: &str = r"\\.\pipe\nojoy";
+#[cfg(windows)]
+const PIPE_NAME_W: PCWSTR = w!(r"\\.\pipe\nojoy");
+
+/// Full access for SYSTEM and administrators, read and write for users
+/// logged on interactively
+#[cfg(windows)]
+const PIPE_SDDL: PCWSTR = w!("D:P(A;;GA;;;SY)(A;;GA;;;BA)(A;;GRGW;;;IU)");
+
+/// How long a client waits for the server to finish with another client
+#[cfg(windows)]
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[cfg(windows)]
+const BUFFER_SIZE: u32 = 64 * 1024;
+
+#[cfg(windows)]
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum Request {
+    /// Print the controllers as JSON
+    List,
+    Enable {
+        /// Instance id, alias or selector
+        device: String,
+    },
+    Disable {
+        /// Instance id, alias or selector
+        device: String,
+    },
+    Toggle {
+        /// Instance id, alias or selector
+        device: String,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Exit code of the error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change: Option<DeviceChange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub controllers: Option<serde_json::Value>,
+}
+
+impl Response {
+    pub fn failure(err: &impl Failure) -> Self {
+        Self {
+            error: Some(err.to_string()),
+            code: Some(err.exit_code()),
+            ..Default::default()
+        }
+    }
+}
+
+/// The security descriptor of the pipe, freed when dropped
+#[cfg(windows)]
+struct Security(PSECURITY_DESCRIPTOR);
+
+#[cfg(windows)]
+impl Security {
+    fn new() -> Result<Self, Error> {
+        let mut descriptor = PSECURITY_DESCRIPTOR::default();
+        unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                PIPE_SDDL,
+                SDDL_REVISION_1,
+                &mut descriptor,
+                None,
+            )?;
+        }
+        Ok(Self(descriptor))
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Security {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = LocalFree(HLOCAL(self.0 .0));
+        }
+    }
+}
+
+/// Carry out the request
+#[cfg(windows)]
+pub fn handle(provider: &impl DeviceProvider, vid: Option<u16>, request: Request) -> Response {
+    let (device, enable) = match request {
+        Request::List => {
+            return match provider.controllers() {
+                Ok(controllers) => Response {
+                    ok: true,
+                    controllers: Some(serde_json::to_value(controllers).unwrap()),
+                    ..Default::default()
+                },
+                Err(err) => Response::failure(&err),
+            }
+        }
+        Request::Enable { device } => (device, Some(true)),
+        Request::Disable { device } => (device, Some(false)),
+        Request::Toggle { device } => (device, None),
+    };
+    journal::start_run();
+    let result = Aliases::load()
+        .map_err(|e| exit::Message::of(&e))
+        .and_then(|aliases| {
+            selector::select(provider, &aliases, vid, &device).map_err(|e| exit::Message::of(&e))
+        })
+        .and_then(|id| {
+            let change = match enable {
+                Some(enable) => provider.set_enabled(&id, enable),
+                None => provider.toggle(&id),
+            };
+            change
+                .map(|change| (id, change))
+                .map_err(|e| exit::Message::of(&e))
+        });
+    match result {
+        Ok((id, change)) => Response {
+            ok: true,
+            instance_id: Some(id),
+            change: Some(change),
+            ..Default::default()
+        },
+        Err(err) => Response::failure(&err),
+    }
+}
+
+/// Answer the requests of one client until it disconnects
+#[cfg(windows)]
+fn serve_client(pipe: &File, provider: &impl DeviceProvider, vid: Option<u16>) {
+    for line in BufReader::new(pipe).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        log::info!("request {}", line);
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => handle(provider, vid, request),
+            Err(err) => Response::failure(&exit::Message::new(
+                exit::INVALID_ARGUMENT,
+                format!("invalid request: {}", err),
+            )),
+        };
+        let mut text = serde_json::to_string(&response).unwrap();
+        text.push('\n');
+        let mut pipe = pipe;
+        if let Err(err) = pipe.write_all(text.as_bytes()) {
+            log::warn!("can't send the response: {}", err);
+            break;
+        }
+    }
+}
+
+/// Serve clients one after the other until an error occurs. Fails if
+/// another process has the pipe already, so that it can't pretend to be the
+/// server.
+#[cfg(windows)]
+pub fn serve(provider: &impl DeviceProvider, vid: Option<u16>) -> Result<(), Error> {
+    let security = Security::new()?;
+    let attributes = SECURITY_ATTRIBUTES {
+        nLength: size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: security.0 .0,
+        bInheritHandle: false.into(),
+    };
+    let handle = unsafe {
+        CreateNamedPipeW(
+            PIPE_NAME_W,
+            PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE,
+            PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            1,
+            BUFFER_SIZE,
+            BUFFER_SIZE,
+            0,
+            Some(&attributes),
+        )
+    };
+    if handle.is_invalid() {
+        return Err(Error::Win32(windows::core::Error::from_win32()));
+    }
+    // the same instance is used for every client, closing it would let
+    // another process create the pipe
+    let pipe = ManuallyDrop::new(unsafe { File::from_raw_handle(handle.0 as RawHandle) });
+    let result = loop {
+        match unsafe { ConnectNamedPipe(handle, None) } {
+            Err(err) if err.code() != ERROR_PIPE_CONNECTED.to_hresult() => break Err(err),
+            _ => (),
+        }
+        log::info!("client connected");
+        serve_client(&pipe, provider, vid);
+        let _ = pipe.sync_all();
+        if let Err(err) = unsafe { DisconnectNamedPipe(handle) } {
+            break Err(err);
+        }
+    };
+    unsafe {
+        let _ = CloseHandle(handle);
+    }
+    result.map_err(Error::Win32)
+}
+
+/// Send a request to the server and wait for its response
+#[cfg(windows)]
+pub fn request(request: &Request) -> Result<Response, exit::Message> {
+    let failed =
+        |e: std::io::Error| exit::Message::new(exit::FAILURE, format!("{}: {}", PIPE_NAME, e));
+    let open = || OpenOptions::new().read(true).write(true).open(PIPE_NAME);
+    let mut pipe = match open() {
+        Err(err) if err.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) => {
+            unsafe { WaitNamedPipeW(PIPE_NAME_W, BUSY_TIMEOUT.as_millis() as u32) };
+            open()
+        }
+        result => result,
+    }
+    .map_err(|e| match e.raw_os_error() {
+        Some(code) if code == ERROR_FILE_NOT_FOUND.0 as i32 => exit::Message::new(
+            exit::NOT_FOUND,
+            "the server isn't running, start it with serve",
+        ),
+        _ => failed(e),
+    })?;
+    let mut text = serde_json::to_string(request).unwrap();
+    text.push('\n');
+    pipe.write_all(text.as_bytes()).map_err(failed)?;
+    let mut line = String::new();
+    BufReader::new(pipe).read_line(&mut line).map_err(failed)?;
+    serde_json::from_str(&line).map_err(|e| {
+        exit::Message::new(
+            exit::FAILURE,
+            format!("invalid response from the server: {}", e),
+        )
+    })
+}
@@ -0,0 +1,176 @@
+/// Applying profiles and putting devices back the way they were
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use nojoy_rs::{
+    devenum::{self, Error, GameControllerStatus},
+    provider::{DeviceChange, DeviceProvider},
+};
+
+use serde::{Deserialize, Serialize};
+use windows::Win32::System::SystemInformation::GetLocalTime;
+
+use crate::{
+    aliases::{self, Aliases},
+    config::{Profile, TimeOfDay, Window},
+    selector::{self, SelectError},
+};
+
+/// What happened to one device: its instance id, or the selector when that
+/// couldn't be resolved, and the result
+pub type Change = (String, Result<DeviceChange, SelectError>);
+
+const SNAPSHOT_FILE_NAME: &str = "snapshot.json";
+
+/// Enabled state of a connected device at some point in time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceState {
+    pub instance_id: String,
+    /// Only there to make saved snapshots readable
+    #[serde(default)]
+    pub name: String,
+    pub enabled: bool,
+    /// [`devenum::identity`], to find the device again under another
+    /// instance id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot(pub Vec<DeviceState>);
+
+impl Snapshot {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Where `save` and `restore` keep the snapshot when no file is given
+pub fn default_snapshot_path() -> Option<PathBuf> {
+    aliases::config_dir()
+        .ok()
+        .map(|dir| dir.join(SNAPSHOT_FILE_NAME))
+}
+
+/// Disable, then enable, the devices of the profile
+pub fn apply(
+    provider: &impl DeviceProvider,
+    aliases: &Aliases,
+    vid: Option<u16>,
+    profile: &Profile,
+) -> Result<Vec<Change>, Error> {
+    let mut changes = Vec::new();
+    for (all, enable) in [(profile.disable_all, false), (profile.enable_all, true)] {
+        if !all {
+            continue;
+        }
+        for result in provider.set_all_enabled(enable)? {
+            changes.push((result.instance_id, result.result.map_err(SelectError::from)));
+        }
+    }
+    let targets = profile
+        .disable
+        .iter()
+        .map(|arg| (arg, false))
+        .chain(profile.enable.iter().map(|arg| (arg, true)));
+    for (arg, enable) in targets {
+        changes.push(match selector::select(provider, aliases, vid, arg) {
+            Ok(id) => {
+                let result = provider.set_enabled(&id, enable).map_err(SelectError::from);
+                (id, result)
+            }
+            Err(err) => (arg.clone(), Err(err)),
+        });
+    }
+    Ok(changes)
+}
+
+/// States of the connected controllers
+pub fn snapshot(provider: &impl DeviceProvider) -> Result<Snapshot, Error> {
+    Ok(Snapshot(
+        provider
+            .controllers()?
+            .into_iter()
+            .filter(|c| c.status != GameControllerStatus::Disconnected)
+            .map(|c| DeviceState {
+                enabled: c.status == GameControllerStatus::Enabled,
+                identity: devenum::identity(&c),
+                instance_id: c.instance_id,
+                name: c.name,
+            })
+            .collect(),
+    ))
+}
+
+/// Bring the devices in the snapshot that have changed since back to the
+/// state they had. A device that isn't there under its saved instance id is
+/// looked for by its identity, in case it was plugged into another port.
+pub fn restore(provider: &impl DeviceProvider, snapshot: &Snapshot) -> Vec<Change> {
+    let current = self::snapshot(provider).unwrap_or_default();
+    snapshot
+        .0
+        .iter()
+        .filter_map(|state| {
+            let device = current
+                .0
+                .iter()
+                .find(|c| c.instance_id == state.instance_id)
+                .or_else(|| {
+                    let identity = state.identity.as_deref()?;
+                    current
+                        .0
+                        .iter()
+                        .find(|c| c.identity.as_deref() == Some(identity))
+                });
+            match device {
+                Some(device) if device.enabled == state.enabled => None,
+                Some(device) => Some((&device.instance_id, state.enabled)),
+                None => Some((&state.instance_id, state.enabled)),
+            }
+        })
+        .map(|(id, enabled)| {
+            let result = provider
+                .set_enabled(id, enabled)
+                .map_err(SelectError::from);
+            (id.clone(), result)
+        })
+        .collect()
+}
+
+impl TimeOfDay {
+    pub fn now() -> Self {
+        let time = unsafe { GetLocalTime() };
+        Self(time.wHour * 60 + time.wMinute)
+    }
+}
+
+impl Window {
+    /// Whether the window is open at given time, it's open at `from` and
+    /// closed at `to`
+    pub fn contains(&self, time: TimeOfDay) -> bool {
+        match self.from <= self.to {
+            true => self.from <= time && time < self.to,
+            false => self.from <= time || time < self.to,
+        }
+    }
+}
+
+/// The profile of the first window that's open at given time
+pub fn scheduled(schedule: &[Window], time: TimeOfDay) -> Option<&str> {
+    schedule
+        .iter()
+        .find(|window| window.contains(time))
+        .map(|window| window.profile.as_str())
+}
@@ -0,0 +1,287 @@
+//! Power state of devices, and whether they can wake the computer
+//!
+//! The device power state comes from the `CM_POWER_DATA` Windows keeps in
+//! `DEVPKEY_Device_PowerData`. Wake arming ("allow this device to wake the
+//! computer" in Device Manager) is only offered by powrprof.dll, which knows
+//! devices by their description the way `powercfg -devicequery wake_armed`
+//! prints them, so devices that share a name can't be told apart.
+//!
+//! A HID controller usually can't wake the computer itself, its USB parent
+//! does, so the parent is looked at when the controller can't.
+//!
+//! Selective suspend is the `SelectiveSuspendEnabled` value input.inf puts
+//! in the `Device Parameters` of USB devices that hidusb drives, which are
+//! the parents of the controllers. hidusb reads it when the device starts.
+//!
+//! Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use core::{mem::size_of, ptr};
+
+use serde::Serialize;
+use windows::{
+    core::{w, HSTRING},
+    Win32::{
+        Devices::Properties::{
+            DEVPKEY_Device_DeviceDesc, DEVPKEY_Device_FriendlyName, DEVPKEY_Device_PowerData,
+        },
+        Foundation::{ERROR_ACCESS_DENIED, ERROR_FILE_NOT_FOUND, ERROR_SUCCESS, WIN32_ERROR},
+        System::{
+            Power::{
+                DevicePowerClose, DevicePowerEnumDevices, DevicePowerOpen,
+                DevicePowerSetDeviceState, PowerDeviceD0, PowerDeviceD1, PowerDeviceD2,
+                PowerDeviceD3, PowerSystemSleeping1, CM_POWER_DATA, DEVICEPOWER_CLEAR_WAKEENABLED,
+                DEVICEPOWER_FILTER_DEVICES_PRESENT, DEVICEPOWER_FILTER_WAKEENABLED,
+                DEVICEPOWER_SET_WAKEENABLED, PDCAP_WAKE_FROM_S0_SUPPORTED,
+                PDCAP_WAKE_FROM_S1_SUPPORTED, PDCAP_WAKE_FROM_S2_SUPPORTED,
+                PDCAP_WAKE_FROM_S3_SUPPORTED,
+            },
+            Registry::{
+                RegCloseKey, RegQueryValueExW, RegSetValueExW, KEY_QUERY_VALUE, KEY_SET_VALUE,
+                REG_BINARY,
+            },
+        },
+    },
+};
+
+use crate::{
+    audit,
+    devenum::{DevInfoSet, Error, GameController, Outcome, PropertyValue},
+};
+
+const SELECTIVE_SUSPEND: windows::core::PCWSTR = w!("SelectiveSuspendEnabled");
+
+/// Longest device description powrprof.dll returns, in characters
+const NAME_LENGTH: usize = 512;
+
+/// Device power state, D0 is fully on and D3 is off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PowerState {
+    D0,
+    D1,
+    D2,
+    D3,
+    Unspecified,
+}
+
+impl PowerState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PowerState::D0 => "D0 (on)",
+            PowerState::D1 => "D1 (low power)",
+            PowerState::D2 => "D2 (low power)",
+            PowerState::D3 => "D3 (off)",
+            PowerState::Unspecified => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PowerInfo {
+    pub state: PowerState,
+    /// Instance id of the device that wakes the computer for the controller,
+    /// the controller itself or its parent. None if neither can.
+    pub wake_device: Option<String>,
+    /// Whether the wake device is allowed to wake the computer
+    pub wake_armed: bool,
+}
+
+/// The power state of a connected controller and its wake settings
+pub fn power_info(controller: &GameController) -> Result<PowerInfo, Error> {
+    let data = power_data(&controller.instance_id)?.ok_or(Error::PropertyMissing)?;
+    let wake = wake_device(controller)?;
+    let wake_armed = match &wake {
+        Some((_, name)) => wake_armed_devices().iter().any(|n| n == name),
+        None => false,
+    };
+    Ok(PowerInfo {
+        state: [
+            (PowerDeviceD0, PowerState::D0),
+            (PowerDeviceD1, PowerState::D1),
+            (PowerDeviceD2, PowerState::D2),
+            (PowerDeviceD3, PowerState::D3),
+        ]
+        .into_iter()
+        .find(|(value, _)| *value == data.PD_MostRecentPowerState)
+        .map_or(PowerState::Unspecified, |(_, state)| state),
+        wake_device: wake.map(|(id, _)| id),
+        wake_armed,
+    })
+}
+
+/// Instance id and description of the device that can wake the computer for
+/// the controller, if any
+pub fn wake_device(controller: &GameController) -> Result<Option<(String, String)>, Error> {
+    let candidates = [
+        Some(controller.instance_id.as_str()),
+        controller.parent_id.as_deref(),
+    ];
+    for id in candidates.into_iter().flatten() {
+        let can_wake = power_data(id)?
+            .is_some_and(|data| data.PD_DeepestSystemWake.0 >= PowerSystemSleeping1.0);
+        if can_wake {
+            return Ok(Some((id.to_string(), description(id)?)));
+        }
+    }
+    Ok(None)
+}
+
+/// Allow the controller to wake the computer, or stop it from doing so.
+/// Returns the instance id of the device that was changed with the outcome.
+pub fn set_wake_armed(
+    controller: &GameController,
+    armed: bool,
+) -> Result<(String, Outcome), Error> {
+    let (id, name) = wake_device(controller)?.ok_or(Error::Unsupported("waking the computer"))?;
+    if wake_armed_devices().contains(&name) == armed {
+        return Ok((id, Outcome::AlreadyInState));
+    }
+    let flags = match armed {
+        true => DEVICEPOWER_SET_WAKEENABLED,
+        false => DEVICEPOWER_CLEAR_WAKEENABLED,
+    };
+    let result =
+        WIN32_ERROR(unsafe { DevicePowerSetDeviceState(&HSTRING::from(&name), flags, None) });
+    log::info!(
+        "DevicePowerSetDeviceState({}, {:#x}) -> {:?}",
+        name,
+        flags,
+        result
+    );
+    let result = check(result).map(|()| Outcome::Changed);
+    audit::record(audit::Action::Power, &id, &result);
+    Ok((id, result?))
+}
+
+/// Let Windows suspend the controller's USB device while it's idle, or keep
+/// it powered. Takes effect when the USB device is restarted, like when the
+/// controller is plugged in again. Returns the instance id of the USB
+/// device with the outcome.
+pub fn set_selective_suspend(
+    controller: &GameController,
+    enabled: bool,
+) -> Result<(String, Outcome), Error> {
+    let id = controller
+        .parent_id
+        .as_deref()
+        .filter(|id| id.starts_with("USB\\"))
+        .ok_or(Error::Unsupported(
+            "selective suspend, it isn't a USB device",
+        ))?;
+    let set = DevInfoSet::open_device(id)?;
+    let device = set.iter().next().ok_or(Error::NotFound)?;
+    let key = device
+        .open_hardware_key(KEY_QUERY_VALUE | KEY_SET_VALUE)
+        .map_err(access_error)?;
+    let result = unsafe {
+        let mut value = [0u8; 4];
+        let mut len = value.len() as u32;
+        let current = RegQueryValueExW(
+            key,
+            SELECTIVE_SUSPEND,
+            None,
+            None,
+            Some(value.as_mut_ptr()),
+            Some(&mut len),
+        );
+        log::debug!(
+            "RegQueryValueExW({}) -> {:?} {:?}",
+            id,
+            current,
+            &value[..len as usize]
+        );
+        match current {
+            // a DWORD or a byte, whichever the INF wrote
+            ERROR_SUCCESS if (value[0] != 0) == enabled => Ok(Outcome::AlreadyInState),
+            ERROR_SUCCESS | ERROR_FILE_NOT_FOUND => {
+                let result = RegSetValueExW(
+                    key,
+                    SELECTIVE_SUSPEND,
+                    0,
+                    REG_BINARY,
+                    Some(&[enabled as u8]),
+                );
+                log::info!("RegSetValueExW({}, {}) -> {:?}", id, enabled, result);
+                check(result).map(|()| Outcome::Changed)
+            }
+            err => check(err).map(|()| Outcome::Changed),
+        }
+    };
+    let _ = unsafe { RegCloseKey(key) };
+    audit::record(audit::Action::Power, id, &result);
+    Ok((id.to_string(), result?))
+}
+
+/// Descriptions of the present devices that are allowed to wake the computer
+pub fn wake_armed_devices() -> Vec<String> {
+    let wake_from_any = PDCAP_WAKE_FROM_S0_SUPPORTED
+        | PDCAP_WAKE_FROM_S1_SUPPORTED
+        | PDCAP_WAKE_FROM_S2_SUPPORTED
+        | PDCAP_WAKE_FROM_S3_SUPPORTED;
+    let mut names = Vec::new();
+    unsafe {
+        // otherwise every call builds the device list again
+        let opened = DevicePowerOpen(0).as_bool();
+        let mut buffer = vec![0u16; NAME_LENGTH];
+        for index in 0.. {
+            let mut size = (buffer.len() * size_of::<u16>()) as u32;
+            let found = DevicePowerEnumDevices(
+                index,
+                DEVICEPOWER_FILTER_DEVICES_PRESENT | DEVICEPOWER_FILTER_WAKEENABLED,
+                wake_from_any,
+                Some(buffer.as_mut_ptr().cast()),
+                &mut size,
+            );
+            if !found.as_bool() {
+                break;
+            }
+            let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+            names.push(String::from_utf16_lossy(&buffer[..len]));
+        }
+        if opened {
+            DevicePowerClose();
+        }
+    }
+    log::debug!("wake armed devices: {:?}", names);
+    names
+}
+
+/// Description powrprof.dll knows the device by, its friendly name if it has
+/// one
+pub(crate) fn description(id: &str) -> Result<String, Error> {
+    let set = DevInfoSet::open_device(id)?;
+    let device = set.iter().next().ok_or(Error::NotFound)?;
+    match device.prop_string(&DEVPKEY_Device_FriendlyName)? {
+        Some(name) => Ok(name),
+        None => Ok(device
+            .prop_string(&DEVPKEY_Device_DeviceDesc)?
+            .unwrap_or_default()),
+    }
+}
+
+fn power_data(id: &str) -> Result<Option<CM_POWER_DATA>, Error> {
+    let set = DevInfoSet::open_device(id)?;
+    let device = set.iter().next().ok_or(Error::NotFound)?;
+    match device.prop(&DEVPKEY_Device_PowerData)? {
+        Some(PropertyValue::Binary(bytes)) if bytes.len() >= size_of::<CM_POWER_DATA>() => {
+            // the bytes aren't aligned for the struct
+            Ok(Some(unsafe { ptr::read_unaligned(bytes.as_ptr().cast()) }))
+        }
+        Some(PropertyValue::Binary(bytes)) => Err(Error::PropertyLength(bytes.len())),
+        _ => Ok(None),
+    }
+}
+
+fn check(result: WIN32_ERROR) -> Result<(), Error> {
+    match result {
+        ERROR_SUCCESS => Ok(()),
+        ERROR_ACCESS_DENIED => Err(Error::AccessDenied),
+        err => Err(windows::core::Error::from(err.to_hresult()).into()),
+    }
+}
+
+fn access_error(err: Error) -> Error {
+    match err {
+        #[cfg(windows)]
+        Error::Win32(err) if err.code() == ERROR_ACCESS_DENIED.to_hresult() => Error::AccessDenied,
+        err => err,
+    }
+}
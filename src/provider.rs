@@ -0,0 +1,756 @@
+//! Abstraction over where devices come from, so that the logic on top of
+//! enumeration can run against something other than live SetupAPI calls.
+//!
+//! Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::cell::{OnceCell, RefCell};
+
+use serde::{Deserialize, Serialize};
+
+use crate::devenum::{
+    self, disable_device, enable_device, game_controllers, restart_device, set_all_devices,
+    set_devices_state, toggle_device, BulkChange, Bus, DeviceSelector, Error, GameController,
+    GameControllerStatus, Kind, Outcome, Retry, Scope,
+};
+#[cfg(windows)]
+use crate::{devenum::change_device_in_profile, elevation, hidhide::HidHide, watch::Notifications};
+
+/// What happened to a device after it was asked to change state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeviceChange {
+    Enabled,
+    Disabled,
+    AlreadyEnabled,
+    AlreadyDisabled,
+    /// Windows has to be restarted for the change to take effect
+    EnabledAfterRestart,
+    DisabledAfterRestart,
+}
+
+/// Outcome of a bulk enable/disable for one device
+#[derive(Debug)]
+pub struct BulkResult {
+    pub instance_id: String,
+    pub name: String,
+    pub result: Result<DeviceChange, Error>,
+}
+
+impl BulkResult {
+    fn new(change: BulkChange, enabled: bool) -> Self {
+        Self {
+            instance_id: change.instance_id,
+            name: change.name,
+            result: change.result.map(|outcome| DeviceChange::new(enabled, outcome)),
+        }
+    }
+}
+
+impl DeviceChange {
+    fn new(enabled: bool, outcome: Outcome) -> Self {
+        match (enabled, outcome) {
+            (true, Outcome::Changed) => DeviceChange::Enabled,
+            (false, Outcome::Changed) => DeviceChange::Disabled,
+            (true, Outcome::AlreadyInState) => DeviceChange::AlreadyEnabled,
+            (false, Outcome::AlreadyInState) => DeviceChange::AlreadyDisabled,
+            (true, Outcome::RestartRequired) => DeviceChange::EnabledAfterRestart,
+            (false, Outcome::RestartRequired) => DeviceChange::DisabledAfterRestart,
+        }
+    }
+}
+
+pub trait DeviceProvider {
+    fn controllers(&self) -> Result<Vec<GameController>, Error>;
+
+    /// Enable or disable the device with given instance id
+    fn set_enabled(&self, id: &str, enabled: bool) -> Result<DeviceChange, Error>;
+
+    /// Flip the state of the device with given instance id
+    fn toggle(&self, id: &str) -> Result<DeviceChange, Error> {
+        let controller = self
+            .controllers()?
+            .into_iter()
+            .find(|c| c.instance_id == id)
+            .ok_or(Error::NotFound)?;
+        self.set_enabled(id, controller.status != GameControllerStatus::Enabled)
+    }
+
+    /// Disable the device then enable it again
+    fn restart(&self, id: &str) -> Result<(), Error> {
+        self.set_enabled(id, false)?;
+        self.set_enabled(id, true)?;
+        Ok(())
+    }
+
+    /// Enable or disable every connected controller, skipping the ones that
+    /// can't be disabled when disabling
+    fn set_all_enabled(&self, enabled: bool) -> Result<Vec<BulkResult>, Error> {
+        Ok(self
+            .controllers()?
+            .into_iter()
+            .filter(|c| c.status != GameControllerStatus::Disconnected)
+            .filter(|c| enabled || c.disableable)
+            .map(|c| BulkResult {
+                result: self.set_enabled(&c.instance_id, enabled),
+                instance_id: c.instance_id,
+                name: c.name,
+            })
+            .collect())
+    }
+
+    /// Enable or disable the devices with given instance ids, each of them
+    /// whether the others fail or not. Ids are matched ignoring case, and a
+    /// device given more than once is changed once. Fails only if the
+    /// devices can't be enumerated at all.
+    fn set_many_enabled(&self, ids: &[String], enabled: bool) -> Result<Vec<BulkResult>, Error> {
+        let controllers = self.controllers()?;
+        let devices = controllers.into_iter().map(|c| (c.instance_id.clone(), c.name));
+        Ok(devenum::select(&instance_ids(ids), devices)
+            .into_iter()
+            .map(|found| match found {
+                Ok((instance_id, name)) => BulkResult {
+                    result: self.set_enabled(&instance_id, enabled),
+                    instance_id,
+                    name,
+                },
+                Err(instance_id) => BulkResult {
+                    instance_id,
+                    name: String::new(),
+                    result: Err(Error::NotFound),
+                },
+            })
+            .collect())
+    }
+}
+
+fn instance_ids(ids: &[String]) -> Vec<DeviceSelector> {
+    ids.iter().cloned().map(DeviceSelector::InstanceId).collect()
+}
+
+impl<P: DeviceProvider + ?Sized> DeviceProvider for Box<P> {
+    fn controllers(&self) -> Result<Vec<GameController>, Error> {
+        (**self).controllers()
+    }
+
+    fn set_enabled(&self, id: &str, enabled: bool) -> Result<DeviceChange, Error> {
+        (**self).set_enabled(id, enabled)
+    }
+
+    fn toggle(&self, id: &str) -> Result<DeviceChange, Error> {
+        (**self).toggle(id)
+    }
+
+    fn restart(&self, id: &str) -> Result<(), Error> {
+        (**self).restart(id)
+    }
+
+    fn set_all_enabled(&self, enabled: bool) -> Result<Vec<BulkResult>, Error> {
+        (**self).set_all_enabled(enabled)
+    }
+
+    fn set_many_enabled(&self, ids: &[String], enabled: bool) -> Result<Vec<BulkResult>, Error> {
+        (**self).set_many_enabled(ids, enabled)
+    }
+}
+
+/// The real thing, backed by SetupAPI and the configuration manager
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SetupApi {
+    pub scope: Scope,
+    pub retry: Retry,
+}
+
+#[cfg(windows)]
+impl DeviceProvider for SetupApi {
+    fn controllers(&self) -> Result<Vec<GameController>, Error> {
+        game_controllers(self.scope)
+    }
+
+    fn set_enabled(&self, id: &str, enabled: bool) -> Result<DeviceChange, Error> {
+        let outcome = match enabled {
+            true => enable_device(id, self.scope, self.retry)?,
+            false => disable_device(id, self.scope, self.retry)?,
+        };
+        Ok(DeviceChange::new(enabled, outcome))
+    }
+
+    fn toggle(&self, id: &str) -> Result<DeviceChange, Error> {
+        toggle_device(id, self.scope, self.retry)
+            .map(|(enabled, outcome)| DeviceChange::new(enabled, outcome))
+    }
+
+    /// Waits for the device to stop before enabling it again
+    fn restart(&self, id: &str) -> Result<(), Error> {
+        restart_device(id, self.scope, self.retry)
+    }
+
+    /// Goes over the devices once instead of enumerating them again for
+    /// every change
+    fn set_all_enabled(&self, enabled: bool) -> Result<Vec<BulkResult>, Error> {
+        Ok(set_all_devices(self.scope, self.retry, enabled)?
+            .into_iter()
+            .map(|c| BulkResult::new(c, enabled))
+            .collect())
+    }
+
+    /// Finds all of the devices in one enumeration
+    fn set_many_enabled(&self, ids: &[String], enabled: bool) -> Result<Vec<BulkResult>, Error> {
+        Ok(set_devices_state(&instance_ids(ids), enabled.into(), self.scope, self.retry)?
+            .into_iter()
+            .map(|c| BulkResult::new(c, enabled))
+            .collect())
+    }
+}
+
+/// Input devices under sysfs on Linux, disabled by inhibiting them. Only
+/// game controllers are in its scope, and retries don't apply there.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sysfs {
+    pub scope: Scope,
+}
+
+#[cfg(target_os = "linux")]
+impl DeviceProvider for Sysfs {
+    fn controllers(&self) -> Result<Vec<GameController>, Error> {
+        game_controllers(self.scope)
+    }
+
+    fn set_enabled(&self, id: &str, enabled: bool) -> Result<DeviceChange, Error> {
+        let outcome = match enabled {
+            true => enable_device(id, self.scope, Retry::default())?,
+            false => disable_device(id, self.scope, Retry::default())?,
+        };
+        Ok(DeviceChange::new(enabled, outcome))
+    }
+
+    fn toggle(&self, id: &str) -> Result<DeviceChange, Error> {
+        toggle_device(id, self.scope, Retry::default())
+            .map(|(enabled, outcome)| DeviceChange::new(enabled, outcome))
+    }
+
+    fn restart(&self, id: &str) -> Result<(), Error> {
+        restart_device(id, self.scope, Retry::default())
+    }
+
+    fn set_all_enabled(&self, enabled: bool) -> Result<Vec<BulkResult>, Error> {
+        Ok(set_all_devices(self.scope, Retry::default(), enabled)?
+            .into_iter()
+            .map(|c| BulkResult::new(c, enabled))
+            .collect())
+    }
+
+    fn set_many_enabled(&self, ids: &[String], enabled: bool) -> Result<Vec<BulkResult>, Error> {
+        Ok(set_devices_state(&instance_ids(ids), enabled.into(), self.scope, Retry::default())?
+            .into_iter()
+            .map(|c| BulkResult::new(c, enabled))
+            .collect())
+    }
+}
+
+/// Changes devices in the current hardware profile only, through their class
+/// installer like Device Manager
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HardwareProfile {
+    pub scope: Scope,
+}
+
+#[cfg(windows)]
+impl DeviceProvider for HardwareProfile {
+    fn controllers(&self) -> Result<Vec<GameController>, Error> {
+        game_controllers(self.scope)
+    }
+
+    fn set_enabled(&self, id: &str, enabled: bool) -> Result<DeviceChange, Error> {
+        let outcome = change_device_in_profile(id, self.scope, enabled)?;
+        Ok(DeviceChange::new(enabled, outcome))
+    }
+}
+
+/// Hides devices from applications with the HidHide driver instead of
+/// disabling them. Hidden devices are reported as disabled while HidHide is
+/// active.
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HidHideProvider {
+    pub scope: Scope,
+}
+
+#[cfg(windows)]
+impl DeviceProvider for HidHideProvider {
+    fn controllers(&self) -> Result<Vec<GameController>, Error> {
+        let hidhide = HidHide::open()?;
+        let hidden = match hidhide.is_active()? {
+            true => hidhide.hidden()?,
+            false => Vec::new(),
+        };
+        let mut controllers = game_controllers(self.scope)?;
+        for c in &mut controllers {
+            if c.status == GameControllerStatus::Enabled
+                && hidden.iter().any(|id| id.eq_ignore_ascii_case(&c.instance_id))
+            {
+                c.status = GameControllerStatus::Disabled;
+            }
+        }
+        Ok(controllers)
+    }
+
+    fn set_enabled(&self, id: &str, enabled: bool) -> Result<DeviceChange, Error> {
+        let hidhide = HidHide::open()?;
+        let mut hidden = hidhide.hidden()?;
+        let active = hidhide.is_active()?;
+        let is_hidden = active && hidden.iter().any(|h| h.eq_ignore_ascii_case(id));
+        if is_hidden != enabled {
+            return Ok(DeviceChange::new(enabled, Outcome::AlreadyInState));
+        }
+        hidden.retain(|h| !h.eq_ignore_ascii_case(id));
+        if !enabled {
+            hidden.push(id.to_string());
+        }
+        hidhide.set_hidden(&hidden)?;
+        if !enabled && !active {
+            hidhide.set_active(true)?;
+        }
+        Ok(DeviceChange::new(enabled, Outcome::Changed))
+    }
+}
+
+/// In-memory devices that change state when asked to
+#[derive(Debug, Default)]
+pub struct FakeProvider {
+    controllers: RefCell<Vec<GameController>>,
+}
+
+impl FakeProvider {
+    pub fn new(controllers: Vec<GameController>) -> Self {
+        Self {
+            controllers: RefCell::new(controllers),
+        }
+    }
+
+    /// Made up controllers that cover the usual cases: an Xbox controller
+    /// over Bluetooth, a DualSense over USB, a wheel whose driver can't be
+    /// disabled and a gamepad that was unplugged
+    pub fn sample() -> Self {
+        Self::new(vec![
+            sample_controller(
+                "Xbox Wireless Controller",
+                r"HID\{00001124-0000-1000-8000-00805F9B34FB}&VID_045E&PID_0B13&IG_00\8&2A1B3C4D&0&0000",
+                Some(Bus::Bluetooth),
+                GameControllerStatus::Enabled,
+                true,
+            ),
+            sample_controller(
+                "DualSense Wireless Controller",
+                r"HID\VID_054C&PID_0CE6&MI_03\7&1F2E3D4C&0&0000",
+                Some(Bus::Usb),
+                GameControllerStatus::Enabled,
+                true,
+            ),
+            sample_controller(
+                "Racing Wheel",
+                r"HID\VID_046D&PID_C24F\6&3B4C5D6E&0&0000",
+                Some(Bus::Usb),
+                GameControllerStatus::Enabled,
+                false,
+            ),
+            sample_controller(
+                "USB Gamepad",
+                r"HID\VID_0079&PID_0006\6&4C5D6E7F&0&0000",
+                Some(Bus::Usb),
+                GameControllerStatus::Disconnected,
+                true,
+            ),
+        ])
+    }
+}
+
+fn sample_controller(
+    name: &str,
+    instance_id: &str,
+    bus: Option<Bus>,
+    status: GameControllerStatus,
+    disableable: bool,
+) -> GameController {
+    GameController {
+        manufacturer: "(Standard system devices)".to_string(),
+        name: name.to_string(),
+        friendly_name: None,
+        location: None,
+        instance_id: instance_id.to_string(),
+        serial: None,
+        bus,
+        parent_id: None,
+        container_id: None,
+        status,
+        disableable,
+        is_virtual: false,
+        kind: Some(Kind::Gamepad),
+        xinput_slot: None,
+        battery: None,
+        status_flags: 0,
+        problem_code: 0,
+        installed_at: None,
+        last_seen: None,
+        driver: None,
+    }
+}
+
+impl DeviceProvider for FakeProvider {
+    fn controllers(&self) -> Result<Vec<GameController>, Error> {
+        Ok(self.controllers.borrow().clone())
+    }
+
+    fn set_enabled(&self, id: &str, enabled: bool) -> Result<DeviceChange, Error> {
+        let mut controllers = self.controllers.borrow_mut();
+        let controller = controllers
+            .iter_mut()
+            .find(|c| c.instance_id.eq_ignore_ascii_case(id))
+            .ok_or(Error::NotFound)?;
+        let status = match enabled {
+            true => GameControllerStatus::Enabled,
+            false => GameControllerStatus::Disabled,
+        };
+        if controller.status == GameControllerStatus::Disconnected {
+            return Err(Error::NotPresent);
+        }
+        if controller.status == status {
+            return Ok(DeviceChange::new(enabled, Outcome::AlreadyInState));
+        }
+        if !enabled && !controller.disableable {
+            return Err(Error::NotDisableable(controller.name.clone()));
+        }
+        controller.status = status;
+        Ok(DeviceChange::new(enabled, Outcome::Changed))
+    }
+}
+
+/// Remembers the devices of the wrapped provider until something changes in
+/// the device tree, so that modes which look at them over and over, like the
+/// tray and the TUI, don't enumerate them every time. Changes made through
+/// it forget the devices right away.
+#[cfg(windows)]
+pub struct Cached<P> {
+    inner: P,
+    devices: RefCell<Option<Vec<GameController>>>,
+    /// None if registering failed, then nothing is remembered
+    notifications: Option<Notifications>,
+}
+
+#[cfg(windows)]
+impl<P: DeviceProvider> Cached<P> {
+    pub fn new(inner: P) -> Self {
+        let notifications = Notifications::register()
+            .inspect_err(|err| log::warn!("devices won't be cached, can't follow changes: {}", err))
+            .ok();
+        Self {
+            inner,
+            devices: RefCell::new(None),
+            notifications,
+        }
+    }
+
+    /// Enumerate the devices again the next time they're needed
+    pub fn refresh(&self) {
+        self.devices.replace(None);
+    }
+
+    fn forget_after<T>(&self, result: T) -> T {
+        self.refresh();
+        result
+    }
+}
+
+#[cfg(windows)]
+impl<P: DeviceProvider> DeviceProvider for Cached<P> {
+    fn controllers(&self) -> Result<Vec<GameController>, Error> {
+        let changed = match &self.notifications {
+            Some(notifications) => notifications.changed(),
+            None => true,
+        };
+        if changed {
+            self.refresh();
+        }
+        if let Some(devices) = self.devices.borrow().as_ref() {
+            return Ok(devices.clone());
+        }
+        let devices = self.inner.controllers()?;
+        self.devices.replace(Some(devices.clone()));
+        Ok(devices)
+    }
+
+    fn set_enabled(&self, id: &str, enabled: bool) -> Result<DeviceChange, Error> {
+        self.forget_after(self.inner.set_enabled(id, enabled))
+    }
+
+    fn toggle(&self, id: &str) -> Result<DeviceChange, Error> {
+        self.forget_after(self.inner.toggle(id))
+    }
+
+    fn restart(&self, id: &str) -> Result<(), Error> {
+        self.forget_after(self.inner.restart(id))
+    }
+
+    fn set_all_enabled(&self, enabled: bool) -> Result<Vec<BulkResult>, Error> {
+        self.forget_after(self.inner.set_all_enabled(enabled))
+    }
+
+    fn set_many_enabled(&self, ids: &[String], enabled: bool) -> Result<Vec<BulkResult>, Error> {
+        self.forget_after(self.inner.set_many_enabled(ids, enabled))
+    }
+}
+
+/// Goes through the checks of a change without making it. The devices of the
+/// wrapped provider are enumerated once, and the changes are applied to that
+/// snapshot, so that a later step sees the state an earlier one would leave.
+#[derive(Debug)]
+pub struct DryRun<P> {
+    inner: P,
+    devices: OnceCell<FakeProvider>,
+    /// Whether the real change would be allowed, Err if that can't be told
+    elevated: fn() -> Result<bool, Error>,
+}
+
+impl<P: DeviceProvider> DryRun<P> {
+    pub fn new(inner: P) -> Self {
+        Self::with_elevation(inner, is_elevated)
+    }
+
+    /// Told whether the process could make the change by `elevated` rather
+    /// than by its token
+    pub fn with_elevation(inner: P, elevated: fn() -> Result<bool, Error>) -> Self {
+        Self {
+            inner,
+            devices: OnceCell::new(),
+            elevated,
+        }
+    }
+
+    fn devices(&self) -> Result<&FakeProvider, Error> {
+        if let Some(devices) = self.devices.get() {
+            return Ok(devices);
+        }
+        let devices = FakeProvider::new(self.inner.controllers()?);
+        Ok(self.devices.get_or_init(|| devices))
+    }
+}
+
+impl<P: DeviceProvider> DeviceProvider for DryRun<P> {
+    fn controllers(&self) -> Result<Vec<GameController>, Error> {
+        self.devices()?.controllers()
+    }
+
+    fn set_enabled(&self, id: &str, enabled: bool) -> Result<DeviceChange, Error> {
+        let devices = self.devices()?;
+        let before = devices.controllers.borrow().clone();
+        let change = devices.set_enabled(id, enabled)?;
+        // like the real change, an unknown elevation isn't held against it
+        if matches!(change, DeviceChange::Enabled | DeviceChange::Disabled)
+            && matches!((self.elevated)(), Ok(false))
+        {
+            // the device would have stayed as it was
+            devices.controllers.replace(before);
+            return Err(Error::NotElevated);
+        }
+        Ok(change)
+    }
+}
+
+#[cfg(windows)]
+fn is_elevated() -> Result<bool, Error> {
+    Ok(elevation::is_elevated()?)
+}
+
+/// Whether sysfs can be written to on Linux depends on udev rules, so it's
+/// left to the real change
+#[cfg(not(windows))]
+fn is_elevated() -> Result<bool, Error> {
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XBOX: &str =
+        r"HID\{00001124-0000-1000-8000-00805F9B34FB}&VID_045E&PID_0B13&IG_00\8&2A1B3C4D&0&0000";
+    const DUALSENSE: &str = r"HID\VID_054C&PID_0CE6&MI_03\7&1F2E3D4C&0&0000";
+    const WHEEL: &str = r"HID\VID_046D&PID_C24F\6&3B4C5D6E&0&0000";
+    const UNPLUGGED: &str = r"HID\VID_0079&PID_0006\6&4C5D6E7F&0&0000";
+
+    fn status(provider: &impl DeviceProvider, id: &str) -> GameControllerStatus {
+        provider
+            .controllers()
+            .unwrap()
+            .into_iter()
+            .find(|c| c.instance_id == id)
+            .unwrap()
+            .status
+    }
+
+    fn changes(results: &[BulkResult]) -> Vec<(&str, Result<DeviceChange, String>)> {
+        results
+            .iter()
+            .map(|r| {
+                let result = r.result.as_ref().copied().map_err(|err| format!("{:?}", err));
+                (r.instance_id.as_str(), result)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn disable_then_enable() {
+        let provider = FakeProvider::sample();
+        assert_eq!(provider.set_enabled(XBOX, false).unwrap(), DeviceChange::Disabled);
+        assert_eq!(status(&provider, XBOX), GameControllerStatus::Disabled);
+        assert_eq!(provider.set_enabled(XBOX, true).unwrap(), DeviceChange::Enabled);
+        assert_eq!(status(&provider, XBOX), GameControllerStatus::Enabled);
+    }
+
+    #[test]
+    fn device_in_state_is_left_alone() {
+        let provider = FakeProvider::sample();
+        assert_eq!(provider.set_enabled(XBOX, true).unwrap(), DeviceChange::AlreadyEnabled);
+    }
+
+    #[test]
+    fn ids_are_matched_ignoring_case() {
+        let provider = FakeProvider::sample();
+        let id = DUALSENSE.to_lowercase();
+        assert_eq!(provider.set_enabled(&id, false).unwrap(), DeviceChange::Disabled);
+    }
+
+    #[test]
+    fn failures_are_told_apart() {
+        let provider = FakeProvider::sample();
+        assert!(matches!(provider.set_enabled("HID\\NOPE", false), Err(Error::NotFound)));
+        assert!(matches!(provider.set_enabled(UNPLUGGED, false), Err(Error::NotPresent)));
+        assert!(matches!(
+            provider.set_enabled(WHEEL, false),
+            Err(Error::NotDisableable(name)) if name == "Racing Wheel"
+        ));
+        assert_eq!(status(&provider, WHEEL), GameControllerStatus::Enabled);
+    }
+
+    #[test]
+    fn toggle_flips_the_state() {
+        let provider = FakeProvider::sample();
+        assert_eq!(provider.toggle(XBOX).unwrap(), DeviceChange::Disabled);
+        assert_eq!(provider.toggle(XBOX).unwrap(), DeviceChange::Enabled);
+        assert!(matches!(provider.toggle("HID\\NOPE"), Err(Error::NotFound)));
+    }
+
+    #[test]
+    fn restart_leaves_the_device_enabled() {
+        let provider = FakeProvider::sample();
+        provider.restart(DUALSENSE).unwrap();
+        assert_eq!(status(&provider, DUALSENSE), GameControllerStatus::Enabled);
+    }
+
+    #[test]
+    fn disabling_all_skips_disconnected_and_non_disableable_devices() {
+        let provider = FakeProvider::sample();
+        let results = provider.set_all_enabled(false).unwrap();
+        assert_eq!(
+            changes(&results),
+            [
+                (XBOX, Ok(DeviceChange::Disabled)),
+                (DUALSENSE, Ok(DeviceChange::Disabled)),
+            ]
+        );
+        assert_eq!(results[0].name, "Xbox Wireless Controller");
+    }
+
+    #[test]
+    fn enabling_all_includes_non_disableable_devices() {
+        let provider = FakeProvider::sample();
+        provider.set_enabled(XBOX, false).unwrap();
+        let results = provider.set_all_enabled(true).unwrap();
+        assert_eq!(
+            changes(&results),
+            [
+                (XBOX, Ok(DeviceChange::Enabled)),
+                (DUALSENSE, Ok(DeviceChange::AlreadyEnabled)),
+                (WHEEL, Ok(DeviceChange::AlreadyEnabled)),
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_keeps_going_after_a_failure() {
+        let provider = FakeProvider::sample();
+        let ids = [WHEEL, "HID\\NOPE", XBOX, UNPLUGGED].map(String::from);
+        let results = provider.set_many_enabled(&ids, false).unwrap();
+        assert_eq!(
+            changes(&results),
+            [
+                (WHEEL, Err("NotDisableable(\"Racing Wheel\")".to_string())),
+                ("HID\\NOPE", Err("NotFound".to_string())),
+                (XBOX, Ok(DeviceChange::Disabled)),
+                (UNPLUGGED, Err("NotPresent".to_string())),
+            ]
+        );
+        let names: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["Racing Wheel", "", "Xbox Wireless Controller", "USB Gamepad"]);
+    }
+
+    #[test]
+    fn batch_changes_a_device_given_twice_once() {
+        let provider = FakeProvider::sample();
+        let ids = [XBOX, DUALSENSE, XBOX].map(String::from);
+        let results = provider.set_many_enabled(&ids, false).unwrap();
+        assert_eq!(
+            changes(&results),
+            [
+                (XBOX, Ok(DeviceChange::Disabled)),
+                (DUALSENSE, Ok(DeviceChange::Disabled)),
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_matches_ids_ignoring_case() {
+        let provider = FakeProvider::sample();
+        let ids = [DUALSENSE.to_lowercase(), DUALSENSE.to_string(), XBOX.to_uppercase()];
+        let results = provider.set_many_enabled(&ids, false).unwrap();
+        assert_eq!(
+            changes(&results),
+            [
+                (DUALSENSE, Ok(DeviceChange::Disabled)),
+                (XBOX, Ok(DeviceChange::Disabled)),
+            ]
+        );
+        assert_eq!(status(&provider, XBOX), GameControllerStatus::Disabled);
+        assert_eq!(results[1].name, "Xbox Wireless Controller");
+    }
+
+    #[test]
+    fn dry_run_changes_only_its_snapshot() {
+        let dry_run = DryRun::with_elevation(FakeProvider::sample(), || Ok(true));
+        assert_eq!(dry_run.set_enabled(DUALSENSE, false).unwrap(), DeviceChange::Disabled);
+        assert_eq!(status(&dry_run, DUALSENSE), GameControllerStatus::Disabled);
+        assert_eq!(
+            dry_run.set_enabled(DUALSENSE, false).unwrap(),
+            DeviceChange::AlreadyDisabled
+        );
+        assert_eq!(status(&dry_run.inner, DUALSENSE), GameControllerStatus::Enabled);
+    }
+
+    #[test]
+    fn dry_run_refuses_changes_without_elevation() {
+        let dry_run = DryRun::with_elevation(FakeProvider::sample(), || Ok(false));
+        assert!(matches!(dry_run.set_enabled(DUALSENSE, false), Err(Error::NotElevated)));
+        assert_eq!(status(&dry_run, DUALSENSE), GameControllerStatus::Enabled);
+        // nothing to change, nothing to be elevated for
+        assert_eq!(dry_run.set_enabled(DUALSENSE, true).unwrap(), DeviceChange::AlreadyEnabled);
+        assert!(matches!(dry_run.set_enabled(WHEEL, false), Err(Error::NotDisableable(_))));
+    }
+
+    #[test]
+    fn dry_run_allows_changes_when_elevation_is_unknown() {
+        let dry_run =
+            DryRun::with_elevation(FakeProvider::sample(), || Err(Error::AccessDenied));
+        assert_eq!(dry_run.set_enabled(DUALSENSE, false).unwrap(), DeviceChange::Disabled);
+        assert_eq!(status(&dry_run, DUALSENSE), GameControllerStatus::Disabled);
+    }
+}
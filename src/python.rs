@@ -0,0 +1,203 @@
+/// The `nojoy` Python module, built with `--features python`. Rename the
+/// library to `nojoy.pyd` (`nojoy.so` on Linux) to import it.
+///
+/// ```python
+/// import nojoy
+///
+/// for controller in nojoy.list_controllers():
+///     print(controller["name"], controller["instance_id"])
+/// nojoy.disable(r"HID\VID_045E&PID_02E0\...")
+/// for event in nojoy.watch():
+///     print(event["kind"], event["controller"]["name"])
+/// ```
+///
+/// Controllers and events are the dicts `nojoy list --format json` and
+/// `nojoy watch --format json` print. Failures raise `nojoy.NojoyError`,
+/// whose `code` is one of the `NOJOY_` status codes of the C API, which are
+/// in the module too without the prefix.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+#[cfg(windows)]
+use std::{collections::VecDeque, time::Duration};
+
+use pyo3::{create_exception, exceptions::PyException, prelude::*};
+use serde::Serialize;
+
+#[cfg(windows)]
+use crate::{
+    devenum::GameController,
+    timestamp::Timestamp,
+    watch::{diff, ControllerEvent, Notifications},
+};
+use crate::{
+    devenum::{self, Error, Outcome, Retry, Scope},
+    ffi,
+};
+
+/// How long a watch waits at a time, so that Ctrl+C gets through
+#[cfg(windows)]
+const POLL_TIME: Duration = Duration::from_millis(500);
+
+create_exception!(
+    nojoy,
+    NojoyError,
+    PyException,
+    "A failed call, `code` is one of the status codes"
+);
+
+fn error(err: Error) -> PyErr {
+    let code = ffi::status_code(&err);
+    let py_err = NojoyError::new_err(err.to_string());
+    Python::with_gil(|py| match py_err.value(py).setattr("code", code) {
+        Ok(()) => py_err,
+        Err(err) => err,
+    })
+}
+
+/// The value as Python objects, through the same JSON the command line prints
+fn to_python(py: Python<'_>, value: &impl Serialize) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value).expect("values are serializable");
+    Ok(py.import("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+/// The connected game controllers, and the disconnected ones Windows still
+/// knows about with `include_disconnected`
+#[pyfunction]
+#[pyo3(signature = (include_disconnected = false))]
+fn list_controllers(py: Python<'_>, include_disconnected: bool) -> PyResult<PyObject> {
+    let scope = Scope {
+        include_disconnected,
+        ..Default::default()
+    };
+    let controllers = py
+        .allow_threads(|| devenum::game_controllers(scope))
+        .map_err(error)?;
+    to_python(py, &controllers)
+}
+
+/// Whether Windows has to be restarted for the change to take effect
+fn restart_required(result: Result<Outcome, Error>) -> PyResult<bool> {
+    match result.map_err(error)? {
+        Outcome::RestartRequired => Ok(true),
+        Outcome::Changed | Outcome::AlreadyInState => Ok(false),
+    }
+}
+
+/// Enable the device, True if Windows has to be restarted for it
+#[pyfunction]
+fn enable(py: Python<'_>, instance_id: &str) -> PyResult<bool> {
+    restart_required(
+        py.allow_threads(|| {
+            devenum::enable_device(instance_id, Scope::default(), Retry::default())
+        }),
+    )
+}
+
+/// Disable the device, True if Windows has to be restarted for it
+#[pyfunction]
+fn disable(py: Python<'_>, instance_id: &str) -> PyResult<bool> {
+    restart_required(
+        py.allow_threads(|| {
+            devenum::disable_device(instance_id, Scope::default(), Retry::default())
+        }),
+    )
+}
+
+/// Controller events as they happen, returned by `watch`
+#[cfg(windows)]
+#[pyclass(unsendable)]
+struct Watch {
+    notifications: Notifications,
+    known: Vec<GameController>,
+    /// Events found but not returned yet
+    pending: VecDeque<ControllerEvent>,
+    /// Stop after this long without an event, forever if None
+    timeout: Option<Duration>,
+}
+
+/// Lets the notifications be waited for with the GIL released
+#[cfg(windows)]
+struct SameThread<'a>(&'a Notifications);
+
+// SAFETY: allow_threads runs the closure on the calling thread, it only
+// asks for Send to keep Python objects out of it
+#[cfg(windows)]
+unsafe impl Send for SameThread<'_> {}
+
+#[cfg(windows)]
+impl SameThread<'_> {
+    fn wait_timeout(&self, timeout: Duration) -> bool {
+        self.0.wait_timeout(timeout)
+    }
+}
+
+#[cfg(windows)]
+#[pymethods]
+impl Watch {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let mut idle = Duration::ZERO;
+        while self.pending.is_empty() {
+            if self.timeout.is_some_and(|timeout| idle >= timeout) {
+                return Ok(None);
+            }
+            py.check_signals()?;
+            let notifications = SameThread(&self.notifications);
+            if !py.allow_threads(move || notifications.wait_timeout(POLL_TIME)) {
+                idle += POLL_TIME;
+                continue;
+            }
+            let current = py
+                .allow_threads(|| devenum::game_controllers(Scope::default()))
+                .map_err(error)?;
+            self.pending
+                .extend(diff(&self.known, &current, Timestamp::now()));
+            self.known = current;
+        }
+        let event = self.pending.pop_front().expect("there's an event");
+        to_python(py, &event).map(Some)
+    }
+}
+
+/// Controller events as they happen, forever or until `timeout` seconds pass
+/// without one
+#[cfg(windows)]
+#[pyfunction]
+#[pyo3(signature = (timeout = None))]
+fn watch(timeout: Option<f64>) -> PyResult<Watch> {
+    let notifications = Notifications::register().map_err(error)?;
+    let known = devenum::game_controllers(Scope::default()).map_err(error)?;
+    Ok(Watch {
+        notifications,
+        known,
+        pending: VecDeque::new(),
+        timeout: timeout.map(|seconds| Duration::from_secs_f64(seconds.max(0.0))),
+    })
+}
+
+#[pymodule]
+fn nojoy(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("NojoyError", m.py().get_type::<NojoyError>())?;
+    m.add_function(wrap_pyfunction!(list_controllers, m)?)?;
+    m.add_function(wrap_pyfunction!(enable, m)?)?;
+    m.add_function(wrap_pyfunction!(disable, m)?)?;
+    #[cfg(windows)]
+    m.add_function(wrap_pyfunction!(watch, m)?)?;
+    for (name, code) in [
+        ("OK", ffi::NOJOY_OK),
+        ("NOT_FOUND", ffi::NOJOY_NOT_FOUND),
+        ("NOT_PRESENT", ffi::NOJOY_NOT_PRESENT),
+        ("ACCESS_DENIED", ffi::NOJOY_ACCESS_DENIED),
+        ("CONFIGRET", ffi::NOJOY_CONFIGRET),
+        ("WIN32", ffi::NOJOY_WIN32),
+        ("INVALID_ARGUMENT", ffi::NOJOY_INVALID_ARGUMENT),
+        ("OTHER", ffi::NOJOY_OTHER),
+        ("NOT_DISABLEABLE", ffi::NOJOY_NOT_DISABLEABLE),
+    ] {
+        m.add(name, code)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,506 @@
+/// Picking devices by something other than their full instance id
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{fmt, str::FromStr};
+
+use crate::{
+    aliases::{self, AliasError, Aliases},
+    ordinal,
+};
+use nojoy_rs::{
+    devenum::{self, vid_pid, GameController},
+    provider::DeviceProvider,
+};
+
+/// A way to match devices given on the command line:
+///
+/// - `name:<text>` matches a case-insensitive substring of the device name
+/// - `manufacturer:<text>` does the same for the manufacturer
+/// - `vid:<vid>` or `vid:<vid>:<pid>` matches hexadecimal USB ids
+/// - `serial:<value>` matches a USB serial number or Bluetooth address
+/// - `device:<identity>` matches the [`devenum::identity`] of a device, which
+///   stays the same when it's plugged into another port or paired again
+/// - `match:<regex>` matches a case-insensitive regular expression anywhere
+///   in the instance id, the name or the manufacturer
+/// - a number picks the device in that row of the last `list` output
+/// - an instance id with `*` or `?` in it matches instance ids like a file
+///   name pattern, e.g. `HID\*VID_045E*`
+/// - anything else is taken as an instance id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    InstanceId(String),
+    Name(String),
+    Manufacturer(String),
+    VidPid { vid: u16, pid: Option<u16> },
+    Serial(String),
+    Device(String),
+    Ordinal(usize),
+    Glob(String),
+    Regex(Pattern),
+}
+
+/// The regular expression of a `match:` selector, compared by its text
+#[derive(Debug, Clone)]
+pub struct Pattern(regex::Regex);
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        regex::RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map(Pattern)
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for Pattern {}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSelector(pub String);
+
+impl fmt::Display for InvalidSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid selector \"{}\"", self.0)
+    }
+}
+
+impl FromStr for Selector {
+    type Err = InvalidSelector;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidSelector(s.to_string());
+        if let Some(name) = s.strip_prefix("name:") {
+            return match name.is_empty() {
+                true => Err(invalid()),
+                false => Ok(Selector::Name(name.to_string())),
+            };
+        }
+        if let Some(manufacturer) = s.strip_prefix("manufacturer:") {
+            return match manufacturer.is_empty() {
+                true => Err(invalid()),
+                false => Ok(Selector::Manufacturer(manufacturer.to_string())),
+            };
+        }
+        if let Some(serial) = s.strip_prefix("serial:") {
+            return match serial.is_empty() {
+                true => Err(invalid()),
+                false => Ok(Selector::Serial(serial.to_string())),
+            };
+        }
+        if let Some(identity) = s.strip_prefix("device:") {
+            return match identity.is_empty() {
+                true => Err(invalid()),
+                false => Ok(Selector::Device(identity.to_string())),
+            };
+        }
+        if let Some(pattern) = s.strip_prefix("match:") {
+            return match pattern.is_empty() {
+                true => Err(invalid()),
+                false => Pattern::new(pattern)
+                    .map(Selector::Regex)
+                    .map_err(|_| invalid()),
+            };
+        }
+        if let Some(ids) = s.strip_prefix("vid:") {
+            let parse = |id: &str| u16::from_str_radix(id, 16).map_err(|_| invalid());
+            return match ids.split_once(':') {
+                Some((vid, pid)) => Ok(Selector::VidPid {
+                    vid: parse(vid)?,
+                    pid: Some(parse(pid)?),
+                }),
+                None => Ok(Selector::VidPid {
+                    vid: parse(ids)?,
+                    pid: None,
+                }),
+            };
+        }
+        if let Ok(ordinal) = s.parse::<usize>() {
+            return match ordinal {
+                0 => Err(invalid()),
+                _ => Ok(Selector::Ordinal(ordinal)),
+            };
+        }
+        match s.is_empty() {
+            true => Err(invalid()),
+            false if s.contains(['*', '?']) => Ok(Selector::Glob(s.to_string())),
+            false => Ok(Selector::InstanceId(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selector::InstanceId(id) => write!(f, "{}", id),
+            Selector::Name(name) => write!(f, "name:{}", name),
+            Selector::Manufacturer(text) => write!(f, "manufacturer:{}", text),
+            Selector::VidPid { vid, pid: None } => write!(f, "vid:{:04X}", vid),
+            Selector::VidPid {
+                vid,
+                pid: Some(pid),
+            } => write!(f, "vid:{:04X}:{:04X}", vid, pid),
+            Selector::Serial(serial) => write!(f, "serial:{}", serial),
+            Selector::Device(identity) => write!(f, "device:{}", identity),
+            Selector::Ordinal(ordinal) => write!(f, "{}", ordinal),
+            Selector::Glob(pattern) => write!(f, "{}", pattern),
+            Selector::Regex(regex) => write!(f, "match:{}", regex),
+        }
+    }
+}
+
+impl Selector {
+    pub fn matches(&self, controller: &GameController) -> bool {
+        match self {
+            Selector::InstanceId(id) => controller.instance_id.eq_ignore_ascii_case(id),
+            Selector::Name(name) => contains_ignore_case(&controller.name, name),
+            Selector::Manufacturer(text) => contains_ignore_case(&controller.manufacturer, text),
+            Selector::VidPid { vid, pid } => vid_pid(&controller.instance_id)
+                .is_some_and(|(v, p)| v == *vid && pid.is_none_or(|pid| p == pid)),
+            Selector::Serial(serial) => controller
+                .serial
+                .as_deref()
+                .is_some_and(|s| normalize_serial(s) == normalize_serial(serial)),
+            Selector::Device(identity) => {
+                devenum::identity(controller).is_some_and(|i| i.eq_ignore_ascii_case(identity))
+            }
+            // resolved through the last list before matching
+            Selector::Ordinal(_) => false,
+            Selector::Glob(pattern) => glob_matches(pattern, &controller.instance_id),
+            Selector::Regex(regex) => [
+                &controller.instance_id,
+                &controller.name,
+                &controller.manufacturer,
+            ]
+            .into_iter()
+            .any(|text| regex.is_match(text)),
+        }
+    }
+}
+
+/// Case-insensitive match of the whole text, `*` is any run of characters
+/// and `?` is any single one
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // where the last `*` was, and the text position it's matched up to
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+/// Bluetooth addresses can be written with or without separators
+fn normalize_serial(serial: &str) -> String {
+    serial
+        .chars()
+        .filter(|c| *c != ':' && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum SelectError {
+    Alias(AliasError),
+    Invalid(InvalidSelector),
+    Device(devenum::Error),
+    NoMatch(Selector),
+    /// An alias whose selector doesn't match any device anymore
+    StaleAlias {
+        alias: String,
+        selector: String,
+    },
+    Ambiguous {
+        selector: Selector,
+        count: usize,
+    },
+    /// A row number that isn't in the last `list` output
+    NoSuchRow(usize),
+}
+
+impl fmt::Display for SelectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectError::Alias(err) => write!(f, "{}", err),
+            SelectError::Invalid(err) => write!(f, "{}", err),
+            SelectError::Device(err) => write!(f, "{}", err),
+            SelectError::NoMatch(selector) => write!(f, "no device matches \"{}\"", selector),
+            SelectError::StaleAlias { alias, selector } => write!(
+                f,
+                "alias \"{}\" points to \"{}\" which doesn't match any device",
+                alias, selector
+            ),
+            SelectError::Ambiguous { selector, count } => {
+                write!(f, "{} devices match \"{}\"", count, selector)
+            }
+            SelectError::NoSuchRow(ordinal) => {
+                write!(
+                    f,
+                    "there is no row {} in the last list, run list again",
+                    ordinal
+                )
+            }
+        }
+    }
+}
+
+impl From<AliasError> for SelectError {
+    fn from(err: AliasError) -> Self {
+        SelectError::Alias(err)
+    }
+}
+
+impl From<InvalidSelector> for SelectError {
+    fn from(err: InvalidSelector) -> Self {
+        SelectError::Invalid(err)
+    }
+}
+
+impl From<devenum::Error> for SelectError {
+    fn from(err: devenum::Error) -> Self {
+        SelectError::Device(err)
+    }
+}
+
+/// What to do when more than one device matches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Pick {
+    /// Fail, the selector is ambiguous
+    #[default]
+    One,
+    /// The first one in enumeration order
+    First,
+    All,
+}
+
+/// Resolve a command line argument, which may be an alias, into the instance
+/// id of the single device it refers to. When `vid` is given, only devices of
+/// that vendor are considered unless the selector names a vendor itself.
+pub fn select(
+    provider: &(impl DeviceProvider + ?Sized),
+    aliases: &Aliases,
+    vid: Option<u16>,
+    arg: &str,
+) -> Result<String, SelectError> {
+    select_all(provider, aliases, vid, arg, Pick::One).map(|mut ids| ids.remove(0))
+}
+
+/// Like `select`, with `pick` deciding what happens when more than one device
+/// matches. Never returns an empty list.
+pub fn select_all(
+    provider: &(impl DeviceProvider + ?Sized),
+    aliases: &Aliases,
+    vid: Option<u16>,
+    arg: &str,
+    pick: Pick,
+) -> Result<Vec<String>, SelectError> {
+    let (alias, selector) = match aliases.get(arg) {
+        Some(stored) => (Some(arg), stored.parse::<Selector>()?),
+        None => (None, arg.parse::<Selector>()?),
+    };
+
+    // literal instance ids are handed to the device layer as is
+    if let (None, Selector::InstanceId(id)) = (alias, &selector) {
+        return Ok(vec![id.clone()]);
+    }
+    if let Selector::Ordinal(ordinal) = selector {
+        return ordinal::lookup(ordinal)
+            .map(|id| vec![id])
+            .ok_or(SelectError::NoSuchRow(ordinal));
+    }
+
+    let controllers = provider.controllers()?;
+    let in_namespace = |c: &GameController| match (&selector, vid) {
+        (Selector::VidPid { .. }, _) | (_, None) => true,
+        (_, Some(vid)) => vid_pid(&c.instance_id).is_some_and(|(v, _)| v == vid),
+    };
+    let matches: Vec<&GameController> = controllers
+        .iter()
+        .filter(|c| in_namespace(c) && selector.matches(c))
+        .collect();
+    if let (Some(alias), Selector::InstanceId(id)) = (alias, &selector) {
+        if let Some(id) = migrate(alias, id, &matches, &controllers) {
+            return Ok(vec![id]);
+        }
+    }
+    let matches: Vec<String> = matches.into_iter().map(|c| c.instance_id.clone()).collect();
+    match (matches.len(), pick) {
+        (0, _) => Err(match alias {
+            Some(alias) => SelectError::StaleAlias {
+                alias: alias.to_string(),
+                selector: selector.to_string(),
+            },
+            None => SelectError::NoMatch(selector),
+        }),
+        (1, _) | (_, Pick::All) => Ok(matches),
+        (_, Pick::First) => Ok(matches.into_iter().take(1).collect()),
+        (count, Pick::One) => Err(SelectError::Ambiguous { count, selector }),
+    }
+}
+
+/// Move an alias of an instance id to the identity of its device, so that
+/// it keeps working on another port. An alias whose device is gone is moved
+/// to the one connected device with the same identity, if there's one,
+/// which is returned. Commands that only look at devices don't move aliases.
+fn migrate(
+    alias: &str,
+    id: &str,
+    matches: &[&GameController],
+    controllers: &[GameController],
+) -> Option<String> {
+    let (device, moved) = match matches {
+        [device] => (*device, false),
+        [] => {
+            let identity = devenum::instance_identity(id)?;
+            let mut candidates = controllers
+                .iter()
+                .filter(|c| devenum::identity(c).as_deref() == Some(identity.as_str()));
+            match (candidates.next(), candidates.next()) {
+                (Some(device), None) => (device, true),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    let selector = Selector::Device(devenum::identity(device)?).to_string();
+    if crate::dry_run() || crate::simulated() || crate::read_only() {
+        return moved.then(|| device.instance_id.clone());
+    }
+    match aliases::repoint(alias, &selector) {
+        Ok(()) if moved => log::warn!("alias {} moved from {} to {}", alias, id, selector),
+        Ok(()) => log::info!("alias {} now points to {}", alias, selector),
+        Err(err) => log::warn!("alias {} can't be updated: {}", alias, err),
+    }
+    moved.then(|| device.instance_id.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use nojoy_rs::provider::FakeProvider;
+
+    use super::*;
+
+    const XBOX: &str =
+        r"HID\{00001124-0000-1000-8000-00805F9B34FB}&VID_045E&PID_0B13&IG_00\8&2A1B3C4D&0&0000";
+    const DUALSENSE: &str = r"HID\VID_054C&PID_0CE6&MI_03\7&1F2E3D4C&0&0000";
+
+    fn select(arg: &str, vid: Option<u16>, pick: Pick) -> Result<Vec<String>, SelectError> {
+        select_all(&FakeProvider::sample(), &Aliases::default(), vid, arg, pick)
+    }
+
+    #[test]
+    fn more_than_one_match_is_ambiguous() {
+        match select("name:wireless", None, Pick::One) {
+            Err(SelectError::Ambiguous { selector, count }) => {
+                assert_eq!(selector, Selector::Name("wireless".to_string()));
+                assert_eq!(count, 2);
+            }
+            other => panic!("not ambiguous: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn pick_decides_between_matches() {
+        assert_eq!(select("name:wireless", None, Pick::First).unwrap(), [XBOX]);
+        assert_eq!(
+            select("name:wireless", None, Pick::All).unwrap(),
+            [XBOX, DUALSENSE]
+        );
+    }
+
+    #[test]
+    fn single_match_is_not_ambiguous() {
+        assert_eq!(select("name:dualsense", None, Pick::One).unwrap(), [DUALSENSE]);
+        assert_eq!(select("vid:045E:0B13", None, Pick::One).unwrap(), [XBOX]);
+        assert_eq!(select(r"HID\VID_054C*", None, Pick::One).unwrap(), [DUALSENSE]);
+        assert_eq!(select("match:^xbox", None, Pick::One).unwrap(), [XBOX]);
+    }
+
+    #[test]
+    fn vendor_namespace_narrows_matches() {
+        let sony = Some(0x054C);
+        assert_eq!(select("name:wireless", sony, Pick::One).unwrap(), [DUALSENSE]);
+        // a selector that names a vendor itself ignores it
+        assert_eq!(select("vid:045E", sony, Pick::One).unwrap(), [XBOX]);
+    }
+
+    #[test]
+    fn no_match_is_an_error() {
+        assert!(matches!(
+            select("name:joystick", None, Pick::All),
+            Err(SelectError::NoMatch(Selector::Name(_)))
+        ));
+    }
+
+    #[test]
+    fn instance_ids_are_passed_through() {
+        let id = r"HID\VID_1234&PID_5678\1&2&3";
+        assert_eq!(select(id, None, Pick::One).unwrap(), [id]);
+    }
+
+    #[test]
+    fn alias_without_a_device_is_stale() {
+        let mut aliases = Aliases::default();
+        aliases.add("pad", "name:joystick").unwrap();
+        let result = select_all(&FakeProvider::sample(), &aliases, None, "pad", Pick::One);
+        assert!(matches!(
+            result,
+            Err(SelectError::StaleAlias { alias, selector }) if alias == "pad" && selector == "name:joystick"
+        ));
+    }
+
+    #[test]
+    fn invalid_selectors_are_rejected() {
+        for arg in ["", "name:", "vid:xyz", "vid:045E:", "0", "match:("] {
+            assert!(
+                matches!(select(arg, None, Pick::One), Err(SelectError::Invalid(_))),
+                "{:?} was accepted",
+                arg
+            );
+        }
+    }
+
+    #[test]
+    fn selectors_are_shown_the_way_they_are_given() {
+        for arg in ["name:pad", "vid:045E", "vid:045E:0B13", "serial:ABC", "device:X", "7", "match:a.b"] {
+            assert_eq!(arg.parse::<Selector>().unwrap().to_string(), arg);
+        }
+    }
+}
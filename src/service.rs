@@ -0,0 +1,192 @@
+/// Running as a Windows service that keeps a profile applied
+///
+/// The service runs as LocalSystem, so it doesn't need an elevated session
+/// and keeps running after logoff. It sees no user's desktop, which is why
+/// it enforces a single profile rather than per-application rules, or the
+/// profile of the `[[schedule]]` window that's open.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicIsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use nojoy_rs::{devenum::Error, watch::Notifications};
+use windows::{
+    core::{w, PCWSTR, PWSTR},
+    Win32::{
+        Foundation::ERROR_CALL_NOT_IMPLEMENTED,
+        Security::SC_HANDLE,
+        System::Services::{
+            CloseServiceHandle, ControlService, CreateServiceW, DeleteService, OpenSCManagerW,
+            OpenServiceW, RegisterServiceCtrlHandlerExW, SetServiceStatus,
+            StartServiceCtrlDispatcherW, StartServiceW, SC_MANAGER_ALL_ACCESS,
+            SERVICE_ACCEPT_SHUTDOWN, SERVICE_ACCEPT_STOP, SERVICE_ALL_ACCESS, SERVICE_AUTO_START,
+            SERVICE_CONTROL_INTERROGATE, SERVICE_CONTROL_SHUTDOWN, SERVICE_CONTROL_STOP,
+            SERVICE_ERROR_NORMAL, SERVICE_RUNNING, SERVICE_STATUS, SERVICE_STATUS_CURRENT_STATE,
+            SERVICE_STATUS_HANDLE, SERVICE_STOPPED, SERVICE_STOP_PENDING, SERVICE_TABLE_ENTRYW,
+            SERVICE_WIN32_OWN_PROCESS,
+        },
+    },
+};
+
+const SERVICE_NAME: PCWSTR = w!("nojoy");
+
+/// How often the stop flag is checked while the device tree is quiet
+const POLL_TIME: Duration = Duration::from_secs(1);
+
+static STOP: AtomicBool = AtomicBool::new(false);
+static STATUS_HANDLE: AtomicIsize = AtomicIsize::new(0);
+
+type Enforce = Box<dyn FnMut(bool) + Send>;
+
+/// What the service does every time the device tree changes, and every
+/// `POLL_TIME` otherwise
+static ENFORCE: Mutex<Option<Enforce>> = Mutex::new(None);
+
+/// Service manager handle, closed when dropped
+struct Handle(SC_HANDLE);
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseServiceHandle(self.0);
+        }
+    }
+}
+
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(Some(0)).collect()
+}
+
+/// Register `exe` as a service that starts with Windows and start it now. It
+/// reads its config from `config_dir` and applies `profile`.
+pub fn install(exe: &Path, profile: &str, config_dir: &Path) -> Result<(), Error> {
+    let command = wide(&format!(
+        "\"{}\" service run --config-dir \"{}\" \"{}\"",
+        exe.display(),
+        config_dir.display(),
+        profile
+    ));
+    unsafe {
+        let manager = Handle(OpenSCManagerW(None, None, SC_MANAGER_ALL_ACCESS)?);
+        let service = Handle(CreateServiceW(
+            manager.0,
+            SERVICE_NAME,
+            w!("nojoy controller policy"),
+            SERVICE_ALL_ACCESS,
+            SERVICE_WIN32_OWN_PROCESS,
+            SERVICE_AUTO_START,
+            SERVICE_ERROR_NORMAL,
+            PCWSTR(command.as_ptr()),
+            None,
+            None,
+            None,
+            // LocalSystem
+            None,
+            None,
+        )?);
+        StartServiceW(service.0, None)?;
+    }
+    Ok(())
+}
+
+/// Stop the service if it's running and remove it
+pub fn uninstall() -> Result<(), Error> {
+    unsafe {
+        let manager = Handle(OpenSCManagerW(None, None, SC_MANAGER_ALL_ACCESS)?);
+        let service = Handle(OpenServiceW(manager.0, SERVICE_NAME, SERVICE_ALL_ACCESS)?);
+        let mut status = SERVICE_STATUS::default();
+        // fails when it isn't running, which is fine
+        let _ = ControlService(service.0, SERVICE_CONTROL_STOP, &mut status);
+        DeleteService(service.0)?;
+    }
+    Ok(())
+}
+
+fn set_status(state: SERVICE_STATUS_CURRENT_STATE) {
+    let status = SERVICE_STATUS {
+        dwServiceType: SERVICE_WIN32_OWN_PROCESS,
+        dwCurrentState: state,
+        dwControlsAccepted: match state {
+            SERVICE_RUNNING => SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN,
+            _ => 0,
+        },
+        ..Default::default()
+    };
+    let handle = SERVICE_STATUS_HANDLE(STATUS_HANDLE.load(Ordering::SeqCst));
+    unsafe {
+        if let Err(err) = SetServiceStatus(handle, &status) {
+            log::warn!("SetServiceStatus failed: {}", err);
+        }
+    }
+}
+
+unsafe extern "system" fn handler(
+    control: u32,
+    _event_type: u32,
+    _event_data: *mut std::ffi::c_void,
+    _context: *mut std::ffi::c_void,
+) -> u32 {
+    match control {
+        SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+            set_status(SERVICE_STOP_PENDING);
+            STOP.store(true, Ordering::SeqCst);
+        }
+        SERVICE_CONTROL_INTERROGATE => (),
+        _ => return ERROR_CALL_NOT_IMPLEMENTED.0,
+    }
+    0
+}
+
+fn enforce(changed: bool) {
+    if let Some(enforce) = ENFORCE.lock().unwrap().as_mut() {
+        enforce(changed);
+    }
+}
+
+/// Apply the policy, then again every time something changes in the device
+/// tree, until the service is stopped
+fn serve() -> Result<(), Error> {
+    let notifications = Notifications::register()?;
+    enforce(true);
+    while !STOP.load(Ordering::SeqCst) {
+        enforce(notifications.wait_timeout(POLL_TIME));
+    }
+    Ok(())
+}
+
+unsafe extern "system" fn service_main(_argc: u32, _argv: *mut PWSTR) {
+    let handle = match RegisterServiceCtrlHandlerExW(SERVICE_NAME, Some(handler), None) {
+        Ok(handle) => handle,
+        Err(err) => return log::warn!("RegisterServiceCtrlHandlerExW failed: {}", err),
+    };
+    STATUS_HANDLE.store(handle.0, Ordering::SeqCst);
+    set_status(SERVICE_RUNNING);
+    if let Err(err) = serve() {
+        log::warn!("{}", err);
+    }
+    set_status(SERVICE_STOPPED);
+}
+
+/// Hand the process over to the service manager, calling `on_change` at the
+/// start, with true after every device change and with false every
+/// `POLL_TIME` when nothing changed, until the service is stopped. Fails when
+/// not started by the service manager.
+pub fn run(on_change: impl FnMut(bool) + Send + 'static) -> Result<(), Error> {
+    *ENFORCE.lock().unwrap() = Some(Box::new(on_change));
+    let mut name = wide("nojoy");
+    let table = [
+        SERVICE_TABLE_ENTRYW {
+            lpServiceName: PWSTR(name.as_mut_ptr()),
+            lpServiceProc: Some(service_main),
+        },
+        SERVICE_TABLE_ENTRYW::default(),
+    ];
+    unsafe { StartServiceCtrlDispatcherW(table.as_ptr())? };
+    Ok(())
+}
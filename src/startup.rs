@@ -0,0 +1,51 @@
+/// Applying a profile at every logon from a scheduled task
+///
+/// Unlike the service, the task runs as the user, so it reads their config
+/// file and aliases as they are. It runs with the highest privileges the
+/// user has, which lets an administrator change devices without a UAC
+/// prompt. The task is managed with schtasks.exe that comes with Windows.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{path::Path, process::Command};
+
+use nojoy_rs::elevation;
+
+use crate::exit;
+
+const TASK_NAME: &str = "nojoy";
+
+/// Create the task, or replace it if there is one
+pub fn register(exe: &Path, profile: &str) -> Result<(), exit::Message> {
+    let command = format!(
+        "{} apply {}",
+        elevation::quote(&exe.to_string_lossy()),
+        elevation::quote(profile)
+    );
+    schtasks(&[
+        "/Create", "/F", "/TN", TASK_NAME, "/SC", "ONLOGON", "/RL", "HIGHEST", "/TR", &command,
+    ])
+}
+
+pub fn unregister() -> Result<(), exit::Message> {
+    schtasks(&["/Delete", "/F", "/TN", TASK_NAME])
+}
+
+fn schtasks(args: &[&str]) -> Result<(), exit::Message> {
+    log::debug!("schtasks {:?}", args);
+    let output = Command::new("schtasks.exe")
+        .args(args)
+        .output()
+        .map_err(|e| exit::Message::new(exit::FAILURE, format!("can't run schtasks.exe: {}", e)))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    // schtasks doesn't have exit codes of its own, only messages like
+    // "ERROR: Access is denied."
+    let text = String::from_utf8_lossy(&output.stderr);
+    let text = text.trim().trim_start_matches("ERROR:").trim();
+    let code = match text.contains("denied") {
+        true => exit::ACCESS_DENIED,
+        false => exit::FAILURE,
+    };
+    Err(exit::Message::new(code, text))
+}
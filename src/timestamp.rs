@@ -0,0 +1,147 @@
+//! Points in time reported by Windows, printed as ISO-8601
+//!
+//! Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Serialize, Serializer};
+
+/// Seconds between 1601-01-01 (FILETIME epoch) and 1970-01-01
+const FILETIME_UNIX_OFFSET_SECS: u64 = 11_644_473_600;
+
+/// FILETIME ticks are 100ns
+const FILETIME_TICKS_PER_SEC: u64 = 10_000_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(pub SystemTime);
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Self(SystemTime::now())
+    }
+
+    /// Convert a FILETIME value (100ns ticks since 1601-01-01 UTC).
+    /// Zero means "not set" in device properties, so it's mapped to None.
+    pub fn from_filetime(filetime: u64) -> Option<Self> {
+        if filetime == 0 {
+            return None;
+        }
+        let since_1601 = Duration::new(
+            filetime / FILETIME_TICKS_PER_SEC,
+            (filetime % FILETIME_TICKS_PER_SEC * 100) as u32,
+        );
+        let offset = Duration::from_secs(FILETIME_UNIX_OFFSET_SECS);
+        let time = match since_1601.checked_sub(offset) {
+            Some(after) => UNIX_EPOCH.checked_add(after),
+            None => UNIX_EPOCH.checked_sub(offset - since_1601),
+        };
+        time.map(Timestamp)
+    }
+
+    /// "YYYY-MM-DDTHH:MM:SSZ" in UTC
+    pub fn to_iso8601(&self) -> String {
+        let secs = match self.0.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs_f64().ceil() as i64),
+        };
+        let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+        let (year, month, day) = civil_from_days(days);
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            rem / 3600,
+            rem % 3600 / 60,
+            rem % 60
+        )
+    }
+}
+
+/// Days since 1970-01-01 to a proleptic Gregorian (year, month, day),
+/// see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_iso8601())
+    }
+}
+
+impl fmt::Debug for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_iso8601())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// FILETIME of 1970-01-01T00:00:00Z
+    const UNIX_EPOCH_FILETIME: u64 = FILETIME_UNIX_OFFSET_SECS * FILETIME_TICKS_PER_SEC;
+
+    fn iso8601(filetime: u64) -> String {
+        Timestamp::from_filetime(filetime).unwrap().to_iso8601()
+    }
+
+    #[test]
+    fn zero_is_not_set() {
+        assert_eq!(Timestamp::from_filetime(0), None);
+    }
+
+    #[test]
+    fn unix_epoch() {
+        let time = Timestamp::from_filetime(UNIX_EPOCH_FILETIME).unwrap();
+        assert_eq!(time.0, UNIX_EPOCH);
+        assert_eq!(time.to_iso8601(), "1970-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn leap_day() {
+        assert_eq!(iso8601(125_963_012_960_000_000), "2000-02-29T12:34:56Z");
+    }
+
+    #[test]
+    fn sub_second_ticks_are_kept_but_not_printed() {
+        let time = Timestamp::from_filetime(UNIX_EPOCH_FILETIME + 1).unwrap();
+        assert_eq!(time.0, UNIX_EPOCH + Duration::from_nanos(100));
+        assert_eq!(time.to_iso8601(), "1970-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn before_the_unix_epoch() {
+        let second_before = UNIX_EPOCH_FILETIME - FILETIME_TICKS_PER_SEC;
+        assert_eq!(iso8601(second_before), "1969-12-31T23:59:59Z");
+        assert_eq!(iso8601(1), "1601-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn serialized_as_iso8601() {
+        let time = Timestamp::from_filetime(UNIX_EPOCH_FILETIME).unwrap();
+        assert_eq!(
+            serde_json::to_string(&time).unwrap(),
+            "\"1970-01-01T00:00:00Z\""
+        );
+        assert_eq!(format!("{:?}", time), "1970-01-01T00:00:00Z");
+    }
+}
@@ -0,0 +1,204 @@
+/// Notification area icon with a menu of controllers to check and uncheck
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{cell::RefCell, mem::size_of, thread};
+
+use nojoy_rs::{
+    devenum::{Error, GameController, GameControllerStatus, Scope},
+    provider::DeviceProvider,
+    watch,
+};
+use windows::{
+    core::{w, PCWSTR},
+    Win32::{
+        Foundation::{HWND, LPARAM, LRESULT, POINT, WPARAM},
+        System::LibraryLoader::GetModuleHandleW,
+        UI::{
+            Shell::{
+                Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE,
+                NOTIFYICONDATAW,
+            },
+            WindowsAndMessaging::{
+                AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu,
+                DestroyWindow, DispatchMessageW, GetCursorPos, GetMessageW, LoadIconW,
+                PostQuitMessage, RegisterClassW, SetForegroundWindow, TrackPopupMenu,
+                TranslateMessage, HMENU, IDI_APPLICATION, MENU_ITEM_FLAGS, MF_CHECKED, MF_GRAYED,
+                MF_SEPARATOR, MF_STRING, MSG, TPM_NONOTIFY, TPM_RETURNCMD, WINDOW_EX_STYLE, WM_APP,
+                WM_LBUTTONUP, WM_RBUTTONUP, WNDCLASSW, WS_OVERLAPPED,
+            },
+        },
+    },
+};
+
+use crate::{
+    journal,
+    notify::{self, Notifier},
+};
+
+/// Sent by the shell when something happens to our icon
+const WM_TRAY: u32 = WM_APP + 1;
+
+/// Menu command ids, controllers get theirs starting from CMD_FIRST_DEVICE
+const CMD_DISABLE_ALL: usize = 1;
+const CMD_ENABLE_ALL: usize = 2;
+const CMD_EXIT: usize = 3;
+const CMD_FIRST_DEVICE: usize = 100;
+
+thread_local! {
+    /// The window procedure has no other way to reach the devices
+    static PROVIDER: RefCell<Option<Box<dyn DeviceProvider>>> = RefCell::new(None);
+}
+
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(Some(0)).collect()
+}
+
+unsafe fn append(menu: HMENU, flags: MENU_ITEM_FLAGS, id: usize, text: &str) -> Result<(), Error> {
+    let text = wide(text);
+    AppendMenuW(menu, flags, id, PCWSTR(text.as_ptr()))?;
+    Ok(())
+}
+
+unsafe fn build_menu(controllers: &[GameController]) -> Result<HMENU, Error> {
+    let menu = CreatePopupMenu()?;
+    for (i, c) in controllers.iter().enumerate() {
+        let flags = match c.status {
+            GameControllerStatus::Enabled => MF_STRING | MF_CHECKED,
+            GameControllerStatus::Disabled => MF_STRING,
+            GameControllerStatus::Disconnected => MF_STRING | MF_GRAYED,
+        };
+        append(menu, flags, CMD_FIRST_DEVICE + i, &c.name)?;
+    }
+    if controllers.is_empty() {
+        append(menu, MF_STRING | MF_GRAYED, 0, "No controllers found")?;
+    }
+    append(menu, MF_SEPARATOR, 0, "")?;
+    append(menu, MF_STRING, CMD_DISABLE_ALL, "Disable all")?;
+    append(menu, MF_STRING, CMD_ENABLE_ALL, "Enable all")?;
+    append(menu, MF_SEPARATOR, 0, "")?;
+    append(menu, MF_STRING, CMD_EXIT, "Exit")?;
+    Ok(menu)
+}
+
+/// Show the menu at the mouse cursor and carry out whatever is picked
+unsafe fn show_menu(hwnd: HWND, provider: &dyn DeviceProvider) -> Result<(), Error> {
+    let controllers = provider.controllers()?;
+    let menu = build_menu(&controllers)?;
+    let mut cursor = POINT::default();
+    GetCursorPos(&mut cursor)?;
+    // without this the menu doesn't go away when clicked elsewhere
+    SetForegroundWindow(hwnd);
+    let command = TrackPopupMenu(
+        menu,
+        TPM_RETURNCMD | TPM_NONOTIFY,
+        cursor.x,
+        cursor.y,
+        0,
+        hwnd,
+        None,
+    )
+    .0 as usize;
+    let _ = DestroyMenu(menu);
+    journal::start_run();
+    match command {
+        0 => (),
+        CMD_EXIT => PostQuitMessage(0),
+        CMD_DISABLE_ALL | CMD_ENABLE_ALL => {
+            for result in provider.set_all_enabled(command == CMD_ENABLE_ALL)? {
+                if let Err(err) = result.result {
+                    log::warn!("{}: {}", result.name, err);
+                }
+            }
+        }
+        n => {
+            if let Some(c) = controllers.get(n - CMD_FIRST_DEVICE) {
+                provider.toggle(&c.instance_id)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == WM_TRAY && matches!(lparam.0 as u32, WM_LBUTTONUP | WM_RBUTTONUP) {
+        PROVIDER.with(|p| {
+            if let Some(provider) = p.borrow().as_deref() {
+                if let Err(err) = show_menu(hwnd, provider) {
+                    log::warn!("{}", err);
+                }
+            }
+        });
+        return LRESULT(0);
+    }
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
+
+/// Run until "Exit" is picked from the menu, showing a notification for
+/// every change to the controllers in `notify` if it's given
+pub fn run(provider: Box<dyn DeviceProvider>, notify: Option<Scope>) -> Result<(), Error> {
+    PROVIDER.with(|p| *p.borrow_mut() = Some(provider));
+    unsafe {
+        let instance = GetModuleHandleW(None)?;
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance.into(),
+            lpszClassName: w!("nojoy-tray"),
+            ..Default::default()
+        };
+        RegisterClassW(&class);
+        // never shown, it's only there to receive the icon's messages
+        let hwnd = CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            w!("nojoy-tray"),
+            w!("nojoy"),
+            WS_OVERLAPPED,
+            0,
+            0,
+            0,
+            0,
+            None,
+            None,
+            instance,
+            None,
+        );
+
+        let mut icon = NOTIFYICONDATAW {
+            cbSize: size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: hwnd,
+            uID: notify::ICON_ID,
+            uFlags: NIF_ICON | NIF_MESSAGE | NIF_TIP,
+            uCallbackMessage: WM_TRAY,
+            hIcon: LoadIconW(None, IDI_APPLICATION)?,
+            ..Default::default()
+        };
+        for (dst, src) in icon.szTip.iter_mut().zip(wide("nojoy")) {
+            *dst = src;
+        }
+        Shell_NotifyIconW(NIM_ADD, &icon).ok()?;
+        if let Some(scope) = notify {
+            let notifier = Notifier::for_icon(hwnd);
+            // the process exits with the message loop, so it's never joined
+            thread::spawn(move || {
+                if let Err(err) = watch::watch(scope, |event| notifier.event(event)) {
+                    log::warn!("{}", err);
+                }
+            });
+        }
+
+        let mut msg = MSG::default();
+        while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+
+        let _ = Shell_NotifyIconW(NIM_DELETE, &icon);
+        let _ = DestroyWindow(hwnd);
+    }
+    PROVIDER.with(|p| p.borrow_mut().take());
+    Ok(())
+}
@@ -0,0 +1,183 @@
+/// Full screen controller table: arrow keys pick a device, Enter or space
+/// toggles it
+///
+/// Drawn with ratatui on crossterm, which sets up the console the same way in
+/// Windows Terminal and conhost.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{io, time::Duration};
+
+use nojoy_rs::{
+    devenum::{GameController, GameControllerStatus},
+    provider::{DeviceChange, DeviceProvider},
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    widgets::{Cell, Paragraph, Row, Table, TableState},
+    DefaultTerminal, Frame,
+};
+
+use crate::{exit, journal, output};
+
+/// The table is refreshed this often even when no key is pressed
+const REFRESH_TIME: Duration = Duration::from_secs(1);
+
+const HELP: &str = "nojoy - up/down to pick, enter/space to toggle, q to quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Toggle,
+    Quit,
+}
+
+/// Wait up to `timeout` for a key we know about
+fn read_key(timeout: Duration) -> io::Result<Option<Key>> {
+    if !event::poll(timeout)? {
+        return Ok(None);
+    }
+    let Event::Key(key) = event::read()? else {
+        return Ok(None);
+    };
+    // Windows reports releases too
+    if key.kind != KeyEventKind::Press {
+        return Ok(None);
+    }
+    Ok(match key.code {
+        KeyCode::Up => Some(Key::Up),
+        KeyCode::Down => Some(Key::Down),
+        KeyCode::Enter | KeyCode::Char(' ') => Some(Key::Toggle),
+        KeyCode::Esc | KeyCode::Char('q') => Some(Key::Quit),
+        _ => None,
+    })
+}
+
+fn status_style(status: GameControllerStatus) -> Style {
+    if !crate::color() {
+        return Style::default();
+    }
+    match status {
+        GameControllerStatus::Enabled => Style::default().fg(Color::Green),
+        GameControllerStatus::Disabled => Style::default().fg(Color::Yellow),
+        GameControllerStatus::Disconnected => Style::default().add_modifier(Modifier::DIM),
+    }
+}
+
+/// The help line, the table with the selected row in reverse video, and the
+/// message of the last toggle
+fn render(frame: &mut Frame, controllers: &[GameController], state: &mut TableState, message: &str) {
+    let [help, table, status] = Layout::vertical([
+        Constraint::Length(2),
+        Constraint::Min(1),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    frame.render_widget(Paragraph::new(HELP), help);
+    if controllers.is_empty() {
+        frame.render_widget(Paragraph::new("No controllers found"), table);
+    } else {
+        let rows = controllers.iter().enumerate().map(|(i, c)| {
+            Row::new([
+                Cell::from(format!("{:>2}", i + 1)),
+                Cell::from(output::status_text(c.status)).style(status_style(c.status)),
+                Cell::from(c.name.as_str()),
+            ])
+        });
+        let widths = [Constraint::Length(2), Constraint::Length(13), Constraint::Fill(1)];
+        let rows = Table::new(rows, widths)
+            .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(rows, table, state);
+    }
+    frame.render_widget(Paragraph::new(message), status);
+}
+
+fn toggle(provider: &impl DeviceProvider, controller: &GameController) -> String {
+    match provider.toggle(&controller.instance_id) {
+        Ok(DeviceChange::Enabled) => format!("{} enabled", controller.name),
+        Ok(DeviceChange::Disabled) => format!("{} disabled", controller.name),
+        Ok(DeviceChange::EnabledAfterRestart) => {
+            format!("{} will be enabled after a restart", controller.name)
+        }
+        Ok(DeviceChange::DisabledAfterRestart) => {
+            format!("{} will be disabled after a restart", controller.name)
+        }
+        Ok(_) => String::new(),
+        Err(err) => format!("Error: {}", err),
+    }
+}
+
+fn run_on(terminal: &mut DefaultTerminal, provider: &impl DeviceProvider) -> Result<(), exit::Message> {
+    let terminal_error = |err: io::Error| exit::Message::new(exit::FAILURE, format!("terminal: {}", err));
+    let mut state = TableState::default().with_selected(0);
+    let mut message = String::new();
+    loop {
+        let controllers = provider.controllers().map_err(|err| exit::Message::of(&err))?;
+        let selected = state.selected().unwrap_or(0);
+        state.select(Some(selected.min(controllers.len().saturating_sub(1))));
+        terminal
+            .draw(|frame| render(frame, &controllers, &mut state, &message))
+            .map_err(terminal_error)?;
+        match read_key(REFRESH_TIME).map_err(terminal_error)? {
+            None => (),
+            Some(Key::Quit) => return Ok(()),
+            Some(Key::Up) => state.select_previous(),
+            Some(Key::Down) => state.select_next(),
+            Some(Key::Toggle) => {
+                if let Some(controller) = state.selected().and_then(|i| controllers.get(i)) {
+                    journal::start_run();
+                    message = toggle(provider, controller);
+                }
+            }
+        }
+    }
+}
+
+pub fn run(provider: &impl DeviceProvider) -> Result<(), exit::Message> {
+    // raw mode and the alternate screen, restored on panics too
+    let mut terminal = ratatui::init();
+    let result = run_on(&mut terminal, provider);
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use nojoy_rs::{devenum::Error, provider::FakeProvider};
+
+    use super::*;
+
+    fn controller(provider: &FakeProvider, name: &str) -> GameController {
+        provider
+            .controllers()
+            .unwrap()
+            .into_iter()
+            .find(|c| c.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn toggling_flips_the_device_and_tells_so() {
+        let provider = FakeProvider::sample();
+        let pad = controller(&provider, "DualSense Wireless Controller");
+        assert_eq!(toggle(&provider, &pad), "DualSense Wireless Controller disabled");
+        assert_eq!(controller(&provider, &pad.name).status, GameControllerStatus::Disabled);
+        assert_eq!(toggle(&provider, &pad), "DualSense Wireless Controller enabled");
+        assert_eq!(controller(&provider, &pad.name).status, GameControllerStatus::Enabled);
+    }
+
+    #[test]
+    fn failed_toggles_leave_the_device_alone() {
+        let provider = FakeProvider::sample();
+        let wheel = controller(&provider, "Racing Wheel");
+        assert_eq!(
+            toggle(&provider, &wheel),
+            format!("Error: {}", Error::NotDisableable(wheel.name.clone()))
+        );
+        assert_eq!(controller(&provider, &wheel.name).status, GameControllerStatus::Enabled);
+        let unplugged = controller(&provider, "USB Gamepad");
+        assert_eq!(toggle(&provider, &unplugged), format!("Error: {}", Error::NotPresent));
+    }
+}
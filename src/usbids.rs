@@ -0,0 +1,276 @@
+/// Vendor and product names by USB ids, for controllers whose driver only
+/// gives a generic name like "HID-compliant game controller"
+///
+/// A few common controllers are built in. More come from a usb.ids file
+/// like the one at http://www.linux-usb.org/usb.ids, the one the `usb-ids`
+/// key of the config file points at, or `usb.ids` in the config directory.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{collections::HashMap, fs, io, path::Path};
+
+use nojoy_rs::devenum::{vid_pid, GameController};
+
+use crate::aliases;
+
+const FILE_NAME: &str = "usb.ids";
+
+/// Names of devices whose driver doesn't give them a better one
+const GENERIC_NAMES: &[&str] = &[
+    "HID-compliant game controller",
+    "HID-compliant device",
+    "USB Input Device",
+    "Bluetooth HID Device",
+    "Xbox Gaming Device",
+];
+
+/// The same format as usb.ids, vendors at the start of a line and their
+/// devices below them after a tab
+const BUILT_IN: &str = "\
+045e  Microsoft Corp.
+\t028e  Xbox360 Controller
+\t028f  Xbox360 Wireless Controller
+\t02d1  Xbox One Controller
+\t02dd  Xbox One Controller (Firmware 2015)
+\t02e0  Xbox One S Controller [Bluetooth]
+\t02e3  Xbox One Elite Controller
+\t02ea  Xbox One S Controller
+\t02fd  Xbox One S Controller [Bluetooth]
+\t0b00  Xbox Elite Series 2 Controller
+\t0b12  Xbox Wireless Controller
+\t0b13  Xbox Wireless Controller [Bluetooth]
+044f  ThrustMaster, Inc.
+046d  Logitech, Inc.
+\tc216  F310 Gamepad [DirectInput Mode]
+\tc21d  F310 Gamepad [XInput Mode]
+\tc21e  F510 Gamepad [XInput Mode]
+\tc21f  F710 Wireless Gamepad [XInput Mode]
+\tc24f  G29 Driving Force Racing Wheel [PS3]
+\tc262  G920 Driving Force Racing Wheel
+054c  Sony Corp.
+\t0268  PlayStation 3 Controller
+\t05c4  DualShock 4 [CUH-ZCT1x]
+\t09cc  DualShock 4 [CUH-ZCT2x]
+\t0ba0  DualShock 4 USB Wireless Adaptor
+\t0ce6  DualSense Wireless Controller
+\t0df2  DualSense Edge Wireless Controller
+057e  Nintendo Co., Ltd
+\t2006  Joy-Con L
+\t2007  Joy-Con R
+\t2009  Switch Pro Controller
+06a3  Saitek PLC
+0738  Mad Catz, Inc.
+0e6f  Logic3
+0f0d  Hori Co., Ltd
+1532  Razer USA, Ltd
+28de  Valve Software
+\t1102  Steam Controller
+\t1142  Steam Controller Dongle
+2dc8  8BitDo
+";
+
+struct Vendor {
+    name: String,
+    products: HashMap<u16, String>,
+}
+
+#[derive(Default)]
+pub struct UsbIds {
+    vendors: HashMap<u16, Vendor>,
+}
+
+impl UsbIds {
+    /// The built-in names with those of the usb.ids file on top. A file
+    /// that was configured but can't be read is logged and skipped.
+    pub fn load(configured: Option<&Path>) -> Self {
+        let mut ids = UsbIds::default();
+        ids.add(BUILT_IN);
+        let (path, optional) = match configured {
+            Some(path) => (path.to_path_buf(), false),
+            None => match aliases::config_dir() {
+                Ok(dir) => (dir.join(FILE_NAME), true),
+                Err(_) => return ids,
+            },
+        };
+        match fs::read(&path) {
+            // usb.ids isn't all UTF-8
+            Ok(bytes) => ids.add(&String::from_utf8_lossy(&bytes)),
+            Err(err) if optional && err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => log::warn!("{} can't be read: {}", path.display(), err),
+        }
+        ids
+    }
+
+    /// Add the vendors and devices of usb.ids text, stopping at the device
+    /// classes that come after them
+    fn add(&mut self, text: &str) {
+        let mut vendor = None;
+        for line in text.lines() {
+            if line.is_empty() || line.starts_with('#') || line.starts_with("\t\t") {
+                continue;
+            }
+            let (device, line) = match line.strip_prefix('\t') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let Some((id, name)) = line.split_once("  ") else {
+                continue;
+            };
+            let Ok(id) = u16::from_str_radix(id, 16) else {
+                if !device {
+                    break;
+                }
+                continue;
+            };
+            match (device, vendor) {
+                (false, _) => {
+                    vendor = Some(id);
+                    self.vendors
+                        .entry(id)
+                        .or_insert_with(|| Vendor {
+                            name: String::new(),
+                            products: HashMap::new(),
+                        })
+                        .name = name.to_string();
+                }
+                (true, Some(vid)) => {
+                    if let Some(vendor) = self.vendors.get_mut(&vid) {
+                        vendor.products.insert(id, name.to_string());
+                    }
+                }
+                (true, None) => (),
+            }
+        }
+    }
+
+    /// "Microsoft Corp. / Xbox360 Controller"
+    pub fn name(&self, vid: u16, pid: u16) -> Option<String> {
+        let vendor = self.vendors.get(&vid)?;
+        let product = vendor.products.get(&pid)?;
+        Some(format!("{} / {}", vendor.name, product))
+    }
+
+    pub fn vendor(&self, vid: u16) -> Option<&str> {
+        self.vendors.get(&vid).map(|v| v.name.as_str())
+    }
+}
+
+/// Whether the name is one Windows gives any device of its kind. Inbox
+/// drivers have their manufacturer in parentheses in every language.
+fn is_generic(controller: &GameController) -> bool {
+    controller.friendly_name.is_none()
+        && (controller.manufacturer.starts_with('(')
+            || GENERIC_NAMES
+                .iter()
+                .any(|n| n.eq_ignore_ascii_case(&controller.name)))
+}
+
+/// Replace generic names with the vendor and product the USB ids stand for
+pub fn rename(controllers: &mut [GameController], ids: &UsbIds) {
+    for controller in controllers.iter_mut().filter(|c| is_generic(c)) {
+        let Some((vid, pid)) = vid_pid(&controller.instance_id) else {
+            continue;
+        };
+        if let Some(name) = ids.name(vid, pid) {
+            controller.name = name;
+        } else if let Some(vendor) = ids.vendor(vid) {
+            controller.name = format!("{} ({})", controller.name, vendor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nojoy_rs::provider::{DeviceProvider, FakeProvider};
+
+    use super::*;
+
+    fn parsed(text: &str) -> UsbIds {
+        let mut ids = UsbIds::default();
+        ids.add(text);
+        ids
+    }
+
+    #[test]
+    fn vendors_and_their_products_are_read() {
+        let ids = parsed(
+            "# comment\n1234  Acme\n\t0001  Pad\n\t0002  Wheel\n\n5678  Other\n\t0001  Stick\n",
+        );
+        assert_eq!(ids.name(0x1234, 0x0001).unwrap(), "Acme / Pad");
+        assert_eq!(ids.name(0x1234, 0x0002).unwrap(), "Acme / Wheel");
+        assert_eq!(ids.name(0x5678, 0x0001).unwrap(), "Other / Stick");
+        assert_eq!(ids.name(0x5678, 0x0002), None);
+        assert_eq!(ids.vendor(0x5678).unwrap(), "Other");
+    }
+
+    #[test]
+    fn interface_lines_are_skipped() {
+        let ids = parsed("1234  Acme\n\t0001  Pad\n\t\t00  Interface\n\t0002  Wheel\n");
+        assert_eq!(ids.name(0x1234, 0x0001).unwrap(), "Acme / Pad");
+        assert_eq!(ids.name(0x1234, 0x0002).unwrap(), "Acme / Wheel");
+        assert_eq!(ids.name(0x1234, 0x0000), None);
+    }
+
+    #[test]
+    fn reading_stops_at_the_device_classes() {
+        let ids = parsed("1234  Acme\n\t0001  Pad\nC 00  (Defined at Interface level)\n\t01  Audio\n5678  Other\n");
+        assert_eq!(ids.name(0x1234, 0x0001).unwrap(), "Acme / Pad");
+        assert_eq!(ids.vendor(0x5678), None);
+    }
+
+    #[test]
+    fn products_before_any_vendor_are_dropped() {
+        let ids = parsed("\t0001  Orphan\n1234  Acme\n");
+        assert_eq!(ids.name(0x1234, 0x0001), None);
+        assert_eq!(ids.vendor(0x1234).unwrap(), "Acme");
+    }
+
+    #[test]
+    fn later_files_override_the_built_in_names() {
+        let mut ids = parsed(BUILT_IN);
+        ids.add("045e  Microsoft\n\t028e  X360\n");
+        assert_eq!(ids.name(0x045e, 0x028e).unwrap(), "Microsoft / X360");
+        assert_eq!(
+            ids.name(0x045e, 0x0b13).unwrap(),
+            "Microsoft / Xbox Wireless Controller [Bluetooth]"
+        );
+    }
+
+    #[test]
+    fn generic_names_are_replaced() {
+        let mut controllers = FakeProvider::sample().controllers().unwrap();
+        rename(&mut controllers, &parsed(BUILT_IN));
+        assert_eq!(
+            controllers[1].name,
+            "Sony Corp. / DualSense Wireless Controller"
+        );
+        // an unknown vendor
+        assert_eq!(controllers[3].name, "USB Gamepad");
+    }
+
+    #[test]
+    fn known_vendors_of_unknown_products_are_added() {
+        let mut controllers = FakeProvider::sample().controllers().unwrap();
+        controllers[3].instance_id = r"HID\VID_044F&PID_B10A\6&1&0&0000".to_string();
+        rename(&mut controllers, &parsed(BUILT_IN));
+        assert_eq!(controllers[3].name, "USB Gamepad (ThrustMaster, Inc.)");
+    }
+
+    #[test]
+    fn friendly_names_are_left_alone() {
+        let mut controllers = FakeProvider::sample().controllers().unwrap();
+        controllers[1].friendly_name = Some("Player 2".to_string());
+        controllers[1].name = "HID-compliant game controller".to_string();
+        assert!(!is_generic(&controllers[1]));
+        rename(&mut controllers, &parsed(BUILT_IN));
+        assert_eq!(controllers[1].name, "HID-compliant game controller");
+    }
+
+    #[test]
+    fn names_from_drivers_are_left_alone() {
+        let mut controllers = FakeProvider::sample().controllers().unwrap();
+        controllers[1].manufacturer = "Sony".to_string();
+        assert!(!is_generic(&controllers[1]));
+        rename(&mut controllers, &parsed(BUILT_IN));
+        assert_eq!(controllers[1].name, "DualSense Wireless Controller");
+    }
+}
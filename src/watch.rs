@@ -0,0 +1,470 @@
+//! Following controllers as they come and go
+//!
+//! Configuration manager notifications only say that something in the device
+//! tree changed, so after every burst of notifications the controllers are
+//! enumerated again and compared with what was seen before.
+//!
+//! Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use std::{
+    collections::VecDeque,
+    ffi::c_void,
+    future,
+    mem::size_of,
+    pin::Pin,
+    sync::{
+        mpsc::{channel, Receiver, Sender, TryRecvError},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use futures_core::Stream;
+use serde::Serialize;
+use windows::Win32::{
+    Devices::DeviceAndDriverInstallation::{
+        CM_Register_Notification, CM_Unregister_Notification, CM_NOTIFY_ACTION,
+        CM_NOTIFY_EVENT_DATA, CM_NOTIFY_FILTER, CM_NOTIFY_FILTER_FLAG_ALL_DEVICE_INSTANCES,
+        CM_NOTIFY_FILTER_FLAG_ALL_INTERFACE_CLASSES, CM_NOTIFY_FILTER_TYPE,
+        CM_NOTIFY_FILTER_TYPE_DEVICEINSTANCE, CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE, CR_SUCCESS,
+        HCMNOTIFICATION,
+    },
+    Foundation::{FILETIME, TRUE},
+    System::Threading::{
+        CloseThreadpoolTimer, CreateThreadpoolTimer, SetThreadpoolTimer,
+        WaitForThreadpoolTimerCallbacks, PTP_CALLBACK_INSTANCE, PTP_TIMER,
+    },
+};
+
+use crate::{
+    decode,
+    devenum::{game_controllers, Error, GameController, GameControllerStatus, Scope},
+    timestamp::Timestamp,
+};
+
+/// How long the device tree has to be quiet before it's enumerated again.
+/// A single connect causes a dozen notifications for the devnodes involved.
+const SETTLE_TIME: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Connected,
+    Disconnected,
+    Enabled,
+    Disabled,
+    /// The devnode is gone altogether
+    Removed,
+    /// The CM_PROB_xx problem code changed without the status changing,
+    /// e.g. a driver failed to start
+    ProblemChanged,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ControllerEvent {
+    pub time: Timestamp,
+    pub kind: EventKind,
+    pub controller: GameController,
+}
+
+/// What happened between two enumerations of the same scope
+pub fn diff(
+    old: &[GameController],
+    new: &[GameController],
+    time: Timestamp,
+) -> Vec<ControllerEvent> {
+    use GameControllerStatus::*;
+    let event = |kind, controller: &GameController| ControllerEvent {
+        time,
+        kind,
+        controller: controller.clone(),
+    };
+    let mut events = Vec::new();
+    for controller in new {
+        let before = old.iter().find(|c| c.instance_id == controller.instance_id);
+        let kind = match (before.map(|c| c.status), controller.status) {
+            (Some(a), b) if a == b => match before.map(|c| c.problem_code) {
+                Some(code) if code != controller.problem_code => EventKind::ProblemChanged,
+                _ => continue,
+            },
+            (None, Disconnected) => continue,
+            (None | Some(Disconnected), _) => EventKind::Connected,
+            (_, Disconnected) => EventKind::Disconnected,
+            (_, Enabled) => EventKind::Enabled,
+            (_, Disabled) => EventKind::Disabled,
+        };
+        events.push(event(kind, controller));
+    }
+    for controller in old {
+        if !new.iter().any(|c| c.instance_id == controller.instance_id) {
+            let kind = match controller.status {
+                Disconnected => EventKind::Removed,
+                // without include_disconnected, leaving the list means this
+                _ => EventKind::Disconnected,
+            };
+            events.push(event(kind, controller));
+        }
+    }
+    events
+}
+
+/// What a registration calls for every notification, on a thread of the
+/// system
+type Callback = Box<dyn Fn() + Send + Sync>;
+
+/// Registrations for device tree changes, removed when dropped
+struct Registration {
+    handles: Vec<HCMNOTIFICATION>,
+    callback: *mut Callback,
+}
+
+// SAFETY: the callback is Send and Sync, and it's only released in drop,
+// after unregistering, whatever thread that happens on
+unsafe impl Send for Registration {}
+
+impl Registration {
+    /// Register for arrival, removal and state changes of all devices and
+    /// device interfaces
+    fn new(callback: impl Fn() + Send + Sync + 'static) -> Result<Self, Error> {
+        let callback: Callback = Box::new(callback);
+        let mut registration = Self {
+            handles: Vec::new(),
+            callback: Box::into_raw(Box::new(callback)),
+        };
+        for (filter_type, flags) in [
+            (
+                CM_NOTIFY_FILTER_TYPE_DEVICEINSTANCE,
+                CM_NOTIFY_FILTER_FLAG_ALL_DEVICE_INSTANCES,
+            ),
+            (
+                CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE,
+                CM_NOTIFY_FILTER_FLAG_ALL_INTERFACE_CLASSES,
+            ),
+        ] {
+            let handle = unsafe { registration.register_filter(filter_type, flags)? };
+            registration.handles.push(handle);
+        }
+        Ok(registration)
+    }
+
+    unsafe fn register_filter(
+        &self,
+        filter_type: CM_NOTIFY_FILTER_TYPE,
+        flags: u32,
+    ) -> Result<HCMNOTIFICATION, Error> {
+        let filter = CM_NOTIFY_FILTER {
+            cbSize: size_of::<CM_NOTIFY_FILTER>() as u32,
+            Flags: flags,
+            FilterType: filter_type,
+            ..Default::default()
+        };
+        let mut handle = HCMNOTIFICATION::default();
+        let result = CM_Register_Notification(
+            &filter,
+            Some(self.callback as *const c_void),
+            Some(notify),
+            &mut handle,
+        );
+        log::debug!(
+            "CM_Register_Notification(type={}) -> {}",
+            filter_type.0,
+            decode::configret(result)
+        );
+        match result {
+            CR_SUCCESS => Ok(handle),
+            x => Err(Error::ConfigRet(x)),
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        unsafe {
+            // unregistering waits for running callbacks, so the callback can
+            // be released afterwards
+            for handle in self.handles.drain(..) {
+                let _ = CM_Unregister_Notification(handle);
+            }
+            drop(Box::from_raw(self.callback));
+        }
+    }
+}
+
+unsafe extern "system" fn notify(
+    _notification: HCMNOTIFICATION,
+    context: *const c_void,
+    action: CM_NOTIFY_ACTION,
+    _event_data: *const CM_NOTIFY_EVENT_DATA,
+    _event_data_size: u32,
+) -> u32 {
+    log::trace!("device notification, action={}", action.0);
+    let callback = &*(context as *const Callback);
+    callback();
+    CR_SUCCESS.0
+}
+
+/// Device tree changes to wait for, unregistered when dropped
+pub struct Notifications {
+    _registration: Registration,
+    receiver: Receiver<()>,
+}
+
+impl Notifications {
+    /// Register for arrival, removal and state changes of all devices and
+    /// device interfaces
+    pub fn register() -> Result<Self, Error> {
+        let (sender, receiver) = channel();
+        // the receiver only goes away while unregistering
+        let registration = Registration::new(move || {
+            let _ = sender.send(());
+        })?;
+        Ok(Self {
+            _registration: registration,
+            receiver,
+        })
+    }
+
+    /// Block until something changed in the device tree and things have
+    /// settled down again
+    pub fn wait(&self) {
+        if self.receiver.recv().is_err() {
+            return;
+        }
+        while self.receiver.recv_timeout(SETTLE_TIME).is_ok() {}
+    }
+
+    /// Whether anything changed in the device tree since the last call,
+    /// without waiting
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        while self.receiver.try_recv().is_ok() {
+            changed = true;
+        }
+        changed
+    }
+
+    /// Like `wait`, but give up after `timeout` if nothing changed. Returns
+    /// whether there was a change.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        if self.receiver.recv_timeout(timeout).is_err() {
+            return false;
+        }
+        while self.receiver.recv_timeout(SETTLE_TIME).is_ok() {}
+        true
+    }
+}
+
+/// Call `on_event` for every change to the controllers in given scope until
+/// an error occurs
+pub fn watch(scope: Scope, mut on_event: impl FnMut(&ControllerEvent)) -> Result<(), Error> {
+    let notifications = Notifications::register()?;
+    let mut known = game_controllers(scope)?;
+    loop {
+        notifications.wait();
+        let current = game_controllers(scope)?;
+        for event in diff(&known, &current, Timestamp::now()) {
+            on_event(&event);
+        }
+        known = current;
+    }
+}
+
+/// A finished enumeration, handed from the timer callback to the task
+type Enumerated = Result<Vec<GameController>, Error>;
+
+/// What the timer callback behind [`ControllerEvents`] enumerates with, and
+/// who it tells
+struct Settled {
+    scope: Scope,
+    /// Locked while enumerating too, so that an enumeration that ran late
+    /// can't be sent after a later one
+    sender: Mutex<Sender<Enumerated>>,
+    waker: Mutex<Option<Waker>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // a panic can't leave them half changed
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A thread pool timer that enumerates the controllers and wakes the task
+/// SETTLE_TIME after it was last armed
+struct SettleTimer {
+    timer: PTP_TIMER,
+    /// From `Arc::into_raw`, the context of the timer callback
+    settled: *const Settled,
+}
+
+// SAFETY: thread pool timers can be set and closed from any thread, and
+// the context is only shared with the callback through a Mutex
+unsafe impl Send for SettleTimer {}
+
+impl SettleTimer {
+    fn new(settled: Arc<Settled>) -> Result<Self, Error> {
+        let settled = Arc::into_raw(settled);
+        let timer =
+            unsafe { CreateThreadpoolTimer(Some(timer_fired), Some(settled as *mut c_void), None) };
+        match timer {
+            Ok(timer) => Ok(Self { timer, settled }),
+            Err(err) => {
+                drop(unsafe { Arc::from_raw(settled) });
+                Err(Error::Win32(err))
+            }
+        }
+    }
+}
+
+impl Drop for SettleTimer {
+    fn drop(&mut self) {
+        unsafe {
+            SetThreadpoolTimer(self.timer, None, 0, 0);
+            WaitForThreadpoolTimerCallbacks(self.timer, TRUE);
+            CloseThreadpoolTimer(self.timer);
+            drop(Arc::from_raw(self.settled));
+        }
+    }
+}
+
+/// Start the timer again, so that it fires SETTLE_TIME after the last of a
+/// burst of notifications
+fn arm(timer: PTP_TIMER) {
+    // relative due times are negative, in 100ns units
+    let due = -((SETTLE_TIME.as_nanos() / 100) as i64);
+    let due = FILETIME {
+        dwLowDateTime: due as u32,
+        dwHighDateTime: (due >> 32) as u32,
+    };
+    unsafe { SetThreadpoolTimer(timer, Some(&due), 0, 0) };
+}
+
+unsafe extern "system" fn timer_fired(
+    _instance: PTP_CALLBACK_INSTANCE,
+    context: *mut c_void,
+    _timer: PTP_TIMER,
+) {
+    let settled = &*(context as *const Settled);
+    {
+        let sender = lock(&settled.sender);
+        // the receiver only goes away after the timer is closed
+        let _ = sender.send(game_controllers(settled.scope));
+    }
+    if let Some(waker) = lock(&settled.waker).take() {
+        waker.wake();
+    }
+}
+
+/// Changes to the controllers for async code, from [`controller_events`].
+/// The controllers are enumerated again on a thread of the system's pool
+/// when the device tree has settled down after a change, so polling never
+/// blocks the executor. The events can be awaited from any thread.
+pub struct ControllerEvents {
+    // dropped in this order, so that nothing arms the timer once it's closed
+    _registration: Registration,
+    _timer: SettleTimer,
+    settled: Arc<Settled>,
+    receiver: Receiver<Enumerated>,
+    known: Vec<GameController>,
+    /// Found but not taken yet
+    pending: VecDeque<ControllerEvent>,
+    /// Set after an error, nothing comes after it
+    finished: bool,
+}
+
+impl ControllerEvents {
+    /// The next event, None after an error was returned
+    pub async fn next(&mut self) -> Option<Result<ControllerEvent, Error>> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for ControllerEvents {
+    type Item = Result<ControllerEvent, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(event) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            if this.finished {
+                return Poll::Ready(None);
+            }
+            let enumerated = match this.receiver.try_recv() {
+                Ok(enumerated) => enumerated,
+                Err(TryRecvError::Empty) => {
+                    *lock(&this.settled.waker) = Some(cx.waker().clone());
+                    // sent before the waker was there to be woken
+                    match this.receiver.try_recv() {
+                        Ok(enumerated) => enumerated,
+                        Err(_) => return Poll::Pending,
+                    }
+                }
+                // the timer outlives the receiver
+                Err(TryRecvError::Disconnected) => return Poll::Ready(None),
+            };
+            match enumerated {
+                Ok(current) => {
+                    let events = diff(&this.known, &current, Timestamp::now());
+                    this.pending.extend(events);
+                    this.known = current;
+                }
+                Err(err) => {
+                    this.finished = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+            }
+        }
+    }
+}
+
+/// Changes to the controllers in given scope as a [`Stream`], to await them
+/// on any async runtime:
+///
+/// ```no_run
+/// # async fn example() {
+/// use nojoy_rs::{devenum::Scope, watch};
+///
+/// let mut events = watch::controller_events(Scope::default()).unwrap();
+/// while let Some(Ok(event)) = events.next().await {
+///     println!("{:?} {}", event.kind, event.controller.name);
+/// }
+/// # }
+/// ```
+///
+/// The configuration manager's notifications arm a thread pool timer. Once
+/// they stopped coming for as long as `watch` waits, the timer enumerates the
+/// controllers, sends them over a channel and wakes the task.
+pub fn controller_events(scope: Scope) -> Result<ControllerEvents, Error> {
+    let (sender, receiver) = channel();
+    let settled = Arc::new(Settled {
+        scope,
+        sender: Mutex::new(sender),
+        waker: Mutex::new(None),
+    });
+    let timer = SettleTimer::new(Arc::clone(&settled))?;
+    let handle = timer.timer;
+    let registration = Registration::new(move || arm(handle))?;
+    let known = game_controllers(scope)?;
+    Ok(ControllerEvents {
+        _registration: registration,
+        _timer: timer,
+        settled,
+        receiver,
+        known,
+        pending: VecDeque::new(),
+        finished: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn events_can_be_awaited_on_another_thread() {
+        assert_send::<ControllerEvents>();
+        assert_send::<Notifications>();
+    }
+}
@@ -0,0 +1,100 @@
+/// Why a device can't be disabled, and what can be done about it
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use serde::Serialize;
+use windows::Win32::Devices::{
+    DeviceAndDriverInstallation::CM_PROB, Properties::DEVPKEY_Device_Class,
+};
+
+use nojoy_rs::{
+    decode,
+    devenum::{self, GameController, GameControllerStatus, PropertyValue, Scope},
+    elevation,
+};
+
+/// Setup classes of devices Windows needs to run, which Device Manager
+/// doesn't offer to disable
+const PROTECTED_CLASSES: &[&str] = &["System", "Computer", "Processor", "Volume", "DiskDrive"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Explanation {
+    pub instance_id: String,
+    pub name: String,
+    /// Whether the device itself can be disabled, elevation aside
+    pub disableable: bool,
+    /// Everything that stands in the way, with what can be done instead
+    pub reasons: Vec<String>,
+}
+
+pub fn explain(controller: &GameController, scope: Scope) -> Explanation {
+    let mut reasons = Vec::new();
+    let connected = controller.status != GameControllerStatus::Disconnected;
+    if !connected {
+        reasons.push("it isn't connected, only connected devices can be disabled".to_string());
+    } else if !controller.disableable {
+        reasons.push(
+            "its driver doesn't allow it (DN_DISABLEABLE isn't set), \
+             `hidhide hide` can hide it from applications instead"
+                .to_string(),
+        );
+    }
+    if controller.problem_code != 0 {
+        let problem = CM_PROB(controller.problem_code);
+        reasons.push(match decode::problem_explanation(problem) {
+            Some(explanation) => format!("it has {}: {}", decode::problem(problem), explanation),
+            None => format!("it has {}", decode::problem(problem)),
+        });
+    }
+    let class =
+        match devenum::device_property(&controller.instance_id, scope, &DEVPKEY_Device_Class) {
+            Ok(Some(PropertyValue::String(class))) => Some(class),
+            _ => None,
+        };
+    let protected_class = class.filter(|class| {
+        PROTECTED_CLASSES
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(class))
+    });
+    if let Some(class) = &protected_class {
+        reasons.push(format!(
+            "it's in the {} setup class, whose devices Windows needs to run",
+            class
+        ));
+    }
+    let composite = controller
+        .parent_id
+        .as_deref()
+        .filter(|p| p.contains("&MI_"));
+    if let (Some(parent), false) = (composite, controller.disableable) {
+        reasons.push(format!(
+            "it sits on {}, a function of a composite USB device, which can \
+             sometimes be disabled instead",
+            parent
+        ));
+    }
+    if matches!(elevation::is_elevated(), Ok(false)) {
+        reasons.push(
+            "this prompt isn't elevated, run it from an administrator prompt or with --elevate"
+                .to_string(),
+        );
+    }
+    Explanation {
+        instance_id: controller.instance_id.clone(),
+        name: controller.name.clone(),
+        disableable: connected && controller.disableable && protected_class.is_none(),
+        reasons,
+    }
+}
+
+pub fn render_text(explanation: &Explanation) -> String {
+    let verdict = match (explanation.disableable, explanation.reasons.is_empty()) {
+        (true, true) => "can be disabled",
+        (true, false) => "can be disabled, but",
+        (false, _) => "can't be disabled,",
+    };
+    let mut text = format!("{} {}\n", explanation.name, verdict);
+    for reason in &explanation.reasons {
+        text += &format!("  - {}\n", reason);
+    }
+    text
+}
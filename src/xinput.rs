@@ -0,0 +1,148 @@
+/// Finding out which XInput user index ("player") a controller has, and how
+/// charged its battery is
+///
+/// The documented XInput API identifies controllers only by user index, so
+/// the undocumented `XInputGetCapabilitiesEx` export (ordinal 108 of
+/// xinput1_4.dll) is used to get each slot's USB vendor and product ids. A
+/// slot is assigned to a devnode only when the ids match exactly one XInput
+/// devnode, identical pads can't be told apart this way.
+///
+/// Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+use windows::{
+    core::{w, PCSTR},
+    Win32::{
+        Foundation::ERROR_SUCCESS,
+        System::LibraryLoader::{GetProcAddress, LoadLibraryW},
+        UI::Input::XboxController::{
+            XInputGetBatteryInformation, XInputSetState, BATTERY_DEVTYPE_GAMEPAD,
+            BATTERY_LEVEL_EMPTY, BATTERY_LEVEL_LOW, BATTERY_LEVEL_MEDIUM,
+            BATTERY_TYPE_DISCONNECTED, BATTERY_TYPE_WIRED, XINPUT_BATTERY_INFORMATION,
+            XINPUT_CAPABILITIES, XINPUT_FLAG, XINPUT_VIBRATION, XUSER_MAX_COUNT,
+        },
+    },
+};
+
+use std::{thread, time::Duration};
+
+pub use crate::devenum::Battery;
+use crate::devenum::{vid_pid, GameController, GameControllerStatus};
+
+const GET_CAPABILITIES_EX_ORDINAL: usize = 108;
+
+#[repr(C)]
+#[derive(Default)]
+#[allow(non_snake_case)]
+struct XINPUT_CAPABILITIES_EX {
+    Capabilities: XINPUT_CAPABILITIES,
+    VendorId: u16,
+    ProductId: u16,
+    ProductVersion: u16,
+    Unknown1: u16,
+    Unknown2: u32,
+}
+
+type GetCapabilitiesEx = unsafe extern "system" fn(
+    reserved: u32,
+    user_index: u32,
+    flags: XINPUT_FLAG,
+    capabilities: *mut XINPUT_CAPABILITIES_EX,
+) -> u32;
+
+/// A connected XInput controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pad {
+    /// User index, 0 to 3
+    pub slot: u32,
+    pub vid: u16,
+    pub pid: u16,
+}
+
+/// Battery of the controller in given slot, None if the slot is empty
+pub fn battery(slot: u32) -> Option<Battery> {
+    let mut info = XINPUT_BATTERY_INFORMATION::default();
+    let result = unsafe { XInputGetBatteryInformation(slot, BATTERY_DEVTYPE_GAMEPAD, &mut info) };
+    if result != ERROR_SUCCESS.0 {
+        return None;
+    }
+    Some(match (info.BatteryType, info.BatteryLevel) {
+        (BATTERY_TYPE_DISCONNECTED, _) => return None,
+        (BATTERY_TYPE_WIRED, _) => Battery::Wired,
+        (_, BATTERY_LEVEL_EMPTY) => Battery::Empty,
+        (_, BATTERY_LEVEL_LOW) => Battery::Low,
+        (_, BATTERY_LEVEL_MEDIUM) => Battery::Medium,
+        _ => Battery::Full,
+    })
+}
+
+/// Run both motors of the controller in given slot at full speed for a
+/// while, false if the slot is empty
+pub fn rumble(slot: u32, duration: Duration) -> bool {
+    let set = |speed| {
+        let vibration = XINPUT_VIBRATION {
+            wLeftMotorSpeed: speed,
+            wRightMotorSpeed: speed,
+        };
+        unsafe { XInputSetState(slot, &vibration) == ERROR_SUCCESS.0 }
+    };
+    if !set(u16::MAX) {
+        return false;
+    }
+    thread::sleep(duration);
+    set(0)
+}
+
+/// Connected XInput controllers, none if xinput1_4.dll or its
+/// `XInputGetCapabilitiesEx` export isn't there
+pub fn pads() -> Vec<Pad> {
+    let get_capabilities: GetCapabilitiesEx = unsafe {
+        let Ok(module) = LoadLibraryW(w!("xinput1_4.dll")) else {
+            log::debug!("xinput1_4.dll can't be loaded");
+            return Vec::new();
+        };
+        // the ordinal goes in place of the name
+        let Some(proc) = GetProcAddress(module, PCSTR(GET_CAPABILITIES_EX_ORDINAL as *const u8))
+        else {
+            log::debug!("xinput1_4.dll has no XInputGetCapabilitiesEx");
+            return Vec::new();
+        };
+        std::mem::transmute(proc)
+    };
+    (0..XUSER_MAX_COUNT)
+        .filter_map(|slot| {
+            let mut caps = XINPUT_CAPABILITIES_EX::default();
+            let result = unsafe { get_capabilities(1, slot, XINPUT_FLAG(0), &mut caps) };
+            (result == ERROR_SUCCESS.0).then_some(Pad {
+                slot,
+                vid: caps.VendorId,
+                pid: caps.ProductId,
+            })
+        })
+        .collect()
+}
+
+/// XInput devnodes have an "IG_xx" part in their instance id
+fn is_xinput(controller: &GameController) -> bool {
+    controller.status != GameControllerStatus::Disconnected
+        && controller.instance_id.to_uppercase().contains("&IG_")
+}
+
+/// Set the XInput slot and battery of each controller that can be told apart
+/// from the others by its USB ids
+pub fn assign_slots(controllers: &mut [GameController]) {
+    let pads = pads();
+    for pad in &pads {
+        let mut candidates = controllers
+            .iter_mut()
+            .filter(|c| is_xinput(c) && vid_pid(&c.instance_id) == Some((pad.vid, pad.pid)));
+        match (candidates.next(), candidates.next()) {
+            (Some(controller), None) => {
+                controller.xinput_slot = Some(pad.slot);
+                controller.battery = battery(pad.slot);
+            }
+            (Some(_), Some(_)) => {
+                log::debug!("XInput slot {} matches more than one devnode", pad.slot)
+            }
+            (None, _) => log::debug!("XInput slot {} matches no devnode", pad.slot),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Loads the cdylib the way a C caller would and goes through the C API
+//!
+//! Copyright (c) 2023 - Sedat Kapanoglu <sedat@kapanoglu.com>
+#![cfg(feature = "ffi")]
+
+use std::{
+    env,
+    ffi::{c_char, CStr, CString},
+    path::PathBuf,
+    ptr,
+};
+
+use libloading::{Library, Symbol};
+use nojoy_rs::ffi::{NOJOY_BUFFER_TOO_SMALL, NOJOY_INVALID_ARGUMENT, NOJOY_OK};
+
+type ListFn = unsafe extern "C" fn(*mut u8, *mut usize) -> i32;
+type ChangeFn = unsafe extern "C" fn(*const c_char) -> i32;
+type LastErrorFn = extern "C" fn() -> *mut c_char;
+type FreeStringFn = unsafe extern "C" fn(*mut c_char);
+type ListAllocFn = unsafe extern "C" fn(*mut *mut c_char, *mut usize) -> i32;
+
+/// Cargo builds the library next to the test executable, and copies it one
+/// directory up on `cargo build`
+fn library() -> Library {
+    let exe = env::current_exe().unwrap();
+    let name = format!(
+        "{}nojoy_rs{}",
+        env::consts::DLL_PREFIX,
+        env::consts::DLL_SUFFIX
+    );
+    let path: PathBuf = exe
+        .ancestors()
+        .skip(1)
+        .take(2)
+        .map(|dir| dir.join(&name))
+        .find(|path| path.exists())
+        .expect("the cdylib is built with the tests");
+    unsafe { Library::new(path) }.unwrap()
+}
+
+fn last_error(lib: &Library) -> Option<String> {
+    unsafe {
+        let last_error: Symbol<LastErrorFn> = lib.get(b"nojoy_last_error_message").unwrap();
+        let free_string: Symbol<FreeStringFn> = lib.get(b"nojoy_free_string").unwrap();
+        let message = last_error();
+        if message.is_null() {
+            return None;
+        }
+        let text = CStr::from_ptr(message).to_string_lossy().into_owned();
+        free_string(message);
+        Some(text)
+    }
+}
+
+#[test]
+fn list_is_queried_then_written() {
+    let lib = library();
+    let list: Symbol<ListFn> = unsafe { lib.get(b"nojoy_list") }.unwrap();
+    let mut len = 0;
+    let status = unsafe { list(ptr::null_mut(), &mut len) };
+    if status != NOJOY_BUFFER_TOO_SMALL {
+        // no controller list on this machine, but the failure has to be told
+        assert!(
+            last_error(&lib).is_some(),
+            "status {} without a message",
+            status
+        );
+        return;
+    }
+    assert!(len >= 2, "the JSON of an empty list is two bytes");
+    let mut buf = vec![0u8; len];
+    assert_eq!(unsafe { list(buf.as_mut_ptr(), &mut len) }, NOJOY_OK);
+    assert_eq!(last_error(&lib), None);
+    let json: serde_json::Value = serde_json::from_slice(&buf[..len]).unwrap();
+    assert!(json.is_array());
+}
+
+#[test]
+fn list_without_out_len_is_invalid() {
+    let lib = library();
+    let list: Symbol<ListFn> = unsafe { lib.get(b"nojoy_list") }.unwrap();
+    assert_eq!(
+        unsafe { list(ptr::null_mut(), ptr::null_mut()) },
+        NOJOY_INVALID_ARGUMENT
+    );
+    assert_eq!(last_error(&lib).as_deref(), Some("out_len is null"));
+}
+
+#[test]
+fn allocated_list_is_freed() {
+    let lib = library();
+    let list_alloc: Symbol<ListAllocFn> = unsafe { lib.get(b"nojoy_list_alloc") }.unwrap();
+    let free_list: Symbol<FreeStringFn> = unsafe { lib.get(b"nojoy_free_list") }.unwrap();
+    let mut json = ptr::null_mut();
+    let mut len = 0;
+    let status = unsafe { list_alloc(&mut json, &mut len) };
+    if status != NOJOY_OK {
+        assert!(json.is_null());
+        assert!(
+            last_error(&lib).is_some(),
+            "status {} without a message",
+            status
+        );
+        return;
+    }
+    let text = unsafe { CStr::from_ptr(json) }
+        .to_str()
+        .unwrap()
+        .to_string();
+    unsafe { free_list(json) };
+    assert_eq!(text.len(), len);
+    let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert!(json.is_array());
+}
+
+#[test]
+fn list_alloc_without_out_json_is_invalid() {
+    let lib = library();
+    let list_alloc: Symbol<ListAllocFn> = unsafe { lib.get(b"nojoy_list_alloc") }.unwrap();
+    assert_eq!(
+        unsafe { list_alloc(ptr::null_mut(), ptr::null_mut()) },
+        NOJOY_INVALID_ARGUMENT
+    );
+    assert_eq!(last_error(&lib).as_deref(), Some("out_json is null"));
+}
+
+#[test]
+fn null_instance_id_is_invalid() {
+    let lib = library();
+    for name in [&b"nojoy_enable"[..], b"nojoy_disable"] {
+        let change: Symbol<ChangeFn> = unsafe { lib.get(name) }.unwrap();
+        assert_eq!(unsafe { change(ptr::null()) }, NOJOY_INVALID_ARGUMENT);
+        assert_eq!(last_error(&lib).as_deref(), Some("instance id is null"));
+    }
+}
+
+#[test]
+fn unknown_device_is_an_error_with_a_message() {
+    let lib = library();
+    let enable: Symbol<ChangeFn> = unsafe { lib.get(b"nojoy_enable") }.unwrap();
+    let id = CString::new(r"HID\VID_0000&PID_0000\NOJOY_TEST").unwrap();
+    assert_ne!(unsafe { enable(id.as_ptr()) }, NOJOY_OK);
+    assert!(last_error(&lib).is_some());
+}
+
+#[test]
+fn null_string_is_freed() {
+    let lib = library();
+    for name in [&b"nojoy_free_string"[..], b"nojoy_free_list"] {
+        let free: Symbol<FreeStringFn> = unsafe { lib.get(name) }.unwrap();
+        unsafe { free(ptr::null_mut()) };
+    }
+}